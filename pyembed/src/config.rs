@@ -31,8 +31,17 @@ pub struct ExtensionModule {
 
 /// A source for packed resources data.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serialization",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "snake_case", tag = "type", content = "path")
+)]
 pub enum PackedResourcesSource<'a> {
     /// A reference to raw resources data in memory.
+    ///
+    /// Cannot be serialized. Callers loading a serialized config must
+    /// register in-memory sources themselves.
+    #[cfg_attr(feature = "serialization", serde(skip))]
     Memory(&'a [u8]),
 
     /// Load resources data from a filesystem path using memory mapped I/O.
@@ -74,7 +83,17 @@ impl<'a> From<&'a [u8]> for PackedResourcesSource<'a> {
 /// configuration that is defined in the CPython initialization and
 /// configuration API and `OxidizedPythonInterpreterConfig` exists to
 /// hold higher-level configuration for features specific to this crate.
+///
+/// With the `serialization` feature enabled, this type can be (de)serialized
+/// with serde. Fields not present in serialized data take their default values.
+/// `extra_extension_modules`, `argv`, and in-memory `packed_resources` entries
+/// are never serialized.
 #[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serialization",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
 pub struct OxidizedPythonInterpreterConfig<'a> {
     /// The path of the currently executing executable.
     ///
@@ -193,11 +212,13 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     /// to the directory of the current executable or the value of
     /// `self.origin` if set. Relative paths without `$ORIGIN` will be evaluated
     /// relative to the process's current working directory.
+    #[cfg_attr(feature = "serialization", serde(borrow))]
     pub packed_resources: Vec<PackedResourcesSource<'a>>,
 
     /// Extra extension modules to make available to the interpreter.
     ///
     /// The values will effectively be passed to ``PyImport_ExtendInitTab()``.
+    #[cfg_attr(feature = "serialization", serde(skip))]
     pub extra_extension_modules: Option<Vec<ExtensionModule>>,
 
     /// Command line arguments to initialize `sys.argv` with.
@@ -209,6 +230,7 @@ pub struct OxidizedPythonInterpreterConfig<'a> {
     /// `None` is evaluated to `Some(std::env::args_os().collect::<Vec<_>>()`
     /// if `.interpreter_config.argv` is `None` or `None` if
     /// `.interpreter_config.argv` is `Some(T)`.
+    #[cfg_attr(feature = "serialization", serde(skip))]
     pub argv: Option<Vec<OsString>>,
 
     /// Whether to set sys.argvb with bytes versions of process arguments.
@@ -284,6 +306,22 @@ impl<'a> Default for OxidizedPythonInterpreterConfig<'a> {
 }
 
 impl<'a> OxidizedPythonInterpreterConfig<'a> {
+    /// Construct an instance from JSON data.
+    ///
+    /// This allows a configuration to be embedded in a binary as data (e.g.
+    /// via `include_bytes!`) instead of as generated Rust code. Packed
+    /// resources held in memory must be added to `packed_resources` after
+    /// loading.
+    #[cfg(feature = "serialization")]
+    pub fn from_json_slice(data: &'a [u8]) -> Result<Self, NewInterpreterError> {
+        serde_json::from_slice(data).map_err(|e| {
+            NewInterpreterError::Dynamic(format!(
+                "unable to parse interpreter config from JSON: {}",
+                e
+            ))
+        })
+    }
+
     /// Create a new type with all values resolved.
     pub fn resolve(
         self,
@@ -463,4 +501,93 @@ mod tests {
 
         Ok(())
     }
+
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn test_from_json_slice() -> Result<()> {
+        let config = OxidizedPythonInterpreterConfig::from_json_slice(
+            br#"{
+                "interpreter_config": {"profile": "isolated", "utf8_mode": true},
+                "allocator_backend": "rust",
+                "packed_resources": [{"type": "memory_mapped_path", "path": "$ORIGIN/packed-resources"}],
+                "terminfo_resolution": "none"
+            }"#,
+        )?;
+
+        assert_eq!(
+            config.interpreter_config.profile,
+            PythonInterpreterProfile::Isolated
+        );
+        assert_eq!(config.interpreter_config.utf8_mode, Some(true));
+        assert_eq!(config.allocator_backend, MemoryAllocatorBackend::Rust);
+        assert_eq!(
+            config.packed_resources,
+            vec![PackedResourcesSource::MemoryMappedPath(PathBuf::from(
+                "$ORIGIN/packed-resources"
+            ))]
+        );
+        assert_eq!(config.terminfo_resolution, TerminfoResolution::None);
+        // Unspecified fields take default values.
+        assert!(config.allocator_raw);
+        assert!(config.filesystem_importer);
+
+        assert!(OxidizedPythonInterpreterConfig::from_json_slice(
+            br#"{"packed_resources": [{"type": "memory", "path": [0]}]}"#
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    /// Loads JSON produced by `pyoxidizer`, which verifies it serializes to this content.
+    #[cfg(feature = "serialization")]
+    #[test]
+    fn test_from_json_slice_pyoxidizer() -> Result<()> {
+        let config = OxidizedPythonInterpreterConfig::from_json_slice(include_bytes!(
+            "../../pyoxidizer/src/py_packaging/pyembed-config.json"
+        ))?;
+
+        assert_eq!(
+            config.interpreter_config.profile,
+            PythonInterpreterProfile::Isolated
+        );
+        assert_eq!(
+            config.interpreter_config.allocator,
+            Some(python_packaging::interpreter::Allocator::Malloc)
+        );
+        assert_eq!(config.interpreter_config.configure_locale, Some(true));
+        assert_eq!(
+            config.interpreter_config.check_hash_pycs_mode,
+            Some(python_packaging::interpreter::CheckHashPycsMode::Always)
+        );
+        assert_eq!(
+            config.interpreter_config.module_search_paths,
+            Some(vec![PathBuf::from("$ORIGIN/lib")])
+        );
+        assert_eq!(
+            config.interpreter_config.optimization_level,
+            Some(python_packaging::resource::BytecodeOptimizationLevel::Two)
+        );
+        assert_eq!(config.allocator_backend, MemoryAllocatorBackend::Rust);
+        assert!(config.oxidized_importer);
+        assert!(config.filesystem_importer);
+        assert_eq!(
+            config.packed_resources,
+            vec![PackedResourcesSource::MemoryMappedPath(PathBuf::from(
+                "$ORIGIN/packed-resources"
+            ))]
+        );
+        assert!(config.sys_frozen);
+        assert_eq!(
+            config.terminfo_resolution,
+            TerminfoResolution::Static("/usr/share/terminfo".to_string())
+        );
+        assert_eq!(config.tcl_library, Some(PathBuf::from("$ORIGIN/tcl8.6")));
+        assert_eq!(
+            config.write_modules_directory_env,
+            Some("MODULES_DIR".to_string())
+        );
+
+        Ok(())
+    }
 }
@@ -66,6 +66,12 @@ The optional `snmalloc` feature controls support for using
 [snmalloc](https://github.com/microsoft/snmalloc) as Python's memory allocator.
 The feature behaves similarly to `jemalloc`, which is documented above.
 
+The optional `serialization` feature enables (de)serializing
+`OxidizedPythonInterpreterConfig` with `serde`. This adds a dependency on the
+`serde` and `serde_json` crates and enables
+`OxidizedPythonInterpreterConfig::from_json_slice()`, which allows an
+interpreter configuration to be embedded in a binary as data.
+
 There exist mutually exclusive `build-mode-*` features to control how the
 `build.rs` build script works.
 
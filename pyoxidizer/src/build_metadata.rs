@@ -0,0 +1,65 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Metadata describing the result of building a PyOxidizer project.
+
+Metadata is written to the build directory after targets are built. It
+records how the build was configured, including the variables passed into
+the Starlark environment, so build artifacts can be traced back to the
+parameters that produced them.
+*/
+
+use {
+    anyhow::{Context, Result},
+    serde::{Deserialize, Serialize},
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+};
+
+/// Filename of build metadata files.
+pub const BUILD_METADATA_NAME: &str = "build-metadata.json";
+
+/// Describes a completed build.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BuildMetadata {
+    /// Version of PyOxidizer performing the build.
+    pub pyoxidizer_version: String,
+
+    /// Target triple being built for.
+    pub target_triple: String,
+
+    /// Whether this was a release build.
+    pub release: bool,
+
+    /// Variables defined in the Starlark environment's `VARS` dict.
+    pub vars: BTreeMap<String, Option<String>>,
+
+    /// Built targets and the directories they were written to.
+    pub targets: BTreeMap<String, PathBuf>,
+}
+
+impl BuildMetadata {
+    /// Read an instance from a JSON file.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)
+            .with_context(|| format!("reading build metadata {}", path.display()))?;
+
+        serde_json::from_slice(&data)
+            .with_context(|| format!("parsing build metadata {}", path.display()))
+    }
+
+    /// Write the instance to a JSON file.
+    pub fn write_path(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("writing build metadata {}", path.display()))
+    }
+}
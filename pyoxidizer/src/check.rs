@@ -0,0 +1,615 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Static validation of PyOxidizer configuration files.
+
+Checking evaluates a configuration file and resolves its targets without
+building them. The resolved values are then inspected for settings that are
+invalid or that likely don't do what was intended.
+*/
+
+use {
+    crate::{
+        py_packaging::config::PyembedPythonInterpreterConfig,
+        starlark::{
+            python_executable::PythonExecutableValue,
+            python_interpreter_config::PythonInterpreterConfigValue,
+            python_packaging_policy::PythonPackagingPolicyValue,
+        },
+    },
+    anyhow::Result,
+    python_packaging::{
+        interpreter::MemoryAllocatorBackend, location::ConcreteResourceLocation,
+        policy::PythonPackagingPolicy, resource_collection::PrePackagedResource,
+    },
+    serde::Serialize,
+    starlark::values::Value,
+    std::{
+        collections::BTreeSet,
+        fmt::Write,
+        path::{Path, PathBuf},
+    },
+};
+
+/// How serious a problem found by checking is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The configuration likely doesn't behave as intended.
+    Warning,
+
+    /// The configuration can't be used.
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Warning => "warning",
+            Self::Error => "error",
+        })
+    }
+}
+
+/// A problem found by checking.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct CheckMessage {
+    pub severity: Severity,
+
+    /// Target the problem was found in.
+    ///
+    /// `None` for problems with the configuration file as a whole.
+    pub target: Option<String>,
+
+    pub message: String,
+}
+
+/// Describes a checked target.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct TargetCheck {
+    pub name: String,
+
+    /// Starlark type of the value the target resolved to.
+    ///
+    /// `None` if the target failed to resolve.
+    pub value_type: Option<String>,
+}
+
+/// The result of checking a configuration file.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct CheckReport {
+    pub config_path: PathBuf,
+    pub targets: Vec<TargetCheck>,
+    pub messages: Vec<CheckMessage>,
+}
+
+impl CheckReport {
+    pub fn new(config_path: impl AsRef<Path>) -> Self {
+        Self {
+            config_path: config_path.as_ref().to_path_buf(),
+            targets: vec![],
+            messages: vec![],
+        }
+    }
+
+    /// Record a problem.
+    pub fn add_message(&mut self, severity: Severity, target: Option<&str>, message: String) {
+        self.messages.push(CheckMessage {
+            severity,
+            target: target.map(|t| t.to_string()),
+            message,
+        });
+    }
+
+    /// Record a target that failed to resolve.
+    pub fn add_target_error(&mut self, target: &str, error: &anyhow::Error) {
+        self.targets.push(TargetCheck {
+            name: target.to_string(),
+            value_type: None,
+        });
+        self.add_message(Severity::Error, Some(target), format!("{:#}", error));
+    }
+
+    /// Record a resolved target and check its value.
+    pub fn add_target_value(&mut self, target: &str, value: &Value) {
+        self.targets.push(TargetCheck {
+            name: target.to_string(),
+            value_type: Some(value.get_type().to_string()),
+        });
+
+        for message in value_warnings(value) {
+            self.add_message(Severity::Warning, Some(target), message);
+        }
+    }
+
+    /// Number of recorded problems having a given severity.
+    pub fn count(&self, severity: Severity) -> usize {
+        self.messages
+            .iter()
+            .filter(|m| m.severity == severity)
+            .count()
+    }
+
+    /// Render the report as human readable text.
+    pub fn to_text(&self) -> String {
+        let mut s = String::new();
+
+        writeln!(s, "checked {}", self.config_path.display()).unwrap();
+
+        for message in self.messages.iter().filter(|m| m.target.is_none()) {
+            writeln!(s, "{}: {}", message.severity, message.message).unwrap();
+        }
+
+        for target in &self.targets {
+            let messages = self
+                .messages
+                .iter()
+                .filter(|m| m.target.as_deref() == Some(target.name.as_str()))
+                .collect::<Vec<_>>();
+
+            write!(s, "target {}", target.name).unwrap();
+            if let Some(value_type) = &target.value_type {
+                write!(s, " ({})", value_type).unwrap();
+            }
+            if messages.is_empty() {
+                write!(s, ": ok").unwrap();
+            }
+            writeln!(s).unwrap();
+
+            for message in messages {
+                writeln!(
+                    s,
+                    "  {}: {}",
+                    message.severity,
+                    message.message.replace('\n', "\n    ")
+                )
+                .unwrap();
+            }
+        }
+
+        writeln!(
+            s,
+            "{} errors, {} warnings",
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        )
+        .unwrap();
+
+        s
+    }
+
+    /// Render the report as JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Obtain warnings about a resolved Starlark value.
+pub fn value_warnings(value: &Value) -> Vec<String> {
+    if let Some(exe) = value.downcast_ref::<PythonExecutableValue>() {
+        let policy = exe.python_packaging_policy();
+        let config = exe.exe.interpreter_config();
+
+        let mut res = packaging_policy_warnings(&policy.inner);
+        res.extend(interpreter_config_warnings(config));
+        res.extend(resource_location_warnings(
+            &policy.inner,
+            config,
+            exe.exe.iter_resources().map(|(_, resource)| resource),
+        ));
+
+        res
+    } else if let Some(policy) = value.downcast_ref::<PythonPackagingPolicyValue>() {
+        packaging_policy_warnings(&policy.inner)
+    } else if let Some(config) = value.downcast_ref::<PythonInterpreterConfigValue>() {
+        interpreter_config_warnings(&config.inner)
+    } else {
+        vec![]
+    }
+}
+
+/// Obtain warnings about a `PythonPackagingPolicy`.
+pub fn packaging_policy_warnings(policy: &PythonPackagingPolicy) -> Vec<String> {
+    let mut res = vec![];
+
+    if !policy.bytecode_optimize_level_zero()
+        && !policy.bytecode_optimize_level_one()
+        && !policy.bytecode_optimize_level_two()
+        && !policy.include_distribution_sources()
+        && !policy.include_non_distribution_sources()
+    {
+        res.push(
+            "packaging policy includes neither source nor bytecode for Python modules; \
+            they can't be imported"
+                .to_string(),
+        );
+    }
+
+    if !policy.file_scanner_emit_files() && !policy.file_scanner_classify_files() {
+        res.push(
+            "file_scanner_emit_files and file_scanner_classify_files are both False; \
+            files found by scanning are discarded"
+                .to_string(),
+        );
+    }
+
+    res
+}
+
+/// Obtain warnings about a `PythonInterpreterConfig`.
+pub fn interpreter_config_warnings(config: &PyembedPythonInterpreterConfig) -> Vec<String> {
+    let mut res = vec![];
+
+    if config.allocator_backend == MemoryAllocatorBackend::Default {
+        for (name, value) in [
+            ("allocator_mem", config.allocator_mem),
+            ("allocator_obj", config.allocator_obj),
+            ("allocator_pymalloc_arena", config.allocator_pymalloc_arena),
+        ]
+        .iter()
+        {
+            if *value {
+                res.push(format!(
+                    "{} has no effect because allocator_backend is 'default'",
+                    name
+                ));
+            }
+        }
+    } else if config.allocator_pymalloc_arena {
+        match (config.allocator_mem, config.allocator_obj) {
+            (true, true) => res.push(
+                "allocator_pymalloc_arena has no effect because allocator_mem and \
+                allocator_obj replace pymalloc"
+                    .to_string(),
+            ),
+            (true, false) => res.push(
+                "allocator_pymalloc_arena only affects the obj domain because \
+                allocator_mem replaces pymalloc for the mem domain"
+                    .to_string(),
+            ),
+            (false, true) => res.push(
+                "allocator_pymalloc_arena only affects the mem domain because \
+                allocator_obj replaces pymalloc for the obj domain"
+                    .to_string(),
+            ),
+            (false, false) => {}
+        }
+    }
+
+    if !config.oxidized_importer && !config.filesystem_importer {
+        res.push(
+            "oxidized_importer and filesystem_importer are both disabled; \
+            only built-in and frozen modules can be imported"
+                .to_string(),
+        );
+    }
+
+    if !config.filesystem_importer
+        && matches!(&config.config.module_search_paths, Some(paths) if !paths.is_empty())
+    {
+        res.push(
+            "module_search_paths has no effect on imports because filesystem_importer \
+            is disabled"
+                .to_string(),
+        );
+    }
+
+    res
+}
+
+/// Obtain the filesystem-relative prefixes importable resources are installed to.
+///
+/// `candidates` are the prefixes the packaging policy can install resources
+/// to. Paths of extension modules and resource files are attributed to the
+/// candidate they are under.
+fn installed_relative_prefixes<'a>(
+    candidates: &BTreeSet<String>,
+    resources: impl Iterator<Item = &'a PrePackagedResource>,
+) -> BTreeSet<String> {
+    let mut res = BTreeSet::new();
+
+    for resource in resources {
+        let mut paths = vec![];
+
+        if let Some((prefix, _)) = &resource.relative_path_module_source {
+            res.insert(prefix.clone());
+        }
+        for (prefix, _, _) in [
+            &resource.relative_path_bytecode,
+            &resource.relative_path_bytecode_opt1,
+            &resource.relative_path_bytecode_opt2,
+        ]
+        .iter()
+        .copied()
+        .flatten()
+        {
+            res.insert(prefix.clone());
+        }
+        if let Some((path, _)) = &resource.relative_path_extension_module_shared_library {
+            paths.push(path);
+        }
+        for resources in [
+            &resource.relative_path_package_resources,
+            &resource.relative_path_distribution_resources,
+        ]
+        .iter()
+        .copied()
+        .flatten()
+        {
+            paths.extend(resources.values().map(|(path, _)| path));
+        }
+
+        for path in paths {
+            if let Some(prefix) = candidates.iter().find(|prefix| path.starts_with(prefix)) {
+                res.insert(prefix.clone());
+            }
+        }
+    }
+
+    res
+}
+
+/// Obtain warnings about resources installed next to the binary not being importable.
+///
+/// Only prefixes `resources` are actually installed to are considered, plus
+/// the location packages depending on the filesystem are relocated to,
+/// since relocation happens when building.
+pub fn resource_location_warnings<'a>(
+    policy: &PythonPackagingPolicy,
+    config: &PyembedPythonInterpreterConfig,
+    resources: impl Iterator<Item = &'a PrePackagedResource>,
+) -> Vec<String> {
+    let mut locations = vec![Some(policy.resources_location().clone())];
+    locations.push(policy.resources_location_fallback().clone());
+    for rule in policy.resource_placement_rules() {
+        locations.push(rule.location.clone());
+        locations.push(rule.location_fallback.clone().flatten());
+    }
+
+    let relative_prefix = |location: Option<ConcreteResourceLocation>| match location {
+        Some(ConcreteResourceLocation::RelativePath(prefix)) => Some(prefix),
+        _ => None,
+    };
+
+    let candidates = locations
+        .into_iter()
+        .filter_map(relative_prefix)
+        .collect::<BTreeSet<_>>();
+
+    let mut prefixes = installed_relative_prefixes(&candidates, resources);
+    if policy.relocate_filesystem_dependent_packages() {
+        prefixes.extend(relative_prefix(
+            policy.filesystem_dependent_packages_location().clone(),
+        ));
+    }
+
+    // oxidized_importer indexes filesystem-relative resources and can import
+    // them. But anything consulting `sys.path` (e.g. `pkgutil` or code looking
+    // for files next to modules) needs the filesystem importer.
+    if config.oxidized_importer {
+        return if config.filesystem_importer {
+            vec![]
+        } else {
+            prefixes
+                .into_iter()
+                .map(|prefix| {
+                    format!(
+                        "resources may be installed to filesystem-relative:{} but \
+                        filesystem_importer is disabled; they are only importable \
+                        through oxidized_importer and won't be found via sys.path",
+                        prefix
+                    )
+                })
+                .collect()
+        };
+    }
+
+    if !config.filesystem_importer {
+        return prefixes
+            .into_iter()
+            .map(|prefix| {
+                format!(
+                    "resources may be installed to filesystem-relative:{} but \
+                    oxidized_importer and filesystem_importer are both disabled; \
+                    they can't be imported",
+                    prefix
+                )
+            })
+            .collect();
+    }
+
+    let search_paths = config
+        .config
+        .module_search_paths
+        .clone()
+        .unwrap_or_default();
+
+    prefixes
+        .into_iter()
+        .filter(|prefix| {
+            let expected = if prefix.is_empty() || prefix == "." {
+                PathBuf::from("$ORIGIN")
+            } else {
+                PathBuf::from(format!("$ORIGIN/{}", prefix))
+            };
+
+            !search_paths.iter().any(|path| path == &expected)
+        })
+        .map(|prefix| {
+            format!(
+                "resources may be installed to filesystem-relative:{} but \
+                oxidized_importer is disabled and $ORIGIN/{} isn't in \
+                module_search_paths; the filesystem importer won't find them",
+                prefix, prefix
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpreter_config_warnings() {
+        let mut config = PyembedPythonInterpreterConfig::default();
+        assert!(interpreter_config_warnings(&config).is_empty());
+
+        config.allocator_pymalloc_arena = true;
+        assert_eq!(
+            interpreter_config_warnings(&config),
+            vec!["allocator_pymalloc_arena has no effect because allocator_backend is 'default'"]
+        );
+
+        config.allocator_backend = MemoryAllocatorBackend::Jemalloc;
+        assert!(interpreter_config_warnings(&config).is_empty());
+
+        config.allocator_mem = true;
+        assert_eq!(
+            interpreter_config_warnings(&config),
+            vec!["allocator_pymalloc_arena only affects the obj domain because allocator_mem replaces pymalloc for the mem domain"]
+        );
+
+        config.allocator_obj = true;
+        assert_eq!(
+            interpreter_config_warnings(&config),
+            vec!["allocator_pymalloc_arena has no effect because allocator_mem and allocator_obj replace pymalloc"]
+        );
+
+        let mut config = PyembedPythonInterpreterConfig::default();
+        config.config.module_search_paths = Some(vec!["$ORIGIN/lib".into()]);
+        assert_eq!(interpreter_config_warnings(&config).len(), 1);
+        config.oxidized_importer = false;
+        assert_eq!(interpreter_config_warnings(&config).len(), 2);
+        config.filesystem_importer = true;
+        assert!(interpreter_config_warnings(&config).is_empty());
+    }
+
+    #[test]
+    fn test_packaging_policy_warnings() {
+        let mut policy = PythonPackagingPolicy::default();
+        assert!(packaging_policy_warnings(&policy).is_empty());
+
+        policy.set_bytecode_optimize_level_zero(false);
+        policy.set_include_distribution_sources(false);
+        policy.set_include_non_distribution_sources(false);
+        assert_eq!(packaging_policy_warnings(&policy).len(), 1);
+    }
+
+    #[test]
+    fn test_resource_location_warnings() {
+        let mut policy = PythonPackagingPolicy::default();
+        policy.set_resources_location(ConcreteResourceLocation::InMemory);
+        policy.set_resources_location_fallback(Some(ConcreteResourceLocation::RelativePath(
+            "lib".to_string(),
+        )));
+
+        let mut config = PyembedPythonInterpreterConfig {
+            oxidized_importer: false,
+            filesystem_importer: false,
+            ..PyembedPythonInterpreterConfig::default()
+        };
+
+        // Nothing is installed to the fallback location.
+        let in_memory = PrePackagedResource {
+            name: "foo".to_string(),
+            is_module: true,
+            in_memory_source: Some(b"".to_vec().into()),
+            ..PrePackagedResource::default()
+        };
+        assert!(
+            resource_location_warnings(&policy, &config, [&in_memory].iter().copied()).is_empty()
+        );
+
+        let module = PrePackagedResource {
+            name: "bar".to_string(),
+            is_module: true,
+            relative_path_module_source: Some(("lib".to_string(), b"".to_vec().into())),
+            ..PrePackagedResource::default()
+        };
+        let extension = PrePackagedResource {
+            name: "baz".to_string(),
+            is_extension_module: true,
+            relative_path_extension_module_shared_library: Some((
+                PathBuf::from("lib/baz.so"),
+                b"".to_vec().into(),
+            )),
+            ..PrePackagedResource::default()
+        };
+
+        for resource in [&module, &extension].iter() {
+            let warnings = |config: &PyembedPythonInterpreterConfig| {
+                resource_location_warnings(&policy, config, [&in_memory, *resource].iter().copied())
+            };
+
+            config.oxidized_importer = true;
+            config.filesystem_importer = true;
+            config.config.module_search_paths = None;
+            assert!(warnings(&config).is_empty());
+
+            config.filesystem_importer = false;
+            assert_eq!(
+                warnings(&config),
+                vec!["resources may be installed to filesystem-relative:lib but filesystem_importer is disabled; they are only importable through oxidized_importer and won't be found via sys.path"]
+            );
+
+            config.oxidized_importer = false;
+            assert_eq!(
+                warnings(&config),
+                vec!["resources may be installed to filesystem-relative:lib but oxidized_importer and filesystem_importer are both disabled; they can't be imported"]
+            );
+
+            config.filesystem_importer = true;
+            assert_eq!(
+                warnings(&config),
+                vec!["resources may be installed to filesystem-relative:lib but oxidized_importer is disabled and $ORIGIN/lib isn't in module_search_paths; the filesystem importer won't find them"]
+            );
+
+            config.config.module_search_paths = Some(vec!["$ORIGIN/lib".into()]);
+            assert!(warnings(&config).is_empty());
+        }
+
+        // Packages may be relocated when building, so the relocation
+        // location is always considered when relocation is enabled.
+        policy.set_filesystem_dependent_packages_location(Some(
+            ConcreteResourceLocation::RelativePath("relocated".to_string()),
+        ));
+        config.oxidized_importer = true;
+        config.filesystem_importer = false;
+        assert!(resource_location_warnings(&policy, &config, std::iter::empty()).is_empty());
+
+        policy.set_relocate_filesystem_dependent_packages(true);
+        assert_eq!(
+            resource_location_warnings(&policy, &config, std::iter::empty()),
+            vec!["resources may be installed to filesystem-relative:relocated but filesystem_importer is disabled; they are only importable through oxidized_importer and won't be found via sys.path"]
+        );
+    }
+
+    #[test]
+    fn test_report() -> Result<()> {
+        let mut report = CheckReport::new("pyoxidizer.bzl");
+        report.add_target_value("dist", &Value::from("value"));
+        report.add_target_error("exe", &anyhow::anyhow!("failed"));
+        report.add_target_value("count", &Value::from(1));
+        report.add_message(Severity::Warning, Some("dist"), "suspicious".to_string());
+
+        assert_eq!(report.count(Severity::Error), 1);
+        assert_eq!(
+            report.to_text(),
+            "checked pyoxidizer.bzl\n\
+            target dist (string)\n  warning: suspicious\n\
+            target exe\n  error: failed\n\
+            target count (int): ok\n\
+            1 errors, 1 warnings\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()?)?;
+        assert_eq!(json["messages"][0]["severity"], "error");
+        assert_eq!(json["messages"][0]["target"], "exe");
+        assert_eq!(json["targets"][1]["value_type"], serde_json::Value::Null);
+
+        Ok(())
+    }
+}
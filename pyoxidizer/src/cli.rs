@@ -10,7 +10,8 @@ use {
     anyhow::{anyhow, Context, Result},
     clap::{App, AppSettings, Arg, ArgMatches, SubCommand},
     std::{
-        collections::HashMap,
+        collections::{BTreeMap, HashMap},
+        ffi::OsString,
         path::{Path, PathBuf},
     },
 };
@@ -41,6 +42,31 @@ existing PyOxidizer enabled project.
 
 This command will invoke Rust's build system tool (Cargo) to build
 the project.
+
+A `pyoxidizer.lock` file recording the inputs to the build (Python
+distributions, Python packages, and toolchain versions) is written next
+to the config file. With `--locked`, the build fails if inputs differ
+from this file and the file is not updated.
+
+With `--incremental`, targets whose recorded inputs and outputs are
+unchanged since they were last built are not built again. Pass `--force`
+to build them anyway.
+
+With `--jobs`, targets not depending on each other are built concurrently.
+";
+
+const CHECK_ABOUT: &str = "\
+Validate a PyOxidizer configuration file without building anything.
+
+The configuration file is evaluated and every registered target is
+resolved. Building is not performed. Python distributions referenced by
+the configuration may be downloaded.
+
+Resolved Python executables, packaging policies and interpreter
+configurations are inspected for settings that are invalid or likely
+don't behave as intended. Problems are reported as errors or warnings.
+
+The command exits with an error if any errors are found.
 ";
 
 const INIT_RUST_PROJECT_ABOUT: &str = "\
@@ -98,13 +124,16 @@ Defines a single string key to set in the VARS global dict.
 This argument can be used to inject variable content into the Starlark
 execution context to influence evaluation.
 
-<name> defines the key in the dict to set and <value> is its string
-value.
+The argument has the form <name>=<value>. <name> defines the key in the
+dict to set and <value> is its string value. For compatibility with older
+versions, the name and value can also be passed as separate arguments
+(`--var <name> <value>`).
 
-For example, `--var my_var my_value` is functionally similar to the
+For example, `--var my_var=my_value` is functionally similar to the
 Starlark expression `VARS[\"my_var\"] = \"my_value\"`.
 
-If a Starlark variable is defined multiple times, an error occurs.
+This argument can be specified multiple times. If a Starlark variable is
+defined multiple times, an error occurs.
 ";
 
 const ENV_VAR_HELP: &str = "\
@@ -122,11 +151,22 @@ be `None` instead of a `string`.
 If a Starlark variable is defined multiple times, an error occurs.
 ";
 
+const VAR_FILE_HELP: &str = "\
+Defines keys to set in the VARS global dict from a JSON file.
+
+The file must contain a JSON object whose values are strings or null. Each
+key-value pair is set in the VARS dict. null values become `None`.
+
+Variables defined by --var and --var-env take precedence over variables
+defined in this file.
+";
+
 fn add_env_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
     app.arg(
         Arg::with_name("vars")
             .long("var")
-            .value_names(&["name", "value"])
+            .value_name("name=value")
+            .number_of_values(1)
             .multiple(true)
             .help("Define a variable in Starlark environment")
             .long_help(VAR_HELP),
@@ -139,13 +179,92 @@ fn add_env_args<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
             .help("Define an environment variable in Starlark environment")
             .long_help(ENV_VAR_HELP),
     )
+    .arg(
+        Arg::with_name("var_file")
+            .long("var-file")
+            .value_name("path")
+            .takes_value(true)
+            .help("Define variables in Starlark environment from a JSON file")
+            .long_help(VAR_FILE_HELP),
+    )
+}
+
+/// Rewrite `--var NAME VALUE` arguments to `--var NAME=VALUE`.
+///
+/// `--var` used to take the name and value as separate arguments. Clap can't
+/// express a varying number of values per occurrence, so the old form is
+/// normalized before parsing. Arguments after `--` are left alone.
+fn normalize_var_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut res = vec![];
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            res.push(arg);
+            res.extend(args);
+            break;
+        }
+
+        let is_var = arg == "--var";
+        res.push(arg);
+
+        if !is_var {
+            continue;
+        }
+
+        let name = match args.next() {
+            Some(name) => name,
+            None => break,
+        };
+
+        match name.to_str() {
+            Some(name_str) if !name_str.contains('=') => match args.next() {
+                Some(value) => {
+                    let mut definition = OsString::from(format!("{}=", name_str));
+                    definition.push(value);
+                    res.push(definition);
+                }
+                None => res.push(name),
+            },
+            _ => res.push(name),
+        }
+    }
+
+    res
+}
+
+/// Parse a `name=value` variable definition.
+fn parse_var(s: &str) -> Result<(&str, &str)> {
+    let mut parts = s.splitn(2, '=');
+
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(value)) if !name.is_empty() => Ok((name, value)),
+        _ => Err(anyhow!(
+            "invalid variable definition {}; expected <name>=<value>",
+            s
+        )),
+    }
+}
+
+/// Read variable definitions from a JSON file.
+fn read_var_file(path: &Path) -> Result<BTreeMap<String, Option<String>>> {
+    let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+
+    serde_json::from_slice(&data).with_context(|| {
+        format!(
+            "parsing {}; expected a JSON object with string or null values",
+            path.display()
+        )
+    })
 }
 
 fn starlark_vars(args: &ArgMatches) -> Result<HashMap<String, Option<String>>> {
     let mut res = HashMap::new();
 
-    if let Some(mut vars) = args.values_of("vars") {
-        while let (Some(name), Some(value)) = (vars.next(), vars.next()) {
+    if let Some(vars) = args.values_of("vars") {
+        for var in vars {
+            let (name, value) = parse_var(var)?;
+
             if res.contains_key(name) {
                 return Err(anyhow!("Starlark variable {} already defined", name));
             }
@@ -164,6 +283,12 @@ fn starlark_vars(args: &ArgMatches) -> Result<HashMap<String, Option<String>>> {
         }
     }
 
+    if let Some(path) = args.value_of("var_file") {
+        for (name, value) in read_var_file(Path::new(path))? {
+            res.entry(name).or_insert(value);
+        }
+    }
+
     Ok(res)
 }
 
@@ -222,6 +347,30 @@ pub fn run_cli() -> Result<()> {
                         .long("release")
                         .help("Build a release binary"),
                 )
+                .arg(
+                    Arg::with_name("locked")
+                        .long("locked")
+                        .help("Fail if build inputs differ from the project's lockfile"),
+                )
+                .arg(
+                    Arg::with_name("incremental")
+                        .long("incremental")
+                        .help("Skip building targets whose inputs and outputs are unchanged"),
+                )
+                .arg(
+                    Arg::with_name("force")
+                        .long("force")
+                        .help("Build targets even if they are up to date"),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .short("j")
+                        .long("jobs")
+                        .takes_value(true)
+                        .default_value("1")
+                        .value_name("N")
+                        .help("Number of targets to build concurrently"),
+                )
                 .arg(
                     Arg::with_name("path")
                         .long("path")
@@ -230,6 +379,15 @@ pub fn run_cli() -> Result<()> {
                         .value_name("PATH")
                         .help("Directory containing project to build"),
                 )
+                .arg(
+                    Arg::with_name("explain_resource")
+                        .long("explain-resource")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("NAME")
+                        .help("Print the decisions made when packaging the named resource"),
+                )
                 .arg(
                     Arg::with_name("targets")
                         .value_name("TARGET")
@@ -238,8 +396,39 @@ pub fn run_cli() -> Result<()> {
                 ),
         ))
         .subcommand(
-            SubCommand::with_name("cache-clear").about("Clear PyOxidizer's user-specific cache"),
+            SubCommand::with_name("cache-clear")
+                .about("Clear PyOxidizer's user-specific cache")
+                .arg(
+                    Arg::with_name("bytecode")
+                        .long("bytecode")
+                        .help("Only clear cached Python bytecode"),
+                ),
         )
+        .subcommand(add_env_args(
+            SubCommand::with_name("check")
+                .about("Validate a configuration file without building anything")
+                .long_about(CHECK_ABOUT)
+                .arg(
+                    Arg::with_name("target_triple")
+                        .long("target-triple")
+                        .takes_value(true)
+                        .help("Rust target triple to evaluate for"),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("Output format"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Path to project to check"),
+                ),
+        ))
         .subcommand(
             SubCommand::with_name("find-resources")
                 .about("Find resources in a file or directory")
@@ -319,6 +508,14 @@ pub fn run_cli() -> Result<()> {
             SubCommand::with_name("list-targets")
                 .setting(AppSettings::ArgRequiredElseHelp)
                 .about("List targets available to resolve in a configuration file")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "dot", "json"])
+                        .default_value("text")
+                        .help("Output format. dot and json include dependencies between targets"),
+                )
                 .arg(
                     Arg::with_name("path")
                         .default_value(".")
@@ -414,7 +611,7 @@ pub fn run_cli() -> Result<()> {
                 )
                 .arg(Arg::with_name("extra").multiple(true)),
         ))
-        .get_matches();
+        .get_matches_from(normalize_var_args(std::env::args_os()));
 
     let verbose = matches.is_present("verbose");
 
@@ -450,11 +647,23 @@ pub fn run_cli() -> Result<()> {
         ("build", Some(args)) => {
             let starlark_vars = starlark_vars(args)?;
             let release = args.is_present("release");
+            let locked = args.is_present("locked");
+            let incremental = args.is_present("incremental");
+            let force = args.is_present("force");
+            let jobs = args
+                .value_of("jobs")
+                .unwrap()
+                .parse::<usize>()
+                .context("parsing --jobs")?;
             let target_triple = args.value_of("target_triple");
             let path = args.value_of("path").unwrap();
             let resolve_targets = args
                 .values_of("targets")
                 .map(|values| values.map(|x| x.to_string()).collect());
+            let explain_resources = args
+                .values_of("explain_resource")
+                .map(|values| values.map(|x| x.to_string()).collect())
+                .unwrap_or_default();
 
             projectmgmt::build(
                 &env,
@@ -463,12 +672,34 @@ pub fn run_cli() -> Result<()> {
                 target_triple,
                 resolve_targets,
                 starlark_vars,
+                explain_resources,
                 release,
+                locked,
+                incremental,
+                force,
+                jobs,
                 verbose,
             )
         }
 
-        ("cache-clear", Some(_)) => projectmgmt::cache_clear(&env),
+        ("cache-clear", Some(args)) => projectmgmt::cache_clear(&env, args.is_present("bytecode")),
+
+        ("check", Some(args)) => {
+            let starlark_vars = starlark_vars(args)?;
+            let target_triple = args.value_of("target_triple");
+            let format = args.value_of("format").unwrap();
+            let path = args.value_of("path").unwrap();
+
+            projectmgmt::check(
+                &env,
+                &logger_context.logger,
+                Path::new(path),
+                target_triple,
+                starlark_vars,
+                format,
+                verbose,
+            )
+        }
 
         ("find-resources", Some(args)) => {
             let path = args.value_of("path").map(|value| Path::new(value));
@@ -510,8 +741,9 @@ pub fn run_cli() -> Result<()> {
 
         ("list-targets", Some(args)) => {
             let path = args.value_of("path").unwrap();
+            let format = args.value_of("format").unwrap();
 
-            projectmgmt::list_targets(&env, &logger_context.logger, Path::new(path))
+            projectmgmt::list_targets(&env, &logger_context.logger, Path::new(path), format)
         }
 
         ("init-rust-project", Some(args)) => {
@@ -587,3 +819,44 @@ pub fn run_cli() -> Result<()> {
         _ => Err(anyhow!("invalid sub-command")),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(args: &[&str]) -> Vec<String> {
+        normalize_var_args(args.iter().map(OsString::from))
+            .into_iter()
+            .map(|x| x.into_string().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_normalize_var_args() {
+        assert_eq!(
+            normalize(&[
+                "pyoxidizer",
+                "build",
+                "--var",
+                "a=1",
+                "--var",
+                "b",
+                "2",
+                "exe"
+            ]),
+            vec!["pyoxidizer", "build", "--var", "a=1", "--var", "b=2", "exe"]
+        );
+        assert_eq!(
+            normalize(&["pyoxidizer", "build", "--var", "a", "x=y"]),
+            vec!["pyoxidizer", "build", "--var", "a=x=y"]
+        );
+        assert_eq!(
+            normalize(&["pyoxidizer", "build", "--var", "a"]),
+            vec!["pyoxidizer", "build", "--var", "a"]
+        );
+        assert_eq!(
+            normalize(&["pyoxidizer", "run", "--", "--var", "a", "1"]),
+            vec!["pyoxidizer", "run", "--", "--var", "a", "1"]
+        );
+    }
+}
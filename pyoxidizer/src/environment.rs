@@ -8,6 +8,7 @@ use {
     crate::project_layout::PyembedLocation,
     anyhow::{anyhow, Context, Result},
     once_cell::sync::Lazy,
+    python_packaging::{bytecode::BytecodeCompilerPool, bytecode_cache::BytecodeCache},
     semver::Version,
     slog::{info, warn},
     std::{
@@ -29,6 +30,9 @@ const PYEMBED_CRATE_VERSION: &str = "0.14.0-pre";
 /// URL of Git repository we were built from.
 const GIT_REPO_URL: &str = env!("GIT_REPO_URL");
 
+/// Default maximum size in bytes of the Python bytecode cache.
+const DEFAULT_BYTECODE_CACHE_MAX_SIZE: u64 = 512 * 1024 * 1024;
+
 /// Version string of PyOxidizer.
 pub const PYOXIDIZER_VERSION: &str = env!("PYOXIDIZER_VERSION");
 
@@ -214,6 +218,14 @@ pub struct Environment {
     /// Whether we should use a Rust installation we manage ourselves.
     managed_rust: bool,
 
+    /// Maximum size in bytes of the Python bytecode cache.
+    ///
+    /// 0 disables the cache.
+    bytecode_cache_max_size: u64,
+
+    /// Number of Python processes to compile bytecode with.
+    bytecode_compile_workers: usize,
+
     /// Rust environment to use.
     ///
     /// Cached because lookups may be expensive.
@@ -235,10 +247,40 @@ impl Environment {
 
         let managed_rust = std::env::var("PYOXIDIZER_SYSTEM_RUST").is_err();
 
+        let bytecode_cache_max_size =
+            if let Ok(value) = std::env::var("PYOXIDIZER_BYTECODE_CACHE_MAX_SIZE") {
+                value.parse::<u64>().with_context(|| {
+                    format!(
+                        "parsing PYOXIDIZER_BYTECODE_CACHE_MAX_SIZE value {} as integer",
+                        value
+                    )
+                })?
+            } else {
+                DEFAULT_BYTECODE_CACHE_MAX_SIZE
+            };
+
+        let bytecode_compile_workers =
+            if let Ok(value) = std::env::var("PYOXIDIZER_BYTECODE_COMPILE_WORKERS") {
+                value
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|workers| *workers > 0)
+                    .ok_or_else(|| {
+                        anyhow!(
+                        "PYOXIDIZER_BYTECODE_COMPILE_WORKERS value {} is not a positive integer",
+                        value
+                    )
+                    })?
+            } else {
+                BytecodeCompilerPool::default_worker_count()
+            };
+
         Ok(Self {
             pyoxidizer_source,
             cache_dir,
             managed_rust,
+            bytecode_cache_max_size,
+            bytecode_compile_workers,
             rust_environment: Arc::new(RwLock::new(None)),
         })
     }
@@ -260,6 +302,30 @@ impl Environment {
         self.cache_dir.join("rust")
     }
 
+    /// Directory to hold compiled Python bytecode.
+    pub fn bytecode_cache_dir(&self) -> PathBuf {
+        self.cache_dir.join("bytecode")
+    }
+
+    /// Obtain the cache of compiled Python bytecode.
+    ///
+    /// Returns `None` if the bytecode cache is disabled.
+    pub fn bytecode_cache(&self) -> Option<BytecodeCache> {
+        if self.bytecode_cache_max_size == 0 {
+            None
+        } else {
+            Some(BytecodeCache::new(
+                self.bytecode_cache_dir(),
+                Some(self.bytecode_cache_max_size),
+            ))
+        }
+    }
+
+    /// Number of Python processes to compile bytecode with.
+    pub fn bytecode_compile_workers(&self) -> usize {
+        self.bytecode_compile_workers
+    }
+
     /// Do not use a managed Rust.
     ///
     /// When called, [self.ensure_rust_toolchain()] will attempt to locate a
@@ -342,6 +408,36 @@ impl Environment {
             .clone())
     }
 
+    /// Obtain the version of the Rust toolchain used for building.
+    ///
+    /// Unlike [self.ensure_rust_toolchain()], this does not install a managed
+    /// toolchain.
+    pub fn rust_toolchain_version(&self) -> Result<String> {
+        if let Some(rust_env) = self
+            .rust_environment
+            .read()
+            .map_err(|e| anyhow!("failed to acquire rust environment lock: {}", e))?
+            .as_ref()
+        {
+            return Ok(rust_env.rust_version.semver.to_string());
+        }
+
+        if self.managed_rust {
+            Ok(RUST_TOOLCHAIN_VERSION.to_string())
+        } else {
+            Ok(self
+                .system_rust_environment()?
+                .rust_version
+                .semver
+                .to_string())
+        }
+    }
+
+    /// The version of the `pyembed` crate used by generated projects.
+    pub fn pyembed_crate_version(&self) -> &'static str {
+        PYEMBED_CRATE_VERSION
+    }
+
     /// Obtain the path to a `rustc` executable.
     ///
     /// This respects the `RUSTC` environment variable.
@@ -11,7 +11,10 @@ distribution and embedding it in a larger binary, oftentimes an executable.
 This library exposes that functionality to other tools.
 */
 
+pub mod build_metadata;
+pub mod check;
 pub mod environment;
+pub mod lockfile;
 pub mod logging;
 pub mod project_building;
 pub mod project_layout;
@@ -0,0 +1,631 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Lockfiles recording the inputs of a PyOxidizer project build.
+
+A lockfile is written next to the project's configuration file. It records
+the Python distributions and Python packages that went into a build along
+with the toolchain versions used, so later builds can detect drift.
+*/
+
+use {
+    crate::py_packaging::distribution::PythonDistributionLocation,
+    anyhow::{anyhow, Context, Result},
+    python_packaging::resource::{PythonPackageDistributionResourceFlavor, PythonResource},
+    serde::{Deserialize, Serialize},
+    sha2::Digest,
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        path::Path,
+    },
+};
+
+/// Filename of lockfiles.
+pub const LOCKFILE_NAME: &str = "pyoxidizer.lock";
+
+/// Version of the lockfile format.
+const LOCKFILE_VERSION: u32 = 1;
+
+/// SHA-256 digests of package artifacts, keyed by package name and version.
+///
+/// Artifacts are the wheels and source distributions packages were obtained
+/// from.
+pub type ArtifactDigests = BTreeMap<(String, String), String>;
+
+/// A Python distribution used by a build.
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct LockedDistribution {
+    /// URL the distribution was obtained from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// Local filesystem path the distribution was obtained from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_path: Option<String>,
+
+    /// SHA-256 of the distribution archive.
+    pub sha256: String,
+}
+
+impl LockedDistribution {
+    /// The URL or local path the distribution was obtained from.
+    pub fn location(&self) -> &str {
+        self.url
+            .as_deref()
+            .or(self.local_path.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+impl From<&PythonDistributionLocation> for LockedDistribution {
+    fn from(location: &PythonDistributionLocation) -> Self {
+        match location {
+            PythonDistributionLocation::Url { url, sha256 } => Self {
+                url: Some(url.clone()),
+                local_path: None,
+                sha256: sha256.clone(),
+            },
+            PythonDistributionLocation::Local { local_path, sha256 } => Self {
+                url: None,
+                local_path: Some(local_path.clone()),
+                sha256: sha256.clone(),
+            },
+        }
+    }
+}
+
+/// A Python package distribution collected by a build.
+#[derive(Clone, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct LockedPackage {
+    /// The Starlark method that obtained the package (e.g. `pip_install`).
+    pub source: String,
+
+    /// Name of the package.
+    pub name: String,
+
+    /// Version of the package.
+    pub version: String,
+
+    /// SHA-256 of the package's content.
+    ///
+    /// This is derived from the distribution's `RECORD` file, which holds
+    /// the digest of every file in the distribution. Entries added by the
+    /// installer (bytecode, `INSTALLER`, etc) are ignored. If there is no
+    /// `RECORD` file, the package metadata is digested instead.
+    pub sha256: String,
+
+    /// SHA-256 of the wheel or source distribution the package was obtained from.
+    ///
+    /// This is the digest `pip install --require-hashes` verifies. It is
+    /// only known when PyOxidizer saw the artifact, such as for
+    /// `pip_download()`, or when the installer recorded it in
+    /// `direct_url.json`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact_sha256: Option<String>,
+}
+
+/// Whether a `RECORD` entry was produced by an installer rather than the package.
+///
+/// Paths outside the install directory (e.g. `../../bin/foo`) are console
+/// scripts and other files the installer generates or rewrites for the
+/// interpreter being installed to.
+fn is_installer_record_entry(path: &str) -> bool {
+    path.starts_with("../")
+        || path.contains("__pycache__/")
+        || path.ends_with(".pyc")
+        || path.ends_with(".dist-info/RECORD")
+        || path.ends_with(".dist-info/INSTALLER")
+        || path.ends_with(".dist-info/REQUESTED")
+        || path.ends_with(".dist-info/direct_url.json")
+}
+
+/// Compute the content digest of a package given its `RECORD` file.
+fn record_digest(record: &[u8]) -> String {
+    let record = String::from_utf8_lossy(record);
+
+    let mut lines = record
+        .lines()
+        .filter(|line| {
+            let path = line.split(',').next().unwrap_or_default();
+            !path.is_empty() && !is_installer_record_entry(path)
+        })
+        .collect::<Vec<_>>();
+    lines.sort_unstable();
+
+    let mut hasher = sha2::Sha256::new();
+    for line in lines {
+        hasher.update(line.as_bytes());
+        hasher.update(b"\n");
+    }
+
+    hex::encode(hasher.finalize())
+}
+
+/// Obtain the archive digest recorded in a PEP 610 `direct_url.json` file.
+fn direct_url_archive_digest(data: &[u8]) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_slice(data).ok()?;
+    let hash = value.get("archive_info")?.get("hash")?.as_str()?;
+
+    hash.strip_prefix("sha256=").map(|x| x.to_string())
+}
+
+impl LockedPackage {
+    /// Derive records for every package distribution in a set of resources.
+    ///
+    /// `artifacts` holds digests of the artifacts packages were obtained from.
+    pub fn from_resources<'a>(
+        source: &str,
+        resources: impl Iterator<Item = &'a PythonResource<'a>>,
+        artifacts: &ArtifactDigests,
+    ) -> Result<Vec<Self>> {
+        // (name, version) -> (RECORD digest, metadata files, direct_url.json digest).
+        let mut packages = BTreeMap::new();
+
+        for resource in resources {
+            if let PythonResource::PackageDistributionResource(r) = resource {
+                let entry = packages
+                    .entry((r.package.clone(), r.version.clone()))
+                    .or_insert_with(|| (None, BTreeMap::new(), None));
+
+                if r.location == PythonPackageDistributionResourceFlavor::DistInfo
+                    && r.name == "RECORD"
+                {
+                    entry.0 = Some(record_digest(&r.data.resolve()?));
+                } else if r.location == PythonPackageDistributionResourceFlavor::DistInfo
+                    && r.name == "direct_url.json"
+                {
+                    entry.2 = direct_url_archive_digest(&r.data.resolve()?);
+                } else if !is_installer_record_entry(&format!(".dist-info/{}", r.name)) {
+                    entry.1.insert(r.name.clone(), r.data.resolve()?);
+                }
+            }
+        }
+
+        Ok(packages
+            .into_iter()
+            .map(|((name, version), (record, metadata, direct_url_digest))| {
+                let sha256 = record.unwrap_or_else(|| {
+                    let mut hasher = sha2::Sha256::new();
+                    for (name, data) in metadata {
+                        hasher.update(name.as_bytes());
+                        hasher.update(b"\0");
+                        hasher.update(&data);
+                    }
+
+                    hex::encode(hasher.finalize())
+                });
+
+                let artifact_sha256 = artifacts
+                    .get(&(name.clone(), version.clone()))
+                    .cloned()
+                    .or(direct_url_digest);
+
+                Self {
+                    source: source.to_string(),
+                    name,
+                    version,
+                    sha256,
+                    artifact_sha256,
+                }
+            })
+            .collect())
+    }
+}
+
+/// Records the inputs to a project build.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProjectLock {
+    /// Version of the lockfile format.
+    pub version: u32,
+
+    /// Version of the Rust toolchain.
+    pub rust_toolchain: String,
+
+    /// Version of the `pyembed` crate.
+    pub pyembed_version: String,
+
+    /// Rust target triple being built for.
+    pub target_triple: String,
+
+    /// Python distributions used.
+    #[serde(default, rename = "distribution")]
+    pub distributions: BTreeSet<LockedDistribution>,
+
+    /// Python packages collected.
+    #[serde(default, rename = "package")]
+    pub packages: BTreeSet<LockedPackage>,
+}
+
+impl ProjectLock {
+    /// Construct an instance without any distributions or packages.
+    pub fn new(rust_toolchain: &str, pyembed_version: &str, target_triple: &str) -> Self {
+        Self {
+            version: LOCKFILE_VERSION,
+            rust_toolchain: rust_toolchain.to_string(),
+            pyembed_version: pyembed_version.to_string(),
+            target_triple: target_triple.to_string(),
+            distributions: BTreeSet::new(),
+            packages: BTreeSet::new(),
+        }
+    }
+
+    /// Parse an instance from TOML.
+    pub fn from_toml(data: &str) -> Result<Self> {
+        let lock: Self = toml::from_str(data).context("parsing lockfile")?;
+
+        if lock.version != LOCKFILE_VERSION {
+            return Err(anyhow!(
+                "unsupported lockfile version {}; expected {}",
+                lock.version,
+                LOCKFILE_VERSION
+            ));
+        }
+
+        Ok(lock)
+    }
+
+    /// Read an instance from a file.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let data = std::fs::read_to_string(path)
+            .with_context(|| format!("reading lockfile {}", path.display()))?;
+
+        Self::from_toml(&data).with_context(|| format!("reading lockfile {}", path.display()))
+    }
+
+    /// Serialize the instance to TOML.
+    pub fn to_toml(&self) -> Result<String> {
+        let value = toml::Value::try_from(self).context("converting lockfile to TOML")?;
+
+        Ok(format!(
+            "# This file is automatically generated by PyOxidizer.\n\
+            # It is not intended for manual editing.\n\
+            {}",
+            toml::to_string(&value).context("serializing lockfile")?
+        ))
+    }
+
+    /// Write the instance to a file.
+    pub fn write_path(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_toml()?)
+            .with_context(|| format!("writing lockfile {}", path.display()))
+    }
+
+    /// Merge the inputs of a build of a subset of targets into this instance.
+    ///
+    /// The Rust toolchain, `pyembed` version, and target triple are taken from
+    /// `other`. Distributions and packages of `other` replace entries having
+    /// the same location or the same source and name. Other entries are kept,
+    /// as targets that weren't built may use them.
+    pub fn merge(&mut self, other: &Self) {
+        self.rust_toolchain = other.rust_toolchain.clone();
+        self.pyembed_version = other.pyembed_version.clone();
+        self.target_triple = other.target_triple.clone();
+
+        self.distributions.retain(|dist| {
+            !other
+                .distributions
+                .iter()
+                .any(|x| x.location() == dist.location())
+        });
+        self.distributions
+            .extend(other.distributions.iter().cloned());
+
+        self.packages.retain(|package| {
+            !other
+                .packages
+                .iter()
+                .any(|x| x.source == package.source && x.name == package.name)
+        });
+        self.packages.extend(other.packages.iter().cloned());
+    }
+
+    /// Describe how the Rust toolchain, `pyembed` version, and target triple differ.
+    fn field_differences(&self, other: &Self) -> Vec<String> {
+        let mut res = vec![];
+
+        for (field, ours, theirs) in [
+            (
+                "Rust toolchain",
+                &self.rust_toolchain,
+                &other.rust_toolchain,
+            ),
+            (
+                "pyembed version",
+                &self.pyembed_version,
+                &other.pyembed_version,
+            ),
+            ("target triple", &self.target_triple, &other.target_triple),
+        ]
+        .iter()
+        {
+            if ours != theirs {
+                res.push(format!("{} changed from {} to {}", field, ours, theirs));
+            }
+        }
+
+        res
+    }
+
+    /// Describe distributions and packages of another instance not in this one.
+    fn new_inputs(&self, other: &Self) -> Vec<String> {
+        let mut res = vec![];
+
+        for dist in other.distributions.difference(&self.distributions) {
+            res.push(format!(
+                "new Python distribution: {} (sha256 {})",
+                dist.location(),
+                dist.sha256
+            ));
+        }
+
+        for package in other.packages.difference(&self.packages) {
+            res.push(format!(
+                "new {} package: {} {} (sha256 {})",
+                package.source, package.name, package.version, package.sha256
+            ));
+        }
+
+        res
+    }
+
+    /// Describe how another instance differs from this one.
+    ///
+    /// Returns an empty vector if the instances are equivalent.
+    pub fn differences(&self, other: &Self) -> Vec<String> {
+        let mut res = self.field_differences(other);
+
+        for dist in self.distributions.difference(&other.distributions) {
+            res.push(format!(
+                "Python distribution no longer used: {} (sha256 {})",
+                dist.location(),
+                dist.sha256
+            ));
+        }
+
+        for package in self.packages.difference(&other.packages) {
+            res.push(format!(
+                "{} package no longer present: {} {} (sha256 {})",
+                package.source, package.name, package.version, package.sha256
+            ));
+        }
+
+        res.extend(self.new_inputs(other));
+
+        res
+    }
+
+    /// Verify that another instance matches this one.
+    pub fn verify(&self, other: &Self) -> Result<()> {
+        Self::differences_to_result(self.differences(other))
+    }
+
+    /// Verify that the inputs of a build of a subset of targets are recorded by this instance.
+    ///
+    /// Unlike `verify()`, distributions and packages that `other` doesn't use
+    /// are ignored.
+    pub fn verify_subset(&self, other: &Self) -> Result<()> {
+        let mut differences = self.field_differences(other);
+        differences.extend(self.new_inputs(other));
+
+        Self::differences_to_result(differences)
+    }
+
+    fn differences_to_result(differences: Vec<String>) -> Result<()> {
+        if differences.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "build inputs differ from lockfile:\n{}",
+                differences
+                    .iter()
+                    .map(|x| format!("  {}", x))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, python_packaging::resource::PythonPackageDistributionResource,
+        tugger_file_manifest::FileData,
+    };
+
+    fn dist_resource(name: &str, data: &[u8]) -> PythonResource<'static> {
+        PythonPackageDistributionResource {
+            location: PythonPackageDistributionResourceFlavor::DistInfo,
+            package: "foo".to_string(),
+            version: "1.0".to_string(),
+            name: name.to_string(),
+            data: FileData::Memory(data.to_vec()),
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_package_digest_ignores_installer_entries() -> Result<()> {
+        let wheel_record = b"foo/__init__.py,sha256=abc,3\nfoo-1.0.dist-info/METADATA,sha256=def,10\nfoo-1.0.dist-info/RECORD,,\n";
+        let installed_record = b"../../bin/foo,sha256=123,200\nfoo-1.0.dist-info/INSTALLER,sha256=xyz,4\nfoo-1.0.dist-info/METADATA,sha256=def,10\nfoo-1.0.dist-info/RECORD,,\nfoo-1.0.dist-info/REQUESTED,sha256=e3b,0\nfoo/__init__.py,sha256=abc,3\nfoo/__pycache__/__init__.cpython-39.pyc,,\n";
+
+        let wheel = LockedPackage::from_resources(
+            "pip_download",
+            [
+                dist_resource("METADATA", b"Name: foo"),
+                dist_resource("RECORD", wheel_record),
+            ]
+            .iter(),
+            &ArtifactDigests::new(),
+        )?;
+        let installed = LockedPackage::from_resources(
+            "pip_download",
+            [
+                dist_resource("INSTALLER", b"pip\n"),
+                dist_resource("RECORD", installed_record),
+            ]
+            .iter(),
+            &ArtifactDigests::new(),
+        )?;
+
+        assert_eq!(wheel.len(), 1);
+        assert_eq!(wheel[0].name, "foo");
+        assert_eq!(wheel[0].version, "1.0");
+        assert_eq!(wheel, installed);
+
+        let modified = LockedPackage::from_resources(
+            "pip_download",
+            [dist_resource(
+                "RECORD",
+                b"foo/__init__.py,sha256=changed,3\nfoo-1.0.dist-info/METADATA,sha256=def,10\n",
+            )]
+            .iter(),
+            &ArtifactDigests::new(),
+        )?;
+        assert_ne!(wheel, modified);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_package_artifact_digest() -> Result<()> {
+        let mut artifacts = ArtifactDigests::new();
+        artifacts.insert(("foo".to_string(), "1.0".to_string()), "abcd".to_string());
+
+        let downloaded = LockedPackage::from_resources(
+            "pip_download",
+            [dist_resource("METADATA", b"Name: foo")].iter(),
+            &artifacts,
+        )?;
+        assert_eq!(downloaded[0].artifact_sha256, Some("abcd".to_string()));
+
+        let installed = LockedPackage::from_resources(
+            "pip_install",
+            [
+                dist_resource("METADATA", b"Name: foo"),
+                dist_resource(
+                    "direct_url.json",
+                    br#"{"url": "https://example.com/foo-1.0.tar.gz", "archive_info": {"hash": "sha256=ef01"}}"#,
+                ),
+            ]
+            .iter(),
+            &ArtifactDigests::new(),
+        )?;
+        assert_eq!(installed[0].artifact_sha256, Some("ef01".to_string()));
+
+        let unknown = LockedPackage::from_resources(
+            "pip_install",
+            [dist_resource("METADATA", b"Name: foo")].iter(),
+            &ArtifactDigests::new(),
+        )?;
+        assert_eq!(unknown[0].artifact_sha256, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip_and_verify() -> Result<()> {
+        let mut lock = ProjectLock::new("1.51.0", "0.14.0-pre", "x86_64-unknown-linux-gnu");
+        lock.distributions
+            .insert(LockedDistribution::from(&PythonDistributionLocation::Url {
+                url: "https://example.com/python.tar.zst".to_string(),
+                sha256: "deadbeef".to_string(),
+            }));
+        lock.packages.insert(LockedPackage {
+            source: "pip_install".to_string(),
+            name: "foo".to_string(),
+            version: "1.0".to_string(),
+            sha256: "cafe".to_string(),
+            artifact_sha256: Some("beef".to_string()),
+        });
+
+        let toml = lock.to_toml()?;
+        assert!(toml.contains("[[distribution]]"));
+        assert!(toml.contains("[[package]]"));
+
+        let parsed = ProjectLock::from_toml(&toml)?;
+        assert_eq!(parsed, lock);
+        parsed.verify(&lock)?;
+
+        let mut drifted = lock.clone();
+        drifted.rust_toolchain = "1.52.0".to_string();
+        drifted.packages.clear();
+        drifted.packages.insert(LockedPackage {
+            source: "pip_install".to_string(),
+            name: "foo".to_string(),
+            version: "1.1".to_string(),
+            sha256: "f00d".to_string(),
+            artifact_sha256: None,
+        });
+
+        let differences = lock.differences(&drifted);
+        assert_eq!(
+            differences,
+            vec![
+                "Rust toolchain changed from 1.51.0 to 1.52.0".to_string(),
+                "pip_install package no longer present: foo 1.0 (sha256 cafe)".to_string(),
+                "new pip_install package: foo 1.1 (sha256 f00d)".to_string(),
+            ]
+        );
+        assert!(lock.verify(&drifted).is_err());
+
+        assert!(ProjectLock::from_toml(
+            "version = 42\nrust_toolchain = ''\npyembed_version = ''\ntarget_triple = ''\n"
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_subset() -> Result<()> {
+        let package = |name: &str, version: &str| LockedPackage {
+            source: "pip_install".to_string(),
+            name: name.to_string(),
+            version: version.to_string(),
+            sha256: format!("{}-{}", name, version),
+            artifact_sha256: None,
+        };
+
+        let mut lock = ProjectLock::new("1.51.0", "0.14.0-pre", "x86_64-unknown-linux-gnu");
+        lock.distributions
+            .insert(LockedDistribution::from(&PythonDistributionLocation::Url {
+                url: "https://example.com/python.tar.zst".to_string(),
+                sha256: "deadbeef".to_string(),
+            }));
+        lock.packages.insert(package("foo", "1.0"));
+        lock.packages.insert(package("bar", "1.0"));
+
+        // A build of a subset of targets only using some inputs.
+        let mut subset = ProjectLock::new("1.51.0", "0.14.0-pre", "x86_64-unknown-linux-gnu");
+        subset.packages.insert(package("foo", "1.0"));
+
+        assert!(lock.verify(&subset).is_err());
+        lock.verify_subset(&subset)?;
+
+        let mut merged = lock.clone();
+        merged.merge(&subset);
+        assert_eq!(merged, lock);
+
+        subset.packages.clear();
+        subset.packages.insert(package("foo", "1.1"));
+        assert_eq!(
+            lock.verify_subset(&subset).unwrap_err().to_string(),
+            "build inputs differ from lockfile:\n  new pip_install package: foo 1.1 (sha256 foo-1.1)"
+        );
+
+        merged.merge(&subset);
+        assert_eq!(
+            merged.packages,
+            [package("bar", "1.0"), package("foo", "1.1")]
+                .iter()
+                .cloned()
+                .collect::<BTreeSet<_>>()
+        );
+        assert_eq!(merged.distributions, lock.distributions);
+
+        Ok(())
+    }
+}
@@ -27,8 +27,11 @@ is implemented in C. Rust is simply a tool to achieve an end goal, albeit
 a rather effective and powerful tool.
 */
 
+mod build_metadata;
+mod check;
 mod cli;
 mod environment;
+mod lockfile;
 mod logging;
 mod project_building;
 mod project_layout;
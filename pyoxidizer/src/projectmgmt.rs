@@ -6,7 +6,9 @@
 
 use {
     crate::{
+        check::{CheckReport, Severity},
         environment::{Environment, PyOxidizerSource},
+        lockfile::{ProjectLock, LOCKFILE_NAME},
         project_building::find_pyoxidizer_config_file_env,
         project_layout::{initialize_project, write_new_pyoxidizer_config_file},
         py_packaging::{
@@ -22,6 +24,7 @@ use {
     python_packaging::{
         filesystem_scanning::find_python_resources, resource::PythonResource, wheel::WheelArchive,
     },
+    slog::info,
     std::{
         collections::HashMap,
         fs::create_dir_all,
@@ -58,7 +61,16 @@ pub fn resolve_target(target: Option<&str>) -> Result<String> {
     }
 }
 
-pub fn list_targets(env: &Environment, logger: &slog::Logger, project_path: &Path) -> Result<()> {
+/// List targets in a project's configuration file.
+///
+/// `format` is one of `text`, `dot` or `json`. For `dot` and `json`, all
+/// targets are resolved so the types of their values can be reported.
+pub fn list_targets(
+    env: &Environment,
+    logger: &slog::Logger,
+    project_path: &Path,
+    format: &str,
+) -> Result<()> {
     let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
         anyhow!(
             "unable to find PyOxidizder config file at {}",
@@ -75,6 +87,26 @@ pub fn list_targets(env: &Environment, logger: &slog::Logger, project_path: &Pat
 
     context.evaluate_file(&config_path)?;
 
+    match format {
+        "text" => {}
+        "dot" | "json" => {
+            for target in context.target_names()? {
+                context.resolve_target(&target)?;
+            }
+
+            let graph = context.target_graph()?;
+
+            if format == "dot" {
+                print!("{}", graph.to_dot());
+            } else {
+                println!("{}", graph.to_json()?);
+            }
+
+            return Ok(());
+        }
+        _ => return Err(anyhow!("unknown output format: {}", format)),
+    }
+
     if context.default_target()?.is_none() {
         println!("(no targets defined)");
         return Ok(());
@@ -92,6 +124,63 @@ pub fn list_targets(env: &Environment, logger: &slog::Logger, project_path: &Pat
     Ok(())
 }
 
+/// Validate a project's configuration file without building anything.
+///
+/// `format` is one of `text` or `json`.
+pub fn check(
+    env: &Environment,
+    logger: &slog::Logger,
+    project_path: &Path,
+    target_triple: Option<&str>,
+    extra_vars: HashMap<String, Option<String>>,
+    format: &str,
+    verbose: bool,
+) -> Result<()> {
+    if !matches!(format, "text" | "json") {
+        return Err(anyhow!("unknown output format: {}", format));
+    }
+
+    let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+    let target_triple = resolve_target(target_triple)?;
+
+    let mut context =
+        EvaluationContextBuilder::new(env, logger.clone(), config_path.clone(), target_triple)
+            .extra_vars(extra_vars)
+            .verbose(verbose)
+            .resolve_targets(vec![])
+            .into_context()?;
+
+    let mut report = CheckReport::new(&config_path);
+
+    match context.evaluate_file(&config_path) {
+        Ok(()) => {
+            for target in context.target_names()? {
+                match context.resolve_target(&target) {
+                    Ok(value) => report.add_target_value(&target, &value),
+                    Err(e) => report.add_target_error(&target, &e),
+                }
+            }
+        }
+        Err(e) => report.add_message(Severity::Error, None, format!("{:#}", e)),
+    }
+
+    if format == "json" {
+        println!("{}", report.to_json()?);
+    } else {
+        print!("{}", report.to_text());
+    }
+
+    match report.count(Severity::Error) {
+        0 => Ok(()),
+        count => Err(anyhow!("configuration check found {} errors", count)),
+    }
+}
+
 /// Build a PyOxidizer enabled project.
 ///
 /// This is a glorified wrapper around `cargo build`. Our goal is to get the
@@ -104,7 +193,12 @@ pub fn build(
     target_triple: Option<&str>,
     resolve_targets: Option<Vec<String>>,
     extra_vars: HashMap<String, Option<String>>,
+    explain_resources: Vec<String>,
     release: bool,
+    locked: bool,
+    incremental: bool,
+    force: bool,
+    jobs: usize,
     verbose: bool,
 ) -> Result<()> {
     let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
@@ -115,18 +209,66 @@ pub fn build(
     })?;
     let target_triple = resolve_target(target_triple)?;
 
+    // Builds of explicitly requested targets only see the inputs of those
+    // targets. So they are checked against and merged into the lockfile
+    // instead of replacing it.
+    let partial = resolve_targets.is_some();
+
     let mut context =
         EvaluationContextBuilder::new(env, logger.clone(), config_path.clone(), target_triple)
             .extra_vars(extra_vars)
+            .explain_resources(explain_resources)
             .release(release)
             .verbose(verbose)
+            .incremental(incremental)
+            .force_build(force)
             .resolve_targets_optional(resolve_targets)
             .into_context()?;
 
     context.evaluate_file(&config_path)?;
 
-    for target in context.targets_to_resolve()? {
-        context.build_resolved_target(&target)?;
+    // Targets are resolved as part of evaluation. So all distributions and
+    // packages are known by now and we can verify them before building.
+    let lock_path = config_path.with_file_name(LOCKFILE_NAME);
+    let lock = context.project_lock()?;
+
+    if locked {
+        if !lock_path.exists() {
+            return Err(anyhow!(
+                "--locked requires a lockfile but {} does not exist",
+                lock_path.display()
+            ));
+        }
+
+        let existing = ProjectLock::from_path(&lock_path)?;
+
+        if partial {
+            existing.verify_subset(&lock)
+        } else {
+            existing.verify(&lock)
+        }
+        .with_context(|| format!("verifying {}", lock_path.display()))?;
+    }
+
+    let targets = context.targets_to_resolve()?;
+    let built = context.build_resolved_targets(&targets, jobs)?;
+
+    let metadata_path = context.build_metadata_path()?;
+    context
+        .build_metadata(&targets, &built)?
+        .write_path(&metadata_path)?;
+
+    if !locked {
+        let lock = if partial && lock_path.exists() {
+            let mut existing = ProjectLock::from_path(&lock_path)?;
+            existing.merge(&lock);
+            existing
+        } else {
+            lock
+        };
+
+        info!(logger, "writing {}", lock_path.display());
+        lock.write_path(&lock_path)?;
     }
 
     Ok(())
@@ -165,8 +307,16 @@ pub fn run(
     context.run_target(target)
 }
 
-pub fn cache_clear(env: &Environment) -> Result<()> {
-    let cache_dir = env.cache_dir();
+pub fn cache_clear(env: &Environment, bytecode_only: bool) -> Result<()> {
+    let cache_dir = if bytecode_only {
+        env.bytecode_cache_dir()
+    } else {
+        env.cache_dir().to_path_buf()
+    };
+
+    if !cache_dir.exists() {
+        return Ok(());
+    }
 
     println!("removing {}", cache_dir.display());
     remove_dir_all::remove_dir_all(&cache_dir)?;
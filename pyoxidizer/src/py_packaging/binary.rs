@@ -8,8 +8,11 @@ Defining and manipulating binaries embedding Python.
 
 use {
     super::{config::PyembedPythonInterpreterConfig, distribution::AppleSdkInfo},
+    crate::lockfile::ArtifactDigests,
     anyhow::{anyhow, Context, Result},
     python_packaging::{
+        bytecode_cache::BytecodeCache,
+        module_graph::ModuleReachability,
         policy::PythonPackagingPolicy,
         resource::{
             PythonExtensionModule, PythonModuleSource, PythonPackageDistributionResource,
@@ -17,16 +20,18 @@ use {
         },
         resource_collection::{
             CompiledResourcesCollection, PrePackagedResource, PythonResourceAddCollectionContext,
+            ResourceDecision,
         },
     },
     std::{
-        collections::HashMap,
+        collections::{BTreeMap, HashMap},
         convert::TryFrom,
         io::Write,
         path::{Path, PathBuf},
         sync::Arc,
     },
     tugger_file_manifest::{File, FileManifest},
+    tugger_licensing::LicensedComponents,
     tugger_windows::VcRedistributablePlatform,
 };
 
@@ -148,12 +153,14 @@ impl TryFrom<&str> for WindowsRuntimeDllsMode {
 }
 
 /// A callable that can influence PythonResourceAddCollectionContext.
+///
+/// Returns the decisions that influenced the context.
 pub type ResourceAddCollectionContextCallback<'a> = Box<
     dyn Fn(
             &PythonPackagingPolicy,
             &PythonResource,
             &mut PythonResourceAddCollectionContext,
-        ) -> Result<()>
+        ) -> Result<Vec<ResourceDecision>>
         + 'a,
 >;
 
@@ -165,7 +172,10 @@ pub type ResourceAddCollectionContextCallback<'a> = Box<
 ///
 /// Concrete implementations can be turned into build artifacts or binaries
 /// themselves.
-pub trait PythonBinaryBuilder {
+///
+/// Implementations must be `Send` and `Sync` so binaries can be built on
+/// worker threads.
+pub trait PythonBinaryBuilder: Send + Sync {
     /// Clone self into a Box'ed trait object.
     fn clone_trait(&self) -> Arc<dyn PythonBinaryBuilder>;
 
@@ -191,6 +201,14 @@ pub trait PythonBinaryBuilder {
     /// Obtain the `PythonPackagingPolicy` for the builder.
     fn python_packaging_policy(&self) -> &PythonPackagingPolicy;
 
+    /// Replace the `PythonPackagingPolicy` for the builder.
+    ///
+    /// The new policy applies to resources added afterwards.
+    fn set_python_packaging_policy(&mut self, policy: PythonPackagingPolicy);
+
+    /// Obtain the configuration of the embedded Python interpreter.
+    fn interpreter_config(&self) -> &PyembedPythonInterpreterConfig;
+
     /// Path to Python executable that can be used to derive info at build time.
     ///
     /// The produced binary is effectively a clone of the Python distribution behind the
@@ -233,6 +251,22 @@ pub trait PythonBinaryBuilder {
     /// Set how packed Python resources will be loaded by the binary.
     fn set_packed_resources_load_mode(&mut self, load_mode: PackedResourcesLoadMode);
 
+    /// The cache of compiled Python bytecode to use when building.
+    fn bytecode_cache(&self) -> Option<&BytecodeCache>;
+
+    /// Set the cache of compiled Python bytecode to use when building.
+    ///
+    /// `None` disables caching.
+    fn set_bytecode_cache(&mut self, cache: Option<BytecodeCache>);
+
+    /// The number of Python processes used to compile bytecode.
+    fn bytecode_compile_workers(&self) -> usize;
+
+    /// Set the number of Python processes used to compile bytecode.
+    ///
+    /// `0` is treated as `1`.
+    fn set_bytecode_compile_workers(&mut self, workers: usize);
+
     /// Obtain an iterator over all resource entries that will be embedded in the binary.
     ///
     /// This likely does not return extension modules that are statically linked
@@ -254,13 +288,14 @@ pub trait PythonBinaryBuilder {
 
     /// Runs `pip download` using the binary builder's settings.
     ///
-    /// Returns resources discovered from the Python packages downloaded.
+    /// Returns resources discovered from the Python packages downloaded and
+    /// the digests of the downloaded wheels.
     fn pip_download(
         &mut self,
         logger: &slog::Logger,
         verbose: bool,
         args: &[String],
-    ) -> Result<Vec<PythonResource>>;
+    ) -> Result<(Vec<PythonResource>, ArtifactDigests)>;
 
     /// Runs `pip install` using the binary builder's settings.
     ///
@@ -300,6 +335,20 @@ pub trait PythonBinaryBuilder {
         extra_global_arguments: &[String],
     ) -> Result<Vec<PythonResource>>;
 
+    /// Builds a wheel from a source distribution using its PEP 517 build backend.
+    ///
+    /// Build requirements are installed from the `wheelhouse` directory.
+    ///
+    /// Returns resources discovered in the built wheel and the digest of the
+    /// source distribution archive, if `path` is one.
+    fn sdist_install(
+        &mut self,
+        logger: &slog::Logger,
+        path: &Path,
+        wheelhouse: &Path,
+        verbose: bool,
+    ) -> Result<(Vec<PythonResource>, ArtifactDigests)>;
+
     /// Add resources from the Python distribution to the builder.
     ///
     /// This method should likely be called soon after object construction
@@ -388,6 +437,44 @@ pub trait PythonBinaryBuilder {
         glob_patterns: &[&str],
     ) -> Result<()>;
 
+    /// Resolve which Python modules are reachable via imports.
+    ///
+    /// `entry_modules` are the modules to begin import analysis from. If empty,
+    /// the `run_module` of the interpreter config is used.
+    ///
+    /// `allow_modules` are module patterns (e.g. `foo` or `foo.*`) which are
+    /// always considered reachable. This should be used for modules that are
+    /// imported dynamically. Modules needed by the interpreter itself are
+    /// always considered reachable.
+    fn resolve_module_reachability(
+        &self,
+        entry_modules: &[String],
+        allow_modules: &[String],
+    ) -> Result<ModuleReachability>;
+
+    /// Remove Python modules which aren't reachable via imports.
+    ///
+    /// Arguments have the same meaning as for `resolve_module_reachability()`.
+    /// Returns the reachability analysis used to filter resources.
+    fn filter_unreachable_resources(
+        &mut self,
+        logger: &slog::Logger,
+        entry_modules: &[String],
+        allow_modules: &[String],
+    ) -> Result<ModuleReachability>;
+
+    /// Obtain the licensed software components that are part of the binary.
+    ///
+    /// This includes the Python distribution, the Rust crates embedding the
+    /// interpreter, and Python packages having resources in the binary.
+    fn licensed_components(&self) -> Result<LicensedComponents>;
+
+    /// Record decisions made about a named resource before it is added.
+    ///
+    /// Callers passing an add context to `add_*` methods should record the
+    /// decisions that influenced that context.
+    fn record_resource_decisions(&mut self, name: &str, decisions: Vec<ResourceDecision>);
+
     /// Whether the binary requires the jemalloc library.
     fn requires_jemalloc(&self) -> bool;
 
@@ -447,6 +534,9 @@ pub struct EmbeddedPythonContext<'a> {
 
     /// Rust target triple for the target we are building for.
     pub target_triple: String,
+
+    /// Decisions made about how resources were packaged, keyed by resource name.
+    pub resource_decisions: BTreeMap<String, Vec<ResourceDecision>>,
 }
 
 impl<'a> EmbeddedPythonContext<'a> {
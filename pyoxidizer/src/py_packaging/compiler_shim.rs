@@ -0,0 +1,936 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Capturing compiler and archiver invocations.
+
+Statically linking third-party extension modules requires the object files
+and libraries that went into building them. The modified distutils only sees
+extensions built through distutils' `build_ext`. This module complements it
+by installing small `cc` / `ar` wrappers ahead of the real tools on `PATH`.
+The wrappers run the real tool then record shared library links and static
+archive creation, regardless of which build system invoked the tool.
+
+Wrappers are POSIX shell scripts invoking a Python script. So this only works
+on non-Windows hosts. Build systems invoking compilers by absolute path
+bypass the wrappers.
+*/
+
+use {
+    anyhow::{anyhow, Context, Result},
+    python_packaging::resource::{LibraryDependency, PythonExtensionModule},
+    serde::Deserialize,
+    slog::warn,
+    std::{
+        collections::{BTreeMap, HashMap},
+        path::{Path, PathBuf},
+    },
+    tugger_file_manifest::FileData,
+};
+
+/// Tools that are wrapped, with the kind of tool they are.
+const WRAPPED_TOOLS: &[(&str, &str)] = &[
+    ("ar", "ar"),
+    ("c++", "cc"),
+    ("cc", "cc"),
+    ("clang", "cc"),
+    ("clang++", "cc"),
+    ("g++", "cc"),
+    ("gcc", "cc"),
+];
+
+/// Environment variable holding the directory wrappers record state to.
+pub const STATE_DIR_ENV: &str = "PYOXIDIZER_COMPILER_SHIM_STATE_DIR";
+
+/// Python code run by wrappers.
+///
+/// Arguments are the state directory, the tool kind (`cc` or `ar`) and the
+/// path of the real tool, followed by arguments to the tool. Failures to
+/// record state are reported but never fail the build.
+const SHIM_SCRIPT: &str = r#"
+import json, os, shlex, shutil, subprocess, sys, tempfile, time
+
+state_dir, kind, real = sys.argv[1:4]
+args = sys.argv[4:]
+
+rc = subprocess.call([real] + args)
+if rc != 0:
+    sys.exit(rc)
+
+TAKES_VALUE = {
+    "-arch", "-D", "-framework", "-I", "-include", "-install_name", "-isysroot",
+    "-isystem", "-L", "-l", "-MF", "-MQ", "-MT", "-o", "-target", "-U", "-x",
+}
+
+LIBRARY_SUFFIXES = (".so", ".dylib", ".tbd")
+
+
+def expand(args):
+    res = []
+    for arg in args:
+        if arg.startswith("@") and os.path.isfile(arg[1:]):
+            with open(arg[1:]) as fh:
+                res.extend(shlex.split(fh.read()))
+        else:
+            res.append(arg)
+    return res
+
+
+def inline_linker_args(args):
+    """Replace -Wl,<args> and -Xlinker <arg> with the arguments passed to the linker."""
+    res = []
+    i = 0
+    while i < len(args):
+        arg = args[i]
+        if arg.startswith("-Wl,"):
+            res.extend(a for a in arg[4:].split(",") if a)
+        elif arg == "-Xlinker" and i + 1 < len(args):
+            i += 1
+            res.append(args[i])
+        else:
+            res.append(arg)
+        i += 1
+    return res
+
+
+def find_library(name, library_dirs, static):
+    """Resolve -l<name> the way the linker would."""
+    if name.startswith(":"):
+        candidates = [name[1:]]
+    elif static:
+        candidates = ["lib%s.a" % name]
+    else:
+        candidates = ["lib%s%s" % (name, s) for s in LIBRARY_SUFFIXES] + ["lib%s.a" % name]
+
+    for d in library_dirs:
+        for c in candidates:
+            path = os.path.join(d, c)
+            if os.path.isfile(path):
+                return path
+
+    return None
+
+
+def save(path):
+    objects_dir = os.path.join(state_dir, "objects")
+    os.makedirs(objects_dir, exist_ok=True)
+    fd, dest = tempfile.mkstemp(suffix="-" + os.path.basename(path), dir=objects_dir)
+    os.close(fd)
+    shutil.copyfile(path, dest)
+    return dest
+
+
+def write_record(record):
+    name = "%020d.%d.json" % (time.time_ns(), os.getpid())
+    with open(os.path.join(state_dir, name), "w") as fh:
+        json.dump(record, fh)
+
+
+def record_cc(args):
+    if any(a in ("-c", "-E", "-S", "-M", "-MM") for a in args):
+        return
+    if not any(a in ("-shared", "-bundle", "-dynamiclib") for a in args):
+        return
+
+    record = {
+        "kind": "link",
+        "output": None,
+        "objects": [],
+        "archives": [],
+        "libraries": [],
+        "library_dirs": [],
+        "frameworks": [],
+    }
+
+    # (name, whether only static libraries are searched)
+    libraries = []
+    static = "-static" in args
+
+    i = 0
+    while i < len(args):
+        arg = args[i]
+        value = args[i + 1] if i + 1 < len(args) else None
+
+        if arg in TAKES_VALUE:
+            i += 2
+            if arg == "-o":
+                record["output"] = os.path.abspath(value)
+            elif arg == "-l":
+                libraries.append((value, static))
+            elif arg == "-L":
+                record["library_dirs"].append(os.path.abspath(value))
+            elif arg == "-framework":
+                record["frameworks"].append(value)
+            continue
+
+        i += 1
+        if arg in ("-Bstatic", "-dn", "-non_shared", "-static"):
+            static = True
+        elif arg in ("-Bdynamic", "-dy", "-call_shared"):
+            static = False
+        elif arg.startswith("-o"):
+            record["output"] = os.path.abspath(arg[2:])
+        elif arg.startswith("-l"):
+            libraries.append((arg[2:], static))
+        elif arg.startswith("-L"):
+            record["library_dirs"].append(os.path.abspath(arg[2:]))
+        elif arg.startswith("-"):
+            pass
+        elif arg.endswith((".o", ".obj", ".lo")) and os.path.isfile(arg):
+            record["objects"].append(save(arg))
+        elif arg.endswith(".a") and os.path.isfile(arg):
+            record["archives"].append({"path": os.path.abspath(arg), "copy": save(arg)})
+
+    # -L applies to all -l arguments, regardless of order.
+    for name, static in libraries:
+        path = find_library(name, record["library_dirs"], static)
+        record["libraries"].append({
+            "name": name,
+            "path": path and os.path.abspath(path),
+            "copy": path and save(path),
+        })
+
+    if record["output"]:
+        write_record(record)
+
+
+def record_ar(args):
+    if len(args) < 2 or not any(c in args[0] for c in "rq"):
+        return
+
+    write_record({
+        "kind": "archive",
+        "archive": os.path.abspath(args[1]),
+        "members": [
+            {"name": os.path.basename(m), "copy": save(m)}
+            for m in args[2:]
+            if os.path.isfile(m)
+        ],
+    })
+
+
+try:
+    if kind == "cc":
+        record_cc(inline_linker_args(expand(args)))
+    elif kind == "ar":
+        record_ar(expand(args))
+except Exception as e:
+    sys.stderr.write("pyoxidizer compiler shim: unable to record %s: %s\n" % (real, e))
+"#;
+
+/// Quote a string for use in a POSIX shell script.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Install compiler and archiver wrappers into a directory.
+///
+/// `python_exe` is the Python interpreter used to run the wrappers. `env`
+/// holds the environment build tools will run with, which is used to find
+/// the real tools.
+///
+/// Returns environment variables to set for build tools to use the
+/// wrappers.
+pub fn install_compiler_shims(
+    logger: &slog::Logger,
+    python_exe: &Path,
+    dest_dir: &Path,
+    env: &HashMap<String, String>,
+) -> Result<HashMap<String, String>> {
+    let bin_dir = dest_dir.join("bin");
+    let state_dir = dest_dir.join("state");
+    std::fs::create_dir_all(&bin_dir)?;
+    std::fs::create_dir_all(&state_dir)?;
+
+    let script_path = dest_dir.join("compiler_shim.py");
+    std::fs::write(&script_path, SHIM_SCRIPT)
+        .with_context(|| format!("writing {}", script_path.display()))?;
+
+    let path_var = env.get("PATH").cloned().unwrap_or_default();
+    let cwd = std::env::current_dir()?;
+
+    for (name, kind) in WRAPPED_TOOLS {
+        let real = match which::which_in(name, Some(&path_var), &cwd) {
+            Ok(path) => path,
+            Err(_) => continue,
+        };
+
+        let shim_path = bin_dir.join(name);
+        std::fs::write(
+            &shim_path,
+            format!(
+                "#!/bin/sh\nexec {} -I {} {} {} {} \"$@\"\n",
+                shell_quote(&python_exe.display().to_string()),
+                shell_quote(&script_path.display().to_string()),
+                shell_quote(&state_dir.display().to_string()),
+                kind,
+                shell_quote(&real.display().to_string()),
+            ),
+        )
+        .with_context(|| format!("writing {}", shim_path.display()))?;
+
+        let mut file = std::fs::File::open(&shim_path)?;
+        tugger_file_manifest::set_executable(&mut file)?;
+
+        warn!(
+            logger,
+            "capturing invocations of {} ({})",
+            name,
+            real.display()
+        );
+    }
+
+    let mut res = HashMap::new();
+    res.insert(
+        "PATH".to_string(),
+        if path_var.is_empty() {
+            bin_dir.display().to_string()
+        } else {
+            format!("{}:{}", bin_dir.display(), path_var)
+        },
+    );
+    res.insert(STATE_DIR_ENV.to_string(), state_dir.display().to_string());
+
+    Ok(res)
+}
+
+#[derive(Debug, Deserialize)]
+struct ArchivedObject {
+    name: String,
+    copy: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkedArchive {
+    path: PathBuf,
+    copy: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct LinkedLibrary {
+    name: String,
+    /// Path the library resolved to in the recorded library directories.
+    path: Option<PathBuf>,
+    copy: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum ShimRecord {
+    Archive {
+        archive: PathBuf,
+        members: Vec<ArchivedObject>,
+    },
+    Link {
+        output: PathBuf,
+        objects: Vec<PathBuf>,
+        archives: Vec<LinkedArchive>,
+        libraries: Vec<LinkedLibrary>,
+        frameworks: Vec<String>,
+    },
+}
+
+/// A shared library link captured by the compiler wrappers.
+#[derive(Clone, Debug, Default)]
+pub struct CapturedLink {
+    /// Object files that were linked.
+    ///
+    /// Includes members of static archives created during the build.
+    pub object_file_data: Vec<FileData>,
+
+    /// Libraries that were linked.
+    pub link_libraries: Vec<LibraryDependency>,
+}
+
+impl CapturedLink {
+    /// Obtain a copy of an extension module with the captured link state.
+    pub fn to_extension_module(&self, ext: &PythonExtensionModule) -> PythonExtensionModule {
+        let mut ext = ext.clone();
+        ext.object_file_data = self.object_file_data.clone();
+        ext.link_libraries = self.link_libraries.clone();
+
+        ext
+    }
+}
+
+/// Directories holding libraries provided by the operating system.
+const SYSTEM_LIBRARY_DIRS: &[&str] = &["/lib", "/lib64", "/usr/lib", "/usr/lib64", "/System"];
+
+fn library_name_from_archive(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = name.strip_suffix(".a").unwrap_or(&name);
+
+    name.strip_prefix("lib").unwrap_or(name).to_string()
+}
+
+/// Whether a library resolved from `-l` is provided by the operating system.
+///
+/// Libraries not found in any `-L` directory come from the linker's default
+/// search path, which only holds system libraries. So do libraries found in
+/// well-known system directories, including those of macOS SDKs.
+fn is_system_library(path: Option<&Path>) -> bool {
+    match path {
+        Some(path) => {
+            SYSTEM_LIBRARY_DIRS.iter().any(|dir| path.starts_with(dir))
+                || path.to_string_lossy().contains(".sdk/")
+        }
+        None => true,
+    }
+}
+
+/// Read the saved copy of a file recorded by the wrappers.
+fn read_copy(path: &Path) -> Result<FileData> {
+    Ok(FileData::Memory(
+        std::fs::read(path).with_context(|| format!("reading {}", path.display()))?,
+    ))
+}
+
+/// Read shared library links recorded by wrappers.
+///
+/// Returns captured links keyed by the absolute path of the linked shared
+/// library. If a path was linked multiple times, the last link wins.
+pub fn read_captured_links(state_dir: &Path) -> Result<BTreeMap<PathBuf, CapturedLink>> {
+    let mut record_paths = std::fs::read_dir(state_dir)
+        .with_context(|| format!("reading compiler shim state from {}", state_dir.display()))?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|path| path.extension().map(|x| x == "json").unwrap_or(false))
+        .collect::<Vec<_>>();
+    // File names begin with a timestamp. So this is chronological order.
+    record_paths.sort();
+
+    // Archive path to (member name, object path) of its current members.
+    let mut archives: HashMap<PathBuf, Vec<(String, PathBuf)>> = HashMap::new();
+    let mut res = BTreeMap::new();
+
+    for path in record_paths {
+        let data = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+        let record: ShimRecord =
+            serde_json::from_slice(&data).with_context(|| format!("parsing {}", path.display()))?;
+
+        match record {
+            ShimRecord::Archive { archive, members } => {
+                let entries = archives.entry(archive).or_default();
+
+                // `ar r` replaces existing members having the same name.
+                for member in members {
+                    entries.retain(|(name, _)| name != &member.name);
+                    entries.push((member.name, member.copy));
+                }
+            }
+            ShimRecord::Link {
+                output,
+                objects,
+                archives: linked_archives,
+                libraries,
+                frameworks,
+            } => {
+                let mut link = CapturedLink::default();
+
+                for object in objects {
+                    link.object_file_data.push(FileData::Memory(
+                        std::fs::read(&object)
+                            .with_context(|| format!("reading {}", object.display()))?,
+                    ));
+                }
+
+                // Static archives named explicitly or resolved from -l.
+                let mut static_archives = linked_archives
+                    .into_iter()
+                    .map(|archive| (archive.path, Some(archive.copy)))
+                    .collect::<Vec<_>>();
+
+                for library in libraries {
+                    let name = library.name.trim_start_matches(':').to_string();
+
+                    match library.path {
+                        Some(path) if !is_system_library(Some(&path)) => {
+                            if path.extension().map(|x| x == "a").unwrap_or(false) {
+                                static_archives.push((path, library.copy));
+                            } else {
+                                link.link_libraries.push(LibraryDependency {
+                                    name,
+                                    static_library: None,
+                                    static_filename: None,
+                                    dynamic_library: library
+                                        .copy
+                                        .as_deref()
+                                        .map(read_copy)
+                                        .transpose()?,
+                                    dynamic_filename: path.file_name().map(PathBuf::from),
+                                    framework: false,
+                                    system: false,
+                                });
+                            }
+                        }
+                        _ => {
+                            link.link_libraries.push(LibraryDependency {
+                                name,
+                                static_library: None,
+                                static_filename: None,
+                                dynamic_library: None,
+                                dynamic_filename: None,
+                                framework: false,
+                                system: true,
+                            });
+                        }
+                    }
+                }
+
+                for (path, copy) in static_archives {
+                    // Archives created during the build contribute their
+                    // members. Others are linked as static libraries.
+                    if let Some(members) = archives.get(&path) {
+                        for (_, object) in members {
+                            link.object_file_data.push(read_copy(object)?);
+                        }
+                    } else {
+                        link.link_libraries.push(LibraryDependency {
+                            name: library_name_from_archive(&path),
+                            static_library: copy.as_deref().map(read_copy).transpose()?,
+                            static_filename: path.file_name().map(PathBuf::from),
+                            dynamic_library: None,
+                            dynamic_filename: None,
+                            framework: false,
+                            system: false,
+                        });
+                    }
+                }
+
+                for name in frameworks {
+                    link.link_libraries.push(LibraryDependency {
+                        name,
+                        static_library: None,
+                        static_filename: None,
+                        dynamic_library: None,
+                        dynamic_filename: None,
+                        framework: true,
+                        system: false,
+                    });
+                }
+
+                res.insert(output, link);
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Obtain the path of an extension module's shared library relative to the install root.
+fn extension_module_path(e: &PythonExtensionModule) -> PathBuf {
+    let mut path = e.name.split('.').collect::<PathBuf>();
+    if e.is_package {
+        path.push("__init__");
+    }
+
+    let file_name = format!(
+        "{}{}",
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        e.extension_file_suffix
+    );
+    path.set_file_name(file_name);
+
+    path
+}
+
+/// Match extension modules to the captured links of their shared libraries.
+///
+/// A link matches an extension module if the path of the linked shared
+/// library ends with the module's path relative to the install root. e.g.
+/// `foo/_bar.cpython-39-x86_64-linux-gnu.so` for `foo._bar`. Each link is
+/// attributed to the extension module having the longest matching path.
+///
+/// Returns captured links keyed by extension module name. Errors if multiple
+/// links match the same extension module.
+pub fn match_captured_links<'a, 'b>(
+    links: &'a BTreeMap<PathBuf, CapturedLink>,
+    extension_modules: impl IntoIterator<Item = &'b PythonExtensionModule>,
+) -> Result<BTreeMap<String, &'a CapturedLink>> {
+    let modules = extension_modules
+        .into_iter()
+        .map(|e| (e.name.clone(), extension_module_path(e)))
+        .collect::<Vec<_>>();
+
+    let mut matches: BTreeMap<String, Vec<(&Path, &CapturedLink)>> = BTreeMap::new();
+
+    for (output, link) in links {
+        if let Some((name, _)) = modules
+            .iter()
+            .filter(|(_, path)| output.ends_with(path))
+            .max_by_key(|(_, path)| path.components().count())
+        {
+            matches
+                .entry(name.clone())
+                .or_default()
+                .push((output.as_path(), link));
+        }
+    }
+
+    matches
+        .into_iter()
+        .map(|(name, candidates)| {
+            if candidates.len() > 1 {
+                Err(anyhow!(
+                    "extension module {} matches multiple captured links: {}",
+                    name,
+                    candidates
+                        .iter()
+                        .map(|(output, _)| output.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ))
+            } else {
+                Ok((name, candidates[0].1))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::fs::write};
+
+    #[test]
+    fn test_read_captured_links() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+        let state_dir = td.path();
+
+        let a_o = state_dir.join("a.o");
+        let b_o = state_dir.join("b.o");
+        let b2_o = state_dir.join("b2.o");
+        let ext_o = state_dir.join("ext.o");
+        let external_a = state_dir.join("libexternal.a");
+        let foo_so = state_dir.join("libfoo.so");
+        write(&a_o, "a")?;
+        write(&b_o, "b")?;
+        write(&b2_o, "b2")?;
+        write(&ext_o, "ext")?;
+        write(&external_a, "archive")?;
+        write(&foo_so, "shared")?;
+
+        let records = [
+            serde_json::json!({
+                "kind": "archive",
+                "archive": "/build/libhelper.a",
+                "members": [
+                    {"name": "a.o", "copy": a_o},
+                    {"name": "b.o", "copy": b_o},
+                ],
+            }),
+            // Replaces the b.o member.
+            serde_json::json!({
+                "kind": "archive",
+                "archive": "/build/libhelper.a",
+                "members": [{"name": "b.o", "copy": b2_o}],
+            }),
+            serde_json::json!({
+                "kind": "link",
+                "output": "/build/lib/foo/_bar.cpython-39-x86_64-linux-gnu.so",
+                "objects": [ext_o],
+                "archives": [
+                    {"path": "/build/libhelper.a", "copy": "/nonexistent"},
+                    {"path": "/usr/lib/libexternal.a", "copy": external_a},
+                ],
+                "libraries": [
+                    {"name": "z", "path": "/usr/lib/libz.so", "copy": "/nonexistent"},
+                    {"name": "m", "path": null, "copy": null},
+                    {"name": "foo", "path": "/build/libfoo.so", "copy": foo_so},
+                ],
+                "library_dirs": ["/usr/lib", "/build"],
+                "frameworks": [],
+            }),
+        ];
+
+        for (i, record) in records.iter().enumerate() {
+            write(
+                state_dir.join(format!("{:020}.1.json", i)),
+                serde_json::to_vec(record)?,
+            )?;
+        }
+
+        let links = read_captured_links(state_dir)?;
+        assert_eq!(links.len(), 1);
+
+        let link = links
+            .get(Path::new(
+                "/build/lib/foo/_bar.cpython-39-x86_64-linux-gnu.so",
+            ))
+            .expect("link should be captured");
+
+        let objects = link
+            .object_file_data
+            .iter()
+            .map(|data| data.resolve())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            objects,
+            vec![b"ext".to_vec(), b"a".to_vec(), b"b2".to_vec()]
+        );
+
+        assert_eq!(
+            link.link_libraries
+                .iter()
+                .map(|l| (l.name.as_str(), l.system))
+                .collect::<Vec<_>>(),
+            vec![
+                ("z", true),
+                ("m", true),
+                ("foo", false),
+                ("external", false)
+            ]
+        );
+        assert!(link.link_libraries[0].dynamic_library.is_none());
+        assert_eq!(
+            link.link_libraries[2]
+                .dynamic_library
+                .as_ref()
+                .unwrap()
+                .resolve()?,
+            b"shared".to_vec()
+        );
+        assert_eq!(
+            link.link_libraries[2].dynamic_filename,
+            Some(PathBuf::from("libfoo.so"))
+        );
+        assert_eq!(
+            link.link_libraries[3]
+                .static_library
+                .as_ref()
+                .unwrap()
+                .resolve()?,
+            b"archive".to_vec()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_match_captured_links() -> Result<()> {
+        let extension_module = |name: &str, is_package: bool| PythonExtensionModule {
+            name: name.to_string(),
+            init_fn: None,
+            extension_file_suffix: ".so".to_string(),
+            shared_library: None,
+            object_file_data: vec![],
+            is_package,
+            link_libraries: vec![],
+            is_stdlib: false,
+            builtin_default: false,
+            required: false,
+            variant: None,
+            license: None,
+        };
+        let link = |name: &str| CapturedLink {
+            object_file_data: vec![FileData::Memory(name.as_bytes().to_vec())],
+            link_libraries: vec![],
+        };
+        let object = |link: &CapturedLink| link.object_file_data[0].resolve().unwrap();
+
+        let modules = vec![
+            extension_module("_speedups", false),
+            extension_module("a._speedups", false),
+            extension_module("b._speedups", false),
+            extension_module("c", true),
+            extension_module("unlinked", false),
+        ];
+
+        let mut links = BTreeMap::new();
+        links.insert(PathBuf::from("/build/lib/_speedups.so"), link("top"));
+        links.insert(PathBuf::from("/build/lib/a/_speedups.so"), link("a"));
+        links.insert(PathBuf::from("/build/lib/b/_speedups.so"), link("b"));
+        links.insert(PathBuf::from("/build/lib/c/__init__.so"), link("c"));
+        links.insert(PathBuf::from("/build/lib/other.so"), link("other"));
+
+        let matched = match_captured_links(&links, &modules)?;
+        assert_eq!(
+            matched
+                .iter()
+                .map(|(name, link)| (name.as_str(), object(link)))
+                .collect::<Vec<_>>(),
+            vec![
+                ("_speedups", b"top".to_vec()),
+                ("a._speedups", b"a".to_vec()),
+                ("b._speedups", b"b".to_vec()),
+                ("c", b"c".to_vec()),
+            ]
+        );
+
+        links.insert(PathBuf::from("/build/temp/a/_speedups.so"), link("a2"));
+        let err = match_captured_links(&links, &modules).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "extension module a._speedups matches multiple captured links: \
+            /build/lib/a/_speedups.so, /build/temp/a/_speedups.so"
+        );
+
+        Ok(())
+    }
+
+    /// Run the wrapper script for a tool kind, using `true` as the real tool.
+    fn run_shim(
+        python_exe: &Path,
+        script_path: &Path,
+        state_dir: &Path,
+        kind: &str,
+        args: &[String],
+    ) -> Result<()> {
+        let real = which::which("true")?;
+
+        let status = std::process::Command::new(python_exe)
+            .arg("-I")
+            .arg(script_path)
+            .arg(state_dir)
+            .arg(kind)
+            .arg(real)
+            .args(args)
+            .status()?;
+        assert!(status.success());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shim_script_argument_parsing() -> Result<()> {
+        let python_exe = match which::which("python3") {
+            Ok(p) => p,
+            Err(_) => {
+                eprintln!("python3 not found; skipping test");
+                return Ok(());
+            }
+        };
+
+        let td = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+        let build_dir = td.path().join("build");
+        let libs_dir = build_dir.join("libs");
+        let state_dir = td.path().join("state");
+        std::fs::create_dir_all(&libs_dir)?;
+        std::fs::create_dir_all(&state_dir)?;
+
+        let script_path = td.path().join("compiler_shim.py");
+        write(&script_path, SHIM_SCRIPT)?;
+
+        write(build_dir.join("a.o"), "a")?;
+        write(build_dir.join("ext.o"), "ext")?;
+        write(libs_dir.join("libfoo.so"), "foo-so")?;
+        write(libs_dir.join("libbar.a"), "bar-a")?;
+        write(libs_dir.join("libqux.a"), "qux-a")?;
+        write(libs_dir.join("libqux.so"), "qux-so")?;
+
+        let path = |p: &Path| p.display().to_string();
+
+        // Compiling isn't recorded.
+        run_shim(
+            &python_exe,
+            &script_path,
+            &state_dir,
+            "cc",
+            &[
+                "-c".to_string(),
+                "ext.c".to_string(),
+                "-o".to_string(),
+                path(&build_dir.join("ext.o")),
+            ],
+        )?;
+        assert!(read_captured_links(&state_dir)?.is_empty());
+
+        // libbar.a is created during the build.
+        run_shim(
+            &python_exe,
+            &script_path,
+            &state_dir,
+            "ar",
+            &[
+                "rcs".to_string(),
+                path(&libs_dir.join("libbar.a")),
+                path(&build_dir.join("a.o")),
+            ],
+        )?;
+
+        run_shim(
+            &python_exe,
+            &script_path,
+            &state_dir,
+            "cc",
+            &[
+                "-shared".to_string(),
+                "-o".to_string(),
+                path(&build_dir.join("_ext.so")),
+                path(&build_dir.join("ext.o")),
+                format!("-L{}", path(&libs_dir)),
+                "-lfoo".to_string(),
+                "-Wl,-lz".to_string(),
+                "-Xlinker".to_string(),
+                "-lm".to_string(),
+                "-Wl,-l,bar".to_string(),
+                "-Xlinker".to_string(),
+                "-l".to_string(),
+                "-Xlinker".to_string(),
+                "baz".to_string(),
+                "-Wl,-Bstatic".to_string(),
+                "-lqux".to_string(),
+                "-Wl,-Bdynamic".to_string(),
+                "-framework".to_string(),
+                "CoreFoundation".to_string(),
+            ],
+        )?;
+
+        let links = read_captured_links(&state_dir)?;
+        assert_eq!(
+            links.keys().collect::<Vec<_>>(),
+            vec![&build_dir.join("_ext.so")]
+        );
+        let link = links.get(&build_dir.join("_ext.so")).unwrap();
+
+        let objects = link
+            .object_file_data
+            .iter()
+            .map(|data| data.resolve())
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(objects, vec![b"ext".to_vec(), b"a".to_vec()]);
+
+        assert_eq!(
+            link.link_libraries
+                .iter()
+                .map(|l| (l.name.as_str(), l.system, l.framework))
+                .collect::<Vec<_>>(),
+            vec![
+                ("foo", false, false),
+                ("z", true, false),
+                ("m", true, false),
+                ("baz", true, false),
+                ("qux", false, false),
+                ("CoreFoundation", false, true),
+            ]
+        );
+        assert_eq!(
+            link.link_libraries[0]
+                .dynamic_library
+                .as_ref()
+                .unwrap()
+                .resolve()?,
+            b"foo-so".to_vec()
+        );
+        assert_eq!(
+            link.link_libraries[4]
+                .static_library
+                .as_ref()
+                .unwrap()
+                .resolve()?,
+            b"qux-a".to_vec()
+        );
+
+        Ok(())
+    }
+}
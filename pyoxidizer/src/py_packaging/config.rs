@@ -7,7 +7,7 @@ Configuring a Python interpreter.
 */
 
 use {
-    anyhow::Result,
+    anyhow::{Context, Result},
     itertools::Itertools,
     python_packaging::{
         interpreter::{
@@ -16,6 +16,7 @@ use {
         },
         resource::BytecodeOptimizationLevel,
     },
+    serde::{Deserialize, Serialize, Serializer},
     std::{
         io::Write,
         path::{Path, PathBuf},
@@ -78,7 +79,8 @@ fn optional_vec_string_to_string(value: &Option<Vec<String>>) -> String {
 }
 
 /// Represents sources for loading packed resources data.
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type", content = "path")]
 pub enum PyembedPackedResourcesSource {
     /// Load from memory via an `include_bytes!` directive.
     MemoryIncludeBytes(PathBuf),
@@ -107,14 +109,36 @@ impl ToString for PyembedPackedResourcesSource {
     }
 }
 
+/// Serialize packed resources sources that can be loaded at run-time.
+///
+/// In-memory sources reference build artifacts via `include_bytes!` and must
+/// be registered by code embedding the interpreter.
+fn serialize_packed_resources<S: Serializer>(
+    sources: &[PyembedPackedResourcesSource],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+        sources
+            .iter()
+            .filter(|source| matches!(source, PyembedPackedResourcesSource::MemoryMappedPath(_))),
+    )
+}
+
 /// Represents the run-time configuration of a Python interpreter.
 ///
 /// This type mirrors `pyembed::OxidizedPythonInterpreterConfig`. We can't
 /// use that type verbatim because of lifetime issues. It might be possible.
 /// But that type holds a reference to resources data and this type needs to
 /// be embedded in Starlark values, which have a `static lifetime.
-#[derive(Clone, Debug, PartialEq)]
+///
+/// The type can be serialized to and from JSON and TOML. Field names match
+/// those of `pyembed::OxidizedPythonInterpreterConfig`, so serialized JSON can
+/// be loaded by `pyembed`. Packed resources embedded via `include_bytes!`
+/// aren't serialized since `pyembed` can't load them from serialized data.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
 pub struct PyembedPythonInterpreterConfig {
+    #[serde(rename = "interpreter_config")]
     pub config: PythonInterpreterConfig,
     pub allocator_backend: MemoryAllocatorBackend,
     pub allocator_raw: bool,
@@ -125,6 +149,7 @@ pub struct PyembedPythonInterpreterConfig {
     pub set_missing_path_configuration: bool,
     pub oxidized_importer: bool,
     pub filesystem_importer: bool,
+    #[serde(serialize_with = "serialize_packed_resources")]
     pub packed_resources: Vec<PyembedPackedResourcesSource>,
     pub argvb: bool,
     pub sys_frozen: bool,
@@ -170,6 +195,34 @@ impl Default for PyembedPythonInterpreterConfig {
 }
 
 impl PyembedPythonInterpreterConfig {
+    /// Serialize the instance to a JSON string.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("serializing interpreter config to JSON")
+    }
+
+    /// Construct an instance from JSON.
+    ///
+    /// Fields not present take their default values.
+    pub fn from_json(data: &str) -> Result<Self> {
+        serde_json::from_str(data).context("parsing interpreter config JSON")
+    }
+
+    /// Serialize the instance to a TOML string.
+    pub fn to_toml(&self) -> Result<String> {
+        // TOML requires plain values to be emitted before tables. Going through
+        // `toml::Value` takes care of the ordering.
+        let value = toml::Value::try_from(self).context("converting interpreter config to TOML")?;
+
+        toml::to_string_pretty(&value).context("serializing interpreter config to TOML")
+    }
+
+    /// Construct an instance from TOML.
+    ///
+    /// Fields not present take their default values.
+    pub fn from_toml(data: &str) -> Result<Self> {
+        toml::from_str(data).context("parsing interpreter config TOML")
+    }
+
     /// Convert the instance to Rust code that constructs a `pyembed::OxidizedPythonInterpreterConfig`.
     pub fn to_oxidized_python_interpreter_config_rs(&self) -> Result<String> {
         let code = format!(
@@ -488,6 +541,106 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_json_round_trip() -> Result<()> {
+        let mut config = PyembedPythonInterpreterConfig::default();
+        config.config.allocator = Some(Allocator::Malloc);
+        config.config.module_search_paths = Some(vec![PathBuf::from("$ORIGIN/lib")]);
+        config.config.optimization_level = Some(BytecodeOptimizationLevel::One);
+        config.allocator_backend = MemoryAllocatorBackend::Jemalloc;
+        config.packed_resources = vec![
+            PyembedPackedResourcesSource::MemoryIncludeBytes(PathBuf::from("packed-resources")),
+            PyembedPackedResourcesSource::MemoryMappedPath(PathBuf::from(
+                "$ORIGIN/packed-resources",
+            )),
+        ];
+        config.terminfo_resolution = TerminfoResolution::Static("/usr/share/terminfo".into());
+
+        let json = config.to_json()?;
+        assert_contains(&json, "\"interpreter_config\": {")?;
+        assert_contains(&json, "\"allocator\": \"malloc\"")?;
+        assert_contains(&json, "\"allocator_backend\": \"jemalloc\"")?;
+        assert_contains(&json, "\"type\": \"memory_mapped_path\"")?;
+        assert_contains(&json, "\"path\": \"$ORIGIN/packed-resources\"")?;
+        assert_contains(
+            &json,
+            "\"terminfo_resolution\": \"static:/usr/share/terminfo\"",
+        )?;
+        assert!(!json.contains("memory_include_bytes"));
+
+        // In-memory packed resources aren't serialized.
+        config.packed_resources.remove(0);
+        assert_eq!(PyembedPythonInterpreterConfig::from_json(&json)?, config);
+
+        Ok(())
+    }
+
+    /// JSON shared with `pyembed`'s tests, which verify it can be loaded.
+    const PYEMBED_CONFIG_JSON: &str = include_str!("pyembed-config.json");
+
+    #[test]
+    fn test_json_pyembed_compatible() -> Result<()> {
+        let mut config = PyembedPythonInterpreterConfig::default();
+        config.config.allocator = Some(Allocator::Malloc);
+        config.config.module_search_paths = Some(vec![PathBuf::from("$ORIGIN/lib")]);
+        config.config.optimization_level = Some(BytecodeOptimizationLevel::Two);
+        config.config.check_hash_pycs_mode = Some(CheckHashPycsMode::Always);
+        config.allocator_backend = MemoryAllocatorBackend::Rust;
+        config.filesystem_importer = true;
+        config.packed_resources = vec![
+            PyembedPackedResourcesSource::MemoryIncludeBytes(PathBuf::from("packed-resources")),
+            PyembedPackedResourcesSource::MemoryMappedPath(PathBuf::from(
+                "$ORIGIN/packed-resources",
+            )),
+        ];
+        config.sys_frozen = true;
+        config.terminfo_resolution = TerminfoResolution::Static("/usr/share/terminfo".into());
+        config.tcl_library = Some(PathBuf::from("$ORIGIN/tcl8.6"));
+        config.write_modules_directory_env = Some("MODULES_DIR".to_string());
+
+        assert_eq!(config.to_json()?, PYEMBED_CONFIG_JSON.trim_end());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_toml_round_trip() -> Result<()> {
+        let mut config = PyembedPythonInterpreterConfig::default();
+        config.config.check_hash_pycs_mode = Some(CheckHashPycsMode::Always);
+        config.config.x_options = Some(vec!["dev".to_string()]);
+        config.tcl_library = Some(PathBuf::from("$ORIGIN/tcl8.6"));
+        config.packed_resources = vec![PyembedPackedResourcesSource::MemoryMappedPath(
+            PathBuf::from("$ORIGIN/packed-resources"),
+        )];
+
+        let toml = config.to_toml()?;
+        assert_contains(&toml, "[interpreter_config]")?;
+        assert_contains(&toml, "check_hash_pycs_mode = 'always'")?;
+
+        assert_eq!(PyembedPythonInterpreterConfig::from_toml(&toml)?, config);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_partial() -> Result<()> {
+        let config = PyembedPythonInterpreterConfig::from_toml(
+            "sys_frozen = true\n[interpreter_config]\nprofile = \"python\"\n",
+        )?;
+
+        assert!(config.sys_frozen);
+        assert_eq!(config.config.profile, PythonInterpreterProfile::Python);
+        assert_eq!(config.config.configure_locale, None);
+        assert!(config.oxidized_importer);
+
+        assert!(
+            PyembedPythonInterpreterConfig::from_json("{\"allocator_backend\": \"bogus\"}")
+                .is_err()
+        );
+
+        Ok(())
+    }
+
     // TODO enable once CI has a linkable Python.
     #[test]
     #[ignore]
@@ -84,7 +84,7 @@ pub struct AppleSdkInfo {
 }
 
 /// Describes a generic Python distribution.
-pub trait PythonDistribution {
+pub trait PythonDistribution: Send + Sync {
     /// Clone self into a Box'ed trait object.
     fn clone_trait(&self) -> Arc<dyn PythonDistribution>;
 
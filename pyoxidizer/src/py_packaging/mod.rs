@@ -9,12 +9,14 @@ This module tree holds functionality that is centered around Python.
 */
 
 pub mod binary;
+pub mod compiler_shim;
 pub mod config;
 pub mod distribution;
 pub mod distutils;
 pub mod filtering;
 pub mod libpython;
 pub mod packaging_tool;
+pub mod pep517;
 pub mod resource;
 pub mod standalone_builder;
 pub mod standalone_distribution;
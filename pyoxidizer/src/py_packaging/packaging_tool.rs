@@ -8,15 +8,24 @@ Interaction with Python packaging tools (pip, setuptools, etc).
 
 use {
     super::{
-        binary::LibpythonLinkMode, distribution::PythonDistribution,
-        distutils::read_built_extensions, standalone_distribution::resolve_python_paths,
+        binary::LibpythonLinkMode,
+        compiler_shim::{install_compiler_shims, match_captured_links, read_captured_links},
+        distribution::PythonDistribution,
+        distutils::read_built_extensions,
+        pep517::build_wheel,
+        standalone_distribution::resolve_python_paths,
     },
-    anyhow::{anyhow, Result},
+    crate::lockfile::ArtifactDigests,
+    anyhow::{anyhow, Context, Result},
     duct::cmd,
     python_packaging::{
-        filesystem_scanning::find_python_resources, policy::PythonPackagingPolicy,
-        resource::PythonResource, wheel::WheelArchive,
+        filesystem_scanning::find_python_resources,
+        policy::PythonPackagingPolicy,
+        resource::{PythonExtensionModule, PythonResource},
+        sdist::SourceDistributionArchive,
+        wheel::WheelArchive,
     },
+    sha2::Digest,
     slog::warn,
     std::{
         collections::{hash_map::RandomState, HashMap},
@@ -26,12 +35,109 @@ use {
     },
 };
 
+/// Compute the hex SHA-256 of a file.
+fn sha256_path(path: &Path) -> Result<String> {
+    let mut hasher = sha2::Sha256::new();
+    let mut fh = std::fs::File::open(path)
+        .with_context(|| format!("opening {} for hashing", path.display()))?;
+    std::io::copy(&mut fh, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Resolve the name and version of the package in a wheel.
+fn wheel_package_key(wheel: &WheelArchive) -> Result<(String, String)> {
+    let metadata = wheel.metadata()?;
+
+    Ok((
+        metadata
+            .name()
+            .ok_or_else(|| anyhow!("wheel METADATA does not define Name"))?
+            .to_string(),
+        metadata
+            .version()
+            .ok_or_else(|| anyhow!("wheel METADATA does not define Version"))?
+            .to_string(),
+    ))
+}
+
+/// Install compiler wrappers capturing extension module builds, if needed.
+///
+/// Wrappers are only installed when extension modules need to be statically
+/// linked. `env` is updated to use them. Returns the directory holding state
+/// recorded by the wrappers.
+fn prepare_compiler_shims(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    libpython_link_mode: LibpythonLinkMode,
+    dest_dir: &Path,
+    env: &mut HashMap<String, String>,
+) -> Result<Option<PathBuf>> {
+    if libpython_link_mode != LibpythonLinkMode::Static || cfg!(windows) {
+        return Ok(None);
+    }
+
+    let shim_env = install_compiler_shims(logger, dist.python_exe_path(), dest_dir, env)
+        .context("installing compiler wrappers")?;
+    let state_dir = shim_env
+        .get(super::compiler_shim::STATE_DIR_ENV)
+        .map(PathBuf::from);
+    env.extend(shim_env);
+
+    Ok(state_dir)
+}
+
+/// Replace extension modules with what compiler wrappers captured when linking them.
+///
+/// Extension modules for which `keep` returns true are left as is. They are
+/// still matched against captured links so those links aren't attributed to
+/// other extension modules.
+fn apply_captured_links(
+    logger: &slog::Logger,
+    compiler_shim_state_dir: &Path,
+    resources: &mut [PythonResource],
+    keep: impl Fn(&PythonExtensionModule) -> bool,
+) -> Result<()> {
+    let captured_links = read_captured_links(compiler_shim_state_dir)?;
+
+    let extension_modules = resources.iter().filter_map(|r| match r {
+        PythonResource::ExtensionModule(e) => Some(e.as_ref()),
+        _ => None,
+    });
+    let links = match_captured_links(&captured_links, extension_modules)?;
+
+    for resource in resources.iter_mut() {
+        if let PythonResource::ExtensionModule(e) = resource {
+            if keep(e) {
+                continue;
+            }
+
+            if let Some(link) = links.get(&e.name) {
+                warn!(
+                    logger,
+                    "using captured object files for extension module {}", e.name
+                );
+                let ext = link.to_extension_module(e);
+                *resource = PythonResource::from(ext);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /// Find resources installed as part of a packaging operation.
+///
+/// `state_dir` and `compiler_shim_state_dir` hold state recorded by the
+/// modified distutils and compiler wrappers, respectively. If present,
+/// extension modules are augmented with the captured build state.
 pub fn find_resources<'a>(
+    logger: &slog::Logger,
     dist: &dyn PythonDistribution,
     policy: &PythonPackagingPolicy,
     path: &Path,
     state_dir: Option<PathBuf>,
+    compiler_shim_state_dir: Option<PathBuf>,
 ) -> Result<Vec<PythonResource<'a>>> {
     let mut res = Vec::new();
 
@@ -44,13 +150,16 @@ pub fn find_resources<'a>(
         HashMap::new()
     };
 
-    for r in find_python_resources(
+    let mut iter = find_python_resources(
         &path,
         dist.cache_tag(),
         &dist.python_module_suffixes()?,
         policy.file_scanner_emit_files(),
         policy.file_scanner_classify_files(),
-    ) {
+    );
+    iter.set_follow_external_paths(policy.file_scanner_follow_external_paths());
+
+    for r in &mut iter {
         let r = r?.to_memory()?;
 
         match r {
@@ -68,6 +177,18 @@ pub fn find_resources<'a>(
         }
     }
 
+    for warning in iter.warnings() {
+        warn!(logger, "{}", warning);
+    }
+
+    // Otherwise fall back to what compiler wrappers captured for the shared
+    // library.
+    if let Some(p) = compiler_shim_state_dir {
+        apply_captured_links(logger, &p, &mut res, |e| {
+            built_extensions.contains_key(&e.name)
+        })?;
+    }
+
     Ok(res)
 }
 
@@ -82,6 +203,8 @@ pub fn find_resources<'a>(
 /// distribution targeting a different platform allows this command to
 /// resolve resources for a non-native platform, which enables it to be used
 /// when cross-compiling.
+///
+/// Also returns the SHA-256 of every downloaded wheel.
 pub fn pip_download<'a>(
     logger: &slog::Logger,
     host_dist: &dyn PythonDistribution,
@@ -89,7 +212,7 @@ pub fn pip_download<'a>(
     policy: &PythonPackagingPolicy,
     verbose: bool,
     args: &[String],
-) -> Result<Vec<PythonResource<'a>>> {
+) -> Result<(Vec<PythonResource<'a>>, ArtifactDigests)> {
     let temp_dir = tempfile::Builder::new()
         .prefix("pyoxidizer-pip-download")
         .tempdir()?;
@@ -166,9 +289,11 @@ pub fn pip_download<'a>(
 
     // TODO there's probably a way to do this using iterators.
     let mut res = Vec::new();
+    let mut artifacts = ArtifactDigests::new();
 
     for path in &files {
         let wheel = WheelArchive::from_path(path)?;
+        artifacts.insert(wheel_package_key(&wheel)?, sha256_path(path)?);
 
         res.extend(wheel.python_resources(
             taget_dist.cache_tag(),
@@ -178,7 +303,7 @@ pub fn pip_download<'a>(
         )?);
     }
 
-    Ok(res)
+    Ok((res, artifacts))
 }
 
 /// Run `pip install` and return found resources.
@@ -206,6 +331,14 @@ pub fn pip_install<'a, S: BuildHasher>(
         env.insert(key.clone(), value.clone());
     }
 
+    let compiler_shim_state_dir = prepare_compiler_shims(
+        logger,
+        dist,
+        libpython_link_mode,
+        &temp_dir.path().join("compiler-shims"),
+        &mut env,
+    )?;
+
     let target_dir = temp_dir.path().join("install");
 
     warn!(logger, "pip installing to {}", target_dir.display());
@@ -248,18 +381,33 @@ pub fn pip_install<'a, S: BuildHasher>(
 
     let state_dir = env.get("PYOXIDIZER_DISTUTILS_STATE_DIR").map(PathBuf::from);
 
-    find_resources(dist, policy, &target_dir, state_dir)
+    find_resources(
+        logger,
+        dist,
+        policy,
+        &target_dir,
+        state_dir,
+        compiler_shim_state_dir,
+    )
 }
 
 /// Discover Python resources from a populated virtualenv directory.
 pub fn read_virtualenv<'a>(
+    logger: &slog::Logger,
     dist: &dyn PythonDistribution,
     policy: &PythonPackagingPolicy,
     path: &Path,
 ) -> Result<Vec<PythonResource<'a>>> {
     let python_paths = resolve_python_paths(path, &dist.python_major_minor_version());
 
-    find_resources(dist, policy, &python_paths.site_packages, None)
+    find_resources(
+        logger,
+        dist,
+        policy,
+        &python_paths.site_packages,
+        None,
+        None,
+    )
 }
 
 /// Run `setup.py install` against a path and return found resources.
@@ -306,6 +454,14 @@ pub fn setup_py_install<'a, S: BuildHasher>(
         envs.insert(key.clone(), value.clone());
     }
 
+    let compiler_shim_state_dir = prepare_compiler_shims(
+        logger,
+        dist,
+        libpython_link_mode,
+        &temp_dir.path().join("compiler-shims"),
+        &mut envs,
+    )?;
+
     warn!(
         logger,
         "python setup.py installing {} to {}",
@@ -352,7 +508,97 @@ pub fn setup_py_install<'a, S: BuildHasher>(
         "scanning {} for resources",
         python_paths.site_packages.display()
     );
-    find_resources(dist, policy, &python_paths.site_packages, state_dir)
+    find_resources(
+        logger,
+        dist,
+        policy,
+        &python_paths.site_packages,
+        state_dir,
+        compiler_shim_state_dir,
+    )
+}
+
+/// Build a wheel from a source distribution and return found resources.
+///
+/// `path` can be a source distribution archive or a directory holding a
+/// source tree. The wheel is built with the PEP 517 backend declared by the
+/// project. Build requirements are installed from `wheelhouse`, a directory
+/// of wheels.
+///
+/// When statically linking, compiler wrappers capture how extension modules
+/// in the wheel were built, like `pip_install()` does.
+///
+/// When `path` is an archive, its SHA-256 is also returned.
+pub fn sdist_install<'a>(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    policy: &PythonPackagingPolicy,
+    libpython_link_mode: LibpythonLinkMode,
+    path: &Path,
+    wheelhouse: &Path,
+    verbose: bool,
+) -> Result<(Vec<PythonResource<'a>>, ArtifactDigests)> {
+    let temp_dir = tempfile::Builder::new()
+        .prefix("pyoxidizer-sdist-install")
+        .tempdir()?;
+
+    let source_dir = if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        let sdist = SourceDistributionArchive::from_path(path)?;
+        warn!(
+            logger,
+            "extracting source distribution {} {}",
+            sdist.name()?,
+            sdist.version()?
+        );
+
+        let source_dir = temp_dir.path().join(sdist.root_directory());
+        sdist.extract_to(&source_dir)?;
+
+        source_dir
+    };
+
+    let mut envs: HashMap<String, String> = std::env::vars().collect();
+    let compiler_shim_state_dir = prepare_compiler_shims(
+        logger,
+        dist,
+        libpython_link_mode,
+        &temp_dir.path().join("compiler-shims"),
+        &mut envs,
+    )?;
+
+    let wheel_path = build_wheel(
+        logger,
+        dist,
+        &source_dir,
+        wheelhouse,
+        &temp_dir.path().join("build"),
+        verbose,
+        &envs,
+    )
+    .with_context(|| format!("building wheel from {}", path.display()))?;
+
+    let wheel = WheelArchive::from_path(&wheel_path)?;
+
+    // The source distribution is the artifact consumers of the package fetch.
+    let mut artifacts = ArtifactDigests::new();
+    if path.is_file() {
+        artifacts.insert(wheel_package_key(&wheel)?, sha256_path(path)?);
+    }
+
+    let mut resources = wheel.python_resources(
+        dist.cache_tag(),
+        &dist.python_module_suffixes()?,
+        policy.file_scanner_emit_files(),
+        policy.file_scanner_classify_files(),
+    )?;
+
+    if let Some(p) = compiler_shim_state_dir {
+        apply_captured_links(logger, &p, &mut resources, |_| false)?;
+    }
+
+    Ok((resources, artifacts))
 }
 
 #[cfg(test)]
@@ -437,7 +683,7 @@ mod tests {
 
             let policy = target_dist.create_packaging_policy()?;
 
-            let resources = pip_download(
+            let (resources, artifacts) = pip_download(
                 &logger,
                 &*host_dist,
                 &*target_dist,
@@ -447,6 +693,7 @@ mod tests {
             )?;
 
             assert!(!resources.is_empty());
+            assert!(artifacts.contains_key(&("zstandard".to_string(), "0.15.2".to_string())));
             let zstandard_resources = resources
                 .iter()
                 .filter(|r| r.is_in_packages(&["zstandard".to_string()]))
@@ -552,7 +799,7 @@ mod tests {
                 continue;
             }
 
-            let resources = res?;
+            let (resources, _) = res?;
 
             assert!(!resources.is_empty());
 
@@ -0,0 +1,475 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+A PEP 517 build frontend.
+
+This builds wheels from Python source trees by invoking the build backend
+declared in `pyproject.toml`, using a Python distribution's interpreter. Build
+requirements are installed into an isolated directory from a local directory
+of wheels (a *wheelhouse*). The package index is never consulted.
+*/
+
+use {
+    super::distribution::PythonDistribution,
+    anyhow::{anyhow, Context, Result},
+    duct::cmd,
+    serde::Deserialize,
+    slog::warn,
+    std::{
+        collections::HashMap,
+        io::{BufRead, BufReader},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Python code invoking a PEP 517 hook.
+///
+/// Arguments are the hook name, the build environment directory, the backend
+/// specification, the wheel output directory and a path to write the JSON
+/// encoded hook result to, followed by `backend-path` entries.
+const HOOK_SCRIPT: &str = r#"
+import importlib, json, os, site, sys
+
+hook, build_env, backend_spec, wheel_dir, result_path = sys.argv[1:6]
+backend_paths = [os.path.abspath(p) for p in sys.argv[6:]]
+
+# addsitedir() processes .pth files but appends to sys.path. Move what it
+# added in front of the interpreter's own paths so build requirements take
+# precedence over packages installed in the distribution.
+original_path = list(sys.path)
+site.addsitedir(build_env)
+build_env_paths = [p for p in sys.path if p not in original_path]
+sys.path[:] = backend_paths + build_env_paths + original_path
+
+module_name, _, object_path = backend_spec.partition(":")
+backend = importlib.import_module(module_name)
+for attr in filter(None, object_path.split(".")):
+    backend = getattr(backend, attr)
+
+if hook == "get_requires_for_build_wheel":
+    f = getattr(backend, hook, None)
+    result = list(f({})) if f else []
+elif hook == "build_wheel":
+    result = backend.build_wheel(wheel_dir, {})
+else:
+    raise Exception("unknown hook: %s" % hook)
+
+with open(result_path, "w") as fh:
+    json.dump(result, fh)
+"#;
+
+/// Backend used by projects not declaring one, as mandated by PEP 517.
+const LEGACY_BUILD_BACKEND: &str = "setuptools.build_meta:__legacy__";
+
+#[derive(Debug, Deserialize)]
+struct PyProjectToml {
+    #[serde(rename = "build-system")]
+    build_system: Option<BuildSystemTable>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BuildSystemTable {
+    requires: Option<Vec<String>>,
+    #[serde(rename = "build-backend")]
+    build_backend: Option<String>,
+    #[serde(rename = "backend-path", default)]
+    backend_path: Vec<String>,
+}
+
+/// The `[build-system]` of a Python project.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BuildSystem {
+    /// Requirements that must be installed to run the build backend.
+    pub requires: Vec<String>,
+
+    /// The build backend, as a `module:object` string.
+    pub build_backend: String,
+
+    /// Paths relative to the source tree to import the backend from.
+    pub backend_path: Vec<String>,
+}
+
+impl BuildSystem {
+    /// Resolve the build system from `pyproject.toml` content.
+    ///
+    /// Projects without `pyproject.toml` or a `[build-system]` table use
+    /// the setuptools legacy backend.
+    pub fn from_pyproject_toml(data: Option<&[u8]>) -> Result<Self> {
+        let table = if let Some(data) = data {
+            let pyproject: PyProjectToml =
+                toml::from_slice(data).context("parsing pyproject.toml")?;
+            pyproject.build_system
+        } else {
+            None
+        };
+
+        if let Some(table) = table {
+            Ok(Self {
+                requires: table
+                    .requires
+                    .ok_or_else(|| anyhow!("pyproject.toml [build-system] table lacks requires"))?,
+                build_backend: table
+                    .build_backend
+                    .unwrap_or_else(|| LEGACY_BUILD_BACKEND.to_string()),
+                backend_path: table.backend_path,
+            })
+        } else {
+            Ok(Self {
+                requires: vec!["setuptools>=40.8.0".to_string(), "wheel".to_string()],
+                build_backend: LEGACY_BUILD_BACKEND.to_string(),
+                backend_path: vec![],
+            })
+        }
+    }
+
+    /// Resolve the build system of a source tree on the filesystem.
+    pub fn from_source_dir(path: &Path) -> Result<Self> {
+        let pyproject_path = path.join("pyproject.toml");
+
+        if pyproject_path.exists() {
+            let data = std::fs::read(&pyproject_path)
+                .with_context(|| format!("reading {}", pyproject_path.display()))?;
+            Self::from_pyproject_toml(Some(&data))
+        } else {
+            Self::from_pyproject_toml(None)
+        }
+    }
+}
+
+/// Log the output of a command and wait for it to finish.
+fn run_logged(logger: &slog::Logger, command: duct::Expression, what: &str) -> Result<()> {
+    let command = command.stderr_to_stdout().unchecked().reader()?;
+    {
+        let reader = BufReader::new(&command);
+        for line in reader.lines() {
+            warn!(logger, "{}", line?);
+        }
+    }
+
+    let output = command
+        .try_wait()?
+        .ok_or_else(|| anyhow!("unable to wait on command"))?;
+    if !output.status.success() {
+        return Err(anyhow!("error running {}", what));
+    }
+
+    Ok(())
+}
+
+/// Install requirements into a build environment directory from a wheelhouse.
+fn install_requirements(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    wheelhouse: &Path,
+    env_dir: &Path,
+    requirements: &[String],
+    verbose: bool,
+) -> Result<()> {
+    if requirements.is_empty() {
+        return Ok(());
+    }
+
+    warn!(
+        logger,
+        "installing build requirements from {}: {:?}",
+        wheelhouse.display(),
+        requirements
+    );
+
+    let mut args = vec![
+        "-I".to_string(),
+        "-m".to_string(),
+        "pip".to_string(),
+        "--disable-pip-version-check".to_string(),
+    ];
+
+    if verbose {
+        args.push("--verbose".to_string());
+    }
+
+    args.extend(vec![
+        "install".to_string(),
+        "--no-index".to_string(),
+        "--find-links".to_string(),
+        format!("{}", wheelhouse.display()),
+        "--only-binary=:all:".to_string(),
+        "--target".to_string(),
+        format!("{}", env_dir.display()),
+    ]);
+    args.extend(requirements.iter().cloned());
+
+    run_logged(
+        logger,
+        cmd(dist.python_exe_path(), &args),
+        "pip to install build requirements",
+    )
+}
+
+/// Invoke a PEP 517 hook and return its JSON decoded result.
+///
+/// `python_exe` is the interpreter to run the build backend with.
+#[allow(clippy::too_many_arguments)]
+fn run_hook(
+    logger: &slog::Logger,
+    python_exe: &Path,
+    source_dir: &Path,
+    build_system: &BuildSystem,
+    env_dir: &Path,
+    wheel_dir: &Path,
+    hook: &str,
+    envs: &HashMap<String, String>,
+) -> Result<serde_json::Value> {
+    let result_path = env_dir.with_file_name(format!("{}.json", hook));
+
+    let mut args = vec![
+        "-I".to_string(),
+        "-c".to_string(),
+        HOOK_SCRIPT.to_string(),
+        hook.to_string(),
+        format!("{}", env_dir.display()),
+        build_system.build_backend.clone(),
+        format!("{}", wheel_dir.display()),
+        format!("{}", result_path.display()),
+    ];
+    args.extend(build_system.backend_path.iter().cloned());
+
+    warn!(logger, "calling {} on {}", hook, build_system.build_backend);
+    run_logged(
+        logger,
+        cmd(python_exe, &args).dir(source_dir).full_env(envs),
+        &format!("build backend hook {}", hook),
+    )?;
+
+    let data =
+        std::fs::read(&result_path).with_context(|| format!("reading result of {}", hook))?;
+
+    serde_json::from_slice(&data).with_context(|| format!("parsing result of {}", hook))
+}
+
+/// Build a wheel from a Python source tree.
+///
+/// `work_dir` is a directory used to hold the isolated build environment
+/// and the built wheel. `envs` is the environment the build backend runs
+/// with. Returns the path to the built wheel, which is inside `work_dir`.
+pub fn build_wheel(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    source_dir: &Path,
+    wheelhouse: &Path,
+    work_dir: &Path,
+    verbose: bool,
+    envs: &HashMap<String, String>,
+) -> Result<PathBuf> {
+    let build_system = BuildSystem::from_source_dir(source_dir)?;
+
+    warn!(
+        logger,
+        "building wheel from {} using {}",
+        source_dir.display(),
+        build_system.build_backend
+    );
+
+    dist.ensure_pip(logger)?;
+
+    let env_dir = work_dir.join("build-env");
+    let wheel_dir = work_dir.join("wheels");
+    std::fs::create_dir_all(&env_dir)?;
+    std::fs::create_dir_all(&wheel_dir)?;
+
+    install_requirements(
+        logger,
+        dist,
+        wheelhouse,
+        &env_dir,
+        &build_system.requires,
+        verbose,
+    )?;
+
+    let extra_requires = run_hook(
+        logger,
+        dist.python_exe_path(),
+        source_dir,
+        &build_system,
+        &env_dir,
+        &wheel_dir,
+        "get_requires_for_build_wheel",
+        envs,
+    )?;
+    let extra_requires: Vec<String> = serde_json::from_value(extra_requires)
+        .context("get_requires_for_build_wheel did not return a list of strings")?;
+    install_requirements(logger, dist, wheelhouse, &env_dir, &extra_requires, verbose)?;
+
+    let basename = run_hook(
+        logger,
+        dist.python_exe_path(),
+        source_dir,
+        &build_system,
+        &env_dir,
+        &wheel_dir,
+        "build_wheel",
+        envs,
+    )?;
+    let basename = basename
+        .as_str()
+        .ok_or_else(|| anyhow!("build_wheel did not return a filename"))?;
+
+    let wheel_path = wheel_dir.join(basename);
+    if !wheel_path.exists() {
+        return Err(anyhow!(
+            "build backend did not produce {}",
+            wheel_path.display()
+        ));
+    }
+
+    Ok(wheel_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::testutil::get_logger};
+
+    /// A build backend reporting the `sys.path` it sees.
+    const STUB_BACKEND: &str = r#"
+import os, sys
+
+def build_wheel(wheel_directory, config_settings=None):
+    import env_module, pth_module
+
+    return {
+        "sys_path": sys.path,
+        "env_module": os.path.dirname(env_module.__file__),
+        "pth_module": os.path.dirname(pth_module.__file__),
+        "wheel_directory": wheel_directory,
+    }
+"#;
+
+    #[test]
+    fn test_build_system_parsing() -> Result<()> {
+        assert_eq!(
+            BuildSystem::from_pyproject_toml(None)?.build_backend,
+            LEGACY_BUILD_BACKEND
+        );
+        assert_eq!(
+            BuildSystem::from_pyproject_toml(Some(b"[tool.black]\nline-length = 88\n"))?.requires,
+            vec!["setuptools>=40.8.0".to_string(), "wheel".to_string()]
+        );
+
+        assert_eq!(
+            BuildSystem::from_pyproject_toml(Some(
+                b"[build-system]\nrequires = [\"flit_core >=3.2,<4\"]\nbuild-backend = \"flit_core.buildapi\"\n"
+            ))?,
+            BuildSystem {
+                requires: vec!["flit_core >=3.2,<4".to_string()],
+                build_backend: "flit_core.buildapi".to_string(),
+                backend_path: vec![],
+            }
+        );
+
+        assert_eq!(
+            BuildSystem::from_pyproject_toml(Some(
+                b"[build-system]\nrequires = []\nbuild-backend = \"backend\"\nbackend-path = [\"_build\"]\n"
+            ))?
+            .backend_path,
+            vec!["_build".to_string()]
+        );
+
+        assert!(BuildSystem::from_pyproject_toml(Some(b"[build-system]\n")).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_hook_stub_backend() -> Result<()> {
+        let python_exe = match which::which("python3") {
+            Ok(p) => p,
+            Err(_) => {
+                eprintln!("python3 not found; skipping test");
+                return Ok(());
+            }
+        };
+
+        let logger = get_logger()?;
+        let td = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+        let source_dir = td.path().join("source");
+        let backend_dir = source_dir.join("_build");
+        let env_dir = td.path().join("build-env");
+        let pth_dir = td.path().join("pth-target");
+        let wheel_dir = td.path().join("wheels");
+
+        for dir in &[&backend_dir, &env_dir, &pth_dir, &wheel_dir] {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(backend_dir.join("stub_backend.py"), STUB_BACKEND)?;
+        std::fs::write(env_dir.join("env_module.py"), "")?;
+        std::fs::write(
+            env_dir.join("extra.pth"),
+            format!("{}\n", pth_dir.display()),
+        )?;
+        std::fs::write(pth_dir.join("pth_module.py"), "")?;
+
+        let build_system = BuildSystem {
+            requires: vec![],
+            build_backend: "stub_backend".to_string(),
+            backend_path: vec!["_build".to_string()],
+        };
+        let envs = std::env::vars().collect::<HashMap<_, _>>();
+
+        // A backend without the optional hook requires nothing extra.
+        assert_eq!(
+            run_hook(
+                &logger,
+                &python_exe,
+                &source_dir,
+                &build_system,
+                &env_dir,
+                &wheel_dir,
+                "get_requires_for_build_wheel",
+                &envs,
+            )?,
+            serde_json::json!([])
+        );
+
+        let result = run_hook(
+            &logger,
+            &python_exe,
+            &source_dir,
+            &build_system,
+            &env_dir,
+            &wheel_dir,
+            "build_wheel",
+            &envs,
+        )?;
+
+        let sys_path = result["sys_path"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| PathBuf::from(p.as_str().unwrap()))
+            .collect::<Vec<_>>();
+
+        // backend-path entries come first, followed by the build
+        // environment and the paths its .pth files add.
+        assert_eq!(
+            &sys_path[0..3],
+            &[backend_dir, env_dir.clone(), pth_dir.clone()]
+        );
+        assert_eq!(
+            PathBuf::from(result["env_module"].as_str().unwrap()),
+            env_dir
+        );
+        assert_eq!(
+            PathBuf::from(result["pth_module"].as_str().unwrap()),
+            pth_dir
+        );
+        assert_eq!(
+            PathBuf::from(result["wheel_directory"].as_str().unwrap()),
+            wheel_dir
+        );
+
+        Ok(())
+    }
+}
@@ -14,28 +14,33 @@ use {
         filtering::{filter_btreemap, resolve_resource_names_from_files},
         libpython::link_libpython,
         packaging_tool::{
-            find_resources, pip_download, pip_install, read_virtualenv, setup_py_install,
+            find_resources, pip_download, pip_install, read_virtualenv, sdist_install,
+            setup_py_install,
         },
         standalone_distribution::StandaloneDistribution,
     },
+    crate::lockfile::ArtifactDigests,
     anyhow::{anyhow, Context, Result},
     once_cell::sync::Lazy,
     python_packaging::{
-        bytecode::BytecodeCompiler,
+        bytecode::BytecodeCompilerPool,
+        bytecode_cache::{BytecodeCache, CachingBytecodeCompiler},
         interpreter::MemoryAllocatorBackend,
         libpython::LibPythonBuildContext,
         licensing::derive_package_license_infos,
-        location::AbstractResourceLocation,
+        location::{AbstractResourceLocation, ConcreteResourceLocation},
+        module_graph::{ModuleReachability, INTERPRETER_STARTUP_MODULES},
         policy::PythonPackagingPolicy,
         resource::{
             PythonExtensionModule, PythonModuleSource, PythonPackageDistributionResource,
             PythonPackageResource, PythonResource,
         },
         resource_collection::{
-            PrePackagedResource, PythonResourceAddCollectionContext, PythonResourceCollector,
+            CompiledResourcesCollection, PrePackagedResource, PythonResourceAddCollectionContext,
+            PythonResourceCollector, ResourceDecision,
         },
     },
-    slog::warn,
+    slog::{info, warn},
     std::{
         collections::{BTreeMap, BTreeSet, HashMap},
         convert::TryInto,
@@ -43,7 +48,7 @@ use {
         sync::Arc,
     },
     tugger_file_manifest::{File, FileData, FileEntry, FileManifest},
-    tugger_licensing::{ComponentFlavor, LicensedComponent},
+    tugger_licensing::{ComponentFlavor, LicensedComponent, LicensedComponents},
     tugger_windows::{find_visual_cpp_redistributable, VcRedistributablePlatform},
 };
 
@@ -121,6 +126,12 @@ pub struct StandalonePythonExecutableBuilder {
 
     /// Describes how Windows runtime DLLs should be handled during builds.
     windows_runtime_dlls_mode: WindowsRuntimeDllsMode,
+
+    /// Cache of compiled Python bytecode.
+    bytecode_cache: Option<BytecodeCache>,
+
+    /// Number of Python processes to compile bytecode with.
+    bytecode_compile_workers: usize,
 }
 
 impl StandalonePythonExecutableBuilder {
@@ -221,8 +232,14 @@ impl StandalonePythonExecutableBuilder {
             windows_subsystem: "console".to_string(),
             tcl_files_path: None,
             windows_runtime_dlls_mode: WindowsRuntimeDllsMode::WhenPresent,
+            bytecode_cache: None,
+            bytecode_compile_workers: BytecodeCompilerPool::default_worker_count(),
         });
 
+        builder
+            .resources_collector
+            .set_resource_placement_rules(packaging_policy.resource_placement_rules().to_vec());
+
         builder.add_distribution_core_state()?;
 
         Ok(builder)
@@ -419,6 +436,64 @@ impl StandalonePythonExecutableBuilder {
 
         Ok(manifest)
     }
+
+    /// Derive the add context for a resource from the packaging policy.
+    ///
+    /// The callback, if present, can modify the context. Decisions influencing
+    /// the context are recorded in the resources collector.
+    fn derive_add_context(
+        &mut self,
+        resource: &PythonResource,
+        callback: &Option<ResourceAddCollectionContextCallback>,
+    ) -> Result<PythonResourceAddCollectionContext> {
+        let mut add_context = self
+            .packaging_policy
+            .derive_add_collection_context(resource);
+
+        let decisions = if let Some(callback) = callback {
+            callback(&self.packaging_policy, resource, &mut add_context)?
+        } else {
+            vec![ResourceDecision {
+                actor: "policy".to_string(),
+                message: add_context.describe(),
+            }]
+        };
+
+        self.record_resource_decisions(&resource.full_name(), decisions);
+
+        Ok(add_context)
+    }
+
+    /// Compile resources in a collector, using the bytecode cache if configured.
+    fn compile_collector_resources<'a>(
+        &self,
+        logger: &slog::Logger,
+        collector: &'a PythonResourceCollector,
+    ) -> Result<CompiledResourcesCollection<'a>> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let mut compiler = BytecodeCompilerPool::new(
+            self.host_python_exe_path(),
+            temp_dir.path(),
+            self.bytecode_compile_workers,
+        )?;
+
+        if let Some(cache) = &self.bytecode_cache {
+            let mut compiler = CachingBytecodeCompiler::new(&mut compiler, cache);
+            let compiled = collector.compile_resources(&mut compiler)?;
+            compiler.prune()?;
+
+            info!(
+                logger,
+                "{} bytecode modules obtained from cache; {} compiled",
+                compiler.hits(),
+                compiler.misses()
+            );
+
+            Ok(compiled)
+        } else {
+            collector.compile_resources(&mut compiler)
+        }
+    }
 }
 
 impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
@@ -464,6 +539,16 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         &self.packaging_policy
     }
 
+    fn set_python_packaging_policy(&mut self, policy: PythonPackagingPolicy) {
+        self.resources_collector
+            .set_resource_placement_rules(policy.resource_placement_rules().to_vec());
+        self.packaging_policy = policy;
+    }
+
+    fn interpreter_config(&self) -> &PyembedPythonInterpreterConfig {
+        &self.config
+    }
+
     fn host_python_exe_path(&self) -> &Path {
         &self.host_python_exe
     }
@@ -522,6 +607,22 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         self.resources_load_mode = load_mode;
     }
 
+    fn bytecode_cache(&self) -> Option<&BytecodeCache> {
+        self.bytecode_cache.as_ref()
+    }
+
+    fn set_bytecode_cache(&mut self, cache: Option<BytecodeCache>) {
+        self.bytecode_cache = cache;
+    }
+
+    fn bytecode_compile_workers(&self) -> usize {
+        self.bytecode_compile_workers
+    }
+
+    fn set_bytecode_compile_workers(&mut self, workers: usize) {
+        self.bytecode_compile_workers = workers;
+    }
+
     fn iter_resources<'a>(
         &'a self,
     ) -> Box<dyn Iterator<Item = (&'a String, &'a PrePackagedResource)> + 'a> {
@@ -545,8 +646,8 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         logger: &slog::Logger,
         verbose: bool,
         args: &[String],
-    ) -> Result<Vec<PythonResource>> {
-        let resources = pip_download(
+    ) -> Result<(Vec<PythonResource>, ArtifactDigests)> {
+        let (resources, artifacts) = pip_download(
             logger,
             &*self.host_distribution,
             &*self.target_distribution,
@@ -559,7 +660,7 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         self.index_package_license_info_from_resources(&resources)
             .context("indexing package license metadata")?;
 
-        Ok(resources)
+        Ok((resources, artifacts))
     }
 
     fn pip_install(
@@ -588,15 +689,17 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
 
     fn read_package_root(
         &mut self,
-        _logger: &slog::Logger,
+        logger: &slog::Logger,
         path: &Path,
         packages: &[String],
     ) -> Result<Vec<PythonResource>> {
         let resources = find_resources(
+            logger,
             &*self.target_distribution,
             self.python_packaging_policy(),
             path,
             None,
+            None,
         )
         .context("finding resources")?
         .iter()
@@ -617,10 +720,11 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
 
     fn read_virtualenv(
         &mut self,
-        _logger: &slog::Logger,
+        logger: &slog::Logger,
         path: &Path,
     ) -> Result<Vec<PythonResource>> {
         let resources = read_virtualenv(
+            logger,
             &*self.target_distribution,
             self.python_packaging_policy(),
             path,
@@ -659,6 +763,30 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         Ok(resources)
     }
 
+    fn sdist_install(
+        &mut self,
+        logger: &slog::Logger,
+        path: &Path,
+        wheelhouse: &Path,
+        verbose: bool,
+    ) -> Result<(Vec<PythonResource>, ArtifactDigests)> {
+        let (resources, artifacts) = sdist_install(
+            logger,
+            &*self.target_distribution,
+            self.python_packaging_policy(),
+            self.link_mode,
+            path,
+            wheelhouse,
+            verbose,
+        )
+        .context("building source distribution")?;
+
+        self.index_package_license_info_from_resources(&resources)
+            .context("indexing package license metadata")?;
+
+        Ok((resources, artifacts))
+    }
+
     fn add_distribution_resources(
         &mut self,
         callback: Option<ResourceAddCollectionContextCallback>,
@@ -672,19 +800,25 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         self.resources_collector
             .add_licensed_component(core_component.clone())?;
 
-        // TODO consolidate into loop below.
-        for ext in self.packaging_policy.resolve_python_extension_modules(
+        let extensions = self.packaging_policy.resolve_python_extension_modules(
             self.target_distribution.extension_modules.values(),
             &self.target_triple,
-        )? {
-            let resource = (&ext).into();
-            let mut add_context = self
-                .packaging_policy
-                .derive_add_collection_context(&resource);
-
-            if let Some(callback) = &callback {
-                callback(&self.packaging_policy, &resource, &mut add_context)?;
+        )?;
+
+        for name in self.target_distribution.extension_modules.keys() {
+            if !extensions.iter().any(|ext| &ext.name == name) {
+                self.resources_collector.record_resource_decision(
+                    name,
+                    "policy",
+                    "extension module excluded by the extension module filter or because it is broken on this target",
+                );
             }
+        }
+
+        // TODO consolidate into loop below.
+        for ext in extensions {
+            let resource = (&ext).into();
+            let add_context = self.derive_add_context(&resource, &callback)?;
 
             if let Some(component) = &ext.license {
                 self.resources_collector
@@ -710,13 +844,7 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
                 PythonResource::File(_) => false,
             })
         {
-            let mut add_context = self
-                .packaging_policy
-                .derive_add_collection_context(&resource);
-
-            if let Some(callback) = &callback {
-                callback(&self.packaging_policy, resource, &mut add_context)?;
-            }
+            let add_context = self.derive_add_context(resource, &callback)?;
 
             match resource {
                 PythonResource::ModuleSource(source) => {
@@ -728,6 +856,7 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
                             .to_string(),
                     )?;
                     component.set_flavor(ComponentFlavor::PythonPackage);
+                    component.set_version(&self.target_distribution.version);
 
                     self.resources_collector.add_licensed_component(component)?;
                     self.add_python_module_source(source, Some(add_context))?;
@@ -747,10 +876,10 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         module: &PythonModuleSource,
         add_context: Option<PythonResourceAddCollectionContext>,
     ) -> Result<()> {
-        let add_context = add_context.unwrap_or_else(|| {
-            self.packaging_policy
-                .derive_add_collection_context(&module.into())
-        });
+        let add_context = match add_context {
+            Some(add_context) => add_context,
+            None => self.derive_add_context(&module.into(), &None)?,
+        };
 
         self.resources_collector
             .add_python_module_source_with_context(module, &add_context)
@@ -761,10 +890,10 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         resource: &PythonPackageResource,
         add_context: Option<PythonResourceAddCollectionContext>,
     ) -> Result<()> {
-        let add_context = add_context.unwrap_or_else(|| {
-            self.packaging_policy
-                .derive_add_collection_context(&resource.into())
-        });
+        let add_context = match add_context {
+            Some(add_context) => add_context,
+            None => self.derive_add_context(&resource.into(), &None)?,
+        };
 
         self.resources_collector
             .add_python_package_resource_with_context(resource, &add_context)
@@ -775,10 +904,10 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         resource: &PythonPackageDistributionResource,
         add_context: Option<PythonResourceAddCollectionContext>,
     ) -> Result<()> {
-        let add_context = add_context.unwrap_or_else(|| {
-            self.packaging_policy
-                .derive_add_collection_context(&resource.into())
-        });
+        let add_context = match add_context {
+            Some(add_context) => add_context,
+            None => self.derive_add_context(&resource.into(), &None)?,
+        };
 
         self.resources_collector
             .add_python_package_distribution_resource_with_context(resource, &add_context)
@@ -789,10 +918,10 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         extension_module: &PythonExtensionModule,
         add_context: Option<PythonResourceAddCollectionContext>,
     ) -> Result<()> {
-        let add_context = add_context.unwrap_or_else(|| {
-            self.packaging_policy
-                .derive_add_collection_context(&extension_module.into())
-        });
+        let add_context = match add_context {
+            Some(add_context) => add_context,
+            None => self.derive_add_context(&extension_module.into(), &None)?,
+        };
 
         if let Some(mut build_context) = self
             .resources_collector
@@ -829,10 +958,10 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         file: &File,
         add_context: Option<PythonResourceAddCollectionContext>,
     ) -> Result<()> {
-        let add_context = add_context.unwrap_or_else(|| {
-            self.packaging_policy
-                .derive_add_collection_context(&file.into())
-        });
+        let add_context = match add_context {
+            Some(add_context) => add_context,
+            None => self.derive_add_context(&file.into(), &None)?,
+        };
 
         self.resources_collector
             .add_file_data_with_context(file, &add_context)
@@ -848,21 +977,115 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
 
         warn!(logger, "filtering module entries");
 
+        let mut removed = vec![];
         self.resources_collector.filter_resources_mut(|resource| {
             if !resource_names.contains(&resource.name) {
                 warn!(logger, "removing {}", resource.name);
+                removed.push(resource.name.clone());
                 false
             } else {
                 true
             }
         })?;
 
+        for name in removed {
+            self.resources_collector.record_resource_decision(
+                &name,
+                "resource filter",
+                "removed because name is not in filter files",
+            );
+        }
+
         warn!(logger, "filtering embedded extension modules");
         filter_btreemap(logger, &mut self.extension_build_contexts, &resource_names);
 
         Ok(())
     }
 
+    fn resolve_module_reachability(
+        &self,
+        entry_modules: &[String],
+        allow_modules: &[String],
+    ) -> Result<ModuleReachability> {
+        let entry_modules = if entry_modules.is_empty() {
+            vec![self.config.config.run_module.clone().ok_or_else(|| {
+                anyhow!("entry modules must be specified when run_module is not defined")
+            })?]
+        } else {
+            entry_modules.to_vec()
+        };
+
+        let allow_patterns = INTERPRETER_STARTUP_MODULES
+            .iter()
+            .copied()
+            .chain(allow_modules.iter().map(|x| x.as_str()))
+            .collect::<Vec<_>>();
+
+        let graph = self
+            .resources_collector
+            .module_dependency_graph()
+            .context("resolving module dependency graph")?;
+
+        Ok(graph.reachability(entry_modules.iter().map(|x| x.as_str()), &allow_patterns))
+    }
+
+    fn filter_unreachable_resources(
+        &mut self,
+        logger: &slog::Logger,
+        entry_modules: &[String],
+        allow_modules: &[String],
+    ) -> Result<ModuleReachability> {
+        let reachability = self.resolve_module_reachability(entry_modules, allow_modules)?;
+
+        for name in &reachability.opaque {
+            warn!(
+                logger,
+                "imports of {} could not be analyzed; modules it imports may be removed", name
+            );
+        }
+
+        warn!(logger, "filtering modules unreachable from entry modules");
+
+        let removed = self
+            .resources_collector
+            .remove_unreachable_resources(&reachability)?;
+
+        for name in removed {
+            warn!(logger, "removing {}", name);
+            self.resources_collector.record_resource_decision(
+                &name,
+                "import analysis",
+                "removed because it is not reachable from entry modules",
+            );
+        }
+
+        Ok(reachability)
+    }
+
+    fn licensed_components(&self) -> Result<LicensedComponents> {
+        let mut components = self.resources_collector.licensed_components();
+
+        for component in self
+            .core_build_context
+            .licensed_components
+            .iter_components()
+        {
+            components.add_component(component.clone());
+        }
+
+        Ok(components)
+    }
+
+    fn record_resource_decisions(&mut self, name: &str, decisions: Vec<ResourceDecision>) {
+        for decision in decisions {
+            self.resources_collector.record_resource_decision(
+                name,
+                decision.actor,
+                decision.message,
+            );
+        }
+    }
+
     fn requires_jemalloc(&self) -> bool {
         self.config.allocator_backend == MemoryAllocatorBackend::Jemalloc
     }
@@ -898,6 +1121,70 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
             );
         }
 
+        // Packages flagged as depending on the filesystem are reported. Since
+        // the analysis is heuristic, they are only moved out of memory if this
+        // was explicitly requested. Scanning is only performed when a location
+        // to move them to is configured. We operate on a copy of the collector
+        // since this method doesn't mutate the builder.
+        let relocated_collector = if let Some(ConcreteResourceLocation::RelativePath(prefix)) = self
+            .packaging_policy
+            .filesystem_dependent_packages_location()
+        {
+            let filesystem_dependencies =
+                self.resources_collector.find_filesystem_dependencies()?;
+            let mut collector = if self
+                .packaging_policy
+                .relocate_filesystem_dependent_packages()
+                && !filesystem_dependencies.is_empty()
+            {
+                Some(self.resources_collector.clone())
+            } else {
+                None
+            };
+
+            for (package, modules) in &filesystem_dependencies {
+                let example = modules
+                    .iter()
+                    .find_map(|(module, findings)| {
+                        findings.first().map(|finding| match finding.line {
+                            Some(line) => format!("{}:{} {}", module, line, finding.reason),
+                            None => format!("{} {}", module, finding.reason),
+                        })
+                    })
+                    .unwrap_or_default();
+
+                let action = if let Some(collector) = &mut collector {
+                    let relocated = collector
+                        .relocate_package_to_filesystem(package, prefix)
+                        .with_context(|| {
+                            format!("relocating package {} to the filesystem", package)
+                        })?;
+
+                    format!(
+                        "relocated {} resources to filesystem-relative:{}",
+                        relocated.len(),
+                        prefix
+                    )
+                } else {
+                    "not relocated because relocate_filesystem_dependent_packages is false"
+                        .to_string()
+                };
+
+                warn!(
+                    logger,
+                    "package {} may require a filesystem ({} findings in {} modules, e.g. {}); {}",
+                    package,
+                    modules.values().map(|x| x.len()).sum::<usize>(),
+                    modules.len(),
+                    example,
+                    action
+                );
+            }
+
+            collector
+        } else {
+            None
+        };
         let license_report = self.resources_collector.generate_license_report()?;
         if license_report.no_license_packages.is_empty() {
             warn!(logger, "All Python packages have license metadata");
@@ -932,10 +1219,28 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
             warn!(logger, "license: {}; packages: {:?}", license, packages);
         }
 
-        let compiled_resources = {
-            let temp_dir = tempfile::TempDir::new()?;
-            let mut compiler = BytecodeCompiler::new(self.host_python_exe_path(), temp_dir.path())?;
-            self.resources_collector.compile_resources(&mut compiler)?
+        // Decisions come from the collector whose resources are packaged.
+        let resource_decisions = relocated_collector
+            .as_ref()
+            .unwrap_or(&self.resources_collector)
+            .resource_decision_log()
+            .clone();
+
+        let compiled_resources = if let Some(collector) = &relocated_collector {
+            // The relocated collector is local to this function, so compiled
+            // resources can't borrow from it.
+            let compiled = self.compile_collector_resources(logger, collector)?;
+
+            CompiledResourcesCollection {
+                resources: compiled
+                    .resources
+                    .iter()
+                    .map(|(name, resource)| (name.clone(), resource.to_owned()))
+                    .collect(),
+                extra_files: compiled.extra_files,
+            }
+        } else {
+            self.compile_collector_resources(logger, &self.resources_collector)?
         };
 
         let mut pending_resources = vec![];
@@ -1037,6 +1342,7 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
             extra_files,
             host_triple: self.host_triple.clone(),
             target_triple: self.target_triple.clone(),
+            resource_decisions,
         })
     }
 }
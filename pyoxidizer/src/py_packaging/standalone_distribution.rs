@@ -671,6 +671,7 @@ impl StandaloneDistribution {
                 LicensedComponent::new_spdx(&pi.python_implementation_name, &expression)?;
 
             component.set_flavor(ComponentFlavor::Library);
+            component.set_version(&pi.python_version);
             component.add_license_text(license_text);
 
             core_license.replace(component);
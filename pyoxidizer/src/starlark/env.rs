@@ -3,8 +3,13 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    crate::py_packaging::distribution::DistributionCache,
-    anyhow::{Context, Result},
+    crate::{
+        lockfile::{ArtifactDigests, LockedDistribution, LockedPackage, ProjectLock},
+        py_packaging::distribution::{DistributionCache, PythonDistributionLocation},
+    },
+    anyhow::{anyhow, Context, Result},
+    python_packaging::resource::PythonResource,
+    slog::warn,
     starlark::{
         environment::{Environment, EnvironmentError, TypeValues},
         values::{
@@ -12,16 +17,34 @@ use {
             none::NoneType,
             {Mutable, TypedValue, Value, ValueResult},
         },
+        {
+            starlark_fun, starlark_module, starlark_parse_param_type, starlark_signature,
+            starlark_signature_extraction, starlark_signatures,
+        },
+    },
+    starlark_dialect_build_targets::{
+        fingerprint::TargetInput, get_context_value, record_target_input, EnvironmentContext,
     },
-    starlark_dialect_build_targets::{get_context_value, EnvironmentContext},
     std::{
         collections::HashMap,
         convert::TryFrom,
         path::{Path, PathBuf},
-        sync::Arc,
+        sync::{Arc, Mutex},
     },
 };
 
+/// Environment variables influencing the result of building targets.
+const FINGERPRINT_ENV_VARS: &[&str] = &[
+    "CARGO_BUILD_RUSTFLAGS",
+    "CC",
+    "CFLAGS",
+    "LDFLAGS",
+    "MACOSX_DEPLOYMENT_TARGET",
+    "PYOXIDIZER_SYSTEM_RUST",
+    "RUSTC",
+    "RUSTFLAGS",
+];
+
 /// Holds state for evaluating a Starlark config file.
 #[derive(Debug)]
 pub struct PyOxidizerEnvironmentContext {
@@ -60,7 +83,15 @@ pub struct PyOxidizerEnvironmentContext {
     pub distribution_cache: Arc<DistributionCache>,
 
     /// Extra variables to inject into Starlark environment.
-    extra_vars: HashMap<String, Option<String>>,
+    pub extra_vars: HashMap<String, Option<String>>,
+
+    /// Names of resources whose packaging decisions should be explained.
+    pub explain_resources: Vec<String>,
+
+    /// Python distributions and packages used by the build.
+    ///
+    /// Used to produce a lockfile.
+    locked_inputs: Mutex<ProjectLock>,
 }
 
 impl PyOxidizerEnvironmentContext {
@@ -76,6 +107,7 @@ impl PyOxidizerEnvironmentContext {
         build_opt_level: &str,
         distribution_cache: Option<Arc<DistributionCache>>,
         extra_vars: HashMap<String, Option<String>>,
+        explain_resources: Vec<String>,
     ) -> Result<PyOxidizerEnvironmentContext> {
         let parent = config_path
             .parent()
@@ -105,6 +137,8 @@ impl PyOxidizerEnvironmentContext {
             build_opt_level: build_opt_level.to_string(),
             distribution_cache,
             extra_vars,
+            explain_resources,
+            locked_inputs: Mutex::new(ProjectLock::default()),
         })
     }
 
@@ -125,6 +159,88 @@ impl PyOxidizerEnvironmentContext {
         Ok(context.build_path().to_path_buf())
     }
 
+    /// Record a Python distribution used by the build.
+    ///
+    /// The distribution is recorded in the lockfile and as an input of the
+    /// target being resolved.
+    pub fn record_distribution(
+        &self,
+        type_values: &TypeValues,
+        location: &PythonDistributionLocation,
+    ) -> Result<()> {
+        let distribution = LockedDistribution::from(location);
+
+        record_target_input(
+            type_values,
+            TargetInput::Value {
+                name: format!("Python distribution {}", distribution.location()),
+                value: distribution.sha256.clone(),
+            },
+        )
+        .map_err(|e| anyhow!("recording build input: {:?}", e))?;
+
+        self.locked_inputs
+            .lock()
+            .map_err(|e| anyhow!("unable to lock build inputs: {}", e))?
+            .distributions
+            .insert(distribution);
+
+        Ok(())
+    }
+
+    /// Record Python packages collected by the build.
+    ///
+    /// `source` describes how the packages were obtained and `artifacts`
+    /// holds digests of the wheels or source distributions they came from.
+    /// Packages are recorded in the lockfile and as inputs of the target
+    /// being resolved.
+    pub fn record_packages(
+        &self,
+        type_values: &TypeValues,
+        source: &str,
+        resources: &[PythonResource],
+        artifacts: &ArtifactDigests,
+    ) -> Result<()> {
+        let packages = LockedPackage::from_resources(source, resources.iter(), artifacts)?;
+
+        for package in &packages {
+            record_target_input(
+                type_values,
+                TargetInput::Value {
+                    name: format!("Python package {} from {}", package.name, package.source),
+                    value: format!("{} {}", package.version, package.sha256),
+                },
+            )
+            .map_err(|e| anyhow!("recording build input: {:?}", e))?;
+        }
+
+        self.locked_inputs
+            .lock()
+            .map_err(|e| anyhow!("unable to lock build inputs: {}", e))?
+            .packages
+            .extend(packages);
+
+        Ok(())
+    }
+
+    /// Obtain a lockfile describing the inputs to the build.
+    pub fn project_lock(&self) -> Result<ProjectLock> {
+        let inputs = self
+            .locked_inputs
+            .lock()
+            .map_err(|e| anyhow!("unable to lock build inputs: {}", e))?;
+
+        let mut lock = ProjectLock::new(
+            &self.env.rust_toolchain_version()?,
+            self.env.pyembed_crate_version(),
+            &self.build_target_triple,
+        );
+        lock.distributions = inputs.distributions.clone();
+        lock.packages = inputs.packages.clone();
+
+        Ok(lock)
+    }
+
     pub fn python_distributions_path(&self) -> Result<PathBuf, ValueError> {
         Ok(self.env.python_distributions_dir())
     }
@@ -194,7 +310,9 @@ pub fn register_starlark_dialect(
     super::python_distribution::python_distribution_module(env, type_values);
     super::python_embedded_resources::python_embedded_resources_module(env, type_values);
     super::python_executable::python_executable_env(env, type_values);
+    super::python_interpreter_config::python_interpreter_config_module(env, type_values);
     super::python_packaging_policy::python_packaging_policy_module(env, type_values);
+    pyoxidizer_module(env, type_values);
 
     Ok(())
 }
@@ -205,6 +323,8 @@ pub fn populate_environment(
     context: PyOxidizerEnvironmentContext,
     resolve_targets: Option<Vec<String>>,
     build_script_mode: bool,
+    incremental: bool,
+    force_build: bool,
 ) -> Result<(), EnvironmentError> {
     let mut build_targets_context = EnvironmentContext::new(context.logger(), context.cwd.clone());
 
@@ -213,6 +333,44 @@ pub fn populate_environment(
     }
 
     build_targets_context.build_script_mode = build_script_mode;
+    build_targets_context.set_force_build(force_build);
+
+    // The Rust toolchain influences every built binary. Without knowing it,
+    // we can't tell whether previous outputs are still valid.
+    if incremental {
+        match context.env().rust_toolchain_version() {
+            Ok(version) => {
+                build_targets_context.set_incremental(true);
+                build_targets_context.record_input(TargetInput::Value {
+                    name: "Rust toolchain".to_string(),
+                    value: version,
+                });
+            }
+            Err(e) => {
+                warn!(
+                    context.logger(),
+                    "unable to resolve Rust toolchain version; building all targets: {:?}", e
+                );
+            }
+        }
+    }
+
+    // Starlark values are derived from the config file and the inputs below.
+    // Other inputs are recorded as they are read during evaluation.
+    build_targets_context.record_input(TargetInput::Path(context.config_path.clone()));
+    build_targets_context.record_input(TargetInput::Value {
+        name: "PyOxidizer version".to_string(),
+        value: crate::environment::PYOXIDIZER_VERSION.to_string(),
+    });
+    for (k, v) in context.extra_vars.iter() {
+        build_targets_context.record_input(TargetInput::Value {
+            name: format!("variable {}", k),
+            value: format!("{:?}", v),
+        });
+    }
+    for name in FINGERPRINT_ENV_VARS {
+        build_targets_context.record_input(TargetInput::EnvVar(name.to_string()));
+    }
 
     build_targets_context.set_target_build_path_prefix(Some(
         PathBuf::from(&context.build_target_triple).join(if context.build_release {
@@ -238,7 +396,11 @@ pub fn populate_environment(
         .expect("error inserting variable; this should not happen");
     }
 
-    env.set("VARS", Value::try_from(vars.get_content().clone()).unwrap())?;
+    // VARS is read-only so all code observes the values passed to the build.
+    let mut vars = Value::try_from(vars.get_content().clone()).unwrap();
+    vars.freeze();
+
+    env.set("VARS", vars)?;
     env.set("CWD", Value::from(context.cwd.display().to_string()))?;
     env.set(
         "CONFIG_PATH",
@@ -262,9 +424,43 @@ pub fn populate_environment(
     Ok(())
 }
 
+/// require_var(name)
+fn starlark_require_var(type_values: &TypeValues, name: String) -> ValueResult {
+    let pyoxidizer_context_value = get_context(type_values)?;
+    let pyoxidizer_context = pyoxidizer_context_value
+        .downcast_ref::<PyOxidizerEnvironmentContext>()
+        .ok_or(ValueError::IncorrectParameterType)?;
+
+    match pyoxidizer_context.extra_vars.get(&name) {
+        Some(Some(value)) => Ok(Value::from(value.as_str())),
+        Some(None) => Err(ValueError::from(RuntimeError {
+            code: "PYOXIDIZER_VARS",
+            message: format!(
+                "variable {} is declared but has no value; is the environment variable passed to --var-env set?",
+                name
+            ),
+            label: "require_var()".to_string(),
+        })),
+        None => Err(ValueError::from(RuntimeError {
+            code: "PYOXIDIZER_VARS",
+            message: format!(
+                "variable {} is not defined; define it with --var {}=<value> or in a --var-file",
+                name, name
+            ),
+            label: "require_var()".to_string(),
+        })),
+    }
+}
+
+starlark_module! { pyoxidizer_module =>
+    require_var(env env, name: String) {
+        starlark_require_var(env, name)
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
-    use super::super::testutil::*;
+    use {super::super::testutil::*, anyhow::Result, std::collections::HashMap};
 
     #[test]
     fn test_cwd() {
@@ -283,4 +479,33 @@ pub mod tests {
     fn test_print() {
         starlark_ok("print('hello, world')");
     }
+
+    #[test]
+    fn test_vars() -> Result<()> {
+        let mut extra_vars = HashMap::new();
+        extra_vars.insert("channel".to_string(), Some("beta".to_string()));
+        extra_vars.insert("unset".to_string(), None);
+
+        let mut eval = test_evaluation_context_builder()?
+            .extra_vars(extra_vars)
+            .into_context()?;
+
+        eval_assert(&mut eval, "VARS['channel'] == 'beta'")?;
+        eval_assert(&mut eval, "require_var('channel') == 'beta'")?;
+
+        let err = eval.eval("VARS['channel'] = 'stable'").unwrap_err();
+        assert!(err.to_string().contains("Frozen"), "{}", err);
+
+        let err = eval.eval("require_var('unset')").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("variable unset is declared but has no value"));
+
+        let err = eval.eval("require_var('missing')").unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("variable missing is not defined; define it with --var missing=<value>"));
+
+        Ok(())
+    }
 }
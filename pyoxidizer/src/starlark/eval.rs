@@ -4,10 +4,15 @@
 
 use {
     crate::{
+        build_metadata::{BuildMetadata, BUILD_METADATA_NAME},
+        lockfile::ProjectLock,
         py_packaging::distribution::DistributionCache,
-        starlark::env::{
-            populate_environment, register_starlark_dialect, PyOxidizerContext,
-            PyOxidizerEnvironmentContext,
+        starlark::{
+            env::{
+                populate_environment, register_starlark_dialect, PyOxidizerContext,
+                PyOxidizerEnvironmentContext,
+            },
+            loader::ProjectFileLoader,
         },
     },
     anyhow::{anyhow, Result},
@@ -23,7 +28,8 @@ use {
         },
     },
     starlark_dialect_build_targets::{
-        build_target, run_target, EnvironmentContext, ResolvedTarget,
+        build_target, build_targets, graph::TargetGraph, resolve_target_value, run_target,
+        EnvironmentContext, ResolvedTarget,
     },
     std::{
         collections::HashMap,
@@ -43,9 +49,12 @@ pub struct EvaluationContextBuilder {
     verbose: bool,
     resolve_targets: Option<Vec<String>>,
     build_script_mode: bool,
+    incremental: bool,
+    force_build: bool,
     build_opt_level: String,
     distribution_cache: Option<Arc<DistributionCache>>,
     extra_vars: HashMap<String, Option<String>>,
+    explain_resources: Vec<String>,
 }
 
 impl EvaluationContextBuilder {
@@ -64,9 +73,12 @@ impl EvaluationContextBuilder {
             verbose: false,
             resolve_targets: None,
             build_script_mode: false,
+            incremental: false,
+            force_build: false,
             build_opt_level: "0".to_string(),
             distribution_cache: None,
             extra_vars: HashMap::new(),
+            explain_resources: vec![],
         }
     }
 
@@ -121,6 +133,18 @@ impl EvaluationContextBuilder {
         self
     }
 
+    /// Whether to skip building targets whose inputs and outputs are unchanged.
+    pub fn incremental(mut self, value: bool) -> Self {
+        self.incremental = value;
+        self
+    }
+
+    /// Whether to build targets even if they are up to date.
+    pub fn force_build(mut self, value: bool) -> Self {
+        self.force_build = value;
+        self
+    }
+
     pub fn distribution_cache(mut self, cache: Arc<DistributionCache>) -> Self {
         self.distribution_cache = Some(cache);
         self
@@ -130,6 +154,12 @@ impl EvaluationContextBuilder {
         self.extra_vars = extra_vars;
         self
     }
+
+    /// Names of resources whose packaging decisions should be explained when building.
+    pub fn explain_resources(mut self, names: Vec<String>) -> Self {
+        self.explain_resources = names;
+        self
+    }
 }
 
 /// Interface to evaluate Starlark configuration files.
@@ -143,6 +173,9 @@ pub struct EvaluationContext {
     parent_env: Environment,
     child_env: Environment,
     type_values: TypeValues,
+
+    /// Directory `//` paths in `load()` statements are resolved against.
+    project_path: PathBuf,
 }
 
 impl TryFrom<EvaluationContextBuilder> for EvaluationContext {
@@ -166,8 +199,11 @@ impl EvaluationContext {
             &builder.build_opt_level,
             builder.distribution_cache,
             builder.extra_vars,
+            builder.explain_resources,
         )?;
 
+        let project_path = context.cwd.clone();
+
         let (mut parent_env, mut type_values) = starlark::stdlib::global_environment();
 
         register_starlark_dialect(&mut parent_env, &mut type_values)
@@ -183,6 +219,8 @@ impl EvaluationContext {
             context,
             builder.resolve_targets,
             builder.build_script_mode,
+            builder.incremental,
+            builder.force_build,
         )
         .map_err(|e| anyhow!("error populating Starlark environment: {:?}", e))?;
 
@@ -190,6 +228,7 @@ impl EvaluationContext {
             parent_env,
             child_env,
             type_values,
+            project_path,
         })
     }
 
@@ -206,15 +245,16 @@ impl EvaluationContext {
     /// Evaluate a Starlark configuration file, returning a Diagnostic on error.
     pub fn evaluate_file_diagnostic(&mut self, config_path: &Path) -> Result<(), Diagnostic> {
         let map = Arc::new(Mutex::new(CodeMap::new()));
-        let file_loader_env = self.parent_env.clone();
+        let loader = ProjectFileLoader::new(&map, self.parent_env.clone(), &self.project_path)
+            .file(config_path);
 
-        starlark::eval::simple::eval_file(
+        starlark::eval::eval_file(
             &map,
             &config_path.display().to_string(),
             Dialect::Bzl,
             &mut self.child_env,
             &self.type_values,
-            file_loader_env,
+            &loader,
         )
         .map_err(|e| {
             if let Ok(raw_context) = self.build_targets_context_value() {
@@ -250,16 +290,17 @@ impl EvaluationContext {
         path: &str,
         code: &str,
     ) -> Result<Value, Diagnostic> {
-        let file_loader_env = self.child_env.clone();
+        let loader = ProjectFileLoader::new(map, self.parent_env.clone(), &self.project_path)
+            .code(path, code);
 
-        starlark::eval::simple::eval(
+        starlark::eval::eval(
             &map,
             path,
             code,
             Dialect::Bzl,
             &mut self.child_env,
             &self.type_values,
-            file_loader_env,
+            &loader,
         )
     }
 
@@ -316,6 +357,62 @@ impl EvaluationContext {
         pyoxidizer_context.build_path(&self.type_values)
     }
 
+    /// Obtain a lockfile describing the inputs to the build.
+    ///
+    /// Only inputs used by evaluation so far are recorded.
+    pub fn project_lock(&self) -> Result<ProjectLock> {
+        let pyoxidizer_context_value = self
+            .pyoxidizer_context_value()
+            .map_err(|e| anyhow!("resolving PyOxidizer context: {:?}", e))?;
+        let pyoxidizer_context = pyoxidizer_context_value
+            .downcast_ref::<PyOxidizerEnvironmentContext>()
+            .ok_or_else(|| anyhow!("context has incorrect type"))?;
+
+        pyoxidizer_context.project_lock()
+    }
+
+    /// Obtain metadata describing a build of the given targets.
+    ///
+    /// `built` holds the result of building each entry in `targets`.
+    pub fn build_metadata(
+        &self,
+        targets: &[String],
+        built: &[ResolvedTarget],
+    ) -> Result<BuildMetadata> {
+        let pyoxidizer_context_value = self
+            .pyoxidizer_context_value()
+            .map_err(|e| anyhow!("resolving PyOxidizer context: {:?}", e))?;
+        let pyoxidizer_context = pyoxidizer_context_value
+            .downcast_ref::<PyOxidizerEnvironmentContext>()
+            .ok_or_else(|| anyhow!("context has incorrect type"))?;
+
+        Ok(BuildMetadata {
+            pyoxidizer_version: crate::environment::PYOXIDIZER_VERSION.to_string(),
+            target_triple: pyoxidizer_context.build_target_triple.clone(),
+            release: pyoxidizer_context.build_release,
+            vars: pyoxidizer_context
+                .extra_vars
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            targets: targets
+                .iter()
+                .zip(built.iter())
+                .map(|(name, target)| (name.clone(), target.output_path.clone()))
+                .collect(),
+        })
+    }
+
+    /// Obtain the path build metadata is written to.
+    pub fn build_metadata_path(&self) -> Result<PathBuf> {
+        let context_value = self.build_targets_context_value()?;
+        let context = context_value
+            .downcast_ref::<EnvironmentContext>()
+            .ok_or_else(|| anyhow!("context has incorrect type"))?;
+
+        Ok(context.targets_build_path().join(BUILD_METADATA_NAME))
+    }
+
     pub fn target_build_path(&self, target: &str) -> Result<PathBuf> {
         let context_value = self.build_targets_context_value()?;
         let context = context_value.downcast_ref::<EnvironmentContext>().unwrap();
@@ -345,6 +442,23 @@ impl EvaluationContext {
             .collect::<Vec<_>>())
     }
 
+    /// Obtain the graph of registered targets.
+    pub fn target_graph(&self) -> Result<TargetGraph> {
+        let raw_context = self.build_targets_context_value()?;
+        let context = raw_context
+            .downcast_ref::<EnvironmentContext>()
+            .ok_or_else(|| anyhow!("context has incorrect type"))?;
+
+        Ok(TargetGraph::from(&*context))
+    }
+
+    /// Resolve a named target, returning the value its callable returned.
+    pub fn resolve_target(&mut self, target: &str) -> Result<Value> {
+        let mut call_stack = CallStack::default();
+
+        resolve_target_value(&self.type_values, &mut call_stack, target)
+    }
+
     /// Obtain targets that should be resolved.
     pub fn targets_to_resolve(&self) -> Result<Vec<String>> {
         let raw_context = self.build_targets_context_value()?;
@@ -366,6 +480,23 @@ impl EvaluationContext {
         )
     }
 
+    /// Build multiple resolved targets, running up to `jobs` builds concurrently.
+    pub fn build_resolved_targets(
+        &mut self,
+        targets: &[String],
+        jobs: usize,
+    ) -> Result<Vec<ResolvedTarget>> {
+        let mut call_stack = CallStack::default();
+
+        build_targets(
+            &mut self.child_env,
+            &self.type_values,
+            &mut call_stack,
+            targets,
+            jobs,
+        )
+    }
+
     pub fn run_target(&mut self, target: Option<&str>) -> Result<()> {
         let mut call_stack = CallStack::default();
 
@@ -473,6 +604,20 @@ mod tests {
         let v = vars.get(&Value::from("empty")).unwrap().unwrap();
         assert_eq!(v.get_type(), "NoneType");
 
+        let metadata = context.build_metadata(&[], &[])?;
+        assert_eq!(
+            metadata.vars.get("my_var"),
+            Some(&Some("my_value".to_string()))
+        );
+        assert_eq!(metadata.vars.get("empty"), Some(&None));
+        assert!(!metadata.release);
+        assert_eq!(
+            context.build_metadata_path()?,
+            context
+                .target_build_path("exe")?
+                .with_file_name(BUILD_METADATA_NAME)
+        );
+
         Ok(())
     }
 }
@@ -0,0 +1,516 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Resolution and evaluation of files referenced by `load()` statements.
+
+Paths passed to `load()` can take the following forms:
+
+* `//path/to/file.bzl` is resolved relative to the project directory (the
+  directory containing the main configuration file).
+* `:file.bzl` is resolved relative to the directory of the file containing
+  the `load()` statement.
+* Anything else is treated as a filesystem path. Relative paths are resolved
+  like `:` paths, never against the current working directory.
+
+Each file is evaluated at most once per evaluation context and the values
+it defines are frozen before being made available to the loading file.
+*/
+
+use {
+    codemap::{CodeMap, File, Span},
+    codemap_diagnostic::{Diagnostic, Level, SpanLabel, SpanStyle},
+    starlark::{
+        environment::{Environment, TypeValues},
+        eval::{EvalException, FileLoader},
+        syntax::{
+            dialect::Dialect,
+            lexer::{Lexer, Token},
+        },
+    },
+    starlark_dialect_build_targets::{fingerprint::TargetInput, record_target_input},
+    std::{
+        cell::RefCell,
+        collections::HashMap,
+        path::{Path, PathBuf},
+        rc::Rc,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// Diagnostic code for errors resolving or loading a file.
+const LOAD_ERROR_CODE: &str = "LOAD";
+
+/// A `FileLoader` resolving paths relative to a project directory.
+#[derive(Clone)]
+pub struct ProjectFileLoader {
+    codemap: Arc<Mutex<CodeMap>>,
+
+    /// Environment loaded files are evaluated in a child of.
+    parent_env: Environment,
+
+    /// Root directory for `//` paths.
+    project_path: PathBuf,
+
+    /// File containing the `load()` statements being resolved.
+    ///
+    /// `None` if evaluating code that doesn't come from a file.
+    current_path: Option<PathBuf>,
+
+    /// Name of the code being evaluated, as registered in the codemap.
+    current_name: String,
+
+    /// Source of the code being evaluated, if it doesn't come from a file.
+    current_source: Option<String>,
+
+    /// The code being evaluated, as registered in the codemap by [Self::load_span].
+    ///
+    /// Cached so reporting multiple errors registers the code once.
+    current_file: RefCell<Option<Arc<File>>>,
+
+    /// Environments of files that have been loaded.
+    loaded: Rc<RefCell<HashMap<PathBuf, Environment>>>,
+
+    /// Stack of files currently being evaluated.
+    ///
+    /// Used for cycle detection.
+    loading: Rc<RefCell<Vec<PathBuf>>>,
+}
+
+impl ProjectFileLoader {
+    /// Construct a new instance for evaluating code in a project directory.
+    pub fn new(
+        codemap: &Arc<Mutex<CodeMap>>,
+        parent_env: Environment,
+        project_path: impl AsRef<Path>,
+    ) -> Self {
+        let project_path = project_path.as_ref();
+
+        Self {
+            codemap: codemap.clone(),
+            parent_env,
+            project_path: project_path
+                .canonicalize()
+                .unwrap_or_else(|_| project_path.to_path_buf()),
+            current_path: None,
+            current_name: "<no_file>".to_string(),
+            current_source: None,
+            current_file: RefCell::new(None),
+            loaded: Rc::new(RefCell::new(HashMap::new())),
+            loading: Rc::new(RefCell::new(vec![])),
+        }
+    }
+
+    /// Set the file being evaluated.
+    pub fn file(mut self, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        let resolved = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+
+        self.current_name = path.display().to_string();
+        self.current_path = Some(resolved.clone());
+        self.current_source = None;
+        self.current_file = RefCell::new(None);
+        self.loading.borrow_mut().push(resolved);
+
+        self
+    }
+
+    /// Set the code being evaluated, for code that doesn't come from a file.
+    pub fn code(mut self, name: &str, source: &str) -> Self {
+        self.current_name = name.to_string();
+        self.current_path = None;
+        self.current_source = Some(source.to_string());
+        self.current_file = RefCell::new(None);
+
+        self
+    }
+
+    /// Obtain a loader for evaluating a loaded file.
+    ///
+    /// The returned instance shares state with this one.
+    fn for_loaded_file(&self, path: &Path) -> Self {
+        Self {
+            current_path: Some(path.to_path_buf()),
+            current_name: path.display().to_string(),
+            current_source: None,
+            current_file: RefCell::new(None),
+            ..self.clone()
+        }
+    }
+
+    /// Resolve a path passed to `load()` to a filesystem path.
+    pub fn resolve_path(&self, path: &str) -> Result<PathBuf, String> {
+        let (candidate, within_project) = if let Some(relative) = path.strip_prefix("//") {
+            (
+                Self::join_relative(&self.project_path, path, relative)?,
+                true,
+            )
+        } else if let Some(relative) = path.strip_prefix(':') {
+            (
+                Self::join_relative(self.current_dir(), path, relative)?,
+                false,
+            )
+        } else {
+            (self.current_dir().join(path), false)
+        };
+
+        let resolved = candidate
+            .canonicalize()
+            .map_err(|e| format!("unable to load {}: {}", candidate.display(), e))?;
+
+        if within_project && !resolved.starts_with(&self.project_path) {
+            return Err(format!(
+                "{} resolves to {}, which is outside the project directory {}",
+                path,
+                resolved.display(),
+                self.project_path.display()
+            ));
+        }
+
+        Ok(resolved)
+    }
+
+    /// Directory relative paths are resolved against.
+    ///
+    /// This is the directory of the file being evaluated or the project
+    /// directory for code not coming from a file.
+    fn current_dir(&self) -> &Path {
+        match &self.current_path {
+            Some(current) => current.parent().unwrap_or(&self.project_path),
+            None => &self.project_path,
+        }
+    }
+
+    fn join_relative(dir: &Path, path: &str, relative: &str) -> Result<PathBuf, String> {
+        if relative.is_empty() || Path::new(relative).is_absolute() {
+            Err(format!("{} does not refer to a relative file path", path))
+        } else {
+            Ok(dir.join(relative))
+        }
+    }
+
+    /// Find the span of the path string in the `load()` statement for `path`.
+    fn load_span(&self, path: &str) -> Option<Span> {
+        let file = self.current_file()?;
+
+        let mut tokens = Lexer::new(file.source())
+            .filter_map(|item| item.ok())
+            .peekable();
+
+        while let Some((_, token, _)) = tokens.next() {
+            if token != Token::Load {
+                continue;
+            }
+
+            if !matches!(tokens.next(), Some((_, Token::OpeningParenthesis, _))) {
+                continue;
+            }
+
+            if let Some((begin, Token::StringLiteral(value), end)) = tokens.peek() {
+                if value == path {
+                    return Some(file.span.subspan(*begin, *end));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Obtain the code being evaluated as registered in the codemap.
+    fn current_file(&self) -> Option<Arc<File>> {
+        if let Some(file) = &*self.current_file.borrow() {
+            return Some(file.clone());
+        }
+
+        let source = match (&self.current_source, &self.current_path) {
+            (Some(source), _) => source.clone(),
+            (None, Some(current)) => std::fs::read_to_string(current).ok()?,
+            (None, None) => return None,
+        };
+
+        let file = self
+            .codemap
+            .lock()
+            .unwrap()
+            .add_file(self.current_name.clone(), source);
+        self.current_file.replace(Some(file.clone()));
+
+        Some(file)
+    }
+
+    /// Construct an error for a failure to load `path`.
+    fn load_error(&self, path: &str, message: String) -> EvalException {
+        EvalException::DiagnosedError(Diagnostic {
+            level: Level::Error,
+            message,
+            code: Some(LOAD_ERROR_CODE.to_string()),
+            spans: self
+                .load_span(path)
+                .map(|span| SpanLabel {
+                    span,
+                    style: SpanStyle::Primary,
+                    label: Some("loaded here".to_string()),
+                })
+                .into_iter()
+                .collect(),
+        })
+    }
+}
+
+impl FileLoader for ProjectFileLoader {
+    fn load(&self, path: &str, type_values: &TypeValues) -> Result<Environment, EvalException> {
+        let resolved = self
+            .resolve_path(path)
+            .map_err(|message| self.load_error(path, message))?;
+
+        if let Some(env) = self.loaded.borrow().get(&resolved) {
+            return Ok(env.clone());
+        }
+
+        {
+            let mut loading = self.loading.borrow_mut();
+
+            if let Some(index) = loading.iter().position(|p| p == &resolved) {
+                let cycle = loading[index..]
+                    .iter()
+                    .chain(std::iter::once(&resolved))
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+
+                return Err(self.load_error(path, format!("load cycle detected: {}", cycle)));
+            }
+
+            loading.push(resolved.clone());
+        }
+
+        let res = record_target_input(type_values, TargetInput::Path(resolved.clone()))
+            .map_err(|e| self.load_error(path, format!("error recording {}: {:?}", path, e)))
+            .and_then(|_| {
+                let mut env = self.parent_env.child(&resolved.display().to_string());
+
+                starlark::eval::eval_file(
+                    &self.codemap,
+                    &resolved.display().to_string(),
+                    Dialect::Bzl,
+                    &mut env,
+                    type_values,
+                    &self.for_loaded_file(&resolved),
+                )
+                .map(|_| env)
+                .map_err(|mut diagnostic| {
+                    if let Some(span) = self.load_span(path) {
+                        diagnostic.spans.push(SpanLabel {
+                            span,
+                            style: SpanStyle::Secondary,
+                            label: Some("error in file loaded here".to_string()),
+                        });
+                    }
+
+                    EvalException::DiagnosedError(diagnostic)
+                })
+            });
+
+        self.loading.borrow_mut().pop();
+
+        let env = res?;
+        env.freeze();
+
+        self.loaded.borrow_mut().insert(resolved, env.clone());
+
+        Ok(env)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{starlark::eval::EvaluationContextBuilder, testutil::*},
+        anyhow::Result,
+    };
+
+    fn write_files(root: &Path, files: &[(&str, &str)]) -> Result<()> {
+        for (path, content) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, content)?;
+        }
+
+        Ok(())
+    }
+
+    fn evaluate(root: &Path) -> Result<crate::starlark::eval::EvaluationContext> {
+        let env = get_env()?;
+        let logger = get_logger()?;
+        let config_path = root.join("pyoxidizer.bzl");
+
+        let mut context = EvaluationContextBuilder::new(
+            &env,
+            logger,
+            config_path.clone(),
+            env!("HOST").to_string(),
+        )
+        .into_context()?;
+        context.evaluate_file(&config_path)?;
+
+        Ok(context)
+    }
+
+    #[test]
+    fn test_load_project_and_relative() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+
+        write_files(
+            temp_dir.path(),
+            &[
+                (
+                    "pyoxidizer.bzl",
+                    "load('//lib/common.bzl', 'NAMES')\nload(':lib/common.bzl', names = 'NAMES')\nresult = NAMES\nsame = names == NAMES\n",
+                ),
+                (
+                    "lib/common.bzl",
+                    "load(':names.bzl', 'NAME')\nNAMES = [NAME, 'common']\n",
+                ),
+                ("lib/names.bzl", "NAME = 'names'\n"),
+            ],
+        )?;
+
+        let context = evaluate(temp_dir.path())?;
+        assert_eq!(
+            context.get_var("result").unwrap().to_string(),
+            "[\"names\", \"common\"]"
+        );
+        assert!(context.get_var("same").unwrap().to_bool());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_plain_paths() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+
+        // The current directory of the test process is not the project
+        // directory, so relative paths resolving against it would fail.
+        assert_ne!(std::env::current_dir()?, root);
+
+        write_files(
+            &root,
+            &[
+                (
+                    "pyoxidizer.bzl",
+                    &format!(
+                        "load('lib/common.bzl', 'NAMES')\nload('{}', 'ABSOLUTE')\nresult = NAMES + ABSOLUTE\n",
+                        root.join("absolute.bzl").display()
+                    ),
+                ),
+                ("lib/common.bzl", "load('names.bzl', 'NAME')\nNAMES = [NAME]\n"),
+                ("lib/names.bzl", "NAME = 'names'\n"),
+                ("absolute.bzl", "ABSOLUTE = ['absolute']\n"),
+            ],
+        )?;
+
+        let context = evaluate(&root)?;
+        assert_eq!(
+            context.get_var("result").unwrap().to_string(),
+            "[\"names\", \"absolute\"]"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_loaded_values_frozen() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+
+        write_files(
+            temp_dir.path(),
+            &[
+                (
+                    "pyoxidizer.bzl",
+                    "load('//lib.bzl', 'NAMES')\nNAMES.append('foo')\n",
+                ),
+                ("lib.bzl", "NAMES = []\n"),
+            ],
+        )?;
+
+        assert!(evaluate(temp_dir.path()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_errors() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("pyoxidizer-test")
+            .tempdir()?;
+        let root = temp_dir.path().canonicalize()?;
+
+        write_files(
+            &root,
+            &[
+                ("pyoxidizer.bzl", "load('//a.bzl', 'A')\n"),
+                ("a.bzl", "load(':b.bzl', 'B')\nA = B\n"),
+                ("b.bzl", "load('//a.bzl', 'A')\nB = A\n"),
+            ],
+        )?;
+
+        let err = evaluate(&root).err().unwrap().to_string();
+        assert_eq!(
+            err,
+            format!(
+                "load cycle detected: {} -> {} -> {}",
+                root.join("a.bzl").display(),
+                root.join("b.bzl").display(),
+                root.join("a.bzl").display()
+            )
+        );
+
+        write_files(&root, &[("pyoxidizer.bzl", "load('//missing.bzl', 'A')\n")])?;
+        let err = evaluate(&root).err().unwrap().to_string();
+        assert!(err.starts_with(&format!(
+            "unable to load {}:",
+            root.join("missing.bzl").display()
+        )));
+
+        write_files(
+            &root,
+            &[("sub/pyoxidizer.bzl", "load('//../a.bzl', 'A')\n")],
+        )?;
+        let err = evaluate(&root.join("sub")).err().unwrap().to_string();
+        assert!(err.contains("outside the project directory"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_span() -> Result<()> {
+        let map = Arc::new(Mutex::new(CodeMap::new()));
+        let (env, _) = starlark::stdlib::global_environment();
+
+        let loader = ProjectFileLoader::new(&map, env, std::env::current_dir()?)
+            .code("test.bzl", "x = 1\nload('//foo.bzl', 'foo')\n");
+
+        let span = loader.load_span("//foo.bzl").unwrap();
+        {
+            let map = map.lock().unwrap();
+            let file = map.find_file(span.low());
+            assert_eq!(file.name(), "test.bzl");
+            assert_eq!(file.source_slice(span), "'//foo.bzl'");
+        }
+
+        assert!(loader.load_span("//bar.bzl").is_none());
+
+        // The code is only registered in the codemap once.
+        assert_eq!(loader.load_span("//foo.bzl"), Some(span));
+
+        Ok(())
+    }
+}
@@ -19,8 +19,9 @@ use {
     },
     anyhow::{anyhow, Result},
     python_packaging::{
-        policy::PythonPackagingPolicy, resource::PythonResource,
-        resource_collection::PythonResourceAddCollectionContext,
+        policy::PythonPackagingPolicy,
+        resource::PythonResource,
+        resource_collection::{PythonResourceAddCollectionContext, ResourceDecision},
     },
     starlark::{
         environment::TypeValues,
@@ -71,6 +72,16 @@ impl PythonDistributionValue {
 
             let dest_dir = pyoxidizer_context.python_distributions_path()?;
 
+            pyoxidizer_context
+                .record_distribution(type_values, &self.source)
+                .map_err(|e| {
+                    ValueError::from(RuntimeError {
+                        code: "PYOXIDIZER_BUILD",
+                        message: format!("{:?}", e),
+                        label: label.to_string(),
+                    })
+                })?;
+
             self.distribution = Some(
                 pyoxidizer_context
                     .distribution_cache
@@ -310,6 +321,16 @@ impl PythonDistributionValue {
                 })
             })?;
 
+            pyoxidizer_context
+                .record_distribution(type_values, &location)
+                .map_err(|e| {
+                    ValueError::from(RuntimeError {
+                        code: "PYOXIDIZER_BUILD",
+                        message: format!("{:?}", e),
+                        label: "to_python_executable()".to_string(),
+                    })
+                })?;
+
             Some(
                 pyoxidizer_context
                     .distribution_cache
@@ -349,11 +370,14 @@ impl PythonDistributionValue {
                 })
             })?;
 
+        builder.set_bytecode_cache(pyoxidizer_context.env().bytecode_cache());
+        builder.set_bytecode_compile_workers(pyoxidizer_context.env().bytecode_compile_workers());
+
         let callback = Box::new(
             |_policy: &PythonPackagingPolicy,
              resource: &PythonResource,
              add_context: &mut PythonResourceAddCollectionContext|
-             -> Result<()> {
+             -> Result<Vec<ResourceDecision>> {
                 // Callback is declared Fn, so we can't take a mutable reference.
                 // A copy should be fine.
                 let mut cs = call_stack.clone();
@@ -369,13 +393,16 @@ impl PythonDistributionValue {
                 let value = python_resource_to_value(&type_values, &mut cs, resource, &policy)
                     .map_err(|e| anyhow!("error converting PythonResource to Value: {:?}", e))?;
 
-                let new_add_context = add_context_for_value(&value, "to_python_executable")
-                    .map_err(|e| anyhow!("error obtaining add context from Value: {:?}", e))?
-                    .expect("add context should have been populated as part of Value conversion");
+                let (new_add_context, decisions) =
+                    add_context_for_value(&value, "to_python_executable")
+                        .map_err(|e| anyhow!("error obtaining add context from Value: {:?}", e))?
+                        .expect(
+                            "add context should have been populated as part of Value conversion",
+                        );
 
                 add_context.replace(&new_add_context);
 
-                Ok(())
+                Ok(decisions)
             },
         );
 
@@ -389,7 +416,17 @@ impl PythonDistributionValue {
                 })
             })?;
 
-        Ok(Value::new(PythonExecutableValue::new(builder, policy)))
+        // Share the caller's policy so changes made to it later are honored.
+        let policy_value = if packaging_policy.get_type() == "NoneType" {
+            Value::new(policy)
+        } else {
+            packaging_policy.clone()
+        };
+
+        Ok(Value::new(PythonExecutableValue::new(
+            builder,
+            policy_value,
+        )))
     }
 
     pub fn python_resources_starlark(
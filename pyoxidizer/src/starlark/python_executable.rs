@@ -16,11 +16,14 @@ use {
         },
     },
     crate::{
+        environment::PYOXIDIZER_VERSION,
+        lockfile::ArtifactDigests,
         project_building::build_python_executable,
         py_packaging::binary::PythonBinaryBuilder,
         py_packaging::binary::{PackedResourcesLoadMode, WindowsRuntimeDllsMode},
     },
     anyhow::{anyhow, Context, Result},
+    chrono::TimeZone,
     linked_hash_map::LinkedHashMap,
     python_packaging::resource::PythonModuleSource,
     slog::{info, warn},
@@ -40,7 +43,8 @@ use {
         },
     },
     starlark_dialect_build_targets::{
-        optional_dict_arg, optional_list_arg, optional_type_arg, required_list_arg, ResolvedTarget,
+        fingerprint::TargetInput, optional_dict_arg, optional_list_arg, optional_str_arg,
+        optional_type_arg, record_target_input, required_list_arg, BuildJobValue, ResolvedTarget,
         ResolvedTargetValue, RunMode, ToOptional,
     },
     std::{
@@ -49,14 +53,119 @@ use {
         io::Write,
         ops::Deref,
         path::{Path, PathBuf},
+        sync::Arc,
     },
     tugger::starlark::{
-        file_resource::FileManifestValue, wix_bundle_builder::WiXBundleBuilderValue,
+        appimage_builder::AppImageBuilderValue, debian_package_builder::DebianPackageBuilderValue,
+        file_resource::FileManifestValue, oci_image_builder::OciImageBuilderValue,
+        rpm_builder::RpmBuilderValue, wix_bundle_builder::WiXBundleBuilderValue,
         wix_msi_builder::WiXMsiBuilderValue,
     },
-    tugger_file_manifest::FileData,
+    tugger_file_manifest::{FileData, FileEntry, FileManifest},
+    tugger_licensing::{
+        sbom::{sbom_document, SbomFormat, SbomMetadata},
+        LicensedComponents,
+    },
+    tugger_rpm::RpmFileAttributes,
 };
 
+/// State needed to build a Python executable.
+///
+/// This doesn't reference Starlark values, so building can happen on any
+/// thread.
+struct ExecutableBuild {
+    env: crate::environment::Environment,
+    exe: Arc<dyn PythonBinaryBuilder>,
+    target_triple: String,
+    opt_level: String,
+    release: bool,
+    explain_resources: Vec<String>,
+    output_path: PathBuf,
+}
+
+impl ExecutableBuild {
+    fn new(
+        value: &PythonExecutableValue,
+        context: &PyOxidizerEnvironmentContext,
+        output_path: PathBuf,
+    ) -> Self {
+        Self {
+            env: context.env().clone(),
+            exe: value.exe.clone_trait(),
+            target_triple: context.build_target_triple.clone(),
+            opt_level: context.build_opt_level.clone(),
+            release: context.build_release,
+            explain_resources: context.explain_resources.clone(),
+            output_path,
+        }
+    }
+
+    fn run(self, logger: &slog::Logger) -> Result<ResolvedTarget> {
+        // Build an executable by writing out a temporary Rust project
+        // and building it.
+        let build = build_python_executable(
+            &self.env,
+            logger,
+            &self.exe.name(),
+            self.exe.deref(),
+            &self.target_triple,
+            &self.opt_level,
+            self.release,
+        )?;
+
+        let dest_path = self.output_path.join(build.exe_name);
+        warn!(logger, "writing executable to {}", dest_path.display());
+        let mut fh = std::fs::File::create(&dest_path)
+            .context(format!("creating {}", dest_path.display()))?;
+        fh.write_all(&build.exe_data)
+            .context(format!("writing {}", dest_path.display()))?;
+
+        tugger_file_manifest::set_executable(&mut fh).context("making binary executable")?;
+
+        let decision_log = &build.binary_data.resource_decisions;
+
+        for name in &self.explain_resources {
+            match decision_log.get(name) {
+                Some(decisions) => {
+                    warn!(logger, "decisions for resource {}:", name);
+                    for decision in decisions {
+                        warn!(logger, "  [{}] {}", decision.actor, decision.message);
+                    }
+                }
+                None => {
+                    warn!(logger, "no decisions recorded for resource {}", name);
+                }
+            }
+        }
+
+        let decisions_path = self.output_path.join("resource-decisions.json");
+        let decisions = decision_log
+            .iter()
+            .map(|(name, decisions)| {
+                (
+                    name.clone(),
+                    decisions
+                        .iter()
+                        .map(|decision| {
+                            serde_json::json!({
+                                "actor": decision.actor,
+                                "message": decision.message,
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .collect::<std::collections::BTreeMap<_, _>>();
+        std::fs::write(&decisions_path, serde_json::to_vec_pretty(&decisions)?)
+            .with_context(|| format!("writing {}", decisions_path.display()))?;
+
+        Ok(ResolvedTarget {
+            run_mode: RunMode::Path { path: dest_path },
+            output_path: self.output_path,
+        })
+    }
+}
+
 /// Represents a builder for a Python executable.
 pub struct PythonExecutableValue {
     pub exe: Box<dyn PythonBinaryBuilder>,
@@ -69,10 +178,14 @@ pub struct PythonExecutableValue {
 }
 
 impl PythonExecutableValue {
-    pub fn new(exe: Box<dyn PythonBinaryBuilder>, policy: PythonPackagingPolicyValue) -> Self {
+    /// Construct an instance from a builder and a `PythonPackagingPolicy` value.
+    ///
+    /// `policy` is shared with the caller, so later changes to it apply to
+    /// resources added afterwards.
+    pub fn new(exe: Box<dyn PythonBinaryBuilder>, policy: Value) -> Self {
         Self {
             exe,
-            policy: vec![Value::new(policy)],
+            policy: vec![policy],
         }
     }
 
@@ -84,44 +197,28 @@ impl PythonExecutableValue {
             .clone()
     }
 
+    /// Apply the current state of the packaging policy value to the builder.
+    fn sync_python_packaging_policy(&mut self) {
+        let policy = self.policy[0]
+            .downcast_ref::<PythonPackagingPolicyValue>()
+            .unwrap();
+
+        if self.exe.python_packaging_policy() != &policy.inner {
+            self.exe.set_python_packaging_policy(policy.inner.clone());
+        }
+    }
+
     pub fn build_internal(
         &self,
         type_values: &TypeValues,
         target: &str,
         context: &PyOxidizerEnvironmentContext,
     ) -> Result<ResolvedTarget> {
-        // Build an executable by writing out a temporary Rust project
-        // and building it.
-        let build = build_python_executable(
-            context.env(),
-            context.logger(),
-            &self.exe.name(),
-            self.exe.deref(),
-            &context.build_target_triple,
-            &context.build_opt_level,
-            context.build_release,
-        )?;
-
         let output_path = context
             .get_output_path(type_values, target)
             .map_err(|_| anyhow!("unable to resolve output path"))?;
-        let dest_path = output_path.join(build.exe_name);
-        warn!(
-            context.logger(),
-            "writing executable to {}",
-            dest_path.display()
-        );
-        let mut fh = std::fs::File::create(&dest_path)
-            .context(format!("creating {}", dest_path.display()))?;
-        fh.write_all(&build.exe_data)
-            .context(format!("writing {}", dest_path.display()))?;
-
-        tugger_file_manifest::set_executable(&mut fh).context("making binary executable")?;
 
-        Ok(ResolvedTarget {
-            run_mode: RunMode::Path { path: dest_path },
-            output_path,
-        })
+        ExecutableBuild::new(self, context, output_path).run(context.logger())
     }
 }
 
@@ -242,6 +339,23 @@ impl PythonExecutableValue {
         }))
     }
 
+    /// PythonExecutable.prepare_build(target)
+    ///
+    /// Like `build()` but defers the work to a job that can run on another thread.
+    fn prepare_build(&self, type_values: &TypeValues, target: String) -> ValueResult {
+        let pyoxidizer_context_value = get_context(type_values)?;
+        let pyoxidizer_context = pyoxidizer_context_value
+            .downcast_ref::<PyOxidizerEnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        let output_path = pyoxidizer_context.get_output_path(type_values, &target)?;
+        let build = ExecutableBuild::new(self, &pyoxidizer_context, output_path);
+
+        Ok(Value::new(BuildJobValue::new(Box::new(
+            move |logger: &slog::Logger| build.run(logger),
+        ))))
+    }
+
     /// PythonExecutable.make_python_module_source(name, source, is_package=false)
     pub fn make_python_module_source(
         &self,
@@ -285,7 +399,7 @@ impl PythonExecutableValue {
 
         let python_packaging_policy = self.python_packaging_policy();
 
-        let resources = self
+        let (resources, artifacts) = self
             .exe
             .pip_download(
                 pyoxidizer_context.logger(),
@@ -298,7 +412,19 @@ impl PythonExecutableValue {
                     message: format!("error running pip install: {}", e),
                     label: "pip_install()".to_string(),
                 })
-            })?
+            })?;
+
+        pyoxidizer_context
+            .record_packages(type_values, "pip_download", &resources, &artifacts)
+            .map_err(|e| {
+                ValueError::from(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: format!("{:?}", e),
+                    label: "pip_download()".to_string(),
+                })
+            })?;
+
+        let resources = resources
             .iter()
             .filter(|r| is_resource_starlark_compatible(r))
             .map(|r| python_resource_to_value(type_values, call_stack, r, &python_packaging_policy))
@@ -355,7 +481,24 @@ impl PythonExecutableValue {
                     message: format!("error running pip install: {}", e),
                     label: "pip_install()".to_string(),
                 })
-            })?
+            })?;
+
+        pyoxidizer_context
+            .record_packages(
+                type_values,
+                "pip_install",
+                &resources,
+                &ArtifactDigests::new(),
+            )
+            .map_err(|e| {
+                ValueError::from(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: format!("{:?}", e),
+                    label: "pip_install()".to_string(),
+                })
+            })?;
+
+        let resources = resources
             .iter()
             .filter(|r| is_resource_starlark_compatible(r))
             .map(|r| python_resource_to_value(type_values, call_stack, r, &python_packaging_policy))
@@ -387,6 +530,12 @@ impl PythonExecutableValue {
 
         let python_packaging_policy = self.python_packaging_policy();
 
+        for package in &packages {
+            for input in &[package.clone(), format!("{}.py", package)] {
+                record_target_input(type_values, TargetInput::Path(Path::new(&path).join(input)))?;
+            }
+        }
+
         let resources = self
             .exe
             .read_package_root(pyoxidizer_context.logger(), Path::new(&path), &packages)
@@ -419,6 +568,8 @@ impl PythonExecutableValue {
 
         let python_packaging_policy = self.python_packaging_policy();
 
+        record_target_input(type_values, TargetInput::Path(PathBuf::from(&path)))?;
+
         let resources = self
             .exe
             .read_virtualenv(pyoxidizer_context.logger(), &Path::new(&path))
@@ -485,6 +636,8 @@ impl PythonExecutableValue {
             PathBuf::from(&pyoxidizer_context.cwd).join(package_path)
         };
 
+        record_target_input(type_values, TargetInput::Path(package_path.clone()))?;
+
         let python_packaging_policy = self.python_packaging_policy();
 
         let resources = self
@@ -502,7 +655,24 @@ impl PythonExecutableValue {
                     message: format!("{:?}", e),
                     label: "setup_py_install()".to_string(),
                 })
-            })?
+            })?;
+
+        pyoxidizer_context
+            .record_packages(
+                type_values,
+                "setup_py_install",
+                &resources,
+                &ArtifactDigests::new(),
+            )
+            .map_err(|e| {
+                ValueError::from(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: format!("{:?}", e),
+                    label: "setup_py_install()".to_string(),
+                })
+            })?;
+
+        let resources = resources
             .iter()
             .filter(|r| is_resource_starlark_compatible(r))
             .map(|r| python_resource_to_value(type_values, call_stack, r, &python_packaging_policy))
@@ -517,6 +687,75 @@ impl PythonExecutableValue {
         Ok(Value::from(resources))
     }
 
+    /// PythonExecutable.sdist_install(path, wheelhouse)
+    pub fn sdist_install(
+        &mut self,
+        type_values: &TypeValues,
+        call_stack: &mut CallStack,
+        path: String,
+        wheelhouse: String,
+    ) -> ValueResult {
+        let pyoxidizer_context_value = get_context(type_values)?;
+        let pyoxidizer_context = pyoxidizer_context_value
+            .downcast_ref::<PyOxidizerEnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        let path = PathBuf::from(&pyoxidizer_context.cwd).join(path);
+        let wheelhouse = PathBuf::from(&pyoxidizer_context.cwd).join(wheelhouse);
+
+        let python_packaging_policy = self.python_packaging_policy();
+
+        let (resources, artifacts) = self
+            .exe
+            .sdist_install(
+                pyoxidizer_context.logger(),
+                &path,
+                &wheelhouse,
+                pyoxidizer_context.verbose,
+            )
+            .map_err(|e| {
+                ValueError::from(RuntimeError {
+                    code: "SDIST_ERROR",
+                    message: format!("{:?}", e),
+                    label: "sdist_install()".to_string(),
+                })
+            })?;
+
+        pyoxidizer_context
+            .record_packages(type_values, "sdist_install", &resources, &artifacts)
+            .map_err(|e| {
+                ValueError::from(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: format!("{:?}", e),
+                    label: "sdist_install()".to_string(),
+                })
+            })?;
+
+        let resources = resources
+            .iter()
+            .filter(|r| is_resource_starlark_compatible(r))
+            .map(|r| python_resource_to_value(type_values, call_stack, r, &python_packaging_policy))
+            .collect::<Result<Vec<Value>, ValueError>>()?;
+
+        warn!(
+            pyoxidizer_context.logger(),
+            "collected {} resources from source distribution",
+            resources.len()
+        );
+
+        Ok(Value::from(resources))
+    }
+
+    /// Record the decisions influencing the add context of a resource value.
+    fn record_add_decisions(&mut self, value: &impl ResourceCollectionContext) {
+        if value.add_collection_context().is_some() {
+            self.exe.record_resource_decisions(
+                &value.as_python_resource().full_name(),
+                value.add_collection_decisions().to_vec(),
+            );
+        }
+    }
+
     pub fn add_python_module_source(
         &mut self,
         context: &PyOxidizerEnvironmentContext,
@@ -527,6 +766,7 @@ impl PythonExecutableValue {
             context.logger(),
             "adding Python source module {}", module.inner.name;
         );
+        self.record_add_decisions(module);
         self.exe
             .add_python_module_source(&module.inner, module.add_collection_context().clone())
             .with_context(|| format!("adding {}", module.to_repr()))
@@ -552,6 +792,7 @@ impl PythonExecutableValue {
             "adding Python package resource {}",
             resource.inner.symbolic_name()
         );
+        self.record_add_decisions(resource);
         self.exe
             .add_python_package_resource(&resource.inner, resource.add_collection_context().clone())
             .with_context(|| format!("adding {}", resource.to_repr()))
@@ -578,6 +819,7 @@ impl PythonExecutableValue {
             resource.inner.package,
             resource.inner.name
         );
+        self.record_add_decisions(resource);
         self.exe
             .add_python_package_distribution_resource(
                 &resource.inner,
@@ -605,6 +847,7 @@ impl PythonExecutableValue {
             context.logger(),
             "adding extension module {}", module.inner.name
         );
+        self.record_add_decisions(module);
         self.exe
             .add_python_extension_module(&module.inner, module.add_collection_context().clone())
             .with_context(|| format!("adding {}", module.to_repr()))
@@ -629,6 +872,7 @@ impl PythonExecutableValue {
             context.logger(),
             "adding file data {}", file.inner.path.display();
         );
+        self.record_add_decisions(file);
         self.exe
             .add_file_data(&file.inner, file.add_collection_context().clone())
             .with_context(|| format!("adding {}", file.to_repr()))
@@ -655,6 +899,8 @@ impl PythonExecutableValue {
             .downcast_ref::<PyOxidizerEnvironmentContext>()
             .ok_or(ValueError::IncorrectParameterType)?;
 
+        self.sync_python_packaging_policy();
+
         match resource.get_type() {
             FileValue::TYPE => {
                 let file = resource.downcast_ref::<FileValue>().unwrap();
@@ -749,6 +995,206 @@ impl PythonExecutableValue {
         Ok(manifest_value.clone())
     }
 
+    /// Resolve the Linux architecture of this executable for a packaging method.
+    ///
+    /// Errors if the executable does not target Linux. `None` is returned for
+    /// Linux architectures without known packaging names.
+    fn linux_architecture(
+        &self,
+        label: &str,
+    ) -> Result<Option<&'static LinuxArchitecture>, ValueError> {
+        linux_architecture(self.exe.target_triple())
+            .map_err(|e| python_executable_error(label, format!("{:?}", e)))
+    }
+
+    /// Add the files of this executable to a Linux package.
+    ///
+    /// `add_manifest` receives the files of the executable and `install_prefix`.
+    /// Returns the installed path of the executable, if there is one.
+    fn add_to_linux_package<F>(
+        &self,
+        type_values: &TypeValues,
+        label: &str,
+        install_prefix: &str,
+        add_manifest: F,
+    ) -> Result<Option<String>, ValueError>
+    where
+        F: FnOnce(&FileManifest, &str) -> Result<()>,
+    {
+        let manifest_value = self.to_file_manifest(type_values, ".".to_string())?;
+        let manifest = manifest_value.downcast_ref::<FileManifestValue>().unwrap();
+
+        add_manifest(&manifest.manifest, install_prefix)
+            .map_err(|e| python_executable_error(label, format!("{:?}", e)))?;
+
+        Ok(manifest.run_path.as_ref().map(|run_path| {
+            format!(
+                "{}/{}",
+                install_prefix.trim_end_matches('/'),
+                run_path.display()
+            )
+        }))
+    }
+
+    /// PythonExecutable.to_appimage_builder(name, runtime_path)
+    pub fn to_appimage_builder(
+        &self,
+        type_values: &TypeValues,
+        name: String,
+        runtime_path: String,
+    ) -> ValueResult {
+        const LABEL: &str = "to_appimage_builder()";
+
+        let filename = match self.linux_architecture(LABEL)? {
+            Some(arch) => format!("{}-{}.AppImage", name, arch.appimage),
+            None => format!("{}.AppImage", name),
+        };
+
+        let runtime_path = PathBuf::from(runtime_path);
+        record_target_input(type_values, TargetInput::Path(runtime_path.clone()))?;
+
+        let install_prefix = format!("usr/lib/{}", name);
+
+        let mut builder = AppImageBuilderValue::new(name, runtime_path, Some(filename))
+            .map_err(|e| python_executable_error(LABEL, format!("{:?}", e)))?;
+
+        builder.exec =
+            self.add_to_linux_package(type_values, LABEL, &install_prefix, |manifest, prefix| {
+                builder.add_manifest(manifest, prefix)
+            })?;
+
+        Ok(Value::new(builder))
+    }
+
+    /// PythonExecutable.to_deb_builder(package, version, maintainer, description, architecture=None)
+    pub fn to_deb_builder(
+        &self,
+        type_values: &TypeValues,
+        package: String,
+        version: String,
+        maintainer: String,
+        description: String,
+        architecture: &Value,
+    ) -> ValueResult {
+        const LABEL: &str = "to_deb_builder()";
+
+        optional_str_arg("architecture", architecture)?;
+
+        let target_architecture = self.linux_architecture(LABEL)?;
+
+        let architecture = match (architecture.get_type(), target_architecture) {
+            ("string", _) => architecture.to_string(),
+            (_, Some(arch)) => arch.debian.to_string(),
+            (_, None) => {
+                return Err(python_executable_error(
+                    LABEL,
+                    format!(
+                        "unable to determine Debian architecture for {}; pass architecture",
+                        self.exe.target_triple()
+                    ),
+                ));
+            }
+        };
+
+        let install_prefix = format!("/usr/lib/{}", package);
+
+        let mut builder =
+            DebianPackageBuilderValue::new(package, version, architecture, maintainer, description);
+
+        let exe_path =
+            self.add_to_linux_package(type_values, LABEL, &install_prefix, |manifest, prefix| {
+                builder.add_manifest(manifest, prefix)
+            })?;
+
+        if let Some((path, target)) = exe_path.as_deref().and_then(usr_bin_symlink) {
+            builder.add_symlink(&path, target);
+        }
+
+        Ok(Value::new(builder))
+    }
+
+    /// PythonExecutable.to_oci_image_builder(install_prefix="/app")
+    pub fn to_oci_image_builder(
+        &self,
+        type_values: &TypeValues,
+        install_prefix: String,
+    ) -> ValueResult {
+        const LABEL: &str = "to_oci_image_builder()";
+
+        let (architecture, variant) = self
+            .linux_architecture(LABEL)?
+            .ok_or_else(|| {
+                python_executable_error(
+                    LABEL,
+                    format!(
+                        "unable to determine container image platform for {}",
+                        self.exe.target_triple()
+                    ),
+                )
+            })?
+            .oci;
+
+        let mut builder = OciImageBuilderValue::new(
+            architecture.to_string(),
+            "linux".to_string(),
+            variant.map(|v| v.to_string()),
+        );
+
+        builder.entrypoint = self
+            .add_to_linux_package(type_values, LABEL, &install_prefix, |manifest, prefix| {
+                builder.add_manifest(manifest, prefix)
+            })?
+            .map(|path| vec![path]);
+
+        Ok(Value::new(builder))
+    }
+
+    /// PythonExecutable.to_rpm_builder(name, version, license, description, arch=None)
+    pub fn to_rpm_builder(
+        &self,
+        type_values: &TypeValues,
+        name: String,
+        version: String,
+        license: String,
+        description: String,
+        arch: &Value,
+    ) -> ValueResult {
+        const LABEL: &str = "to_rpm_builder()";
+
+        optional_str_arg("arch", arch)?;
+
+        let target_architecture = self.linux_architecture(LABEL)?;
+
+        let arch = match (arch.get_type(), target_architecture) {
+            ("string", _) => arch.to_string(),
+            (_, Some(target_arch)) => target_arch.rpm.to_string(),
+            (_, None) => {
+                return Err(python_executable_error(
+                    LABEL,
+                    format!(
+                        "unable to determine RPM architecture for {}; pass arch",
+                        self.exe.target_triple()
+                    ),
+                ));
+            }
+        };
+
+        let install_prefix = format!("/usr/lib/{}", name);
+
+        let mut builder = RpmBuilderValue::new(name, version, license, arch, description);
+
+        let exe_path =
+            self.add_to_linux_package(type_values, LABEL, &install_prefix, |manifest, prefix| {
+                builder.add_manifest(manifest, prefix, &RpmFileAttributes::default())
+            })?;
+
+        if let Some((path, target)) = exe_path.as_deref().and_then(usr_bin_symlink) {
+            builder.add_symlink(&path, target);
+        }
+
+        Ok(Value::new(builder))
+    }
+
     /// PythonExecutable.to_wix_bundle_builder(id_prefix, name, version, manufacturer, msi_builder_callback)
     #[allow(clippy::too_many_arguments)]
     pub fn to_wix_bundle_builder(
@@ -891,9 +1337,282 @@ impl PythonExecutableValue {
 
         Ok(Value::new(NoneType::None))
     }
+
+    /// PythonExecutable.find_unreachable_resources(entry_modules=None, allow_modules=None)
+    pub fn find_unreachable_resources(
+        &self,
+        type_values: &TypeValues,
+        entry_modules: &Value,
+        allow_modules: &Value,
+    ) -> ValueResult {
+        let entry_modules = optional_string_list(entry_modules, "entry_modules")?;
+        let allow_modules = optional_string_list(allow_modules, "allow_modules")?;
+
+        let pyoxidizer_context_value = get_context(type_values)?;
+        let pyoxidizer_context = pyoxidizer_context_value
+            .downcast_ref::<PyOxidizerEnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        let reachability = self
+            .exe
+            .resolve_module_reachability(&entry_modules, &allow_modules)
+            .map_err(|e| {
+                ValueError::from(RuntimeError {
+                    code: "RUNTIME_ERROR",
+                    message: format!("{:?}", e),
+                    label: "find_unreachable_resources()".to_string(),
+                })
+            })?;
+
+        for name in &reachability.missing {
+            info!(
+                pyoxidizer_context.logger(),
+                "{} is imported but not available", name
+            );
+        }
+
+        Ok(Value::from(
+            reachability
+                .unreachable
+                .into_iter()
+                .map(Value::from)
+                .collect::<Vec<_>>(),
+        ))
+    }
+
+    /// PythonExecutable.filter_unreachable_resources(entry_modules=None, allow_modules=None)
+    pub fn filter_unreachable_resources(
+        &mut self,
+        type_values: &TypeValues,
+        entry_modules: &Value,
+        allow_modules: &Value,
+    ) -> ValueResult {
+        let entry_modules = optional_string_list(entry_modules, "entry_modules")?;
+        let allow_modules = optional_string_list(allow_modules, "allow_modules")?;
+
+        let pyoxidizer_context_value = get_context(type_values)?;
+        let pyoxidizer_context = pyoxidizer_context_value
+            .downcast_ref::<PyOxidizerEnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        self.exe
+            .filter_unreachable_resources(
+                pyoxidizer_context.logger(),
+                &entry_modules,
+                &allow_modules,
+            )
+            .map_err(|e| {
+                ValueError::from(RuntimeError {
+                    code: "RUNTIME_ERROR",
+                    message: format!("{:?}", e),
+                    label: "filter_unreachable_resources()".to_string(),
+                })
+            })?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    /// PythonExecutable.to_sbom_file_manifest(format="spdx-json", path=None)
+    pub fn to_sbom_file_manifest(&self, format: String, path: &Value) -> ValueResult {
+        let format = SbomFormat::try_from(format.as_str()).map_err(|e| {
+            ValueError::from(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e,
+                label: "to_sbom_file_manifest()".to_string(),
+            })
+        })?;
+
+        let name = self.exe.name();
+        let path = optional_str_arg("path", path)?
+            .unwrap_or_else(|| format!("{}.{}", name, format.file_extension()));
+
+        let manifest_value = FileManifestValue::new_from_args()?;
+        let mut manifest = manifest_value
+            .downcast_mut::<FileManifestValue>()
+            .unwrap()
+            .unwrap();
+
+        self.exe
+            .licensed_components()
+            .and_then(|components| {
+                let created = match tugger::source_date_epoch()? {
+                    Some(epoch) => sbom_timestamp(epoch)?,
+                    None => chrono::Utc::now(),
+                };
+
+                let metadata = sbom_metadata(name, &components, created)?;
+
+                sbom_document(&components, &metadata, format)
+            })
+            .and_then(|document| {
+                manifest.manifest.add_file_entry(
+                    Path::new(&path),
+                    FileEntry {
+                        data: document.into_bytes().into(),
+                        executable: false,
+                    },
+                )?;
+
+                Ok(())
+            })
+            .map_err(|e| {
+                ValueError::from(RuntimeError {
+                    code: "PYOXIDIZER_PYTHON_EXECUTABLE",
+                    message: format!("{:?}", e),
+                    label: "to_sbom_file_manifest()".to_string(),
+                })
+            })?;
+
+        Ok(manifest_value.clone())
+    }
+}
+
+/// Convert an optional Starlark list of strings to a `Vec<String>`.
+fn optional_string_list(value: &Value, label: &str) -> Result<Vec<String>, ValueError> {
+    optional_list_arg(label, "string", value)?;
+
+    Ok(match value.get_type() {
+        "list" => value.iter()?.iter().map(|x| x.to_string()).collect(),
+        _ => vec![],
+    })
+}
+
+/// Construct the error raised by a `PythonExecutable` method.
+fn python_executable_error(label: &str, message: String) -> ValueError {
+    ValueError::from(RuntimeError {
+        code: "PYOXIDIZER_PYTHON_EXECUTABLE",
+        message,
+        label: label.to_string(),
+    })
+}
+
+/// Architecture names used by Linux packaging formats.
+#[derive(Debug, PartialEq)]
+struct LinuxArchitecture {
+    /// Architecture component of the Rust target triple.
+    triple: &'static str,
+    /// Architecture in AppImage filenames.
+    appimage: &'static str,
+    /// Debian architecture.
+    debian: &'static str,
+    /// RPM architecture.
+    rpm: &'static str,
+    /// Container image architecture and variant.
+    oci: (&'static str, Option<&'static str>),
+}
+
+const LINUX_ARCHITECTURES: &[LinuxArchitecture] = &[
+    LinuxArchitecture {
+        triple: "x86_64",
+        appimage: "x86_64",
+        debian: "amd64",
+        rpm: "x86_64",
+        oci: ("amd64", None),
+    },
+    LinuxArchitecture {
+        triple: "i686",
+        appimage: "i686",
+        debian: "i386",
+        rpm: "i686",
+        oci: ("386", None),
+    },
+    LinuxArchitecture {
+        triple: "aarch64",
+        appimage: "aarch64",
+        debian: "arm64",
+        rpm: "aarch64",
+        oci: ("arm64", None),
+    },
+    LinuxArchitecture {
+        triple: "armv7",
+        appimage: "armhf",
+        debian: "armhf",
+        rpm: "armv7hl",
+        oci: ("arm", Some("v7")),
+    },
+    LinuxArchitecture {
+        triple: "powerpc64le",
+        appimage: "ppc64le",
+        debian: "ppc64el",
+        rpm: "ppc64le",
+        oci: ("ppc64le", None),
+    },
+    LinuxArchitecture {
+        triple: "s390x",
+        appimage: "s390x",
+        debian: "s390x",
+        rpm: "s390x",
+        oci: ("s390x", None),
+    },
+];
+
+/// Resolve the packaging architecture names for a Rust target triple.
+///
+/// Errors if the triple does not target Linux. `None` is returned for Linux
+/// architectures not in `LINUX_ARCHITECTURES`.
+fn linux_architecture(target_triple: &str) -> Result<Option<&'static LinuxArchitecture>> {
+    if !target_triple.contains("-linux-") {
+        return Err(anyhow!(
+            "{} is not a Linux target; Linux packages can only be produced for Linux executables",
+            target_triple
+        ));
+    }
+
+    let arch = target_triple.split('-').next().unwrap_or_default();
+
+    Ok(LINUX_ARCHITECTURES.iter().find(|a| a.triple == arch))
+}
+
+/// Convert seconds since the UNIX epoch to an SBOM creation time.
+fn sbom_timestamp(seconds: u64) -> Result<chrono::DateTime<chrono::Utc>> {
+    i64::try_from(seconds)
+        .ok()
+        .and_then(|seconds| chrono::Utc.timestamp_opt(seconds, 0).single())
+        .ok_or_else(|| anyhow!("{} is not a valid SBOM creation time", seconds))
+}
+
+/// Derive the metadata of an SBOM document describing `components`.
+///
+/// The document's UUID and namespace are a v5 UUID derived from the
+/// document's content excluding its creation time. So identical inputs
+/// always produce the same document identity.
+fn sbom_metadata(
+    name: String,
+    components: &LicensedComponents,
+    created: chrono::DateTime<chrono::Utc>,
+) -> Result<SbomMetadata> {
+    let mut metadata = SbomMetadata {
+        namespace: String::new(),
+        name,
+        uuid: None,
+        created: sbom_timestamp(0)?,
+        tool_name: "PyOxidizer".to_string(),
+        tool_version: PYOXIDIZER_VERSION.to_string(),
+    };
+
+    let content = sbom_document(components, &metadata, SbomFormat::SpdxJson)?;
+    let uuid = uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, content.as_bytes());
+
+    metadata.namespace = format!("https://spdx.org/spdxdocs/{}-{}", metadata.name, uuid);
+    metadata.uuid = Some(uuid.to_string());
+    metadata.created = created;
+
+    Ok(metadata)
+}
+
+/// Resolve the `/usr/bin` symlink to an executable installed at `exe_path`.
+fn usr_bin_symlink(exe_path: &str) -> Option<(String, &str)> {
+    let exe_name = Path::new(exe_path).file_name()?;
+
+    Some((format!("/usr/bin/{}", exe_name.to_string_lossy()), exe_path))
 }
 
 starlark_module! { python_executable_env =>
+    PythonExecutable.prepare_build(env env, this, target: String) {
+        let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
+        this.prepare_build(env, target)
+    }
+
     PythonExecutable.build(env env, this, target: String) {
         let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
         this.build(env, target)
@@ -971,6 +1690,18 @@ starlark_module! { python_executable_env =>
         this.setup_py_install(&env, cs, package_path, &extra_envs, &extra_global_arguments)
     }
 
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    PythonExecutable.sdist_install(
+        env env,
+        call_stack cs,
+        this,
+        path: String,
+        wheelhouse: String
+    ) {
+        let mut this = this.downcast_mut::<PythonExecutableValue>().unwrap().unwrap();
+        this.sdist_install(&env, cs, path, wheelhouse)
+    }
+
     #[allow(non_snake_case, clippy::ptr_arg)]
     PythonExecutable.add_python_resource(
         env env,
@@ -1009,6 +1740,28 @@ starlark_module! { python_executable_env =>
         this.filter_resources_from_files(&env, &files, &glob_files)
     }
 
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.find_unreachable_resources(
+        env env,
+        this,
+        entry_modules=NoneType::None,
+        allow_modules=NoneType::None)
+    {
+        let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
+        this.find_unreachable_resources(&env, &entry_modules, &allow_modules)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.filter_unreachable_resources(
+        env env,
+        this,
+        entry_modules=NoneType::None,
+        allow_modules=NoneType::None)
+    {
+        let mut this = this.downcast_mut::<PythonExecutableValue>().unwrap().unwrap();
+        this.filter_unreachable_resources(&env, &entry_modules, &allow_modules)
+    }
+
     #[allow(clippy::ptr_arg)]
     PythonExecutable.to_embedded_resources(this) {
         let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
@@ -1020,6 +1773,61 @@ starlark_module! { python_executable_env =>
         this.to_file_manifest(&env, prefix)
     }
 
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.to_sbom_file_manifest(
+        this,
+        format: String = "spdx-json".to_string(),
+        path=NoneType::None)
+    {
+        let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
+        this.to_sbom_file_manifest(format, &path)
+    }
+
+    PythonExecutable.to_appimage_builder(
+        env env,
+        this,
+        name: String,
+        runtime_path: String
+    ) {
+        let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
+        this.to_appimage_builder(env, name, runtime_path)
+    }
+
+    PythonExecutable.to_deb_builder(
+        env env,
+        this,
+        package: String,
+        version: String,
+        maintainer: String,
+        description: String,
+        architecture = NoneType::None
+    ) {
+        let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
+        this.to_deb_builder(env, package, version, maintainer, description, &architecture)
+    }
+
+    PythonExecutable.to_oci_image_builder(
+        env env,
+        this,
+        install_prefix: String = "/app".to_string()
+    ) {
+        let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
+        this.to_oci_image_builder(env, install_prefix)
+    }
+
+    PythonExecutable.to_rpm_builder(
+        env env,
+        this,
+        name: String,
+        version: String,
+        license: String,
+        description: String,
+        arch = NoneType::None
+    ) {
+        let this = this.downcast_ref::<PythonExecutableValue>().unwrap();
+        this.to_rpm_builder(env, name, version, license, description, &arch)
+    }
+
     PythonExecutable.to_wix_bundle_builder(
         env env,
         call_stack cs,
@@ -1140,6 +1948,26 @@ mod tests {
             true
         );
 
+        // Changes made by the callback are recorded next to the add context.
+        let module = m.downcast_ref::<PythonModuleSourceValue>().unwrap();
+        let decisions = module
+            .add_collection_decisions()
+            .iter()
+            .map(|d| (d.actor.as_str(), d.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(decisions.len(), 3);
+        assert_eq!(decisions[0].0, "policy");
+        assert_eq!(
+            &decisions[1..],
+            &[
+                ("callback my_func", "store source changed to true"),
+                (
+                    "callback my_func",
+                    "bytecode optimization level 2 changed to true"
+                ),
+            ]
+        );
+
         Ok(())
     }
 
@@ -1248,6 +2076,80 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_find_unreachable_resources() -> Result<()> {
+        let mut env = test_evaluation_context_builder()?.into_context()?;
+        env.eval("dist = default_python_distribution()")?;
+        env.eval("policy = dist.make_python_packaging_policy()")?;
+        env.eval("policy.include_distribution_sources = False")?;
+        env.eval("exe = dist.to_python_executable('testapp', packaging_policy = policy)")?;
+        env.eval("exe.add_python_resource(exe.make_python_module_source('app', 'import helper'))")?;
+        env.eval("exe.add_python_resource(exe.make_python_module_source('helper', ''))")?;
+        env.eval("exe.add_python_resource(exe.make_python_module_source('plugin', ''))")?;
+        env.eval("exe.add_python_resource(exe.make_python_module_source('unused', ''))")?;
+
+        let value = env.eval("exe.find_unreachable_resources(entry_modules = ['app'])")?;
+        assert_eq!(value.get_type(), "list");
+        let names = value
+            .iter()
+            .unwrap()
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"unused".to_string()));
+        assert!(names.contains(&"plugin".to_string()));
+        assert!(!names.contains(&"helper".to_string()));
+
+        env.eval(
+            "exe.filter_unreachable_resources(entry_modules = ['app'], allow_modules = ['plugin'])",
+        )?;
+        let value = env.eval("exe.find_unreachable_resources(entry_modules = ['app'])")?;
+        let names = value
+            .iter()
+            .unwrap()
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"plugin".to_string()));
+        assert!(!names.contains(&"unused".to_string()));
+
+        // run_module isn't defined, so entry modules are required.
+        assert!(env.eval("exe.find_unreachable_resources()").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_sbom_file_manifest() -> Result<()> {
+        let mut env = test_evaluation_context_builder()?.into_context()?;
+        env.eval("dist = default_python_distribution()")?;
+        env.eval("exe = dist.to_python_executable('testapp')")?;
+
+        let value = env.eval("exe.to_sbom_file_manifest()")?;
+        let manifest = value.downcast_ref::<FileManifestValue>().unwrap();
+        let entries = manifest.manifest.iter_entries().collect::<Vec<_>>();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, &PathBuf::from("testapp.spdx.json"));
+
+        let document: serde_json::Value = serde_json::from_slice(&entries[0].1.data.resolve()?)?;
+        let packages = document["packages"].as_array().unwrap();
+        assert!(packages.iter().any(|p| p["name"] == "anyhow"));
+        assert!(packages
+            .iter()
+            .any(|p| p["name"] == "cpython" && p["versionInfo"].is_string()));
+
+        let value = env
+            .eval("exe.to_sbom_file_manifest(format = 'cyclonedx-json', path = 'sbom/bom.json')")?;
+        let manifest = value.downcast_ref::<FileManifestValue>().unwrap();
+        assert!(manifest.manifest.has_path(Path::new("sbom/bom.json")));
+
+        assert!(env
+            .eval("exe.to_sbom_file_manifest(format = 'invalid')")
+            .is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_windows_runtime_dlls_mode() -> Result<()> {
         let mut env = test_evaluation_context_builder()?.into_context()?;
@@ -1346,6 +2248,196 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_linux_architecture() -> Result<()> {
+        let arch = linux_architecture("x86_64-unknown-linux-gnu")?.unwrap();
+        assert_eq!(arch.appimage, "x86_64");
+        assert_eq!(arch.debian, "amd64");
+        assert_eq!(arch.rpm, "x86_64");
+        assert_eq!(arch.oci, ("amd64", None));
+
+        let arch = linux_architecture("armv7-unknown-linux-gnueabihf")?.unwrap();
+        assert_eq!(arch.appimage, "armhf");
+        assert_eq!(arch.debian, "armhf");
+        assert_eq!(arch.rpm, "armv7hl");
+        assert_eq!(arch.oci, ("arm", Some("v7")));
+
+        assert_eq!(
+            linux_architecture("aarch64-unknown-linux-musl")?
+                .unwrap()
+                .debian,
+            "arm64"
+        );
+        assert_eq!(linux_architecture("riscv64gc-unknown-linux-gnu")?, None);
+
+        assert!(linux_architecture("x86_64-apple-darwin").is_err());
+        assert!(linux_architecture("x86_64-pc-windows-msvc").is_err());
+        assert!(linux_architecture("wasm32-unknown-unknown").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sbom_metadata() -> Result<()> {
+        let mut components = LicensedComponents::default();
+        components.add_component(tugger_licensing::LicensedComponent::new_spdx("foo", "MIT")?);
+
+        let first = sbom_metadata("testapp".to_string(), &components, sbom_timestamp(1000)?)?;
+        let second = sbom_metadata("testapp".to_string(), &components, sbom_timestamp(2000)?)?;
+        assert_eq!(first.namespace, second.namespace);
+        assert_eq!(first.uuid, second.uuid);
+        assert_eq!(first.created, sbom_timestamp(1000)?);
+        assert_eq!(
+            sbom_document(&components, &first, SbomFormat::SpdxJson)?,
+            sbom_document(
+                &components,
+                &sbom_metadata("testapp".to_string(), &components, sbom_timestamp(1000)?)?,
+                SbomFormat::SpdxJson
+            )?
+        );
+
+        components.add_component(tugger_licensing::LicensedComponent::new_spdx(
+            "bar",
+            "Apache-2.0",
+        )?);
+        let third = sbom_metadata("testapp".to_string(), &components, sbom_timestamp(1000)?)?;
+        assert_ne!(first.uuid, third.uuid);
+        assert!(third
+            .namespace
+            .starts_with("https://spdx.org/spdxdocs/testapp-"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_usr_bin_symlink() {
+        assert_eq!(
+            usr_bin_symlink("/usr/lib/myapp/testapp"),
+            Some(("/usr/bin/testapp".to_string(), "/usr/lib/myapp/testapp"))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_to_appimage_builder() -> Result<()> {
+        let mut env = test_evaluation_context_builder()?.into_context()?;
+        add_exe(&mut env)?;
+        let appimage_value = env.eval("exe.to_appimage_builder('myapp', 'runtime-x86_64')")?;
+
+        let appimage = appimage_value
+            .downcast_ref::<AppImageBuilderValue>()
+            .unwrap();
+        let arch = linux_architecture(crate::project_building::HOST)?.unwrap();
+        assert_eq!(
+            appimage.filename,
+            format!("myapp-{}.AppImage", arch.appimage)
+        );
+        assert_eq!(appimage.runtime_path, PathBuf::from("runtime-x86_64"));
+        assert!(
+            appimage
+                .files
+                .get("usr/lib/myapp/testapp")
+                .expect("executable installed under usr/lib/myapp")
+                .executable
+        );
+        assert_eq!(appimage.exec, Some("usr/lib/myapp/testapp".to_string()));
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_to_deb_builder() -> Result<()> {
+        let mut env = test_evaluation_context_builder()?.into_context()?;
+        add_exe(&mut env)?;
+        let deb_value =
+            env.eval("exe.to_deb_builder('myapp', '0.1', 'Me <me@example.com>', 'My app')")?;
+
+        let deb = deb_value
+            .downcast_ref::<DebianPackageBuilderValue>()
+            .unwrap();
+        assert_eq!(deb.control_field("Package"), Some("myapp"));
+        let arch = linux_architecture(crate::project_building::HOST)?.unwrap();
+        assert_eq!(deb.control_field("Architecture"), Some(arch.debian));
+        assert!(
+            deb.install_files
+                .get("usr/lib/myapp/testapp")
+                .expect("executable installed under /usr/lib/myapp")
+                .executable
+        );
+        assert_eq!(
+            deb.install_symlinks.get(Path::new("usr/bin/testapp")),
+            Some(&PathBuf::from("/usr/lib/myapp/testapp"))
+        );
+
+        let deb_value = env.eval(
+            "exe.to_deb_builder('myapp', '0.1', 'Me <me@example.com>', 'My app', architecture = 'all')",
+        )?;
+        let deb = deb_value
+            .downcast_ref::<DebianPackageBuilderValue>()
+            .unwrap();
+        assert_eq!(deb.control_field("Architecture"), Some("all"));
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_to_oci_image_builder() -> Result<()> {
+        let mut env = test_evaluation_context_builder()?.into_context()?;
+        add_exe(&mut env)?;
+        let image_value = env.eval("exe.to_oci_image_builder()")?;
+
+        let image = image_value.downcast_ref::<OciImageBuilderValue>().unwrap();
+        let arch = linux_architecture(crate::project_building::HOST)?.unwrap();
+        assert_eq!(image.architecture, arch.oci.0);
+        assert_eq!(image.os, "linux");
+        assert_eq!(image.variant.as_deref(), arch.oci.1);
+        assert!(
+            image
+                .files
+                .get("app/testapp")
+                .expect("executable installed under /app")
+                .executable
+        );
+        assert_eq!(image.entrypoint, Some(vec!["/app/testapp".to_string()]));
+
+        let image_value = env.eval("exe.to_oci_image_builder(install_prefix = '/opt/app/')")?;
+        let image = image_value.downcast_ref::<OciImageBuilderValue>().unwrap();
+        assert!(image.files.has_path("opt/app/testapp"));
+        assert_eq!(image.entrypoint, Some(vec!["/opt/app/testapp".to_string()]));
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_to_rpm_builder() -> Result<()> {
+        let mut env = test_evaluation_context_builder()?.into_context()?;
+        add_exe(&mut env)?;
+        let rpm_value = env.eval("exe.to_rpm_builder('myapp', '0.1', 'MPL-2.0', 'My app')")?;
+
+        let rpm = rpm_value.downcast_ref::<RpmBuilderValue>().unwrap();
+        let arch = linux_architecture(crate::project_building::HOST)?.unwrap();
+        assert_eq!(rpm.rpm_filename(), format!("myapp-0.1-1.{}.rpm", arch.rpm));
+        assert!(
+            rpm.install_files
+                .get("usr/lib/myapp/testapp")
+                .expect("executable installed under /usr/lib/myapp")
+                .executable
+        );
+        assert_eq!(
+            rpm.file_attributes.get(Path::new("usr/lib/myapp/testapp")),
+            Some(&RpmFileAttributes::default())
+        );
+        assert_eq!(
+            rpm.install_symlinks.get(Path::new("usr/bin/testapp")),
+            Some(&PathBuf::from("/usr/lib/myapp/testapp"))
+        );
+
+        Ok(())
+    }
+
     #[cfg(windows)]
     #[test]
     fn test_to_wix_bundle_builder() -> Result<()> {
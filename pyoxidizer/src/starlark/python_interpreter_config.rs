@@ -12,12 +12,16 @@ use {
         },
         resource::BytecodeOptimizationLevel,
     },
-    starlark::values::{
-        error::{
-            RuntimeError, UnsupportedOperation, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+    starlark::{
+        starlark_fun, starlark_module, starlark_parse_param_type, starlark_signature,
+        starlark_signature_extraction, starlark_signatures,
+        values::{
+            error::{
+                RuntimeError, UnsupportedOperation, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            },
+            none::NoneType,
+            {Mutable, TypedValue, Value, ValueResult},
         },
-        none::NoneType,
-        {Mutable, TypedValue, Value, ValueResult},
     },
     starlark_dialect_build_targets::{ToOptional, TryToOptional},
     std::convert::TryFrom,
@@ -114,6 +118,46 @@ impl PythonInterpreterConfigValue {
     pub fn new(inner: PyembedPythonInterpreterConfig) -> Self {
         Self { inner }
     }
+
+    fn serialization_error(label: &str, e: anyhow::Error) -> ValueError {
+        ValueError::from(RuntimeError {
+            code: "PYOXIDIZER_INTERPRETER_CONFIG",
+            message: format!("{:?}", e),
+            label: label.to_string(),
+        })
+    }
+
+    fn starlark_export_json(&self) -> ValueResult {
+        let data = self
+            .inner
+            .to_json()
+            .map_err(|e| Self::serialization_error("export_json()", e))?;
+
+        Ok(Value::from(data))
+    }
+
+    fn starlark_export_toml(&self) -> ValueResult {
+        let data = self
+            .inner
+            .to_toml()
+            .map_err(|e| Self::serialization_error("export_toml()", e))?;
+
+        Ok(Value::from(data))
+    }
+
+    fn starlark_import_json(&mut self, data: String) -> ValueResult {
+        self.inner = PyembedPythonInterpreterConfig::from_json(&data)
+            .map_err(|e| Self::serialization_error("import_json()", e))?;
+
+        Ok(Value::from(NoneType::None))
+    }
+
+    fn starlark_import_toml(&mut self, data: String) -> ValueResult {
+        self.inner = PyembedPythonInterpreterConfig::from_toml(&data)
+            .map_err(|e| Self::serialization_error("import_toml()", e))?;
+
+        Ok(Value::from(NoneType::None))
+    }
 }
 
 impl TypedValue for PythonInterpreterConfigValue {
@@ -587,6 +631,28 @@ impl TypedValue for PythonInterpreterConfigValue {
     }
 }
 
+starlark_module! { python_interpreter_config_module =>
+    PythonInterpreterConfig.export_json(this) {
+        let this = this.downcast_ref::<PythonInterpreterConfigValue>().unwrap();
+        this.starlark_export_json()
+    }
+
+    PythonInterpreterConfig.export_toml(this) {
+        let this = this.downcast_ref::<PythonInterpreterConfigValue>().unwrap();
+        this.starlark_export_toml()
+    }
+
+    PythonInterpreterConfig.import_json(this, data: String) {
+        let mut this = this.downcast_mut::<PythonInterpreterConfigValue>().unwrap().unwrap();
+        this.starlark_import_json(data)
+    }
+
+    PythonInterpreterConfig.import_toml(this, data: String) {
+        let mut this = this.downcast_mut::<PythonInterpreterConfigValue>().unwrap().unwrap();
+        this.starlark_import_toml(data)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::starlark::eval::EvaluationContext;
@@ -1297,4 +1363,44 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_export_import_json() -> Result<()> {
+        let mut env = get_env()?;
+
+        env.eval("config.allocator_backend = 'rust'")?;
+        env.eval("config.utf8_mode = True")?;
+        env.eval("data = config.export_json()")?;
+        eval_assert(&mut env, "type(data) == 'string'")?;
+
+        env.eval("other = dist.make_python_interpreter_config()")?;
+        eval_assert(&mut env, "other.utf8_mode == None")?;
+        env.eval("other.import_json(data)")?;
+        eval_assert(&mut env, "other.allocator_backend == 'rust'")?;
+        eval_assert(&mut env, "other.utf8_mode == True")?;
+
+        assert!(env
+            .eval("other.import_json('{\"allocator_backend\": 42}')")
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_export_import_toml() -> Result<()> {
+        let mut env = get_env()?;
+
+        env.eval("config.sys_frozen = True")?;
+        env.eval("data = config.export_toml()")?;
+
+        env.eval("other = dist.make_python_interpreter_config()")?;
+        env.eval("other.import_toml(data)")?;
+        eval_assert(&mut env, "other.sys_frozen == True")?;
+
+        env.eval("other.import_toml('[interpreter_config]\\nprofile = \"python\"\\n')")?;
+        eval_assert(&mut env, "other.config_profile == 'python'")?;
+        eval_assert(&mut env, "other.sys_frozen == False")?;
+
+        Ok(())
+    }
 }
@@ -7,7 +7,11 @@ use {
     linked_hash_map::LinkedHashMap,
     python_packaging::{
         location::ConcreteResourceLocation,
-        policy::{ExtensionModuleFilter, PythonPackagingPolicy, ResourceHandlingMode},
+        policy::{
+            ExtensionModuleFilter, PythonPackagingPolicy, ResourceHandlingMode,
+            ResourcePlacementRule, ResourceType,
+        },
+        resource_collection::ResourceDecision,
     },
     starlark::{
         environment::TypeValues,
@@ -20,7 +24,9 @@ use {
             Mutable, TypedValue, Value, ValueResult,
         },
     },
-    starlark_dialect_build_targets::required_type_arg,
+    starlark_dialect_build_targets::{
+        optional_bool_arg, optional_list_arg, optional_str_arg, required_type_arg,
+    },
     std::convert::TryFrom,
     std::ops::Deref,
 };
@@ -58,6 +64,10 @@ impl PythonPackagingPolicyValue {
         let new_context = self
             .inner
             .derive_add_collection_context(&value.as_python_resource());
+        *value.add_collection_decisions_mut() = vec![ResourceDecision {
+            actor: "policy".to_string(),
+            message: new_context.describe(),
+        }];
         value.add_collection_context_mut().replace(new_context);
 
         for func in &self.derive_context_callbacks {
@@ -85,9 +95,18 @@ impl PythonPackagingPolicyValue {
 
             let downcast_value = temp_value.downcast_ref::<T>().unwrap();
             let inner: &T = downcast_value.deref();
+            let new_context = inner.add_collection_context().as_ref().unwrap();
+            let context = value.add_collection_context_mut().as_mut().unwrap();
+            let changes = context.describe_changes(new_context);
+            context.replace(new_context);
+
+            let actor = format!("callback {}", func.to_str());
             value
-                .add_collection_context_mut()
-                .replace(inner.add_collection_context().as_ref().unwrap().clone());
+                .add_collection_decisions_mut()
+                .extend(changes.into_iter().map(|message| ResourceDecision {
+                    actor: actor.clone(),
+                    message,
+                }));
         }
 
         Ok(Value::from(NoneType::None))
@@ -118,6 +137,15 @@ impl TypedValue for PythonPackagingPolicyValue {
             "extension_module_filter" => Value::from(self.inner.extension_module_filter().as_ref()),
             "file_scanner_classify_files" => Value::from(self.inner.file_scanner_classify_files()),
             "file_scanner_emit_files" => Value::from(self.inner.file_scanner_emit_files()),
+            "file_scanner_follow_external_paths" => {
+                Value::from(self.inner.file_scanner_follow_external_paths())
+            }
+            "filesystem_dependent_packages_location" => {
+                match self.inner.filesystem_dependent_packages_location() {
+                    Some(location) => Value::from(location.to_string()),
+                    None => Value::from(NoneType::None),
+                }
+            }
             "include_distribution_sources" => {
                 Value::from(self.inner.include_distribution_sources())
             }
@@ -135,6 +163,9 @@ impl TypedValue for PythonPackagingPolicyValue {
             "preferred_extension_module_variants" => {
                 Value::try_from(self.inner.preferred_extension_module_variants().clone())?
             }
+            "relocate_filesystem_dependent_packages" => {
+                Value::from(self.inner.relocate_filesystem_dependent_packages())
+            }
             "resources_location" => Value::from(self.inner.resources_location().to_string()),
             "resources_location_fallback" => match self.inner.resources_location_fallback() {
                 Some(location) => Value::from(location.to_string()),
@@ -163,6 +194,8 @@ impl TypedValue for PythonPackagingPolicyValue {
                 | "extension_module_filter"
                 | "file_scanner_classify_files"
                 | "file_scanner_emit_files"
+                | "file_scanner_follow_external_paths"
+                | "filesystem_dependent_packages_location"
                 | "include_distribution_sources"
                 | "include_distribution_resources"
                 | "include_classified_resources"
@@ -170,6 +203,7 @@ impl TypedValue for PythonPackagingPolicyValue {
                 | "include_non_distribution_sources"
                 | "include_test"
                 | "preferred_extension_module_variants"
+                | "relocate_filesystem_dependent_packages"
                 | "resources_location"
                 | "resources_location_fallback"
         ))
@@ -211,6 +245,33 @@ impl TypedValue for PythonPackagingPolicyValue {
             "file_scanner_emit_files" => {
                 self.inner.set_file_scanner_emit_files(value.to_bool());
             }
+            "file_scanner_follow_external_paths" => {
+                self.inner
+                    .set_file_scanner_follow_external_paths(value.to_bool());
+            }
+            "filesystem_dependent_packages_location" => {
+                if value.get_type() == "NoneType" {
+                    self.inner.set_filesystem_dependent_packages_location(None);
+                } else {
+                    let location = ConcreteResourceLocation::try_from(value.to_string().as_str())
+                        .and_then(|location| match location {
+                            ConcreteResourceLocation::RelativePath(_) => Ok(location),
+                            ConcreteResourceLocation::InMemory => {
+                                Err("only filesystem-relative locations are supported".to_string())
+                            }
+                        })
+                        .map_err(|e| {
+                            ValueError::from(RuntimeError {
+                                code: "PYOXIDIZER_BUILD",
+                                message: e,
+                                label: format!("{}.{} = {}", Self::TYPE, attribute, value),
+                            })
+                        })?;
+
+                    self.inner
+                        .set_filesystem_dependent_packages_location(Some(location));
+                }
+            }
             "include_classified_resources" => {
                 self.inner.set_include_classified_resources(value.to_bool());
             }
@@ -231,6 +292,10 @@ impl TypedValue for PythonPackagingPolicyValue {
             "include_test" => {
                 self.inner.set_include_test(value.to_bool());
             }
+            "relocate_filesystem_dependent_packages" => {
+                self.inner
+                    .set_relocate_filesystem_dependent_packages(value.to_bool());
+            }
             "resources_location" => {
                 self.inner.set_resources_location(
                     ConcreteResourceLocation::try_from(value.to_string().as_str()).map_err(
@@ -319,6 +384,84 @@ impl PythonPackagingPolicyValue {
 
         Ok(Value::from(NoneType::None))
     }
+
+    #[allow(clippy::too_many_arguments)]
+    fn starlark_add_resource_placement_rule(
+        &mut self,
+        pattern: &Value,
+        resource_types: &Value,
+        is_extension_module: &Value,
+        include: &Value,
+        location: &Value,
+        location_fallback: &Value,
+        store_source: &Value,
+        optimize_level_zero: &Value,
+        optimize_level_one: &Value,
+        optimize_level_two: &Value,
+    ) -> ValueResult {
+        const LABEL: &str = "add_resource_placement_rule()";
+
+        let parse_location = |value: &str| {
+            ConcreteResourceLocation::try_from(value).map_err(|e| {
+                ValueError::from(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e,
+                    label: LABEL.to_string(),
+                })
+            })
+        };
+
+        optional_list_arg("resource_types", "string", resource_types)?;
+        let resource_types = match resource_types.get_type() {
+            "list" => resource_types
+                .iter()?
+                .iter()
+                .map(|x| {
+                    ResourceType::try_from(x.to_string().as_str()).map_err(|e| {
+                        ValueError::from(RuntimeError {
+                            code: "PYOXIDIZER_BUILD",
+                            message: e,
+                            label: LABEL.to_string(),
+                        })
+                    })
+                })
+                .collect::<Result<Vec<_>, ValueError>>()?,
+            _ => vec![],
+        };
+
+        let location = optional_str_arg("location", location)?
+            .map(|x| parse_location(&x))
+            .transpose()?;
+
+        let location_fallback = match optional_str_arg("location_fallback", location_fallback)? {
+            Some(x) if x == "none" => Some(None),
+            Some(x) => Some(Some(parse_location(&x)?)),
+            None => None,
+        };
+
+        self.inner
+            .add_resource_placement_rule(ResourcePlacementRule {
+                pattern: optional_str_arg("pattern", pattern)?,
+                resource_types,
+                is_extension_module: optional_bool_arg("is_extension_module", is_extension_module)?,
+                include: optional_bool_arg("include", include)?,
+                location,
+                location_fallback,
+                store_source: optional_bool_arg("store_source", store_source)?,
+                optimize_level_zero: optional_bool_arg("optimize_level_zero", optimize_level_zero)?,
+                optimize_level_one: optional_bool_arg("optimize_level_one", optimize_level_one)?,
+                optimize_level_two: optional_bool_arg("optimize_level_two", optimize_level_two)?,
+            });
+
+        Ok(Value::from(NoneType::None))
+    }
+
+    #[allow(clippy::unnecessary_wraps)]
+    fn starlark_clear_resource_placement_rules(&mut self) -> ValueResult {
+        self.inner.clear_resource_placement_rules();
+
+        Ok(Value::from(NoneType::None))
+    }
 }
 
 starlark_module! { python_packaging_policy_module =>
@@ -340,6 +483,40 @@ starlark_module! { python_packaging_policy_module =>
         let mut this = this.downcast_mut::<PythonPackagingPolicyValue>().unwrap().unwrap();
         this.starlark_set_resource_handling_mode(mode)
     }
+
+    #[allow(clippy::ptr_arg)]
+    PythonPackagingPolicy.add_resource_placement_rule(
+        this,
+        pattern=NoneType::None,
+        resource_types=NoneType::None,
+        is_extension_module=NoneType::None,
+        include=NoneType::None,
+        location=NoneType::None,
+        location_fallback=NoneType::None,
+        store_source=NoneType::None,
+        optimize_level_zero=NoneType::None,
+        optimize_level_one=NoneType::None,
+        optimize_level_two=NoneType::None
+    ) {
+        let mut this = this.downcast_mut::<PythonPackagingPolicyValue>().unwrap().unwrap();
+        this.starlark_add_resource_placement_rule(
+            &pattern,
+            &resource_types,
+            &is_extension_module,
+            &include,
+            &location,
+            &location_fallback,
+            &store_source,
+            &optimize_level_zero,
+            &optimize_level_one,
+            &optimize_level_two,
+        )
+    }
+
+    PythonPackagingPolicy.clear_resource_placement_rules(this) {
+        let mut this = this.downcast_mut::<PythonPackagingPolicyValue>().unwrap().unwrap();
+        this.starlark_clear_resource_placement_rules()
+    }
 }
 
 #[cfg(test)]
@@ -396,6 +573,16 @@ mod tests {
         assert_eq!(value.get_type(), "bool");
         assert!(!value.to_bool());
 
+        let value = env.eval("policy.file_scanner_follow_external_paths")?;
+        assert_eq!(value.get_type(), "bool");
+        assert!(!value.to_bool());
+
+        let value = env.eval(
+            "policy.file_scanner_follow_external_paths = True; policy.file_scanner_follow_external_paths",
+        )?;
+        assert_eq!(value.get_type(), "bool");
+        assert!(value.to_bool());
+
         let value = env.eval("policy.file_scanner_emit_files")?;
         assert_eq!(value.get_type(), "bool");
         assert!(!value.to_bool());
@@ -501,6 +688,26 @@ mod tests {
         )?;
         assert_eq!(value.get_type(), "NoneType");
 
+        let value = env.eval("policy.filesystem_dependent_packages_location")?;
+        assert_eq!(value.get_type(), "NoneType");
+
+        let value = env.eval("policy.filesystem_dependent_packages_location = 'filesystem-relative:lib'; policy.filesystem_dependent_packages_location")?;
+        assert_eq!(value.to_string(), "filesystem-relative:lib");
+
+        assert!(env
+            .eval("policy.filesystem_dependent_packages_location = 'in-memory'")
+            .is_err());
+
+        let value = env.eval("policy.filesystem_dependent_packages_location = None; policy.filesystem_dependent_packages_location")?;
+        assert_eq!(value.get_type(), "NoneType");
+
+        let value = env.eval("policy.relocate_filesystem_dependent_packages")?;
+        assert_eq!(value.get_type(), "bool");
+        assert!(!value.to_bool());
+
+        let value = env.eval("policy.relocate_filesystem_dependent_packages = True; policy.relocate_filesystem_dependent_packages")?;
+        assert!(value.to_bool());
+
         let value = env.eval("policy.allow_files")?;
         assert_eq!(value.get_type(), "bool");
         assert!(!value.to_bool());
@@ -625,4 +832,58 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_add_resource_placement_rule() -> Result<()> {
+        let mut env = test_evaluation_context_builder()?.into_context()?;
+
+        env.eval("dist = default_python_distribution()")?;
+        env.eval("policy = dist.make_python_packaging_policy()")?;
+
+        assert!(env
+            .eval("policy.add_resource_placement_rule(location = 'invalid')")
+            .is_err());
+        assert!(env
+            .eval("policy.add_resource_placement_rule(resource_types = ['invalid'])")
+            .is_err());
+
+        env.eval("policy.add_resource_placement_rule(pattern = 'foo.*', resource_types = ['PythonModuleSource'], location = 'filesystem-relative:lib', location_fallback = 'none', store_source = True)")?;
+        env.eval("policy.add_resource_placement_rule(is_extension_module = True, location = 'in-memory', location_fallback = 'filesystem-relative:lib')")?;
+
+        let policy_value = env.eval("policy")?;
+        let policy = policy_value
+            .downcast_ref::<PythonPackagingPolicyValue>()
+            .unwrap();
+        assert_eq!(
+            policy.inner.resource_placement_rules(),
+            &[
+                ResourcePlacementRule {
+                    pattern: Some("foo.*".to_string()),
+                    resource_types: vec![ResourceType::ModuleSource],
+                    location: Some(ConcreteResourceLocation::RelativePath("lib".to_string())),
+                    location_fallback: Some(None),
+                    store_source: Some(true),
+                    ..ResourcePlacementRule::default()
+                },
+                ResourcePlacementRule {
+                    is_extension_module: Some(true),
+                    location: Some(ConcreteResourceLocation::InMemory),
+                    location_fallback: Some(Some(ConcreteResourceLocation::RelativePath(
+                        "lib".to_string()
+                    ))),
+                    ..ResourcePlacementRule::default()
+                },
+            ]
+        );
+        drop(policy);
+
+        env.eval("policy.clear_resource_placement_rules()")?;
+        let policy_value = env.eval("policy")?;
+        let policy = policy_value
+            .downcast_ref::<PythonPackagingPolicyValue>()
+            .unwrap();
+        assert!(policy.inner.resource_placement_rules().is_empty());
+
+        Ok(())
+    }
 }
@@ -10,7 +10,7 @@ use {
             PythonExtensionModule, PythonModuleSource, PythonPackageDistributionResource,
             PythonPackageResource, PythonResource,
         },
-        resource_collection::PythonResourceAddCollectionContext,
+        resource_collection::{PythonResourceAddCollectionContext, ResourceDecision},
     },
     starlark::{
         environment::TypeValues,
@@ -23,7 +23,10 @@ use {
             {Mutable, TypedValue, Value, ValueResult},
         },
     },
-    std::convert::{TryFrom, TryInto},
+    std::{
+        convert::{TryFrom, TryInto},
+        ops::Deref,
+    },
     tugger_file_manifest::File,
 };
 
@@ -105,6 +108,12 @@ pub trait ResourceCollectionContext {
     /// Obtain the mutable `PythonResourceAddCollectionContext` associated with this instance, if available.
     fn add_collection_context_mut(&mut self) -> &mut Option<PythonResourceAddCollectionContext>;
 
+    /// Obtain the decisions that influenced the add collection context.
+    fn add_collection_decisions(&self) -> &[ResourceDecision];
+
+    /// Obtain the mutable decisions that influenced the add collection context.
+    fn add_collection_decisions_mut(&mut self) -> &mut Vec<ResourceDecision>;
+
     /// Cast this instance to a `PythonResource`.
     fn as_python_resource(&self) -> PythonResource;
 
@@ -162,7 +171,9 @@ pub trait ResourceCollectionContext {
 
         match context {
             Some(context) => {
-                match attribute {
+                let before = context.clone();
+
+                let res = match attribute {
                     "add_bytecode_optimization_level_zero" => {
                         context.optimize_level_zero = value.to_bool();
                         Ok(())
@@ -216,7 +227,17 @@ pub trait ResourceCollectionContext {
                         Ok(())
                     }
                     attr => panic!("set_attr_add_collection_context({}) called when it shouldn't have been", attr)
-                }
+                };
+
+                let changes = before.describe_changes(context);
+                let actor = format!("assignment to {}", attribute);
+                self.add_collection_decisions_mut()
+                    .extend(changes.into_iter().map(|message| ResourceDecision {
+                        actor: actor.clone(),
+                        message,
+                    }));
+
+                res
             },
             None => Err(ValueError::from(RuntimeError {
                 code: "PYOXIDIZER",
@@ -232,6 +253,7 @@ pub trait ResourceCollectionContext {
 pub struct PythonModuleSourceValue {
     pub inner: PythonModuleSource,
     pub add_context: Option<PythonResourceAddCollectionContext>,
+    pub add_decisions: Vec<ResourceDecision>,
 }
 
 impl PythonModuleSourceValue {
@@ -239,6 +261,7 @@ impl PythonModuleSourceValue {
         Self {
             inner: module,
             add_context: None,
+            add_decisions: vec![],
         }
     }
 }
@@ -252,6 +275,14 @@ impl ResourceCollectionContext for PythonModuleSourceValue {
         &mut self.add_context
     }
 
+    fn add_collection_decisions(&self) -> &[ResourceDecision] {
+        &self.add_decisions
+    }
+
+    fn add_collection_decisions_mut(&mut self) -> &mut Vec<ResourceDecision> {
+        &mut self.add_decisions
+    }
+
     fn as_python_resource(&self) -> PythonResource<'_> {
         PythonResource::from(&self.inner)
     }
@@ -341,6 +372,7 @@ impl TypedValue for PythonModuleSourceValue {
 pub struct PythonPackageResourceValue {
     pub inner: PythonPackageResource,
     pub add_context: Option<PythonResourceAddCollectionContext>,
+    pub add_decisions: Vec<ResourceDecision>,
 }
 
 impl PythonPackageResourceValue {
@@ -348,6 +380,7 @@ impl PythonPackageResourceValue {
         Self {
             inner: resource,
             add_context: None,
+            add_decisions: vec![],
         }
     }
 }
@@ -361,6 +394,14 @@ impl ResourceCollectionContext for PythonPackageResourceValue {
         &mut self.add_context
     }
 
+    fn add_collection_decisions(&self) -> &[ResourceDecision] {
+        &self.add_decisions
+    }
+
+    fn add_collection_decisions_mut(&mut self) -> &mut Vec<ResourceDecision> {
+        &mut self.add_decisions
+    }
+
     fn as_python_resource(&self) -> PythonResource<'_> {
         PythonResource::from(&self.inner)
     }
@@ -429,6 +470,7 @@ impl TypedValue for PythonPackageResourceValue {
 pub struct PythonPackageDistributionResourceValue {
     pub inner: PythonPackageDistributionResource,
     pub add_context: Option<PythonResourceAddCollectionContext>,
+    pub add_decisions: Vec<ResourceDecision>,
 }
 
 impl PythonPackageDistributionResourceValue {
@@ -436,6 +478,7 @@ impl PythonPackageDistributionResourceValue {
         Self {
             inner: resource,
             add_context: None,
+            add_decisions: vec![],
         }
     }
 }
@@ -449,6 +492,14 @@ impl ResourceCollectionContext for PythonPackageDistributionResourceValue {
         &mut self.add_context
     }
 
+    fn add_collection_decisions(&self) -> &[ResourceDecision] {
+        &self.add_decisions
+    }
+
+    fn add_collection_decisions_mut(&mut self) -> &mut Vec<ResourceDecision> {
+        &mut self.add_decisions
+    }
+
     fn as_python_resource(&self) -> PythonResource<'_> {
         PythonResource::from(&self.inner)
     }
@@ -521,6 +572,7 @@ impl TypedValue for PythonPackageDistributionResourceValue {
 pub struct PythonExtensionModuleValue {
     pub inner: PythonExtensionModule,
     pub add_context: Option<PythonResourceAddCollectionContext>,
+    pub add_decisions: Vec<ResourceDecision>,
 }
 
 impl PythonExtensionModuleValue {
@@ -528,6 +580,7 @@ impl PythonExtensionModuleValue {
         Self {
             inner: em,
             add_context: None,
+            add_decisions: vec![],
         }
     }
 }
@@ -541,6 +594,14 @@ impl ResourceCollectionContext for PythonExtensionModuleValue {
         &mut self.add_context
     }
 
+    fn add_collection_decisions(&self) -> &[ResourceDecision] {
+        &self.add_decisions
+    }
+
+    fn add_collection_decisions_mut(&mut self) -> &mut Vec<ResourceDecision> {
+        &mut self.add_decisions
+    }
+
     fn as_python_resource(&self) -> PythonResource<'_> {
         PythonResource::from(&self.inner)
     }
@@ -600,6 +661,7 @@ impl TypedValue for PythonExtensionModuleValue {
 pub struct FileValue {
     pub inner: File,
     pub add_context: Option<PythonResourceAddCollectionContext>,
+    pub add_decisions: Vec<ResourceDecision>,
 }
 
 impl FileValue {
@@ -607,6 +669,7 @@ impl FileValue {
         Self {
             inner: file,
             add_context: None,
+            add_decisions: vec![],
         }
     }
 }
@@ -620,6 +683,14 @@ impl ResourceCollectionContext for FileValue {
         &mut self.add_context
     }
 
+    fn add_collection_decisions(&self) -> &[ResourceDecision] {
+        &self.add_decisions
+    }
+
+    fn add_collection_decisions_mut(&mut self) -> &mut Vec<ResourceDecision> {
+        &mut self.add_decisions
+    }
+
     fn as_python_resource(&self) -> PythonResource<'_> {
         PythonResource::from(&self.inner)
     }
@@ -743,36 +814,47 @@ pub fn python_resource_to_value(
 }
 
 /// Attempt to resolve the `PythonResourceAddCollectionContext` for a Value.
+///
+/// The decisions that influenced the context are returned alongside it.
 pub fn add_context_for_value(
     value: &Value,
     label: &str,
-) -> Result<Option<PythonResourceAddCollectionContext>, ValueError> {
-    match value.get_type() {
-        "PythonModuleSource" => Ok(value
-            .downcast_ref::<PythonModuleSourceValue>()
-            .unwrap()
-            .add_collection_context()
-            .clone()),
-        "PythonPackageResource" => Ok(value
-            .downcast_ref::<PythonPackageResourceValue>()
-            .unwrap()
-            .add_collection_context()
-            .clone()),
-        "PythonPackageDistributionResource" => Ok(value
-            .downcast_ref::<PythonPackageDistributionResourceValue>()
-            .unwrap()
+) -> Result<Option<(PythonResourceAddCollectionContext, Vec<ResourceDecision>)>, ValueError> {
+    fn resolve<T: ResourceCollectionContext>(
+        value: &T,
+    ) -> Option<(PythonResourceAddCollectionContext, Vec<ResourceDecision>)> {
+        value
             .add_collection_context()
-            .clone()),
-        "PythonExtensionModule" => Ok(value
-            .downcast_ref::<PythonExtensionModuleValue>()
-            .unwrap()
-            .add_collection_context()
-            .clone()),
-        "File" => Ok(value
-            .downcast_ref::<FileValue>()
-            .unwrap()
-            .add_collection_context()
-            .clone()),
+            .clone()
+            .map(|context| (context, value.add_collection_decisions().to_vec()))
+    }
+
+    match value.get_type() {
+        "PythonModuleSource" => Ok(resolve(
+            value
+                .downcast_ref::<PythonModuleSourceValue>()
+                .unwrap()
+                .deref(),
+        )),
+        "PythonPackageResource" => Ok(resolve(
+            value
+                .downcast_ref::<PythonPackageResourceValue>()
+                .unwrap()
+                .deref(),
+        )),
+        "PythonPackageDistributionResource" => Ok(resolve(
+            value
+                .downcast_ref::<PythonPackageDistributionResourceValue>()
+                .unwrap()
+                .deref(),
+        )),
+        "PythonExtensionModule" => Ok(resolve(
+            value
+                .downcast_ref::<PythonExtensionModuleValue>()
+                .unwrap()
+                .deref(),
+        )),
+        "File" => Ok(resolve(value.downcast_ref::<FileValue>().unwrap().deref())),
         t => Err(ValueError::from(RuntimeError {
             code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
             message: format!("unable to obtain add collection context from {}", t),
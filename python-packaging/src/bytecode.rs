@@ -10,8 +10,9 @@ use {
     byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt},
     std::{
         io::{BufRead, BufReader, Read, Write},
-        path::Path,
+        path::{Path, PathBuf},
         process,
+        sync::atomic::{AtomicUsize, Ordering},
     },
 };
 
@@ -30,6 +31,38 @@ pub trait PythonBytecodeCompiler {
         optimize: BytecodeOptimizationLevel,
         output_mode: CompileMode,
     ) -> Result<Vec<u8>>;
+
+    /// Compile multiple Python sources into bytecode.
+    ///
+    /// Results are returned in the same order as `requests`. The default
+    /// implementation compiles each request serially via `compile()`.
+    fn compile_many(&mut self, requests: &[CompileRequest]) -> Result<Vec<Vec<u8>>> {
+        requests
+            .iter()
+            .map(|request| {
+                self.compile(
+                    &request.source,
+                    &request.filename,
+                    request.optimize,
+                    request.output_mode,
+                )
+                .with_context(|| format!("compiling {}", request.filename))
+            })
+            .collect()
+    }
+}
+
+/// A request to compile Python source into bytecode.
+#[derive(Clone, Debug)]
+pub struct CompileRequest {
+    /// Python source code to compile.
+    pub source: Vec<u8>,
+    /// Filename to record in the compiled code object.
+    pub filename: String,
+    /// Optimization level to compile with.
+    pub optimize: BytecodeOptimizationLevel,
+    /// What to emit.
+    pub output_mode: CompileMode,
 }
 
 /// An entity to perform Python bytecode compilation.
@@ -42,6 +75,7 @@ pub struct BytecodeCompiler {
 }
 
 /// Output mode for BytecodeCompiler.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum CompileMode {
     /// Emit just Python bytecode.
     Bytecode,
@@ -221,6 +255,170 @@ impl Drop for BytecodeCompiler {
     }
 }
 
+/// A pool of `BytecodeCompiler` which compiles bytecode in parallel.
+///
+/// Each member of the pool is a separate Python process. `compile_many()`
+/// distributes requests across all processes. Results are always returned
+/// in request order, so output is deterministic regardless of how work
+/// is scheduled.
+#[derive(Debug)]
+pub struct BytecodeCompilerPool {
+    compilers: Vec<BytecodeCompiler>,
+
+    /// Python executable used to start compilers.
+    python: PathBuf,
+
+    /// Directory holding the compiler script when a compiler is started.
+    script_dir: PathBuf,
+
+    /// Magic number for bytecode header.
+    magic_number: u32,
+}
+
+impl BytecodeCompilerPool {
+    /// Create a pool of bytecode compilers using a Python executable.
+    ///
+    /// `workers` is the number of Python processes to start. A value of `0`
+    /// is treated as `1`. See `default_worker_count()` for a sensible default.
+    ///
+    /// `script_dir` has the same semantics as for `BytecodeCompiler::new()`.
+    /// It must exist for as long as the pool is used, as it is needed to
+    /// replace compilers whose worker thread panicked.
+    pub fn new(
+        python: &Path,
+        script_dir: impl AsRef<Path>,
+        workers: usize,
+    ) -> Result<BytecodeCompilerPool> {
+        let compilers = (0..workers.max(1))
+            .map(|_| BytecodeCompiler::new(python, script_dir.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let magic_number = compilers[0].get_magic_number();
+
+        Ok(BytecodeCompilerPool {
+            compilers,
+            python: python.to_path_buf(),
+            script_dir: script_dir.as_ref().to_path_buf(),
+            magic_number,
+        })
+    }
+
+    /// The default number of workers for a pool.
+    ///
+    /// This is the number of logical CPUs.
+    pub fn default_worker_count() -> usize {
+        num_cpus::get()
+    }
+
+    /// The number of Python processes in this pool.
+    pub fn worker_count(&self) -> usize {
+        self.compilers.len()
+    }
+}
+
+impl PythonBytecodeCompiler for BytecodeCompilerPool {
+    fn get_magic_number(&self) -> u32 {
+        self.magic_number
+    }
+
+    fn compile(
+        &mut self,
+        source: &[u8],
+        filename: &str,
+        optimize: BytecodeOptimizationLevel,
+        output_mode: CompileMode,
+    ) -> Result<Vec<u8>> {
+        self.compilers[0].compile(source, filename, optimize, output_mode)
+    }
+
+    fn compile_many(&mut self, requests: &[CompileRequest]) -> Result<Vec<Vec<u8>>> {
+        if self.compilers.len() < 2 || requests.len() < 2 {
+            return self.compilers[0].compile_many(requests);
+        }
+
+        let next = AtomicUsize::new(0);
+
+        // Each thread borrows a compiler and pulls request indices from a
+        // shared counter until all are consumed. This balances load without
+        // having to know the cost of each request up front.
+        let (mut results, panicked) = crossbeam_utils::thread::scope(|scope| {
+            let handles = self
+                .compilers
+                .iter_mut()
+                .map(|compiler| {
+                    let next = &next;
+
+                    scope.spawn(move |_| {
+                        let mut results = vec![];
+
+                        loop {
+                            let index = next.fetch_add(1, Ordering::SeqCst);
+                            let request = match requests.get(index) {
+                                Some(request) => request,
+                                None => break,
+                            };
+
+                            results.push((
+                                index,
+                                compiler.compile(
+                                    &request.source,
+                                    &request.filename,
+                                    request.optimize,
+                                    request.output_mode,
+                                ),
+                            ));
+                        }
+
+                        results
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let mut results = Vec::with_capacity(requests.len());
+            results.resize_with(requests.len(), || None);
+            let mut panicked = vec![];
+
+            for (worker, handle) in handles.into_iter().enumerate() {
+                match handle.join() {
+                    Ok(compiled) => {
+                        for (index, result) in compiled {
+                            results[index] = Some(result);
+                        }
+                    }
+                    Err(_) => panicked.push(worker),
+                }
+            }
+
+            (results, panicked)
+        })
+        .map_err(|_| anyhow!("bytecode compiler thread panicked"))?;
+
+        // A compiler whose thread panicked may be in the middle of a request.
+        // Replace it so the pool remains usable.
+        for worker in &panicked {
+            self.compilers[*worker] = BytecodeCompiler::new(&self.python, &self.script_dir)?;
+        }
+
+        if !panicked.is_empty() {
+            return Err(anyhow!(
+                "{} bytecode compiler thread(s) panicked",
+                panicked.len()
+            ));
+        }
+
+        // Iterate in request order so the reported error is deterministic.
+        results
+            .drain(..)
+            .zip(requests.iter())
+            .map(|(result, request)| {
+                result
+                    .ok_or_else(|| anyhow!("no bytecode compiled for {}", request.filename))?
+                    .with_context(|| format!("compiling {}", request.filename))
+            })
+            .collect()
+    }
+}
+
 /// How to write out a .pyc bytecode header.
 #[derive(Debug, Clone, Copy)]
 pub enum BytecodeHeaderMode {
@@ -263,6 +461,71 @@ pub fn compute_bytecode_header(magic_number: u32, mode: BytecodeHeaderMode) -> R
 mod tests {
     use super::*;
 
+    /// Find a Python 3 executable on `PATH` to run compilers with.
+    fn find_python() -> Option<PathBuf> {
+        let name = if cfg!(windows) {
+            "python.exe"
+        } else {
+            "python3"
+        };
+
+        std::env::split_paths(&std::env::var_os("PATH")?)
+            .map(|dir| dir.join(name))
+            .find(|path| path.is_file())
+    }
+
+    fn request(filename: &str, source: &str) -> CompileRequest {
+        CompileRequest {
+            source: source.as_bytes().to_vec(),
+            filename: filename.to_string(),
+            optimize: BytecodeOptimizationLevel::Zero,
+            output_mode: CompileMode::Bytecode,
+        }
+    }
+
+    #[test]
+    fn test_pool_compile_many() -> Result<()> {
+        let python = match find_python() {
+            Some(python) => python,
+            None => {
+                eprintln!("python3 not found; skipping");
+                return Ok(());
+            }
+        };
+
+        let temp_dir = tempfile::TempDir::new()?;
+        let mut pool = BytecodeCompilerPool::new(&python, temp_dir.path(), 3)?;
+        assert_eq!(pool.worker_count(), 3);
+        let mut serial = BytecodeCompiler::new(&python, temp_dir.path())?;
+        assert_eq!(pool.get_magic_number(), serial.get_magic_number());
+
+        let requests = (0..32)
+            .map(|i| {
+                request(
+                    &format!("m{}.py", i),
+                    &format!("value = '{}'\n", "x".repeat(i + 1)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Results are in request order and identical to serial compilation.
+        let compiled = pool.compile_many(&requests)?;
+        assert_eq!(compiled, serial.compile_many(&requests)?);
+        assert_eq!(compiled.len(), requests.len());
+
+        // The first failing request in request order is reported.
+        let mut bad = requests.clone();
+        bad[20] = request("bad20.py", "def (\n");
+        bad[5] = request("bad5.py", "if\n");
+        let err = pool.compile_many(&bad).unwrap_err();
+        assert_eq!(err.to_string(), "compiling bad5.py");
+
+        // Compilers remain usable after an error.
+        assert_eq!(pool.compile_many(&requests)?, compiled);
+
+        Ok(())
+    }
+
     #[test]
     fn test_header() -> Result<()> {
         assert_eq!(
@@ -0,0 +1,483 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Persistent caching of compiled Python bytecode.
+
+Compiling bytecode requires invoking a Python interpreter and can be
+expensive for large sets of modules. This module defines an on-disk,
+content-addressed cache of compilation results so that bytecode can be
+reused across builds.
+
+Cache entries are keyed by everything that influences compilation output:
+the bytecode magic number, the optimization level, the `CompileMode`, the
+filename recorded in the code object and the source code itself.
+*/
+
+use {
+    crate::{
+        bytecode::{CompileMode, CompileRequest, PythonBytecodeCompiler},
+        resource::BytecodeOptimizationLevel,
+    },
+    anyhow::{Context, Result},
+    sha2::Digest,
+    std::{
+        io::Write,
+        path::{Path, PathBuf},
+        time::SystemTime,
+    },
+};
+
+/// An on-disk cache of compiled Python bytecode.
+///
+/// Entries are stored as individual files in a directory. The cache is safe
+/// to use from multiple processes simultaneously: entries are written to a
+/// temporary file and atomically renamed into place.
+#[derive(Clone, Debug)]
+pub struct BytecodeCache {
+    path: PathBuf,
+
+    /// Maximum size in bytes of all cache entries.
+    max_size: Option<u64>,
+}
+
+impl BytecodeCache {
+    /// Construct an instance storing entries in a directory.
+    ///
+    /// `max_size` is the maximum size in bytes of all cache entries. When
+    /// exceeded, `prune()` removes the least recently used entries.
+    pub fn new(path: impl AsRef<Path>, max_size: Option<u64>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            max_size,
+        }
+    }
+
+    /// The directory holding cache entries.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Compute the cache key for a compilation request.
+    pub fn cache_key(magic_number: u32, request: &CompileRequest) -> String {
+        let mut hasher = sha2::Sha256::new();
+
+        hasher.update(magic_number.to_le_bytes());
+        hasher.update([match request.optimize {
+            BytecodeOptimizationLevel::Zero => 0u8,
+            BytecodeOptimizationLevel::One => 1,
+            BytecodeOptimizationLevel::Two => 2,
+        }]);
+        hasher.update([match request.output_mode {
+            CompileMode::Bytecode => 0u8,
+            CompileMode::PycCheckedHash => 1,
+            CompileMode::PycUncheckedHash => 2,
+        }]);
+        hasher.update((request.filename.len() as u64).to_le_bytes());
+        hasher.update(request.filename.as_bytes());
+        hasher.update(&request.source);
+
+        hex::encode(hasher.finalize())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.path.join(&key[0..2]).join(key)
+    }
+
+    /// Obtain cached bytecode for a compilation request.
+    ///
+    /// Returns `None` if there is no cache entry.
+    pub fn get(&self, magic_number: u32, request: &CompileRequest) -> Result<Option<Vec<u8>>> {
+        let path = self.entry_path(&Self::cache_key(magic_number, request));
+
+        match std::fs::read(&path) {
+            Ok(data) => {
+                // Record the access so pruning evicts least recently used
+                // entries first. Failure is harmless.
+                let _ = filetime::set_file_mtime(&path, filetime::FileTime::now());
+
+                Ok(Some(data))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+
+    /// Store compiled bytecode for a compilation request.
+    pub fn put(&self, magic_number: u32, request: &CompileRequest, bytecode: &[u8]) -> Result<()> {
+        let key = Self::cache_key(magic_number, request);
+        let path = self.entry_path(&key);
+        let parent = path.parent().expect("entry path should have parent");
+
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating directory {}", parent.display()))?;
+
+        let temp_path = parent.join(format!("{}.{}.tmp", key, std::process::id()));
+
+        {
+            let mut fh = std::fs::File::create(&temp_path)
+                .with_context(|| format!("creating {}", temp_path.display()))?;
+            fh.write_all(bytecode)
+                .with_context(|| format!("writing {}", temp_path.display()))?;
+        }
+
+        std::fs::rename(&temp_path, &path)
+            .with_context(|| format!("renaming {} to {}", temp_path.display(), path.display()))
+    }
+
+    /// Obtain all cache entries and their sizes and modified times.
+    fn entries(&self) -> Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let mut res = vec![];
+
+        if !self.path.exists() {
+            return Ok(res);
+        }
+
+        for entry in walkdir::WalkDir::new(&self.path) {
+            let entry = entry?;
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let metadata = entry.metadata()?;
+            res.push((
+                entry.path().to_path_buf(),
+                metadata.len(),
+                metadata.modified()?,
+            ));
+        }
+
+        Ok(res)
+    }
+
+    /// The total size in bytes of all cache entries.
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.entries()?.iter().map(|(_, size, _)| size).sum())
+    }
+
+    /// Remove least recently used entries until the cache is within its size limit.
+    ///
+    /// Returns the number of bytes removed.
+    pub fn prune(&self) -> Result<u64> {
+        let max_size = if let Some(max_size) = self.max_size {
+            max_size
+        } else {
+            return Ok(0);
+        };
+
+        let mut entries = self.entries()?;
+        let mut size = entries.iter().map(|(_, size, _)| size).sum::<u64>();
+        let mut removed = 0;
+
+        entries.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(&b.0)));
+
+        for (path, entry_size, _) in entries {
+            if size <= max_size {
+                break;
+            }
+
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                // Another process may have removed the entry.
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(e).with_context(|| format!("removing {}", path.display()));
+                }
+            }
+
+            size -= entry_size;
+            removed += entry_size;
+        }
+
+        Ok(removed)
+    }
+
+    /// Remove all cache entries.
+    pub fn clear(&self) -> Result<()> {
+        if self.path.exists() {
+            std::fs::remove_dir_all(&self.path)
+                .with_context(|| format!("removing {}", self.path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A `PythonBytecodeCompiler` that consults a `BytecodeCache` before compiling.
+///
+/// Requests not in the cache are forwarded to the wrapped compiler and
+/// their results are stored in the cache.
+///
+/// The cache isn't pruned automatically, as that requires walking the
+/// entire cache directory. Call `prune()` once compilation is done.
+pub struct CachingBytecodeCompiler<'a> {
+    compiler: &'a mut dyn PythonBytecodeCompiler,
+    cache: &'a BytecodeCache,
+    hits: usize,
+    misses: usize,
+}
+
+impl<'a> CachingBytecodeCompiler<'a> {
+    pub fn new(compiler: &'a mut dyn PythonBytecodeCompiler, cache: &'a BytecodeCache) -> Self {
+        Self {
+            compiler,
+            cache,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// The number of requests satisfied by the cache.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// The number of requests that had to be compiled.
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Prune the cache if this compiler added entries to it.
+    ///
+    /// Returns the number of bytes removed.
+    pub fn prune(&self) -> Result<u64> {
+        if self.misses == 0 {
+            Ok(0)
+        } else {
+            self.cache.prune().context("pruning bytecode cache")
+        }
+    }
+}
+
+impl<'a> PythonBytecodeCompiler for CachingBytecodeCompiler<'a> {
+    fn get_magic_number(&self) -> u32 {
+        self.compiler.get_magic_number()
+    }
+
+    fn compile(
+        &mut self,
+        source: &[u8],
+        filename: &str,
+        optimize: BytecodeOptimizationLevel,
+        output_mode: CompileMode,
+    ) -> Result<Vec<u8>> {
+        let request = CompileRequest {
+            source: source.to_vec(),
+            filename: filename.to_string(),
+            optimize,
+            output_mode,
+        };
+
+        Ok(self
+            .compile_many(&[request])?
+            .pop()
+            .expect("should have compiled 1 request"))
+    }
+
+    fn compile_many(&mut self, requests: &[CompileRequest]) -> Result<Vec<Vec<u8>>> {
+        let magic_number = self.get_magic_number();
+
+        let mut results = Vec::with_capacity(requests.len());
+        let mut missing = vec![];
+
+        for (index, request) in requests.iter().enumerate() {
+            let cached = self.cache.get(magic_number, request)?;
+
+            if cached.is_none() {
+                missing.push(index);
+            }
+
+            results.push(cached);
+        }
+
+        self.hits += requests.len() - missing.len();
+        self.misses += missing.len();
+
+        let missing_requests = missing
+            .iter()
+            .map(|index| requests[*index].clone())
+            .collect::<Vec<_>>();
+
+        let compiled = self.compiler.compile_many(&missing_requests)?;
+
+        for ((index, request), bytecode) in missing
+            .into_iter()
+            .zip(missing_requests.iter())
+            .zip(compiled)
+        {
+            self.cache.put(magic_number, request, &bytecode)?;
+            results[index] = Some(bytecode);
+        }
+
+        Ok(results
+            .into_iter()
+            .map(|x| x.expect("all requests should be resolved"))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, anyhow::anyhow};
+
+    /// A compiler which records how many times it compiled something.
+    struct CountingCompiler {
+        compiled: usize,
+    }
+
+    impl PythonBytecodeCompiler for CountingCompiler {
+        fn get_magic_number(&self) -> u32 {
+            42
+        }
+
+        fn compile(
+            &mut self,
+            source: &[u8],
+            filename: &str,
+            optimize: BytecodeOptimizationLevel,
+            _output_mode: CompileMode,
+        ) -> Result<Vec<u8>> {
+            if source.is_empty() {
+                return Err(anyhow!("empty source"));
+            }
+
+            self.compiled += 1;
+
+            let mut res = format!("{}:{}:", filename, i32::from(optimize)).into_bytes();
+            res.extend(source);
+
+            Ok(res)
+        }
+    }
+
+    fn request(source: &[u8], optimize: BytecodeOptimizationLevel) -> CompileRequest {
+        CompileRequest {
+            source: source.to_vec(),
+            filename: "foo".to_string(),
+            optimize,
+            output_mode: CompileMode::Bytecode,
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let a = request(b"a", BytecodeOptimizationLevel::Zero);
+        let key = BytecodeCache::cache_key(42, &a);
+
+        assert_eq!(key, BytecodeCache::cache_key(42, &a.clone()));
+        assert_ne!(key, BytecodeCache::cache_key(43, &a));
+        assert_ne!(
+            key,
+            BytecodeCache::cache_key(42, &request(b"a", BytecodeOptimizationLevel::One))
+        );
+        assert_ne!(
+            key,
+            BytecodeCache::cache_key(42, &request(b"b", BytecodeOptimizationLevel::Zero))
+        );
+
+        let mut pyc = a.clone();
+        pyc.output_mode = CompileMode::PycUncheckedHash;
+        assert_ne!(key, BytecodeCache::cache_key(42, &pyc));
+
+        let mut renamed = a;
+        renamed.filename = "bar".to_string();
+        assert_ne!(key, BytecodeCache::cache_key(42, &renamed));
+    }
+
+    #[test]
+    fn test_caching_compiler() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let cache = BytecodeCache::new(temp_dir.path().join("cache"), None);
+
+        let requests = vec![
+            request(b"a", BytecodeOptimizationLevel::Zero),
+            request(b"b", BytecodeOptimizationLevel::Two),
+        ];
+
+        let mut inner = CountingCompiler { compiled: 0 };
+        let mut compiler = CachingBytecodeCompiler::new(&mut inner, &cache);
+        let first = compiler.compile_many(&requests)?;
+        assert_eq!(first, vec![b"foo:0:a".to_vec(), b"foo:2:b".to_vec()]);
+        assert_eq!(compiler.misses(), 2);
+        assert_eq!(inner.compiled, 2);
+
+        let mut inner = CountingCompiler { compiled: 0 };
+        let mut compiler = CachingBytecodeCompiler::new(&mut inner, &cache);
+        let second = compiler.compile_many(&[
+            request(b"c", BytecodeOptimizationLevel::Zero),
+            request(b"a", BytecodeOptimizationLevel::Zero),
+        ])?;
+        assert_eq!(second, vec![b"foo:0:c".to_vec(), b"foo:0:a".to_vec()]);
+        assert_eq!(compiler.hits(), 1);
+        assert_eq!(compiler.misses(), 1);
+        assert_eq!(inner.compiled, 1);
+
+        // Errors aren't cached.
+        let mut inner = CountingCompiler { compiled: 0 };
+        let mut compiler = CachingBytecodeCompiler::new(&mut inner, &cache);
+        assert!(compiler
+            .compile_many(&[request(b"", BytecodeOptimizationLevel::Zero)])
+            .is_err());
+        assert_eq!(cache.size()?, 21);
+
+        cache.clear()?;
+        assert_eq!(cache.size()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prune() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let cache = BytecodeCache::new(temp_dir.path(), Some(10));
+
+        let old = request(b"old", BytecodeOptimizationLevel::Zero);
+        let new = request(b"new", BytecodeOptimizationLevel::Zero);
+
+        cache.put(42, &old, b"0123456789")?;
+        filetime::set_file_mtime(
+            cache.entry_path(&BytecodeCache::cache_key(42, &old)),
+            filetime::FileTime::from_unix_time(1, 0),
+        )?;
+        cache.put(42, &new, b"abcde")?;
+
+        assert_eq!(cache.size()?, 15);
+        assert_eq!(cache.prune()?, 10);
+        assert_eq!(cache.get(42, &old)?, None);
+        assert_eq!(cache.get(42, &new)?, Some(b"abcde".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_caching_compiler_prune() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let cache = BytecodeCache::new(temp_dir.path(), Some(10));
+
+        let a = request(b"a", BytecodeOptimizationLevel::Zero);
+        let b = request(b"b", BytecodeOptimizationLevel::Zero);
+
+        // Compiling doesn't prune by itself.
+        let mut inner = CountingCompiler { compiled: 0 };
+        let mut compiler = CachingBytecodeCompiler::new(&mut inner, &cache);
+        compiler.compile_many(&[a.clone(), b.clone()])?;
+        assert_eq!(cache.size()?, 14);
+
+        assert_eq!(compiler.prune()?, 7);
+        assert_eq!(cache.size()?, 7);
+
+        // Nothing is pruned when only cache hits occurred.
+        let remaining = if cache.get(42, &a)?.is_some() { a } else { b };
+
+        let cache = BytecodeCache::new(temp_dir.path(), Some(0));
+        let mut inner = CountingCompiler { compiled: 0 };
+        let mut compiler = CachingBytecodeCompiler::new(&mut inner, &cache);
+        compiler.compile_many(&[remaining])?;
+        assert_eq!(compiler.hits(), 1);
+        assert_eq!(compiler.prune()?, 0);
+        assert_eq!(cache.size()?, 7);
+
+        Ok(())
+    }
+}
@@ -17,7 +17,7 @@ use {
             PythonResource,
         },
     },
-    anyhow::Result,
+    anyhow::{anyhow, Context, Result},
     std::{
         collections::HashSet,
         ffi::OsStr,
@@ -76,15 +76,47 @@ enum PathItem<'a> {
 #[derive(Debug, PartialEq)]
 struct PathEntry {
     path: PathBuf,
+    /// The root directory `path` was found under.
+    ///
+    /// This is the scanned directory unless the path was discovered by
+    /// following a `.pth` or `.egg-link` file.
+    root: PathBuf,
     /// Whether we emitted a `PythonResource::File` instance.
     file_emitted: bool,
     /// Whether we emitted a non-`PythonResource::File` instance.
     non_file_emitted: bool,
 }
 
+/// Obtain `PathEntry` for all files under a directory, sorted by name.
+fn walk_path_entries(root: &Path) -> Vec<PathEntry> {
+    walk_tree_files(root)
+        .map(|entry| PathEntry {
+            path: entry.path().to_path_buf(),
+            root: root.to_path_buf(),
+            file_emitted: false,
+            non_file_emitted: false,
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Obtain a path as a `&str`, erroring if it isn't valid UTF-8.
+fn path_to_str(path: &Path) -> Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!("path {} is not valid UTF-8", path.display()))
+}
+
+/// Obtain the components of a path as `&str`.
+fn path_components(path: &Path) -> Result<Vec<&str>> {
+    path.iter()
+        .map(|p| {
+            p.to_str()
+                .ok_or_else(|| anyhow!("path {} is not valid UTF-8", path.display()))
+        })
+        .collect()
+}
+
 /// An iterator of `PythonResource`.
 pub struct PythonResourceIterator<'a> {
-    root_path: PathBuf,
     cache_tag: String,
     suffixes: PythonModuleSuffixes,
     paths: Vec<PathEntry>,
@@ -98,6 +130,13 @@ pub struct PythonResourceIterator<'a> {
     emit_files: bool,
     // Whether to emit non-`PythonResource::File` entries.
     emit_non_files: bool,
+    // Whether to scan directories referenced by `.pth` and `.egg-link` files.
+    follow_path_extensions: bool,
+    // Whether directories outside the site directory can be followed.
+    follow_external_paths: bool,
+    // Canonical paths of directories being scanned.
+    scanned_roots: Vec<PathBuf>,
+    warnings: Vec<String>,
     _phantom: std::marker::PhantomData<&'a ()>,
 }
 
@@ -109,37 +148,19 @@ impl<'a> PythonResourceIterator<'a> {
         emit_files: bool,
         emit_non_files: bool,
     ) -> PythonResourceIterator<'a> {
-        let res = walkdir::WalkDir::new(path).sort_by(|a, b| a.file_name().cmp(b.file_name()));
-
-        let filtered = res
-            .into_iter()
-            .filter_map(|entry| {
-                let entry = entry.expect("unable to get directory entry");
-
-                let path = entry.path();
-
-                if path.is_dir() {
-                    None
-                } else {
-                    Some(PathEntry {
-                        path: path.to_path_buf(),
-                        file_emitted: false,
-                        non_file_emitted: false,
-                    })
-                }
-            })
-            .collect::<Vec<_>>();
-
         PythonResourceIterator {
-            root_path: path.to_path_buf(),
             cache_tag: cache_tag.to_string(),
             suffixes: suffixes.clone(),
-            paths: filtered,
+            paths: walk_path_entries(path),
             path_content_overrides: FileManifest::default(),
             seen_packages: HashSet::new(),
             resources: Vec::new(),
             emit_files,
             emit_non_files,
+            follow_path_extensions: true,
+            follow_external_paths: false,
+            scanned_roots: vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())],
+            warnings: vec![],
             _phantom: std::marker::PhantomData,
         }
     }
@@ -156,6 +177,7 @@ impl<'a> PythonResourceIterator<'a> {
             .iter()
             .map(|file| PathEntry {
                 path: file.path.clone(),
+                root: PathBuf::new(),
                 file_emitted: false,
                 non_file_emitted: false,
             })
@@ -168,7 +190,6 @@ impl<'a> PythonResourceIterator<'a> {
         }
 
         Ok(PythonResourceIterator {
-            root_path: PathBuf::new(),
             cache_tag: cache_tag.to_string(),
             suffixes: suffixes.clone(),
             paths,
@@ -177,10 +198,33 @@ impl<'a> PythonResourceIterator<'a> {
             resources: Vec::new(),
             emit_files,
             emit_non_files,
+            follow_path_extensions: false,
+            follow_external_paths: false,
+            scanned_roots: vec![],
+            warnings: vec![],
             _phantom: std::marker::PhantomData,
         })
     }
 
+    /// Warnings encountered during iteration.
+    ///
+    /// These describe content that could not be represented as resources,
+    /// such as `.pth` lines executing code. They accumulate as the iterator
+    /// is consumed.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
+    /// Set whether directories outside the site directory can be scanned.
+    ///
+    /// `.pth` and `.egg-link` files can reference arbitrary directories,
+    /// such as the source checkout of a `setup.py develop` install. By
+    /// default, only directories under the site directory holding the file
+    /// are scanned and a warning is recorded for the others.
+    pub fn set_follow_external_paths(&mut self, value: bool) {
+        self.follow_external_paths = value;
+    }
+
     fn resolve_is_executable(&self, path: &Path) -> bool {
         match self.path_content_overrides.get(path) {
             Some(file) => file.executable,
@@ -201,15 +245,151 @@ impl<'a> PythonResourceIterator<'a> {
         }
     }
 
-    fn resolve_path(&mut self, path: &Path) -> Option<PathItem<'a>> {
-        let mut rel_path = path
-            .strip_prefix(&self.root_path)
-            .expect("unable to strip path prefix");
-        let mut rel_str = rel_path.to_str().expect("could not convert path to str");
-        let mut components = rel_path
+    /// Process a `.pth` or `.egg-link` file, scanning directories it adds to `sys.path`.
+    ///
+    /// Only files directly in a site directory are processed, as this is
+    /// where Python's `site` module looks for them.
+    fn process_path_extension(&mut self, entry: &PathEntry) {
+        let parent = match entry.path.parent() {
+            Some(parent) => parent,
+            None => return,
+        };
+
+        if parent != entry.root && parent != entry.root.join("site-packages") {
+            return;
+        }
+
+        let is_pth = entry.path.extension() == Some(OsStr::new("pth"));
+        let is_egg_link = entry.path.extension() == Some(OsStr::new("egg-link"));
+
+        if !is_pth && !is_egg_link {
+            return;
+        }
+
+        let data = match self.resolve_file_data(&entry.path).resolve() {
+            Ok(data) => data,
+            Err(e) => {
+                self.warnings
+                    .push(format!("unable to read {}: {}", entry.path.display(), e));
+                return;
+            }
+        };
+        let data = String::from_utf8_lossy(&data);
+
+        if is_pth {
+            // Semantics mirror site.addpackage(): lines are comments, code to
+            // execute, or paths relative to the site directory. Paths that
+            // don't exist are silently ignored.
+            for (index, line) in data.lines().enumerate() {
+                let line = line.trim_end();
+
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+
+                if line.starts_with("import ") || line.starts_with("import\t") {
+                    self.warnings.push(format!(
+                        "{}:{}: executable .pth line will not be run by the packaged application: {}",
+                        entry.path.display(),
+                        index + 1,
+                        line
+                    ));
+                    continue;
+                }
+
+                let path = parent.join(line);
+                if path.is_dir() {
+                    self.scan_directory(&entry.path, parent, &path);
+                }
+            }
+        } else {
+            // .egg-link files are written by `setup.py develop`. The first
+            // line is the directory added to sys.path.
+            match data.lines().next().map(|line| line.trim()) {
+                Some(line) if !line.is_empty() => {
+                    let path = parent.join(line);
+
+                    if path.is_dir() {
+                        self.scan_directory(&entry.path, parent, &path);
+                    } else {
+                        self.warnings.push(format!(
+                            "{} references missing directory {}",
+                            entry.path.display(),
+                            path.display()
+                        ));
+                    }
+                }
+                _ => {
+                    self.warnings
+                        .push(format!("{} does not contain a path", entry.path.display()));
+                }
+            }
+        }
+    }
+
+    /// Add files in a directory referenced by a path extension to the scan.
+    ///
+    /// `source` is the path extension file and `site_dir` the directory
+    /// holding it. Directories overlapping with one already being scanned
+    /// are ignored so files aren't emitted multiple times.
+    fn scan_directory(&mut self, source: &Path, site_dir: &Path, path: &Path) {
+        if !self.follow_path_extensions {
+            return;
+        }
+
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let site_dir = site_dir
+            .canonicalize()
+            .unwrap_or_else(|_| site_dir.to_path_buf());
+
+        if !self.follow_external_paths && !canonical.starts_with(&site_dir) {
+            self.warnings.push(format!(
+                "{} references {}, which is outside {} and will not be scanned",
+                source.display(),
+                path.display(),
+                site_dir.display()
+            ));
+            return;
+        }
+
+        if self
+            .scanned_roots
             .iter()
-            .map(|p| p.to_str().expect("unable to get path as str"))
-            .collect::<Vec<_>>();
+            .any(|root| canonical.starts_with(root) || root.starts_with(&canonical))
+        {
+            return;
+        }
+
+        self.scanned_roots.push(canonical);
+        self.paths.extend(walk_path_entries(path));
+    }
+
+    /// Resolve the package name and version from a distribution metadata file.
+    fn resolve_distribution_metadata(&self, metadata_path: &Path) -> Option<(String, String)> {
+        let data = if let Some(file) = self.path_content_overrides.get(metadata_path) {
+            file.data.resolve().ok()?
+        } else {
+            std::fs::read(metadata_path).ok()?
+        };
+
+        let metadata = PythonPackageMetadata::from_metadata(&data).ok()?;
+
+        Some((
+            metadata.name()?.to_string(),
+            metadata.version()?.to_string(),
+        ))
+    }
+
+    fn resolve_path(&mut self, root: &Path, path: &Path) -> Result<Option<PathItem<'a>>> {
+        let mut rel_path = path.strip_prefix(root).with_context(|| {
+            format!(
+                "{} is not under scanned directory {}",
+                path.display(),
+                root.display()
+            )
+        })?;
+        let mut rel_str = path_to_str(rel_path)?;
+        let mut components = path_components(rel_path)?;
 
         // Files in .dist-info and .egg-info directories are distribution metadata files.
         // Parsing the package name out of the directory name can be a bit wonky, as
@@ -218,70 +398,59 @@ impl<'a> PythonResourceIterator<'a> {
         // the package info. If the file doesn't exist or can't be parsed, we ignore this
         // distribution entirely.
 
+        //
+        // Name of resource is file path after the initial directory.
         let distribution_info = if components[0].ends_with(".dist-info") {
             Some((
-                self.root_path.join(components[0]).join("METADATA"),
+                root.join(components[0]).join("METADATA"),
                 PythonPackageDistributionResourceFlavor::DistInfo,
+                components[1..components.len()].join("/"),
             ))
         } else if components[0].ends_with(".egg-info") {
-            Some((
-                self.root_path.join(components[0]).join("PKG-INFO"),
-                PythonPackageDistributionResourceFlavor::EggInfo,
-            ))
+            // distutils installs .egg-info as a single file holding the content
+            // of PKG-INFO.
+            if components.len() == 1 {
+                Some((
+                    path.to_path_buf(),
+                    PythonPackageDistributionResourceFlavor::EggInfo,
+                    "PKG-INFO".to_string(),
+                ))
+            } else {
+                Some((
+                    root.join(components[0]).join("PKG-INFO"),
+                    PythonPackageDistributionResourceFlavor::EggInfo,
+                    components[1..components.len()].join("/"),
+                ))
+            }
         } else {
             None
         };
 
-        if let Some((metadata_path, location)) = distribution_info {
-            let data = if let Some(file) = self.path_content_overrides.get(&metadata_path) {
-                if let Ok(data) = file.data.resolve() {
-                    data
-                } else {
-                    return None;
-                }
-            } else if let Ok(data) = std::fs::read(&metadata_path) {
-                data
-            } else {
-                return None;
-            };
-
-            let metadata = if let Ok(metadata) = PythonPackageMetadata::from_metadata(&data) {
-                metadata
-            } else {
-                return None;
+        if let Some((metadata_path, location, name)) = distribution_info {
+            let (package, version) = match self.resolve_distribution_metadata(&metadata_path) {
+                Some(v) => v,
+                None => return Ok(None),
             };
 
-            let package = metadata.name()?;
-            let version = metadata.version()?;
-
-            // Name of resource is file path after the initial directory.
-            let name = components[1..components.len()].join("/");
-
-            return Some(PathItem::PythonResource(
+            return Ok(Some(PathItem::PythonResource(
                 PythonPackageDistributionResource {
                     location,
-                    package: package.to_string(),
-                    version: version.to_string(),
+                    package,
+                    version,
                     name,
                     data: self.resolve_file_data(path),
                 }
                 .into(),
-            ));
+            )));
         }
 
         // site-packages directories are package roots within package roots. Treat them as
         // such.
         let in_site_packages = if components[0] == "site-packages" {
-            let sp_path = self.root_path.join("site-packages");
-            rel_path = path
-                .strip_prefix(sp_path)
-                .expect("unable to strip site-packages prefix");
-
-            rel_str = rel_path.to_str().expect("could not convert path to str");
-            components = rel_path
-                .iter()
-                .map(|p| p.to_str().expect("unable to get path as str"))
-                .collect::<Vec<_>>();
+            let sp_path = root.join("site-packages");
+            rel_path = path.strip_prefix(sp_path)?;
+            rel_str = path_to_str(rel_path)?;
+            components = path_components(rel_path)?;
 
             true
         } else {
@@ -295,7 +464,7 @@ impl<'a> PythonResourceIterator<'a> {
             .iter()
             .any(|p| p.ends_with(".egg"))
         {
-            let mut egg_root_path = self.root_path.clone();
+            let mut egg_root_path = root.to_path_buf();
 
             if in_site_packages {
                 egg_root_path = egg_root_path.join("site-packages");
@@ -309,21 +478,36 @@ impl<'a> PythonResourceIterator<'a> {
                 }
             }
 
-            rel_path = path
-                .strip_prefix(egg_root_path)
-                .expect("unable to strip egg prefix");
-            components = rel_path
-                .iter()
-                .map(|p| p.to_str().expect("unable to get path as str"))
-                .collect::<Vec<_>>();
+            rel_path = path.strip_prefix(&egg_root_path)?;
+            components = path_components(rel_path)?;
 
-            // Ignore EGG-INFO directory, as it is just packaging metadata.
+            // The EGG-INFO directory holds the egg's packaging metadata. Like
+            // .egg-info directories, it is ignored if PKG-INFO isn't valid.
             if components[0] == "EGG-INFO" {
-                return None;
+                let (package, version) = match self
+                    .resolve_distribution_metadata(&egg_root_path.join("EGG-INFO").join("PKG-INFO"))
+                {
+                    Some(v) => v,
+                    None => return Ok(None),
+                };
+
+                return Ok(Some(PathItem::PythonResource(
+                    PythonPackageDistributionResource {
+                        location: PythonPackageDistributionResourceFlavor::EggInfo,
+                        package,
+                        version,
+                        name: components[1..components.len()].join("/"),
+                        data: self.resolve_file_data(path),
+                    }
+                    .into(),
+                )));
             }
         }
 
-        let file_name = rel_path.file_name().unwrap().to_string_lossy();
+        let file_name = rel_path
+            .file_name()
+            .ok_or_else(|| anyhow!("unable to get file name of {}", path.display()))?
+            .to_string_lossy();
 
         for ext_suffix in &self.suffixes.extension {
             if file_name.ends_with(ext_suffix) {
@@ -350,7 +534,7 @@ impl<'a> PythonResourceIterator<'a> {
                 let final_name = module_components[module_components.len() - 1];
                 let init_fn = Some(format!("PyInit_{}", final_name));
 
-                return Some(PathItem::PythonResource(
+                return Ok(Some(PathItem::PythonResource(
                     PythonExtensionModule {
                         name: full_module_name,
                         init_fn,
@@ -366,7 +550,7 @@ impl<'a> PythonResourceIterator<'a> {
                         license: None,
                     }
                     .into(),
-                ));
+                )));
             }
         }
 
@@ -382,9 +566,8 @@ impl<'a> PythonResourceIterator<'a> {
 
             let module_name = rel_path
                 .file_stem()
-                .expect("unable to get file stem")
-                .to_str()
-                .expect("unable to convert path to str");
+                .and_then(OsStr::to_str)
+                .ok_or_else(|| anyhow!("unable to get file stem of {}", path.display()))?;
 
             let mut full_module_name: Vec<&str> = package_parts.to_vec();
 
@@ -400,7 +583,7 @@ impl<'a> PythonResourceIterator<'a> {
 
             self.seen_packages.insert(package);
 
-            return Some(PathItem::PythonResource(
+            return Ok(Some(PathItem::PythonResource(
                 PythonModuleSource {
                     name: full_module_name,
                     source: self.resolve_file_data(path),
@@ -410,7 +593,7 @@ impl<'a> PythonResourceIterator<'a> {
                     is_test: false,
                 }
                 .into(),
-            ));
+            )));
         }
 
         if self
@@ -421,12 +604,12 @@ impl<'a> PythonResourceIterator<'a> {
         {
             // .pyc files should be in a __pycache__ directory.
             if components.len() < 2 {
-                return None;
+                return Ok(None);
             }
 
             // Possibly from Python 2?
             if components[components.len() - 2] != "__pycache__" {
-                return None;
+                return Ok(None);
             }
 
             let package_parts = &components[0..components.len() - 2];
@@ -435,14 +618,14 @@ impl<'a> PythonResourceIterator<'a> {
             // Files have format <package>/__pycache__/<module>.<cache_tag>.<extra tag><suffix>>
             let filename = rel_path
                 .file_name()
-                .expect("unable to get file name")
+                .ok_or_else(|| anyhow!("unable to get file name of {}", path.display()))?
                 .to_string_lossy()
                 .to_string();
 
             let filename_parts = filename.split('.').collect::<Vec<&str>>();
 
             if filename_parts.len() < 3 {
-                return None;
+                return Ok(None);
             }
 
             let mut remaining_filename = filename.clone();
@@ -453,7 +636,7 @@ impl<'a> PythonResourceIterator<'a> {
 
             // The second part is the cache tag. It should match ours.
             if filename_parts[1] != self.cache_tag {
-                return None;
+                return Ok(None);
             }
 
             // Keep the leading dot in case there is no cache tag: in this case the
@@ -473,7 +656,7 @@ impl<'a> PythonResourceIterator<'a> {
 
             // Only the bytecode suffix should remain.
             if !self.suffixes.bytecode.contains(&remaining_filename) {
-                return None;
+                return Ok(None);
             }
 
             let mut full_module_name: Vec<&str> = package_parts.to_vec();
@@ -490,7 +673,7 @@ impl<'a> PythonResourceIterator<'a> {
 
             self.seen_packages.insert(package);
 
-            return Some(PathItem::PythonResource(
+            return Ok(Some(PathItem::PythonResource(
                 PythonModuleBytecode::from_path(
                     &full_module_name,
                     optimization_level,
@@ -498,7 +681,7 @@ impl<'a> PythonResourceIterator<'a> {
                     path,
                 )
                 .into(),
-            ));
+            )));
         }
 
         let resource = match rel_path.extension().and_then(OsStr::to_str) {
@@ -525,7 +708,7 @@ impl<'a> PythonResourceIterator<'a> {
             }
         };
 
-        Some(resource)
+        Ok(Some(resource))
     }
 }
 
@@ -546,11 +729,10 @@ impl<'a> Iterator for PythonResourceIterator<'a> {
             if self.emit_files && !self.paths[0].file_emitted {
                 self.paths[0].file_emitted = true;
 
-                let rel_path = self.paths[0]
-                    .path
-                    .strip_prefix(&self.root_path)
-                    .expect("unable to strip path prefix")
-                    .to_path_buf();
+                let rel_path = match self.paths[0].path.strip_prefix(&self.paths[0].root) {
+                    Ok(p) => p.to_path_buf(),
+                    Err(e) => return Some(Err(e.into())),
+                };
 
                 let f = File {
                     path: rel_path,
@@ -567,17 +749,20 @@ impl<'a> Iterator for PythonResourceIterator<'a> {
                 self.paths[0].non_file_emitted = true;
 
                 // Because resolve_path is a mutable borrow.
+                let root_temp = self.paths[0].root.clone();
                 let path_temp = self.paths[0].path.clone();
 
-                if let Some(entry) = self.resolve_path(&path_temp) {
+                match self.resolve_path(&root_temp, &path_temp) {
                     // Buffer Resource entries until later.
-                    match entry {
-                        PathItem::ResourceFile(resource) => {
-                            self.resources.push(resource);
-                        }
-                        PathItem::PythonResource(resource) => {
-                            return Some(Ok(resource));
-                        }
+                    Ok(Some(PathItem::ResourceFile(resource))) => {
+                        self.resources.push(resource);
+                    }
+                    Ok(Some(PathItem::PythonResource(resource))) => {
+                        return Some(Ok(resource));
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        return Some(Err(e));
                     }
                 }
             }
@@ -587,7 +772,8 @@ impl<'a> Iterator for PythonResourceIterator<'a> {
             //
             // Removing the first element is a bit inefficient. Should we
             // reverse storage / iteration order instead?
-            self.paths.remove(0);
+            let entry = self.paths.remove(0);
+            self.process_path_extension(&entry);
             continue;
         }
 
@@ -1408,6 +1594,170 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_pth_file_path_entries() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("python-packaging-test")
+            .tempdir()?;
+        let tp = td.path();
+
+        let site_packages = tp.join("site-packages");
+        let extra_path = tp.join("extra");
+        create_dir_all(&site_packages)?;
+        create_dir_all(&extra_path)?;
+
+        let pth_path = site_packages.join("foo.pth");
+        write(
+            &pth_path,
+            "# comment\n../extra\nmissing\nimport sys; sys.path.append('x')\n",
+        )?;
+        write(extra_path.join("bar.py"), "")?;
+
+        // Directories outside the site directory aren't followed by default.
+        let mut iter = PythonResourceIterator::new(
+            &site_packages,
+            DEFAULT_CACHE_TAG,
+            &DEFAULT_SUFFIXES,
+            false,
+            true,
+        );
+        let resources = (&mut iter).collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            resources,
+            vec![PythonPathExtension {
+                data: FileData::Path(pth_path.clone())
+            }
+            .into()]
+        );
+        assert_eq!(
+            iter.warnings(),
+            &[
+                format!(
+                    "{} references {}, which is outside {} and will not be scanned",
+                    pth_path.display(),
+                    site_packages.join("../extra").display(),
+                    site_packages.canonicalize()?.display()
+                ),
+                format!(
+                    "{}:4: executable .pth line will not be run by the packaged application: import sys; sys.path.append('x')",
+                    pth_path.display()
+                )
+            ]
+        );
+
+        let mut iter = PythonResourceIterator::new(
+            &site_packages,
+            DEFAULT_CACHE_TAG,
+            &DEFAULT_SUFFIXES,
+            false,
+            true,
+        );
+        iter.set_follow_external_paths(true);
+        let resources = (&mut iter).collect::<Result<Vec<_>>>()?;
+        assert_eq!(resources.len(), 2);
+
+        assert_eq!(
+            resources[0],
+            PythonPathExtension {
+                data: FileData::Path(pth_path.clone())
+            }
+            .into()
+        );
+        assert_eq!(
+            resources[1],
+            PythonModuleSource {
+                name: "bar".to_string(),
+                source: FileData::Path(site_packages.join("../extra").join("bar.py")),
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            }
+            .into()
+        );
+
+        assert_eq!(
+            iter.warnings(),
+            &[format!(
+                "{}:4: executable .pth line will not be run by the packaged application: import sys; sys.path.append('x')",
+                pth_path.display()
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_egg_link() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("python-packaging-test")
+            .tempdir()?;
+        let tp = td.path();
+
+        let site_packages = tp.join("site-packages");
+        let project_path = tp.join("project");
+        let egg_info_path = project_path.join("foo.egg-info");
+        create_dir_all(&site_packages)?;
+        create_dir_all(&egg_info_path)?;
+
+        write(
+            site_packages.join("foo.egg-link"),
+            format!("{}\n.", project_path.display()),
+        )?;
+        // easy-install.pth references the same directory. It should only be
+        // scanned once.
+        write(
+            site_packages.join("easy-install.pth"),
+            format!("{}\n", project_path.display()),
+        )?;
+        write(egg_info_path.join("PKG-INFO"), "Name: foo\nVersion: 1.0\n")?;
+        write(project_path.join("foo.py"), "")?;
+
+        let mut iter = PythonResourceIterator::new(
+            &site_packages,
+            DEFAULT_CACHE_TAG,
+            &DEFAULT_SUFFIXES,
+            false,
+            true,
+        );
+        iter.set_follow_external_paths(true);
+        let resources = iter.collect::<Result<Vec<_>>>()?;
+        assert_eq!(resources.len(), 3);
+
+        assert_eq!(
+            resources[0],
+            PythonPathExtension {
+                data: FileData::Path(site_packages.join("easy-install.pth"))
+            }
+            .into()
+        );
+        assert_eq!(
+            resources[1],
+            PythonPackageDistributionResource {
+                location: PythonPackageDistributionResourceFlavor::EggInfo,
+                package: "foo".to_string(),
+                version: "1.0".to_string(),
+                name: "PKG-INFO".to_string(),
+                data: FileData::Path(egg_info_path.join("PKG-INFO")),
+            }
+            .into()
+        );
+        assert_eq!(
+            resources[2],
+            PythonModuleSource {
+                name: "foo".to_string(),
+                source: FileData::Path(project_path.join("foo.py")),
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            }
+            .into()
+        );
+
+        Ok(())
+    }
+
     /// Resource files without a package are not valid.
     #[test]
     fn test_root_resource_file() -> Result<()> {
@@ -1752,6 +2102,84 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_egginfo_file() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("python-packaging-test")
+            .tempdir()?;
+        let tp = td.path();
+
+        let egg_info_path = tp.join("black-1.2.3-py3.8.egg-info");
+        write(&egg_info_path, "Name: black\nVersion: 1.2.3\n")?;
+
+        let resources =
+            PythonResourceIterator::new(tp, DEFAULT_CACHE_TAG, &DEFAULT_SUFFIXES, false, true)
+                .collect::<Result<Vec<_>>>()?;
+        assert_eq!(resources.len(), 1);
+
+        assert_eq!(
+            resources[0],
+            PythonPackageDistributionResource {
+                location: PythonPackageDistributionResourceFlavor::EggInfo,
+                package: "black".to_string(),
+                version: "1.2.3".to_string(),
+                name: "PKG-INFO".to_string(),
+                data: FileData::Path(egg_info_path),
+            }
+            .into()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_egg_dir_metadata() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("python-packaging-test")
+            .tempdir()?;
+        let tp = td.path();
+
+        let egg_path = tp.join("foo-1.0-py3.8.egg");
+        let egg_info_path = egg_path.join("EGG-INFO");
+        create_dir_all(&egg_info_path)?;
+        create_dir_all(egg_path.join("foo"))?;
+
+        write(egg_info_path.join("PKG-INFO"), "Name: foo\nVersion: 1.0\n")?;
+        write(egg_info_path.join("top_level.txt"), "foo\n")?;
+        write(egg_path.join("foo").join("__init__.py"), "")?;
+
+        let resources =
+            PythonResourceIterator::new(tp, DEFAULT_CACHE_TAG, &DEFAULT_SUFFIXES, false, true)
+                .collect::<Result<Vec<_>>>()?;
+        assert_eq!(resources.len(), 3);
+
+        assert_eq!(
+            resources[0],
+            PythonPackageDistributionResource {
+                location: PythonPackageDistributionResourceFlavor::EggInfo,
+                package: "foo".to_string(),
+                version: "1.0".to_string(),
+                name: "PKG-INFO".to_string(),
+                data: FileData::Path(egg_info_path.join("PKG-INFO")),
+            }
+            .into()
+        );
+        assert_eq!(
+            resources[1],
+            PythonPackageDistributionResource {
+                location: PythonPackageDistributionResourceFlavor::EggInfo,
+                package: "foo".to_string(),
+                version: "1.0".to_string(),
+                name: "top_level.txt".to_string(),
+                data: FileData::Path(egg_info_path.join("top_level.txt")),
+            }
+            .into()
+        );
+        assert!(matches!(resources[2], PythonResource::ModuleSource(_)));
+
+        Ok(())
+    }
+
     #[test]
     fn test_memory_resources() -> Result<()> {
         let inputs = vec![
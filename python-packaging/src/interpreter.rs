@@ -9,6 +9,35 @@ use {
     std::{convert::TryFrom, ffi::OsString, os::raw::c_ulong, path::PathBuf},
 };
 
+/// Implement serde traits for a type via its `ToString` and `TryFrom<&str>` implementations.
+///
+/// This ensures serialized values are the same strings used everywhere else.
+macro_rules! serde_via_string {
+    ($t:ty) => {
+        #[cfg(feature = "serialization")]
+        impl serde::Serialize for $t {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: serde::Serializer,
+            {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+
+        #[cfg(feature = "serialization")]
+        impl<'de> serde::Deserialize<'de> for $t {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: serde::Deserializer<'de>,
+            {
+                let value = String::deserialize(deserializer)?;
+
+                Self::try_from(value.as_str()).map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
 /// Defines the profile to use to configure a Python interpreter.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PythonInterpreterProfile {
@@ -54,6 +83,8 @@ impl TryFrom<&str> for PythonInterpreterProfile {
     }
 }
 
+serde_via_string!(PythonInterpreterProfile);
+
 /// Defines `terminfo`` database resolution semantics.
 #[derive(Clone, Debug, PartialEq)]
 pub enum TerminfoResolution {
@@ -94,6 +125,8 @@ impl TryFrom<&str> for TerminfoResolution {
     }
 }
 
+serde_via_string!(TerminfoResolution);
+
 /// Defines a backend for a memory allocator.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum MemoryAllocatorBackend {
@@ -147,6 +180,8 @@ impl TryFrom<&str> for MemoryAllocatorBackend {
     }
 }
 
+serde_via_string!(MemoryAllocatorBackend);
+
 /// Holds values for coerce_c_locale.
 ///
 /// See https://docs.python.org/3/c-api/init_config.html#c.PyPreConfig.coerce_c_locale.
@@ -179,6 +214,8 @@ impl TryFrom<&str> for CoerceCLocale {
     }
 }
 
+serde_via_string!(CoerceCLocale);
+
 /// Defines what to do when comparing bytes with str.
 ///
 /// See https://docs.python.org/3/c-api/init_config.html#c.PyConfig.bytes_warning.
@@ -213,6 +250,8 @@ impl TryFrom<&str> for BytesWarning {
     }
 }
 
+serde_via_string!(BytesWarning);
+
 impl From<i32> for BytesWarning {
     fn from(value: i32) -> BytesWarning {
         match value {
@@ -258,6 +297,8 @@ impl TryFrom<&str> for CheckHashPycsMode {
     }
 }
 
+serde_via_string!(CheckHashPycsMode);
+
 /// See https://docs.python.org/3/c-api/init_config.html#c.PyPreConfig.allocator.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Allocator {
@@ -302,6 +343,8 @@ impl TryFrom<&str> for Allocator {
     }
 }
 
+serde_via_string!(Allocator);
+
 /// Holds configuration of a Python interpreter.
 ///
 /// This struct holds fields that are exposed by `PyPreConfig` and
@@ -311,6 +354,11 @@ impl TryFrom<&str> for Allocator {
 /// `PyPreConfig` and `PyConfig`), all fields are optional. Only fields
 /// with `Some(T)` will be updated from the defaults.
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(
+    feature = "serialization",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(default)
+)]
 pub struct PythonInterpreterConfig {
     /// Profile to use to initialize pre-config and config state of interpreter.
     pub profile: PythonInterpreterProfile,
@@ -347,6 +395,9 @@ pub struct PythonInterpreterConfig {
     pub utf8_mode: Option<bool>,
     // The following fields are from PyConfig.
     /// See https://docs.python.org/3/c-api/init_config.html#c.PyConfig.argv.
+    ///
+    /// Not serialized: arguments are a property of the running process.
+    #[cfg_attr(feature = "serialization", serde(skip))]
     pub argv: Option<Vec<OsString>>,
 
     /// See https://docs.python.org/3/c-api/init_config.html#c.PyConfig.base_exec_prefix.
@@ -481,3 +532,62 @@ pub struct PythonInterpreterConfig {
     /// See https://docs.python.org/3/c-api/init_config.html#c.PyConfig.xoptions.
     pub x_options: Option<Vec<String>>,
 }
+
+#[cfg(all(test, feature = "serialization"))]
+mod tests {
+    use {super::*, anyhow::Result};
+
+    #[test]
+    fn test_serialize_config() -> Result<()> {
+        let config = PythonInterpreterConfig {
+            profile: PythonInterpreterProfile::Python,
+            allocator: Some(Allocator::PyMallocDebug),
+            argv: Some(vec!["prog".into()]),
+            check_hash_pycs_mode: Some(CheckHashPycsMode::Never),
+            hash_seed: Some(42),
+            module_search_paths: Some(vec!["$ORIGIN/lib".into()]),
+            optimization_level: Some(BytecodeOptimizationLevel::Two),
+            ..PythonInterpreterConfig::default()
+        };
+
+        let value = serde_json::to_value(&config)?;
+        assert_eq!(value["profile"], "python");
+        assert_eq!(value["allocator"], "py-malloc-debug");
+        assert_eq!(value["check_hash_pycs_mode"], "never");
+        assert_eq!(value["optimization_level"], 2);
+        assert!(value.get("argv").is_none());
+
+        let parsed: PythonInterpreterConfig = serde_json::from_value(value)?;
+        assert_eq!(
+            parsed,
+            PythonInterpreterConfig {
+                argv: None,
+                ..config
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_defaults() -> Result<()> {
+        let config: PythonInterpreterConfig = serde_json::from_str("{\"utf8_mode\": true}")?;
+        assert_eq!(config.profile, PythonInterpreterProfile::Isolated);
+        assert_eq!(config.utf8_mode, Some(true));
+
+        let terminfo: TerminfoResolution = serde_json::from_str("\"static:/usr/share/terminfo\"")?;
+        assert_eq!(
+            terminfo,
+            TerminfoResolution::Static("/usr/share/terminfo".to_string())
+        );
+
+        assert!(
+            serde_json::from_str::<PythonInterpreterConfig>("{\"allocator\": \"bogus\"}").is_err()
+        );
+        assert!(
+            serde_json::from_str::<PythonInterpreterConfig>("{\"optimization_level\": 3}").is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -9,11 +9,14 @@ and packaging facilities.
 */
 
 pub mod bytecode;
+pub mod bytecode_cache;
 pub mod filesystem_scanning;
 pub mod interpreter;
 pub mod libpython;
 pub mod licensing;
 pub mod location;
+pub mod marshal;
+pub mod module_graph;
 pub mod module_util;
 pub mod package_metadata;
 pub mod policy;
@@ -21,5 +24,8 @@ pub mod python_source;
 pub mod resource;
 pub mod resource_collection;
 
+#[cfg(feature = "sdist")]
+pub mod sdist;
+
 #[cfg(feature = "wheel")]
 pub mod wheel;
@@ -89,6 +89,9 @@ impl TryInto<LicensedComponent> for PackageLicenseInfo {
         };
 
         component.set_flavor(ComponentFlavor::PythonPackage);
+        if !self.version.is_empty() {
+            component.set_version(&self.version);
+        }
 
         for text in self
             .license_texts
@@ -315,6 +318,7 @@ mod tests {
         let c: LicensedComponent = li.try_into()?;
         let mut wanted = LicensedComponent::new_none("foo");
         wanted.set_flavor(ComponentFlavor::PythonPackage);
+        wanted.set_version("0.1");
         assert_eq!(c, wanted);
 
         Ok(())
@@ -332,6 +336,7 @@ mod tests {
         let c: LicensedComponent = li.try_into()?;
         let mut wanted = LicensedComponent::new_spdx("foo", "MIT")?;
         wanted.set_flavor(ComponentFlavor::PythonPackage);
+        wanted.set_version("0.1");
         assert_eq!(c, wanted);
 
         Ok(())
@@ -349,6 +354,7 @@ mod tests {
         let c: LicensedComponent = li.try_into()?;
         let mut wanted = LicensedComponent::new_spdx("foo", "Apache-2.0")?;
         wanted.set_flavor(ComponentFlavor::PythonPackage);
+        wanted.set_version("0.1");
         assert_eq!(c, wanted);
 
         Ok(())
@@ -366,6 +372,7 @@ mod tests {
         let c: LicensedComponent = li.try_into()?;
         let mut wanted = LicensedComponent::new_spdx("foo", "Apache-2.0 OR MIT")?;
         wanted.set_flavor(ComponentFlavor::PythonPackage);
+        wanted.set_version("0.1");
         assert_eq!(c, wanted);
 
         Ok(())
@@ -383,6 +390,7 @@ mod tests {
         let c: LicensedComponent = li.try_into()?;
         let mut wanted = LicensedComponent::new_spdx("foo", "Apache-2.0 OR MIT")?;
         wanted.set_flavor(ComponentFlavor::PythonPackage);
+        wanted.set_version("0.1");
         assert_eq!(c, wanted);
 
         Ok(())
@@ -400,6 +408,7 @@ mod tests {
         let c: LicensedComponent = li.try_into()?;
         let mut wanted = LicensedComponent::new_spdx("foo", "MIT OR Apache-2.0")?;
         wanted.set_flavor(ComponentFlavor::PythonPackage);
+        wanted.set_version("0.1");
         assert_eq!(c, wanted);
 
         Ok(())
@@ -417,6 +426,7 @@ mod tests {
         let c: LicensedComponent = li.try_into()?;
         let mut wanted = LicensedComponent::new_spdx("foo", "MIT")?;
         wanted.set_flavor(ComponentFlavor::PythonPackage);
+        wanted.set_version("0.1");
         assert_eq!(c, wanted);
 
         Ok(())
@@ -436,6 +446,7 @@ mod tests {
         let c: LicensedComponent = li.try_into()?;
         let mut wanted = LicensedComponent::new_unknown("foo", terms);
         wanted.set_flavor(ComponentFlavor::PythonPackage);
+        wanted.set_version("0.1");
         assert_eq!(c, wanted);
 
         Ok(())
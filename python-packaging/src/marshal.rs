@@ -0,0 +1,1304 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Reading and writing Python's marshal format.
+
+Python bytecode (`.pyc`) files consist of a small header followed by a
+code object serialized with the `marshal` module. This module implements
+a decoder and encoder for the subset of the marshal format used to
+serialize code objects on CPython 3.8 and 3.9, which share the same code
+object layout.
+
+Decoded code objects can be inspected (e.g. to find referenced names and
+imports) and mutated (e.g. to strip docstrings and line number tables)
+without requiring a Python interpreter.
+*/
+
+use {
+    crate::python_source::{
+        resolve_relative_module, FilesystemDependency, FilesystemDependencyKind, ImportKind,
+        PythonImport,
+    },
+    anyhow::{anyhow, Context, Result},
+    byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt},
+    std::{
+        collections::{BTreeSet, HashMap},
+        convert::TryFrom,
+        io::{Cursor, Read},
+    },
+};
+
+const TYPE_NULL: u8 = b'0';
+const TYPE_NONE: u8 = b'N';
+const TYPE_FALSE: u8 = b'F';
+const TYPE_TRUE: u8 = b'T';
+const TYPE_STOPITER: u8 = b'S';
+const TYPE_ELLIPSIS: u8 = b'.';
+const TYPE_INT: u8 = b'i';
+const TYPE_FLOAT: u8 = b'f';
+const TYPE_BINARY_FLOAT: u8 = b'g';
+const TYPE_COMPLEX: u8 = b'x';
+const TYPE_BINARY_COMPLEX: u8 = b'y';
+const TYPE_LONG: u8 = b'l';
+const TYPE_STRING: u8 = b's';
+const TYPE_INTERNED: u8 = b't';
+const TYPE_REF: u8 = b'r';
+const TYPE_TUPLE: u8 = b'(';
+const TYPE_LIST: u8 = b'[';
+const TYPE_DICT: u8 = b'{';
+const TYPE_CODE: u8 = b'c';
+const TYPE_UNICODE: u8 = b'u';
+const TYPE_SET: u8 = b'<';
+const TYPE_FROZENSET: u8 = b'>';
+const TYPE_ASCII: u8 = b'a';
+const TYPE_ASCII_INTERNED: u8 = b'A';
+const TYPE_SMALL_TUPLE: u8 = b')';
+const TYPE_SHORT_ASCII: u8 = b'z';
+const TYPE_SHORT_ASCII_INTERNED: u8 = b'Z';
+
+const FLAG_REF: u8 = 0x80;
+
+// Opcodes shared by CPython 3.8 and 3.9.
+const HAVE_ARGUMENT: u8 = 90;
+const STORE_NAME: u8 = 90;
+const LOAD_CONST: u8 = 100;
+const LOAD_NAME: u8 = 101;
+const LOAD_ATTR: u8 = 106;
+const IMPORT_NAME: u8 = 108;
+const LOAD_GLOBAL: u8 = 116;
+const CALL_FUNCTION: u8 = 131;
+const EXTENDED_ARG: u8 = 144;
+const LOAD_METHOD: u8 = 160;
+const CALL_METHOD: u8 = 161;
+
+const CO_OPTIMIZED: u32 = 0x1;
+const CO_NEWLOCALS: u32 = 0x2;
+
+/// A Python version whose bytecode can be decoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BytecodeVersion {
+    /// CPython 3.8.
+    Python38,
+    /// CPython 3.9.
+    Python39,
+}
+
+impl BytecodeVersion {
+    /// Resolve the version from the 16-bit magic number in a `.pyc` header.
+    pub fn from_magic(magic: u16) -> Option<Self> {
+        match magic {
+            3400..=3419 => Some(Self::Python38),
+            3420..=3429 => Some(Self::Python39),
+            _ => None,
+        }
+    }
+
+    /// Resolve the version from a bytecode cache tag (e.g. `cpython-39`).
+    pub fn from_cache_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "cpython-38" => Some(Self::Python38),
+            "cpython-39" => Some(Self::Python39),
+            _ => None,
+        }
+    }
+
+    /// The magic number written to `.pyc` headers by the final release of this version.
+    ///
+    /// The value is suitable for `compute_bytecode_header()`.
+    pub fn magic_number(&self) -> u32 {
+        let magic = match self {
+            Self::Python38 => 3413,
+            Self::Python39 => 3425,
+        };
+
+        magic | (u32::from(b'\r') << 16) | (u32::from(b'\n') << 24)
+    }
+}
+
+/// How a string was serialized.
+///
+/// This is tracked so strings are written back in the same form they
+/// were read. Notably, whether a string is interned has semantic meaning.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StringKind {
+    Ascii,
+    AsciiInterned,
+    Unicode,
+    UnicodeInterned,
+}
+
+impl StringKind {
+    fn is_interned(&self) -> bool {
+        matches!(self, Self::AsciiInterned | Self::UnicodeInterned)
+    }
+}
+
+/// A value that can be represented in the marshal format.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MarshalValue {
+    /// The NULL sentinel. Only used to terminate dicts.
+    Null,
+    None,
+    False,
+    True,
+    StopIteration,
+    Ellipsis,
+    Int(i32),
+    /// An arbitrary precision integer.
+    ///
+    /// Digits are 15-bit and stored least significant first.
+    Long {
+        negative: bool,
+        digits: Vec<u16>,
+    },
+    Float(f64),
+    Complex(f64, f64),
+    Bytes(Vec<u8>),
+    Str(String, StringKind),
+    Tuple(Vec<MarshalValue>),
+    List(Vec<MarshalValue>),
+    Dict(Vec<(MarshalValue, MarshalValue)>),
+    Set(Vec<MarshalValue>),
+    FrozenSet(Vec<MarshalValue>),
+    Code(Box<CodeObject>),
+}
+
+impl MarshalValue {
+    /// Obtain the string value if this is a string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::Str(s, _) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Obtain the code object if this is a code object.
+    pub fn as_code(&self) -> Option<&CodeObject> {
+        match self {
+            Self::Code(code) => Some(code),
+            _ => None,
+        }
+    }
+}
+
+/// A Python code object.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeObject {
+    pub argcount: u32,
+    pub posonlyargcount: u32,
+    pub kwonlyargcount: u32,
+    pub nlocals: u32,
+    pub stacksize: u32,
+    pub flags: u32,
+    /// Raw bytecode instructions.
+    pub code: Vec<u8>,
+    pub consts: Vec<MarshalValue>,
+    pub names: Vec<String>,
+    pub varnames: Vec<String>,
+    pub freevars: Vec<String>,
+    pub cellvars: Vec<String>,
+    pub filename: String,
+    pub name: String,
+    pub firstlineno: u32,
+    /// Encoded mapping of bytecode offsets to line numbers.
+    pub lnotab: Vec<u8>,
+}
+
+/// A decoded bytecode instruction.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Instruction {
+    /// Byte offset of the instruction, excluding any `EXTENDED_ARG` prefixes.
+    pub offset: usize,
+    pub opcode: u8,
+    /// Argument, with `EXTENDED_ARG` prefixes applied.
+    pub arg: u32,
+}
+
+impl CodeObject {
+    /// Decode the instructions in this code object.
+    pub fn instructions(&self) -> Result<Vec<Instruction>> {
+        if self.code.len() & 1 != 0 {
+            return Err(anyhow!("bytecode of {} has odd length", self.name));
+        }
+
+        let mut res = vec![];
+        let mut extended = 0u32;
+
+        for (i, chunk) in self.code.chunks(2).enumerate() {
+            let opcode = chunk[0];
+            let arg = if opcode >= HAVE_ARGUMENT {
+                extended | u32::from(chunk[1])
+            } else {
+                0
+            };
+
+            if opcode == EXTENDED_ARG {
+                extended = arg << 8;
+                continue;
+            }
+            extended = 0;
+
+            res.push(Instruction {
+                offset: i * 2,
+                opcode,
+                arg,
+            });
+        }
+
+        Ok(res)
+    }
+
+    /// Obtain this code object and all code objects nested within it.
+    pub fn code_objects(&self) -> Vec<&CodeObject> {
+        let mut res = vec![self];
+
+        for value in &self.consts {
+            if let MarshalValue::Code(code) = value {
+                res.extend(code.code_objects());
+            }
+        }
+
+        res
+    }
+
+    /// Names referenced by this and nested code objects.
+    ///
+    /// These are the names of globals, attributes and imports accessed by
+    /// the code.
+    pub fn referenced_names(&self) -> BTreeSet<String> {
+        self.code_objects()
+            .into_iter()
+            .flat_map(|code| code.names.iter().cloned())
+            .collect()
+    }
+
+    /// Whether this or nested code objects reference `__file__`.
+    pub fn has_dunder_file(&self) -> bool {
+        self.code_objects()
+            .iter()
+            .any(|code| code.names.iter().any(|name| name == "__file__"))
+    }
+
+    /// Find imports performed by this and nested code objects.
+    ///
+    /// Arguments and semantics are the same as for
+    /// `python_source::find_imports()`, except that dynamic imports are
+    /// only detected when their arguments are constants.
+    pub fn find_imports(&self, module: &str, is_package: bool) -> Result<Vec<PythonImport>> {
+        let package = if is_package {
+            module
+        } else if let Some(idx) = module.rfind('.') {
+            &module[0..idx]
+        } else {
+            ""
+        };
+
+        let mut res = vec![];
+
+        for code in self.code_objects() {
+            code.find_imports_in_code(module, package, &mut res)?;
+        }
+
+        Ok(res)
+    }
+
+    fn name_at(&self, index: u32) -> Result<&str> {
+        self.names
+            .get(index as usize)
+            .map(|s| s.as_str())
+            .ok_or_else(|| anyhow!("name index {} out of range in {}", index, self.name))
+    }
+
+    fn const_at(&self, index: u32) -> Result<&MarshalValue> {
+        self.consts
+            .get(index as usize)
+            .ok_or_else(|| anyhow!("constant index {} out of range in {}", index, self.name))
+    }
+
+    fn find_imports_in_code(
+        &self,
+        module: &str,
+        package: &str,
+        res: &mut Vec<PythonImport>,
+    ) -> Result<()> {
+        let instructions = self.instructions()?;
+
+        for (i, instruction) in instructions.iter().enumerate() {
+            match instruction.opcode {
+                IMPORT_NAME => {
+                    let name = self.name_at(instruction.arg)?;
+
+                    // IMPORT_NAME is preceded by LOAD_CONST <level> and LOAD_CONST <fromlist>.
+                    let (level, fromlist) = match (
+                        i.checked_sub(2).and_then(|i| instructions.get(i)),
+                        i.checked_sub(1).and_then(|i| instructions.get(i)),
+                    ) {
+                        (Some(level), Some(fromlist))
+                            if level.opcode == LOAD_CONST && fromlist.opcode == LOAD_CONST =>
+                        {
+                            (self.const_at(level.arg)?, self.const_at(fromlist.arg)?)
+                        }
+                        _ => continue,
+                    };
+
+                    let level = match level {
+                        MarshalValue::Int(level) if *level >= 0 => *level as usize,
+                        _ => continue,
+                    };
+
+                    let (names, kind) = match fromlist {
+                        MarshalValue::Tuple(names) => (
+                            names
+                                .iter()
+                                .filter_map(|name| name.as_str().map(|s| s.to_string()))
+                                .collect::<Vec<_>>(),
+                            ImportKind::FromImport,
+                        ),
+                        _ => (vec![], ImportKind::Import),
+                    };
+
+                    if let Some(module) = resolve_relative_module(package, level, name) {
+                        res.push(PythonImport {
+                            module,
+                            names,
+                            kind,
+                        });
+                    }
+                }
+                LOAD_NAME | LOAD_GLOBAL | LOAD_ATTR | LOAD_METHOD => {
+                    let name = self.name_at(instruction.arg)?;
+                    let dunder = name == "__import__";
+
+                    if name != "import_module" && !dunder {
+                        continue;
+                    }
+
+                    let target = match instructions.get(i + 1) {
+                        Some(next) if next.opcode == LOAD_CONST => {
+                            match self.const_at(next.arg)?.as_str() {
+                                Some(target) => target,
+                                None => continue,
+                            }
+                        }
+                        _ => continue,
+                    };
+
+                    let (level, target) = if dunder {
+                        (0, target)
+                    } else {
+                        let stripped = target.trim_start_matches('.');
+                        (target.len() - stripped.len(), stripped)
+                    };
+
+                    let resolved = if level == 0 {
+                        Some(target.to_string())
+                    } else {
+                        let anchor = match instructions.get(i + 2) {
+                            Some(next) if next.opcode == LOAD_CONST => {
+                                self.const_at(next.arg)?.as_str()
+                            }
+                            Some(next)
+                                if next.opcode == LOAD_NAME || next.opcode == LOAD_GLOBAL =>
+                            {
+                                match self.name_at(next.arg)? {
+                                    "__name__" => Some(module),
+                                    "__package__" => Some(package),
+                                    _ => None,
+                                }
+                            }
+                            _ => None,
+                        };
+
+                        let call = instructions.get(i + 3);
+                        if !matches!(call, Some(call) if call.opcode == CALL_FUNCTION || call.opcode == CALL_METHOD)
+                        {
+                            continue;
+                        }
+
+                        anchor.and_then(|anchor| resolve_relative_module(anchor, level, target))
+                    };
+
+                    if let Some(module) = resolved {
+                        if !module.is_empty() {
+                            res.push(PythonImport {
+                                module,
+                                names: vec![],
+                                kind: ImportKind::Dynamic,
+                            });
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove docstrings from this and nested code objects.
+    ///
+    /// This has the same effect as compiling with `-OO`: the docstrings of
+    /// modules, classes and functions become `None`.
+    pub fn strip_docstrings(&mut self) -> Result<()> {
+        let instructions = self.instructions()?;
+
+        let const_uses = |index: u32| {
+            instructions
+                .iter()
+                .filter(|i| i.opcode == LOAD_CONST && i.arg == index)
+                .count()
+        };
+
+        let mut doc_indices = vec![];
+
+        if self.flags & (CO_OPTIMIZED | CO_NEWLOCALS) == CO_OPTIMIZED | CO_NEWLOCALS {
+            // The first constant of a function is its docstring or None.
+            if matches!(self.consts.first(), Some(MarshalValue::Str(..))) && const_uses(0) == 0 {
+                doc_indices.push(0);
+            }
+        } else {
+            // Module and class bodies assign the docstring to `__doc__`.
+            for pair in instructions.windows(2) {
+                if pair[0].opcode == LOAD_CONST
+                    && pair[1].opcode == STORE_NAME
+                    && self.name_at(pair[1].arg)? == "__doc__"
+                    && matches!(self.const_at(pair[0].arg)?, MarshalValue::Str(..))
+                    && const_uses(pair[0].arg) == 1
+                {
+                    doc_indices.push(pair[0].arg);
+                }
+            }
+        }
+
+        for index in doc_indices {
+            self.consts[index as usize] = MarshalValue::None;
+        }
+
+        for value in self.consts.iter_mut() {
+            if let MarshalValue::Code(code) = value {
+                code.strip_docstrings()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove line number tables from this and nested code objects.
+    ///
+    /// Tracebacks will report the first line of each code object.
+    pub fn strip_line_tables(&mut self) {
+        self.lnotab.clear();
+
+        for value in self.consts.iter_mut() {
+            if let MarshalValue::Code(code) = value {
+                code.strip_line_tables();
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    cursor: Cursor<&'a [u8]>,
+    refs: Vec<Option<MarshalValue>>,
+}
+
+impl<'a> Reader<'a> {
+    fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.cursor.read_u8()?)
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        Ok(self.cursor.read_i32::<LittleEndian>()?)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        Ok(self.cursor.read_u32::<LittleEndian>()?)
+    }
+
+    fn read_size(&mut self) -> Result<usize> {
+        let size = self.read_i32()?;
+
+        usize::try_from(size).map_err(|_| anyhow!("invalid size {}", size))
+    }
+
+    fn read_bytes(&mut self, size: usize) -> Result<Vec<u8>> {
+        let remaining = self.cursor.get_ref().len() - self.cursor.position() as usize;
+        if size > remaining {
+            return Err(anyhow!("unexpected end of marshal data"));
+        }
+
+        let mut data = vec![0; size];
+        self.cursor.read_exact(&mut data)?;
+
+        Ok(data)
+    }
+
+    fn read_string(&mut self, size: usize) -> Result<String> {
+        String::from_utf8(self.read_bytes(size)?)
+            .map_err(|_| anyhow!("string is not valid UTF-8 (lone surrogates are not supported)"))
+    }
+
+    fn read_text_float(&mut self) -> Result<f64> {
+        let size = self.read_u8()? as usize;
+        let s = self.read_string(size)?;
+
+        s.parse::<f64>()
+            .with_context(|| format!("parsing float {}", s))
+    }
+
+    fn read_binary_float(&mut self) -> Result<f64> {
+        Ok(self.cursor.read_f64::<LittleEndian>()?)
+    }
+
+    fn read_values(&mut self, count: usize) -> Result<Vec<MarshalValue>> {
+        (0..count).map(|_| self.read_value()).collect()
+    }
+
+    fn read_value(&mut self) -> Result<MarshalValue> {
+        let code = self.read_u8()?;
+        let flag = code & FLAG_REF != 0;
+        let typ = code & !FLAG_REF;
+
+        if typ == TYPE_REF {
+            let index = self.read_u32()? as usize;
+
+            return match self.refs.get(index) {
+                Some(Some(value)) => Ok(value.clone()),
+                Some(None) => Err(anyhow!("recursive marshal references are not supported")),
+                None => Err(anyhow!("invalid marshal reference {}", index)),
+            };
+        }
+
+        // Like CPython, we reserve the reference slot before reading the
+        // contents of the object so nested references are numbered correctly.
+        let ref_index = if flag {
+            self.refs.push(None);
+            Some(self.refs.len() - 1)
+        } else {
+            None
+        };
+
+        let value = match typ {
+            TYPE_NULL => MarshalValue::Null,
+            TYPE_NONE => MarshalValue::None,
+            TYPE_FALSE => MarshalValue::False,
+            TYPE_TRUE => MarshalValue::True,
+            TYPE_STOPITER => MarshalValue::StopIteration,
+            TYPE_ELLIPSIS => MarshalValue::Ellipsis,
+            TYPE_INT => MarshalValue::Int(self.read_i32()?),
+            TYPE_LONG => {
+                let n = self.read_i32()?;
+                let digits = (0..n.unsigned_abs())
+                    .map(|_| Ok(self.cursor.read_u16::<LittleEndian>()?))
+                    .collect::<Result<Vec<_>>>()?;
+
+                MarshalValue::Long {
+                    negative: n < 0,
+                    digits,
+                }
+            }
+            TYPE_FLOAT => MarshalValue::Float(self.read_text_float()?),
+            TYPE_BINARY_FLOAT => MarshalValue::Float(self.read_binary_float()?),
+            TYPE_COMPLEX => MarshalValue::Complex(self.read_text_float()?, self.read_text_float()?),
+            TYPE_BINARY_COMPLEX => {
+                MarshalValue::Complex(self.read_binary_float()?, self.read_binary_float()?)
+            }
+            TYPE_STRING => {
+                let size = self.read_size()?;
+                MarshalValue::Bytes(self.read_bytes(size)?)
+            }
+            TYPE_ASCII | TYPE_ASCII_INTERNED | TYPE_SHORT_ASCII | TYPE_SHORT_ASCII_INTERNED => {
+                let size = if typ == TYPE_SHORT_ASCII || typ == TYPE_SHORT_ASCII_INTERNED {
+                    self.read_u8()? as usize
+                } else {
+                    self.read_size()?
+                };
+
+                let kind = if typ == TYPE_ASCII_INTERNED || typ == TYPE_SHORT_ASCII_INTERNED {
+                    StringKind::AsciiInterned
+                } else {
+                    StringKind::Ascii
+                };
+
+                MarshalValue::Str(self.read_string(size)?, kind)
+            }
+            TYPE_UNICODE | TYPE_INTERNED => {
+                let size = self.read_size()?;
+                let kind = if typ == TYPE_INTERNED {
+                    StringKind::UnicodeInterned
+                } else {
+                    StringKind::Unicode
+                };
+
+                MarshalValue::Str(self.read_string(size)?, kind)
+            }
+            TYPE_SMALL_TUPLE => {
+                let size = self.read_u8()? as usize;
+                MarshalValue::Tuple(self.read_values(size)?)
+            }
+            TYPE_TUPLE => {
+                let size = self.read_size()?;
+                MarshalValue::Tuple(self.read_values(size)?)
+            }
+            TYPE_LIST => {
+                let size = self.read_size()?;
+                MarshalValue::List(self.read_values(size)?)
+            }
+            TYPE_SET => {
+                let size = self.read_size()?;
+                MarshalValue::Set(self.read_values(size)?)
+            }
+            TYPE_FROZENSET => {
+                let size = self.read_size()?;
+                MarshalValue::FrozenSet(self.read_values(size)?)
+            }
+            TYPE_DICT => {
+                let mut entries = vec![];
+
+                loop {
+                    let key = self.read_value()?;
+                    if key == MarshalValue::Null {
+                        break;
+                    }
+                    let value = self.read_value()?;
+
+                    entries.push((key, value));
+                }
+
+                MarshalValue::Dict(entries)
+            }
+            TYPE_CODE => MarshalValue::Code(Box::new(self.read_code()?)),
+            _ => return Err(anyhow!("unsupported marshal type code 0x{:02x}", code)),
+        };
+
+        if let Some(index) = ref_index {
+            self.refs[index] = Some(value.clone());
+        }
+
+        Ok(value)
+    }
+
+    fn read_u32_field(&mut self, field: &str) -> Result<u32> {
+        let value = self.read_i32()?;
+
+        u32::try_from(value).map_err(|_| anyhow!("invalid value {} for {}", value, field))
+    }
+
+    fn read_bytes_field(&mut self, field: &str) -> Result<Vec<u8>> {
+        match self.read_value()? {
+            MarshalValue::Bytes(data) => Ok(data),
+            _ => Err(anyhow!("expected bytes for {}", field)),
+        }
+    }
+
+    fn read_string_field(&mut self, field: &str) -> Result<String> {
+        match self.read_value()? {
+            MarshalValue::Str(s, _) => Ok(s),
+            _ => Err(anyhow!("expected string for {}", field)),
+        }
+    }
+
+    fn read_tuple_field(&mut self, field: &str) -> Result<Vec<MarshalValue>> {
+        match self.read_value()? {
+            MarshalValue::Tuple(values) => Ok(values),
+            _ => Err(anyhow!("expected tuple for {}", field)),
+        }
+    }
+
+    fn read_names_field(&mut self, field: &str) -> Result<Vec<String>> {
+        self.read_tuple_field(field)?
+            .into_iter()
+            .map(|value| match value {
+                MarshalValue::Str(s, _) => Ok(s),
+                _ => Err(anyhow!("expected tuple of strings for {}", field)),
+            })
+            .collect()
+    }
+
+    fn read_code(&mut self) -> Result<CodeObject> {
+        Ok(CodeObject {
+            argcount: self.read_u32_field("co_argcount")?,
+            posonlyargcount: self.read_u32_field("co_posonlyargcount")?,
+            kwonlyargcount: self.read_u32_field("co_kwonlyargcount")?,
+            nlocals: self.read_u32_field("co_nlocals")?,
+            stacksize: self.read_u32_field("co_stacksize")?,
+            flags: self.read_u32_field("co_flags")?,
+            code: self.read_bytes_field("co_code")?,
+            consts: self.read_tuple_field("co_consts")?,
+            names: self.read_names_field("co_names")?,
+            varnames: self.read_names_field("co_varnames")?,
+            freevars: self.read_names_field("co_freevars")?,
+            cellvars: self.read_names_field("co_cellvars")?,
+            filename: self.read_string_field("co_filename")?,
+            name: self.read_string_field("co_name")?,
+            firstlineno: self.read_u32_field("co_firstlineno")?,
+            lnotab: self.read_bytes_field("co_lnotab")?,
+        })
+    }
+}
+
+/// Decode a value from marshal data.
+///
+/// References between objects are resolved: the returned value doesn't
+/// share structure.
+pub fn read_marshal(data: &[u8]) -> Result<MarshalValue> {
+    let mut reader = Reader {
+        cursor: Cursor::new(data),
+        refs: vec![],
+    };
+
+    reader.read_value()
+}
+
+/// Decode a code object from marshal data.
+pub fn read_code_object(data: &[u8]) -> Result<CodeObject> {
+    match read_marshal(data)? {
+        MarshalValue::Code(code) => Ok(*code),
+        _ => Err(anyhow!("marshal data does not contain a code object")),
+    }
+}
+
+/// Decode a `.pyc` file into its Python version and code object.
+pub fn read_pyc(data: &[u8]) -> Result<(BytecodeVersion, CodeObject)> {
+    if data.len() < 16 {
+        return Err(anyhow!("bytecode file is too short"));
+    }
+
+    if &data[2..4] != b"\r\n" {
+        return Err(anyhow!("bytecode file has invalid magic number"));
+    }
+
+    let magic = u16::from_le_bytes([data[0], data[1]]);
+    let version = BytecodeVersion::from_magic(magic)
+        .ok_or_else(|| anyhow!("unsupported bytecode magic number {}", magic))?;
+
+    Ok((version, read_code_object(&data[16..])?))
+}
+
+struct Writer {
+    data: Vec<u8>,
+    interned: HashMap<String, u32>,
+    ref_count: u32,
+}
+
+impl Writer {
+    fn write_size(&mut self, size: usize) -> Result<()> {
+        let size = i32::try_from(size).map_err(|_| anyhow!("object too large to marshal"))?;
+        self.data.write_i32::<LittleEndian>(size)?;
+
+        Ok(())
+    }
+
+    fn write_values(&mut self, values: &[MarshalValue]) -> Result<()> {
+        for value in values {
+            self.write_value(value)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_str(&mut self, s: &str, kind: StringKind) -> Result<()> {
+        // Interned strings are written once and referenced afterwards, like
+        // CPython does.
+        if kind.is_interned() {
+            if let Some(index) = self.interned.get(s) {
+                self.data.push(TYPE_REF);
+                self.data.write_u32::<LittleEndian>(*index)?;
+                return Ok(());
+            }
+
+            self.interned.insert(s.to_string(), self.ref_count);
+            self.ref_count += 1;
+        }
+
+        let flag = if kind.is_interned() { FLAG_REF } else { 0 };
+
+        match kind {
+            StringKind::Ascii | StringKind::AsciiInterned if s.is_ascii() => {
+                if s.len() < 256 {
+                    self.data.push(
+                        flag | if kind.is_interned() {
+                            TYPE_SHORT_ASCII_INTERNED
+                        } else {
+                            TYPE_SHORT_ASCII
+                        },
+                    );
+                    self.data.push(s.len() as u8);
+                } else {
+                    self.data.push(
+                        flag | if kind.is_interned() {
+                            TYPE_ASCII_INTERNED
+                        } else {
+                            TYPE_ASCII
+                        },
+                    );
+                    self.write_size(s.len())?;
+                }
+            }
+            _ => {
+                self.data.push(
+                    flag | if kind.is_interned() {
+                        TYPE_INTERNED
+                    } else {
+                        TYPE_UNICODE
+                    },
+                );
+                self.write_size(s.len())?;
+            }
+        }
+
+        self.data.extend_from_slice(s.as_bytes());
+
+        Ok(())
+    }
+
+    fn write_name(&mut self, s: &str) -> Result<()> {
+        let kind = if s.is_ascii() {
+            StringKind::AsciiInterned
+        } else {
+            StringKind::UnicodeInterned
+        };
+
+        self.write_str(s, kind)
+    }
+
+    fn write_names(&mut self, names: &[String]) -> Result<()> {
+        self.write_tuple_header(names.len())?;
+
+        for name in names {
+            self.write_name(name)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_tuple_header(&mut self, size: usize) -> Result<()> {
+        if size < 256 {
+            self.data.push(TYPE_SMALL_TUPLE);
+            self.data.push(size as u8);
+        } else {
+            self.data.push(TYPE_TUPLE);
+            self.write_size(size)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_value(&mut self, value: &MarshalValue) -> Result<()> {
+        match value {
+            MarshalValue::Null => self.data.push(TYPE_NULL),
+            MarshalValue::None => self.data.push(TYPE_NONE),
+            MarshalValue::False => self.data.push(TYPE_FALSE),
+            MarshalValue::True => self.data.push(TYPE_TRUE),
+            MarshalValue::StopIteration => self.data.push(TYPE_STOPITER),
+            MarshalValue::Ellipsis => self.data.push(TYPE_ELLIPSIS),
+            MarshalValue::Int(v) => {
+                self.data.push(TYPE_INT);
+                self.data.write_i32::<LittleEndian>(*v)?;
+            }
+            MarshalValue::Long { negative, digits } => {
+                self.data.push(TYPE_LONG);
+                let size = i32::try_from(digits.len())
+                    .map_err(|_| anyhow!("integer too large to marshal"))?;
+                self.data
+                    .write_i32::<LittleEndian>(if *negative { -size } else { size })?;
+                for digit in digits {
+                    self.data.write_u16::<LittleEndian>(*digit)?;
+                }
+            }
+            MarshalValue::Float(v) => {
+                self.data.push(TYPE_BINARY_FLOAT);
+                self.data.write_f64::<LittleEndian>(*v)?;
+            }
+            MarshalValue::Complex(real, imag) => {
+                self.data.push(TYPE_BINARY_COMPLEX);
+                self.data.write_f64::<LittleEndian>(*real)?;
+                self.data.write_f64::<LittleEndian>(*imag)?;
+            }
+            MarshalValue::Bytes(data) => {
+                self.data.push(TYPE_STRING);
+                self.write_size(data.len())?;
+                self.data.extend_from_slice(data);
+            }
+            MarshalValue::Str(s, kind) => self.write_str(s, *kind)?,
+            MarshalValue::Tuple(values) => {
+                self.write_tuple_header(values.len())?;
+                self.write_values(values)?;
+            }
+            MarshalValue::List(values) => {
+                self.data.push(TYPE_LIST);
+                self.write_size(values.len())?;
+                self.write_values(values)?;
+            }
+            MarshalValue::Set(values) => {
+                self.data.push(TYPE_SET);
+                self.write_size(values.len())?;
+                self.write_values(values)?;
+            }
+            MarshalValue::FrozenSet(values) => {
+                self.data.push(TYPE_FROZENSET);
+                self.write_size(values.len())?;
+                self.write_values(values)?;
+            }
+            MarshalValue::Dict(entries) => {
+                self.data.push(TYPE_DICT);
+                for (key, value) in entries {
+                    self.write_value(key)?;
+                    self.write_value(value)?;
+                }
+                self.data.push(TYPE_NULL);
+            }
+            MarshalValue::Code(code) => self.write_code(code)?,
+        }
+
+        Ok(())
+    }
+
+    fn write_code(&mut self, code: &CodeObject) -> Result<()> {
+        self.data.push(TYPE_CODE);
+
+        for value in &[
+            code.argcount,
+            code.posonlyargcount,
+            code.kwonlyargcount,
+            code.nlocals,
+            code.stacksize,
+            code.flags,
+        ] {
+            self.data.write_u32::<LittleEndian>(*value)?;
+        }
+
+        self.write_value(&MarshalValue::Bytes(code.code.clone()))?;
+        self.write_tuple_header(code.consts.len())?;
+        self.write_values(&code.consts)?;
+        self.write_names(&code.names)?;
+        self.write_names(&code.varnames)?;
+        self.write_names(&code.freevars)?;
+        self.write_names(&code.cellvars)?;
+        self.write_str(
+            &code.filename,
+            if code.filename.is_ascii() {
+                StringKind::Ascii
+            } else {
+                StringKind::Unicode
+            },
+        )?;
+        self.write_name(&code.name)?;
+        self.data.write_u32::<LittleEndian>(code.firstlineno)?;
+        self.write_value(&MarshalValue::Bytes(code.lnotab.clone()))?;
+
+        Ok(())
+    }
+}
+
+/// Encode a value to marshal data.
+///
+/// The output can be read by CPython 3.8 and 3.9 `marshal.loads()`. It
+/// isn't guaranteed to be byte identical to what CPython would produce.
+pub fn write_marshal(value: &MarshalValue) -> Result<Vec<u8>> {
+    let mut writer = Writer {
+        data: vec![],
+        interned: HashMap::new(),
+        ref_count: 0,
+    };
+
+    writer.write_value(value)?;
+
+    Ok(writer.data)
+}
+
+/// Encode a code object to marshal data.
+pub fn write_code_object(code: &CodeObject) -> Result<Vec<u8>> {
+    let mut writer = Writer {
+        data: vec![],
+        interned: HashMap::new(),
+        ref_count: 0,
+    };
+
+    writer.write_code(code)?;
+
+    Ok(writer.data)
+}
+
+/// Whether `__file__` is referenced by marshalled bytecode.
+///
+/// This is the bytecode equivalent of `python_source::has_dunder_file()`.
+pub fn bytecode_has_dunder_file(bytecode: &[u8]) -> Result<bool> {
+    Ok(read_code_object(bytecode)?.has_dunder_file())
+}
+
+/// Find imports performed by marshalled bytecode.
+///
+/// This is the bytecode equivalent of `python_source::find_imports()`.
+pub fn find_bytecode_imports(
+    bytecode: &[u8],
+    module: &str,
+    is_package: bool,
+) -> Result<Vec<PythonImport>> {
+    read_code_object(bytecode)?.find_imports(module, is_package)
+}
+
+/// Find constructs in marshalled bytecode that require files on the filesystem.
+///
+/// This is the bytecode equivalent of `python_source::find_filesystem_dependencies()`.
+/// Only references to names are detected, so fewer constructs are found and
+/// line numbers aren't reported.
+pub fn find_bytecode_filesystem_dependencies(bytecode: &[u8]) -> Result<Vec<FilesystemDependency>> {
+    let names = read_code_object(bytecode)?.referenced_names();
+
+    let mut res = vec![];
+
+    if names.contains("__file__") {
+        res.push(FilesystemDependency {
+            kind: FilesystemDependencyKind::DunderFile,
+            line: None,
+            reason: "references __file__".to_string(),
+        });
+    }
+
+    if names.contains("resource_filename") {
+        res.push(FilesystemDependency {
+            kind: FilesystemDependencyKind::PkgResourcesFilename,
+            line: None,
+            reason: "calls pkg_resources.resource_filename(), which requires resources to be files"
+                .to_string(),
+        });
+    }
+
+    Ok(res)
+}
+
+/// Strip docstrings and/or line number tables from marshalled bytecode.
+pub fn strip_bytecode(bytecode: &[u8], docstrings: bool, line_tables: bool) -> Result<Vec<u8>> {
+    let mut code = read_code_object(bytecode)?;
+
+    if docstrings {
+        code.strip_docstrings()?;
+    }
+    if line_tables {
+        code.strip_line_tables();
+    }
+
+    write_code_object(&code)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::bytecode::compute_bytecode_header, crate::bytecode::BytecodeHeaderMode};
+
+    /// `marshal.dumps(compile(source, "mod.py", "exec"))` from CPython 3.9, where
+    /// `source` is:
+    ///
+    /// ```text
+    /// """Module doc."""
+    /// import os
+    /// from . import sibling
+    /// from .pkg import thing as t
+    /// import importlib
+    /// importlib.import_module("dyn.mod")
+    ///
+    /// class C:
+    ///     """Class doc."""
+    ///     def method(self):
+    ///         """Method doc."""
+    ///         return __file__
+    /// ```
+    fn fixture() -> Vec<u8> {
+        hex::decode(
+            [
+                "e3000000000000000000000000000000000300000040000000734800000064005a00640164026c01",
+                "5a01640364046c026d035a030100640364056c046d055a060100640164026c075a076507a0086406",
+                "a10101004700640764088400640883025a096402530029097a0b4d6f64756c6520646f632ee90000",
+                "00004ee9010000002901da077369626c696e672901da057468696e677a0764796e2e6d6f64630000",
+                "00000000000000000000000000000200000040000000731800000065005a0164005a0264015a0364",
+                "02640384005a04640453002905da01437a0a436c61737320646f632e630100000000000000000000",
+                "0001000000010000004300000073040000007400530029017a0b4d6574686f6420646f632e2901da",
+                "085f5f66696c655f5f2901da0473656c66a9007208000000fa066d6f642e7079da066d6574686f64",
+                "0a000000730200000000027a08432e6d6574686f644e2905da085f5f6e616d655f5fda0a5f5f6d6f",
+                "64756c655f5fda0c5f5f7175616c6e616d655f5fda075f5f646f635f5f720a000000720800000072",
+                "08000000720800000072090000007205000000080000007304000000080104017205000000290a72",
+                "0e000000da026f73da0072030000005a03706b677204000000da01745a09696d706f72746c69625a",
+                "0d696d706f72745f6d6f64756c6572050000007208000000720800000072080000007209000000da",
+                "083c6d6f64756c653e01000000730c000000040108010c010c0108010a02",
+            ]
+            .concat(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_magic_number() {
+        assert_eq!(BytecodeVersion::Python38.magic_number(), 168627541);
+        assert_eq!(
+            BytecodeVersion::from_magic(3425),
+            Some(BytecodeVersion::Python39)
+        );
+        assert_eq!(BytecodeVersion::from_magic(3439), None);
+        assert_eq!(
+            BytecodeVersion::from_cache_tag("cpython-38"),
+            Some(BytecodeVersion::Python38)
+        );
+        assert_eq!(BytecodeVersion::from_cache_tag("cpython-310"), None);
+    }
+
+    #[test]
+    fn test_read_code_object() -> Result<()> {
+        let code = read_code_object(&fixture())?;
+
+        assert_eq!(code.name, "<module>");
+        assert_eq!(code.filename, "mod.py");
+        assert_eq!(code.firstlineno, 1);
+        assert_eq!(
+            code.names,
+            vec![
+                "__doc__",
+                "os",
+                "",
+                "sibling",
+                "pkg",
+                "thing",
+                "t",
+                "importlib",
+                "import_module",
+                "C"
+            ]
+        );
+        assert_eq!(
+            code.consts[0],
+            MarshalValue::Str("Module doc.".to_string(), StringKind::Ascii)
+        );
+        assert_eq!(code.code_objects().len(), 3);
+        assert_eq!(code.code_objects()[2].name, "method");
+        assert_eq!(code.code_objects()[2].varnames, vec!["self"]);
+        // The method's filename is a back-reference to the module's.
+        assert_eq!(code.code_objects()[2].filename, "mod.py");
+
+        assert!(code.referenced_names().contains("__qualname__"));
+        assert!(code.has_dunder_file());
+        assert!(!code.code_objects()[0]
+            .names
+            .contains(&"__file__".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let code = read_code_object(&fixture())?;
+        let written = write_code_object(&code)?;
+
+        assert_eq!(read_code_object(&written)?, code);
+        assert_eq!(read_marshal(&written)?, MarshalValue::Code(Box::new(code)));
+
+        let value = MarshalValue::Dict(vec![
+            (
+                MarshalValue::Str("k".to_string(), StringKind::AsciiInterned),
+                MarshalValue::List(vec![
+                    MarshalValue::Long {
+                        negative: true,
+                        digits: vec![1, 2],
+                    },
+                    MarshalValue::Float(1.5),
+                    MarshalValue::Complex(0.0, -1.0),
+                    MarshalValue::FrozenSet(vec![MarshalValue::Bytes(b"x".to_vec())]),
+                    MarshalValue::Str("\u{e9}".to_string(), StringKind::Unicode),
+                    MarshalValue::Str("k".to_string(), StringKind::AsciiInterned),
+                ]),
+            ),
+            (MarshalValue::Ellipsis, MarshalValue::Set(vec![])),
+        ]);
+        assert_eq!(read_marshal(&write_marshal(&value)?)?, value);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_pyc() -> Result<()> {
+        let mut data = compute_bytecode_header(
+            BytecodeVersion::Python39.magic_number(),
+            BytecodeHeaderMode::UncheckedHash(0),
+        )?;
+        data.extend(fixture());
+
+        let (version, code) = read_pyc(&data)?;
+        assert_eq!(version, BytecodeVersion::Python39);
+        assert_eq!(code.name, "<module>");
+
+        data[0] = 0;
+        assert!(read_pyc(&data).is_err());
+        assert!(read_pyc(&data[0..10]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_imports() -> Result<()> {
+        let imports = find_bytecode_imports(&fixture(), "foo.mod", false)?;
+
+        assert_eq!(
+            imports,
+            vec![
+                PythonImport {
+                    module: "os".to_string(),
+                    names: vec![],
+                    kind: ImportKind::Import,
+                },
+                PythonImport {
+                    module: "foo".to_string(),
+                    names: vec!["sibling".to_string()],
+                    kind: ImportKind::FromImport,
+                },
+                PythonImport {
+                    module: "foo.pkg".to_string(),
+                    names: vec!["thing".to_string()],
+                    kind: ImportKind::FromImport,
+                },
+                PythonImport {
+                    module: "importlib".to_string(),
+                    names: vec![],
+                    kind: ImportKind::Import,
+                },
+                PythonImport {
+                    module: "dyn.mod".to_string(),
+                    names: vec![],
+                    kind: ImportKind::Dynamic,
+                },
+            ]
+        );
+
+        assert!(bytecode_has_dunder_file(&fixture())?);
+        assert_eq!(
+            find_bytecode_filesystem_dependencies(&fixture())?
+                .into_iter()
+                .map(|d| d.kind)
+                .collect::<Vec<_>>(),
+            vec![FilesystemDependencyKind::DunderFile]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip() -> Result<()> {
+        let code = read_code_object(&strip_bytecode(&fixture(), true, true)?)?;
+
+        for code in code.code_objects() {
+            assert!(code.lnotab.is_empty());
+            assert!(!code
+                .consts
+                .iter()
+                .any(|value| matches!(value.as_str(), Some(s) if s.ends_with(" doc."))));
+        }
+
+        // Other string constants are retained.
+        let class = code.code_objects()[1];
+        assert_eq!(
+            class.consts[0],
+            MarshalValue::Str("C".to_string(), StringKind::AsciiInterned)
+        );
+        assert_eq!(code.consts[1], MarshalValue::Int(0));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,477 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Static analysis of import relationships between Python modules.
+
+This module defines a dependency graph of Python modules derived from
+scanning Python source code for imports. It is conceptually similar to
+Python's `modulefinder` module, except it never executes Python code.
+
+Static analysis can't see imports whose module names are computed at
+run-time. So consumers should provide an allow-list of modules that are
+imported dynamically.
+*/
+
+use {
+    crate::{
+        marshal::find_bytecode_imports,
+        module_util::packages_from_module_name,
+        python_source::{find_imports, ImportKind, PythonImport},
+        resource::PythonModuleSource,
+    },
+    anyhow::Result,
+    std::collections::{BTreeMap, BTreeSet},
+};
+
+/// Module patterns that the Python interpreter needs to initialize and run code.
+///
+/// These are imported by the interpreter itself and not by Python source code,
+/// so static analysis won't discover them. This covers the modules imported
+/// during startup of every supported Python version. e.g. `_bootlocale` is
+/// imported by `io.TextIOWrapper` on Python 3.8 and 3.9.
+pub const INTERPRETER_STARTUP_MODULES: &[&str] = &[
+    "_bootlocale",
+    "_collections_abc",
+    "_sitebuiltins",
+    "abc",
+    "codecs",
+    "encodings.*",
+    "genericpath",
+    "importlib.*",
+    "io",
+    "linecache",
+    "ntpath",
+    "os",
+    "posixpath",
+    "runpy",
+    "site",
+    "stat",
+    "traceback",
+    "warnings",
+    "zipimport",
+];
+
+/// Whether a module name matches a module pattern.
+///
+/// Patterns are either a fully qualified module name or a module name
+/// followed by `.*`, which matches the named module and every module
+/// beneath it.
+pub fn module_matches_pattern(name: &str, pattern: &str) -> bool {
+    if let Some(prefix) = pattern.strip_suffix(".*") {
+        name == prefix
+            || (name.len() > prefix.len()
+                && name.starts_with(prefix)
+                && name.as_bytes()[prefix.len()] == b'.')
+    } else {
+        name == pattern
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct ModuleNode {
+    is_package: bool,
+    /// Whether the module's imports are known.
+    analyzed: bool,
+    imports: Vec<PythonImport>,
+}
+
+/// Result of a reachability analysis on a `ModuleDependencyGraph`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModuleReachability {
+    /// Modules in the graph reachable from the entry modules.
+    pub reachable: BTreeSet<String>,
+
+    /// Modules in the graph not reachable from the entry modules.
+    pub unreachable: BTreeSet<String>,
+
+    /// Modules imported by reachable modules but not present in the graph.
+    pub missing: BTreeSet<String>,
+
+    /// Reachable modules whose imports could not be analyzed.
+    ///
+    /// Modules imported by these modules may be incorrectly reported as
+    /// unreachable.
+    pub opaque: BTreeSet<String>,
+}
+
+/// A graph of import dependencies between Python modules.
+#[derive(Clone, Debug, Default)]
+pub struct ModuleDependencyGraph {
+    modules: BTreeMap<String, ModuleNode>,
+}
+
+impl ModuleDependencyGraph {
+    /// Register a module whose imports can't be analyzed.
+    ///
+    /// This should be used for extension modules and modules only
+    /// available as bytecode that can't be decoded. If the module is already
+    /// known, this is a no-op.
+    pub fn add_module(&mut self, name: &str, is_package: bool) {
+        let node = self.modules.entry(name.to_string()).or_default();
+        node.is_package |= is_package;
+    }
+
+    /// Register a module and the imports found in its source code.
+    pub fn add_module_source(&mut self, name: &str, is_package: bool, source: &[u8]) -> Result<()> {
+        let imports = find_imports(source, name, is_package)?;
+
+        let node = self.modules.entry(name.to_string()).or_default();
+        node.is_package |= is_package;
+        node.analyzed = true;
+        node.imports = imports;
+
+        Ok(())
+    }
+
+    /// Register a module and the imports found in its marshalled bytecode.
+    ///
+    /// `bytecode` is a marshalled code object, without a `.pyc` header.
+    pub fn add_module_bytecode(
+        &mut self,
+        name: &str,
+        is_package: bool,
+        bytecode: &[u8],
+    ) -> Result<()> {
+        let imports = find_bytecode_imports(bytecode, name, is_package)?;
+
+        let node = self.modules.entry(name.to_string()).or_default();
+        node.is_package |= is_package;
+        node.analyzed = true;
+        node.imports = imports;
+
+        Ok(())
+    }
+
+    /// Register a `PythonModuleSource`.
+    pub fn add_python_module_source(&mut self, module: &PythonModuleSource) -> Result<()> {
+        self.add_module_source(&module.name, module.is_package, &module.source.resolve()?)
+    }
+
+    /// Whether a module is known to the graph.
+    pub fn contains(&self, name: &str) -> bool {
+        self.modules.contains_key(name)
+    }
+
+    /// Obtain an iterator over names of modules in the graph.
+    pub fn module_names(&self) -> impl Iterator<Item = &String> {
+        self.modules.keys()
+    }
+
+    /// Obtain the names of modules directly imported by a module.
+    ///
+    /// Only modules present in the graph are returned. Parent packages of
+    /// imported modules are included, as importing a module imports its
+    /// parents. Returns `None` if the module isn't known.
+    pub fn dependencies(&self, name: &str) -> Option<BTreeSet<String>> {
+        let node = self.modules.get(name)?;
+
+        let mut res = BTreeSet::new();
+        for candidate in Self::import_candidates(node) {
+            for name in
+                std::iter::once(candidate.clone()).chain(packages_from_module_name(&candidate))
+            {
+                if self.modules.contains_key(&name) {
+                    res.insert(name);
+                }
+            }
+        }
+
+        Some(res)
+    }
+
+    /// Resolve names that may be modules imported by a module node.
+    fn import_candidates(node: &ModuleNode) -> Vec<String> {
+        let mut res = vec![];
+
+        for import in &node.imports {
+            res.push(import.module.clone());
+
+            if import.kind == ImportKind::FromImport {
+                res.extend(
+                    import
+                        .names
+                        .iter()
+                        .filter(|name| name.as_str() != "*")
+                        .map(|name| format!("{}.{}", import.module, name)),
+                );
+            }
+        }
+
+        res
+    }
+
+    /// Resolve modules reachable from a set of entry modules.
+    ///
+    /// `entry_modules` are fully qualified module names to start from. If an
+    /// entry module is a package, its `__main__` module is also an entry
+    /// module, mimicking `python -m`.
+    ///
+    /// `allow_patterns` are module patterns (see `module_matches_pattern()`)
+    /// whose matching modules are always considered reachable. This can be
+    /// used to retain modules imported dynamically.
+    pub fn reachability<'a>(
+        &self,
+        entry_modules: impl IntoIterator<Item = &'a str>,
+        allow_patterns: &[&str],
+    ) -> ModuleReachability {
+        let mut res = ModuleReachability::default();
+        let mut pending = vec![];
+
+        for name in entry_modules {
+            pending.push(name.to_string());
+
+            if let Some(node) = self.modules.get(name) {
+                if node.is_package {
+                    pending.push(format!("{}.__main__", name));
+                }
+            }
+        }
+
+        pending.extend(
+            self.modules
+                .keys()
+                .filter(|name| {
+                    allow_patterns
+                        .iter()
+                        .any(|pattern| module_matches_pattern(name, pattern))
+                })
+                .cloned(),
+        );
+
+        while let Some(name) = pending.pop() {
+            if res.reachable.contains(&name) || res.missing.contains(&name) {
+                continue;
+            }
+
+            let node = if let Some(node) = self.modules.get(&name) {
+                node
+            } else {
+                res.missing.insert(name);
+                continue;
+            };
+
+            if !node.analyzed {
+                res.opaque.insert(name.clone());
+            }
+
+            for import in &node.imports {
+                for package in packages_from_module_name(&import.module) {
+                    pending.push(package);
+                }
+
+                // A module listed in `from X import Y` may be an attribute
+                // rather than a module. So only follow it if it exists.
+                match import.kind {
+                    ImportKind::Import | ImportKind::Dynamic => {
+                        pending.push(import.module.clone());
+                    }
+                    ImportKind::FromImport => {
+                        pending.push(import.module.clone());
+
+                        for from_name in &import.names {
+                            let candidate = format!("{}.{}", import.module, from_name);
+
+                            if from_name != "*" && self.modules.contains_key(&candidate) {
+                                pending.push(candidate);
+                            }
+                        }
+                    }
+                }
+            }
+
+            pending.extend(packages_from_module_name(&name));
+            res.reachable.insert(name);
+        }
+
+        res.unreachable = self
+            .modules
+            .keys()
+            .filter(|name| !res.reachable.contains(*name))
+            .cloned()
+            .collect();
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::marshal::{write_code_object, CodeObject, MarshalValue, StringKind},
+    };
+
+    fn graph() -> Result<ModuleDependencyGraph> {
+        let mut graph = ModuleDependencyGraph::default();
+
+        graph.add_module_source("app", true, b"from . import cli\n")?;
+        graph.add_module_source("app.cli", false, b"import json\nfrom .util import helper\n")?;
+        graph.add_module_source(
+            "app.util",
+            false,
+            b"import importlib\nimportlib.import_module('csv')\n",
+        )?;
+        graph.add_module_source("app.unused", false, b"import xml.dom\n")?;
+        graph.add_module_source("app.__main__", false, b"from app.cli import main\n")?;
+        graph.add_module_source("json", true, b"from .decoder import JSONDecoder\n")?;
+        graph.add_module_source("json.decoder", false, b"import re\n")?;
+        graph.add_module_source("importlib", true, b"import sys\n")?;
+        graph.add_module_source("csv", false, b"import _csv\n")?;
+        graph.add_module_source("xml", true, b"")?;
+        graph.add_module_source("xml.dom", true, b"")?;
+        graph.add_module_source("plugins", true, b"")?;
+        graph.add_module_source("plugins.foo", false, b"")?;
+        graph.add_module("_csv", false);
+        graph.add_module("re", false);
+
+        Ok(graph)
+    }
+
+    #[test]
+    fn test_module_matches_pattern() {
+        assert!(module_matches_pattern("foo", "foo"));
+        assert!(!module_matches_pattern("foo.bar", "foo"));
+        assert!(module_matches_pattern("foo", "foo.*"));
+        assert!(module_matches_pattern("foo.bar.baz", "foo.*"));
+        assert!(!module_matches_pattern("foobar", "foo.*"));
+    }
+
+    #[test]
+    fn test_interpreter_startup_modules() {
+        // Non-builtin modules in `sys.modules` after `python -I -c pass` on
+        // Python 3.8 and 3.9, excluding frozen `importlib` bootstrap modules.
+        for name in &[
+            "_bootlocale",
+            "_collections_abc",
+            "_sitebuiltins",
+            "abc",
+            "codecs",
+            "encodings",
+            "encodings.aliases",
+            "encodings.latin_1",
+            "encodings.utf_8",
+            "genericpath",
+            "io",
+            "os",
+            "posixpath",
+            "site",
+            "stat",
+            "zipimport",
+        ] {
+            assert!(
+                INTERPRETER_STARTUP_MODULES
+                    .iter()
+                    .any(|pattern| module_matches_pattern(name, pattern)),
+                "{} is imported during interpreter startup",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_dependencies() -> Result<()> {
+        let graph = graph()?;
+
+        assert_eq!(
+            graph.dependencies("app.cli"),
+            Some(
+                ["app", "app.util", "json"]
+                    .iter()
+                    .map(|x| x.to_string())
+                    .collect()
+            )
+        );
+        assert_eq!(graph.dependencies("missing"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_module_bytecode() -> Result<()> {
+        // `from .util import helper` followed by `return None`.
+        let code = CodeObject {
+            argcount: 0,
+            posonlyargcount: 0,
+            kwonlyargcount: 0,
+            nlocals: 0,
+            stacksize: 2,
+            flags: 0x40,
+            code: vec![100, 0, 100, 1, 108, 0, 109, 1, 90, 1, 1, 0, 100, 2, 83, 0],
+            consts: vec![
+                MarshalValue::Int(1),
+                MarshalValue::Tuple(vec![MarshalValue::Str(
+                    "helper".to_string(),
+                    StringKind::AsciiInterned,
+                )]),
+                MarshalValue::None,
+            ],
+            names: vec!["util".to_string(), "helper".to_string()],
+            varnames: vec![],
+            freevars: vec![],
+            cellvars: vec![],
+            filename: "cli.py".to_string(),
+            name: "<module>".to_string(),
+            firstlineno: 1,
+            lnotab: vec![],
+        };
+
+        let mut graph = graph()?;
+        graph.add_module_bytecode("app.other", false, &write_code_object(&code)?)?;
+
+        assert_eq!(
+            graph.dependencies("app.other"),
+            Some(["app", "app.util"].iter().map(|x| x.to_string()).collect())
+        );
+        assert!(graph
+            .add_module_bytecode("app.broken", false, b"dummy")
+            .is_err());
+        assert!(!graph.contains("app.broken"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reachability() -> Result<()> {
+        let graph = graph()?;
+
+        let res = graph.reachability(vec!["app"], &[]);
+        assert_eq!(
+            res.reachable,
+            [
+                "_csv",
+                "app",
+                "app.__main__",
+                "app.cli",
+                "app.util",
+                "csv",
+                "importlib",
+                "json",
+                "json.decoder",
+                "re"
+            ]
+            .iter()
+            .map(|x| x.to_string())
+            .collect()
+        );
+        assert_eq!(
+            res.unreachable,
+            ["app.unused", "plugins", "plugins.foo", "xml", "xml.dom"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect()
+        );
+        assert_eq!(res.missing, ["sys"].iter().map(|x| x.to_string()).collect());
+        assert_eq!(
+            res.opaque,
+            ["_csv", "re"].iter().map(|x| x.to_string()).collect()
+        );
+
+        let res = graph.reachability(vec!["app"], &["plugins.*"]);
+        assert!(res.reachable.contains("plugins.foo"));
+        assert!(res.reachable.contains("plugins"));
+        assert!(!res.reachable.contains("xml"));
+
+        Ok(())
+    }
+}
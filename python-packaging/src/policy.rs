@@ -11,10 +11,10 @@ use {
         licensing::SAFE_SYSTEM_LIBRARIES,
         location::ConcreteResourceLocation,
         resource::{PythonExtensionModule, PythonExtensionModuleVariants, PythonResource},
-        resource_collection::PythonResourceAddCollectionContext,
+        resource_collection::{PythonResourceAddCollectionContext, ResourceDecision},
     },
     anyhow::Result,
-    std::{collections::HashMap, convert::TryFrom},
+    std::{borrow::Cow, collections::HashMap, convert::TryFrom},
     tugger_licensing::LicenseFlavor,
 };
 
@@ -89,6 +89,260 @@ impl AsRef<str> for ResourceHandlingMode {
     }
 }
 
+/// Describes a type of Python resource that a `ResourcePlacementRule` can match.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ResourceType {
+    /// A `PythonModuleSource`.
+    ModuleSource,
+
+    /// A `PythonModuleBytecode` or `PythonModuleBytecodeFromSource`.
+    ModuleBytecode,
+
+    /// A `PythonPackageResource`.
+    PackageResource,
+
+    /// A `PythonPackageDistributionResource`.
+    PackageDistributionResource,
+
+    /// A `PythonExtensionModule`.
+    ExtensionModule,
+
+    /// A `File`.
+    File,
+}
+
+impl TryFrom<&str> for ResourceType {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "PythonModuleSource" => Ok(Self::ModuleSource),
+            "PythonModuleBytecode" => Ok(Self::ModuleBytecode),
+            "PythonPackageResource" => Ok(Self::PackageResource),
+            "PythonPackageDistributionResource" => Ok(Self::PackageDistributionResource),
+            "PythonExtensionModule" => Ok(Self::ExtensionModule),
+            "File" => Ok(Self::File),
+            _ => Err(format!("{} is not a valid resource type", value)),
+        }
+    }
+}
+
+impl AsRef<str> for ResourceType {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::ModuleSource => "PythonModuleSource",
+            Self::ModuleBytecode => "PythonModuleBytecode",
+            Self::PackageResource => "PythonPackageResource",
+            Self::PackageDistributionResource => "PythonPackageDistributionResource",
+            Self::ExtensionModule => "PythonExtensionModule",
+            Self::File => "File",
+        }
+    }
+}
+
+impl ResourceType {
+    /// Resolve the type of a `PythonResource`.
+    ///
+    /// Returns `None` for resource types that rules can't match.
+    pub fn from_resource(resource: &PythonResource) -> Option<Self> {
+        match resource {
+            PythonResource::ModuleSource(_) => Some(Self::ModuleSource),
+            PythonResource::ModuleBytecode(_) => Some(Self::ModuleBytecode),
+            PythonResource::ModuleBytecodeRequest(_) => Some(Self::ModuleBytecode),
+            PythonResource::PackageResource(_) => Some(Self::PackageResource),
+            PythonResource::PackageDistributionResource(_) => {
+                Some(Self::PackageDistributionResource)
+            }
+            PythonResource::ExtensionModule(_) => Some(Self::ExtensionModule),
+            PythonResource::File(_) => Some(Self::File),
+            PythonResource::EggFile(_) | PythonResource::PathExtension(_) => None,
+        }
+    }
+}
+
+/// Whether a name matches a glob pattern.
+///
+/// `*` matches any sequence of characters and `?` matches a single
+/// character. As a special case, a pattern ending in `.*` also matches the
+/// name preceding it. So `foo.*` matches both the `foo` package and all
+/// modules beneath it.
+fn name_matches_glob(name: &str, pattern: &str) -> bool {
+    fn matches(name: &[char], pattern: &[char]) -> bool {
+        match pattern.split_first() {
+            None => name.is_empty(),
+            Some(('*', rest)) => (0..=name.len()).any(|i| matches(&name[i..], rest)),
+            Some(('?', rest)) => !name.is_empty() && matches(&name[1..], rest),
+            Some((c, rest)) => name.first() == Some(c) && matches(&name[1..], rest),
+        }
+    }
+
+    if let Some(prefix) = pattern.strip_suffix(".*") {
+        if !prefix.contains(&['*', '?'][..]) && name == prefix {
+            return true;
+        }
+    }
+
+    matches(
+        &name.chars().collect::<Vec<_>>(),
+        &pattern.chars().collect::<Vec<_>>(),
+    )
+}
+
+/// A declarative rule influencing how matching resources are added to a collection.
+///
+/// A rule consists of match criteria and actions. All criteria that are
+/// defined must match for the rule to apply. Actions that are defined
+/// replace the corresponding field of a `PythonResourceAddCollectionContext`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResourcePlacementRule {
+    /// Glob pattern matched against the module or package name of a resource.
+    ///
+    /// Package resources and distribution resources match against the name
+    /// of the package they belong to. Files match against their path.
+    pub pattern: Option<String>,
+
+    /// Resource types this rule applies to.
+    ///
+    /// An empty list matches all resource types.
+    pub resource_types: Vec<ResourceType>,
+
+    /// Whether the resource must or must not be an extension module.
+    pub is_extension_module: Option<bool>,
+
+    /// Value for `PythonResourceAddCollectionContext.include`.
+    pub include: Option<bool>,
+
+    /// Value for `PythonResourceAddCollectionContext.location`.
+    pub location: Option<ConcreteResourceLocation>,
+
+    /// Value for `PythonResourceAddCollectionContext.location_fallback`.
+    pub location_fallback: Option<Option<ConcreteResourceLocation>>,
+
+    /// Value for `PythonResourceAddCollectionContext.store_source`.
+    pub store_source: Option<bool>,
+
+    /// Value for `PythonResourceAddCollectionContext.optimize_level_zero`.
+    pub optimize_level_zero: Option<bool>,
+
+    /// Value for `PythonResourceAddCollectionContext.optimize_level_one`.
+    pub optimize_level_one: Option<bool>,
+
+    /// Value for `PythonResourceAddCollectionContext.optimize_level_two`.
+    pub optimize_level_two: Option<bool>,
+}
+
+impl ResourcePlacementRule {
+    /// Whether this rule applies to a resource.
+    pub fn matches(&self, resource: &PythonResource) -> bool {
+        let resource_type = if let Some(resource_type) = ResourceType::from_resource(resource) {
+            resource_type
+        } else {
+            return false;
+        };
+
+        if !self.resource_types.is_empty() && !self.resource_types.contains(&resource_type) {
+            return false;
+        }
+
+        if let Some(is_extension_module) = self.is_extension_module {
+            if is_extension_module != (resource_type == ResourceType::ExtensionModule) {
+                return false;
+            }
+        }
+
+        if let Some(pattern) = &self.pattern {
+            let name = match resource {
+                PythonResource::ModuleSource(m) => m.name.clone(),
+                PythonResource::ModuleBytecode(m) => m.name.clone(),
+                PythonResource::ModuleBytecodeRequest(m) => m.name.clone(),
+                PythonResource::PackageResource(r) => r.leaf_package.clone(),
+                PythonResource::PackageDistributionResource(r) => r.package.clone(),
+                PythonResource::ExtensionModule(em) => em.name.clone(),
+                PythonResource::File(f) => format!("{}", f.path.display()),
+                PythonResource::EggFile(_) | PythonResource::PathExtension(_) => return false,
+            };
+
+            if !name_matches_glob(&name, pattern) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Apply the actions of this rule to an add context.
+    pub fn apply(&self, add_context: &mut PythonResourceAddCollectionContext) {
+        if let Some(include) = self.include {
+            add_context.include = include;
+        }
+        if let Some(location) = &self.location {
+            add_context.location = location.clone();
+        }
+        if let Some(location_fallback) = &self.location_fallback {
+            add_context.location_fallback = location_fallback.clone();
+        }
+        if let Some(store_source) = self.store_source {
+            add_context.store_source = store_source;
+        }
+        if let Some(value) = self.optimize_level_zero {
+            add_context.optimize_level_zero = value;
+        }
+        if let Some(value) = self.optimize_level_one {
+            add_context.optimize_level_one = value;
+        }
+        if let Some(value) = self.optimize_level_two {
+            add_context.optimize_level_two = value;
+        }
+    }
+}
+
+/// Apply matching `ResourcePlacementRule` to an add context.
+///
+/// Rules are evaluated in order and every matching rule is applied. So
+/// actions from later rules override those of earlier rules.
+///
+/// What each matching rule changed is appended to `decisions`.
+pub fn apply_resource_placement_rules<'a>(
+    rules: &[ResourcePlacementRule],
+    resource: &PythonResource,
+    add_context: &'a PythonResourceAddCollectionContext,
+    decisions: &mut Vec<ResourceDecision>,
+) -> Cow<'a, PythonResourceAddCollectionContext> {
+    let mut res = Cow::Borrowed(add_context);
+
+    for (index, rule) in rules.iter().enumerate() {
+        if !rule.matches(resource) {
+            continue;
+        }
+
+        let before = res.as_ref().clone();
+        let add_context = res.to_mut();
+        rule.apply(add_context);
+
+        let actor = match &rule.pattern {
+            Some(pattern) => format!("resource placement rule #{} ({})", index, pattern),
+            None => format!("resource placement rule #{}", index),
+        };
+
+        let changes = before.describe_changes(add_context);
+        if changes.is_empty() {
+            decisions.push(ResourceDecision {
+                actor,
+                message: "matched without changing anything".to_string(),
+            });
+        } else {
+            for message in changes {
+                decisions.push(ResourceDecision {
+                    actor: actor.clone(),
+                    message,
+                });
+            }
+        }
+    }
+
+    res
+}
+
 /// Defines how Python resources should be packaged.
 #[derive(Clone, Debug, PartialEq)]
 pub struct PythonPackagingPolicy {
@@ -104,6 +358,16 @@ pub struct PythonPackagingPolicy {
     /// Optional fallback location for resources should `resources_location` fail.
     resources_location_fallback: Option<ConcreteResourceLocation>,
 
+    /// Location to move packages to if they are found to depend on the filesystem.
+    ///
+    /// See `PythonResourceCollector::find_filesystem_dependencies()`.
+    filesystem_dependent_packages_location: Option<ConcreteResourceLocation>,
+
+    /// Whether packages found to depend on the filesystem are moved.
+    ///
+    /// If false, findings are only reported.
+    relocate_filesystem_dependent_packages: bool,
+
     /// Whether to allow in-memory shared library loading.
     ///
     /// If true, we will attempt to load Python extension modules
@@ -140,6 +404,10 @@ pub struct PythonPackagingPolicy {
     /// If false, this classification is not performed.
     file_scanner_classify_files: bool,
 
+    /// Whether file scanning can follow `.pth` and `.egg-link` files to
+    /// directories outside the site directory holding them.
+    file_scanner_follow_external_paths: bool,
+
     /// Whether to classify non-`File` resources as `include = True` by default.
     include_classified_resources: bool,
 
@@ -172,6 +440,9 @@ pub struct PythonPackagingPolicy {
 
     /// Whether to write Python bytecode at optimization level 2.
     bytecode_optimize_level_two: bool,
+
+    /// Ordered rules influencing how matching resources are added.
+    resource_placement_rules: Vec<ResourcePlacementRule>,
 }
 
 impl Default for PythonPackagingPolicy {
@@ -181,10 +452,13 @@ impl Default for PythonPackagingPolicy {
             preferred_extension_module_variants: HashMap::new(),
             resources_location: ConcreteResourceLocation::InMemory,
             resources_location_fallback: None,
+            filesystem_dependent_packages_location: None,
+            relocate_filesystem_dependent_packages: false,
             allow_in_memory_shared_library_loading: false,
             allow_files: false,
             file_scanner_emit_files: false,
             file_scanner_classify_files: true,
+            file_scanner_follow_external_paths: false,
             include_classified_resources: true,
             include_distribution_sources: true,
             include_non_distribution_sources: true,
@@ -195,6 +469,7 @@ impl Default for PythonPackagingPolicy {
             bytecode_optimize_level_zero: true,
             bytecode_optimize_level_one: false,
             bytecode_optimize_level_two: false,
+            resource_placement_rules: vec![],
         }
     }
 }
@@ -246,6 +521,31 @@ impl PythonPackagingPolicy {
         self.resources_location_fallback = location;
     }
 
+    /// Obtain the location packages depending on the filesystem are moved to.
+    pub fn filesystem_dependent_packages_location(&self) -> &Option<ConcreteResourceLocation> {
+        &self.filesystem_dependent_packages_location
+    }
+
+    /// Set the location packages depending on the filesystem are moved to.
+    ///
+    /// `None` disables looking for these packages.
+    pub fn set_filesystem_dependent_packages_location(
+        &mut self,
+        location: Option<ConcreteResourceLocation>,
+    ) {
+        self.filesystem_dependent_packages_location = location;
+    }
+
+    /// Whether packages depending on the filesystem are moved.
+    pub fn relocate_filesystem_dependent_packages(&self) -> bool {
+        self.relocate_filesystem_dependent_packages
+    }
+
+    /// Set whether packages depending on the filesystem are moved.
+    pub fn set_relocate_filesystem_dependent_packages(&mut self, value: bool) {
+        self.relocate_filesystem_dependent_packages = value;
+    }
+
     /// Whether to allow untyped `File` resources.
     pub fn allow_files(&self) -> bool {
         self.allow_files
@@ -276,6 +576,16 @@ impl PythonPackagingPolicy {
         self.file_scanner_classify_files = value;
     }
 
+    /// Whether file scanning can follow path extensions outside the site directory.
+    pub fn file_scanner_follow_external_paths(&self) -> bool {
+        self.file_scanner_follow_external_paths
+    }
+
+    /// Set whether file scanning can follow path extensions outside the site directory.
+    pub fn set_file_scanner_follow_external_paths(&mut self, value: bool) {
+        self.file_scanner_follow_external_paths = value;
+    }
+
     /// Whether to allow in-memory shared library loading.
     pub fn allow_in_memory_shared_library_loading(&self) -> bool {
         self.allow_in_memory_shared_library_loading
@@ -399,6 +709,23 @@ impl PythonPackagingPolicy {
         }
     }
 
+    /// Obtain the resource placement rules of this policy.
+    pub fn resource_placement_rules(&self) -> &[ResourcePlacementRule] {
+        &self.resource_placement_rules
+    }
+
+    /// Append a resource placement rule to this policy.
+    ///
+    /// Rules are applied in the order they are added.
+    pub fn add_resource_placement_rule(&mut self, rule: ResourcePlacementRule) {
+        self.resource_placement_rules.push(rule);
+    }
+
+    /// Remove all resource placement rules from this policy.
+    pub fn clear_resource_placement_rules(&mut self) {
+        self.resource_placement_rules.clear();
+    }
+
     /// Obtain broken extensions for a target triple.
     pub fn broken_extensions_for_triple(&self, target_triple: &str) -> Option<&Vec<String>> {
         self.broken_extensions.get(target_triple)
@@ -460,6 +787,9 @@ impl PythonPackagingPolicy {
     /// Returns true if the resource should be included, false otherwise.
     fn filter_python_resource(&self, resource: &PythonResource) -> bool {
         match resource {
+            // Which extension modules are added is governed by the extension
+            // module filter.
+            PythonResource::ExtensionModule(_) => {}
             PythonResource::File(_) => {
                 if !self.include_file_resources {
                     return false;
@@ -494,7 +824,7 @@ impl PythonPackagingPolicy {
                 }
             }
             PythonResource::PackageDistributionResource(_) => true,
-            PythonResource::ExtensionModule(_) => false,
+            PythonResource::ExtensionModule(_) => true,
             PythonResource::PathExtension(_) => false,
             PythonResource::EggFile(_) => false,
             PythonResource::File(_) => true,
@@ -636,10 +966,116 @@ impl PythonPackagingPolicy {
 mod tests {
     use {
         super::*,
+        crate::resource::PythonModuleSource,
         std::path::PathBuf,
-        tugger_file_manifest::{File, FileEntry},
+        tugger_file_manifest::{File, FileData, FileEntry},
     };
 
+    #[test]
+    fn test_name_matches_glob() {
+        assert!(name_matches_glob("foo", "foo"));
+        assert!(!name_matches_glob("foo.bar", "foo"));
+        assert!(name_matches_glob("foo", "foo.*"));
+        assert!(name_matches_glob("foo.bar", "foo.*"));
+        assert!(name_matches_glob("foo.bar.baz", "foo.*"));
+        assert!(!name_matches_glob("foobar", "foo.*"));
+        assert!(name_matches_glob("foobar", "foo*"));
+        assert!(name_matches_glob("foo.bar", "*.bar"));
+        assert!(name_matches_glob("foo1", "foo?"));
+        assert!(!name_matches_glob("foo", "foo?"));
+    }
+
+    #[test]
+    fn test_resource_placement_rules() -> Result<()> {
+        let source = PythonModuleSource {
+            name: "foo.bar".to_string(),
+            source: FileData::Memory(vec![]),
+            is_package: false,
+            cache_tag: "cpython-39".to_string(),
+            is_stdlib: false,
+            is_test: false,
+        };
+        let module: PythonResource = (&source).into();
+
+        let policy = PythonPackagingPolicy::default();
+        let add_context = policy.derive_add_collection_context(&module);
+
+        let rules = vec![
+            ResourcePlacementRule {
+                pattern: Some("foo.*".to_string()),
+                location: Some(ConcreteResourceLocation::RelativePath("lib".to_string())),
+                optimize_level_two: Some(true),
+                ..ResourcePlacementRule::default()
+            },
+            ResourcePlacementRule {
+                is_extension_module: Some(true),
+                include: Some(false),
+                ..ResourcePlacementRule::default()
+            },
+            ResourcePlacementRule {
+                resource_types: vec![ResourceType::ModuleSource],
+                location_fallback: Some(Some(ConcreteResourceLocation::InMemory)),
+                optimize_level_two: Some(false),
+                ..ResourcePlacementRule::default()
+            },
+            ResourcePlacementRule {
+                pattern: Some("bar.*".to_string()),
+                include: Some(false),
+                ..ResourcePlacementRule::default()
+            },
+        ];
+
+        assert!(rules[0].matches(&module));
+        assert!(!rules[1].matches(&module));
+        assert!(rules[2].matches(&module));
+        assert!(!rules[3].matches(&module));
+
+        let mut decisions = vec![];
+        let res = apply_resource_placement_rules(&rules, &module, &add_context, &mut decisions);
+        let mut expected = vec![];
+        for (actor, message) in &[
+            (
+                "resource placement rule #0 (foo.*)",
+                "location changed to filesystem-relative:lib",
+            ),
+            (
+                "resource placement rule #0 (foo.*)",
+                "bytecode optimization level 2 changed to true",
+            ),
+            (
+                "resource placement rule #2",
+                "location fallback changed to in-memory",
+            ),
+            (
+                "resource placement rule #2",
+                "bytecode optimization level 2 changed to false",
+            ),
+        ] {
+            expected.push(ResourceDecision {
+                actor: actor.to_string(),
+                message: message.to_string(),
+            });
+        }
+        assert_eq!(decisions, expected);
+        assert_eq!(
+            res.as_ref(),
+            &PythonResourceAddCollectionContext {
+                location: ConcreteResourceLocation::RelativePath("lib".to_string()),
+                location_fallback: Some(ConcreteResourceLocation::InMemory),
+                ..add_context.clone()
+            }
+        );
+
+        // No matching rules doesn't copy the context.
+        let mut decisions = vec![];
+        let res =
+            apply_resource_placement_rules(&rules[3..], &module, &add_context, &mut decisions);
+        assert!(matches!(res, Cow::Borrowed(_)));
+        assert!(decisions.is_empty());
+
+        Ok(())
+    }
+
     #[test]
     fn test_add_collection_context_file() -> Result<()> {
         let mut policy = PythonPackagingPolicy::default();
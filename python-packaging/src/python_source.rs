@@ -30,11 +30,11 @@ pub fn python_source_encoding(source: &[u8]) -> Vec<u8> {
     b"utf-8".to_vec()
 }
 
-/// Whether __file__ occurs in Python source code.
-pub fn has_dunder_file(source: &[u8]) -> Result<bool> {
-    // We can't just look for b"__file__ because the source file may be in
-    // encodings like UTF-16. So we need to decode to Unicode first then look for
-    // the code points.
+/// Decode Python source code to a Unicode string.
+///
+/// The source encoding is derived via `python_source_encoding()`, falling
+/// back to UTF-8 if the declared encoding isn't known.
+pub fn decode_python_source(source: &[u8]) -> String {
     let encoding = python_source_encoding(source);
 
     let encoder = match encoding_rs::Encoding::for_label(&encoding) {
@@ -44,5 +44,776 @@ pub fn has_dunder_file(source: &[u8]) -> Result<bool> {
 
     let (source, ..) = encoder.decode(source);
 
-    Ok(source.contains("__file__"))
+    source.into_owned()
+}
+
+/// Whether __file__ occurs in Python source code.
+pub fn has_dunder_file(source: &[u8]) -> Result<bool> {
+    // We can't just look for b"__file__ because the source file may be in
+    // encodings like UTF-16. So we need to decode to Unicode first then look for
+    // the code points.
+    Ok(decode_python_source(source).contains("__file__"))
+}
+
+/// A lexical token in Python source code.
+///
+/// This is a greatly simplified view of Python's tokenizer: we only
+/// distinguish the token types needed to perform static analysis of
+/// statements like imports.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Token {
+    /// An identifier or keyword.
+    Name(String),
+    /// A string literal.
+    ///
+    /// Holds the literal value if the string is a plain string without
+    /// escape sequences. Holds `None` for bytes, f-strings and strings whose
+    /// value can't be trivially derived.
+    Str(Option<String>),
+    /// A numeric literal.
+    Number,
+    /// A single operator or delimiter character.
+    Op(char),
+    /// The end of a logical line.
+    Newline,
+}
+
+/// Tokenize Python source code into a series of `Token`.
+///
+/// Comments and whitespace are discarded. Line continuations and
+/// newlines within brackets don't emit `Token::Newline`.
+pub(crate) fn tokenize(source: &str) -> Vec<Token> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens = vec![];
+    let mut depth = 0usize;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '#' {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '\\' && chars.get(i + 1) == Some(&'\n') {
+            i += 2;
+        } else if c == '\n' {
+            if depth == 0 && !matches!(tokens.last(), None | Some(Token::Newline)) {
+                tokens.push(Token::Newline);
+            }
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            let (token, end) = read_string(&chars, i, "");
+            tokens.push(token);
+            i = end;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name = chars[start..i].iter().collect::<String>();
+
+            if i < chars.len()
+                && (chars[i] == '\'' || chars[i] == '"')
+                && matches!(
+                    name.to_lowercase().as_str(),
+                    "r" | "u" | "b" | "f" | "br" | "rb" | "fr" | "rf"
+                )
+            {
+                let (token, end) = read_string(&chars, i, &name.to_lowercase());
+                tokens.push(token);
+                i = end;
+            } else {
+                tokens.push(Token::Name(name));
+            }
+        } else if c.is_ascii_digit() {
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Number);
+        } else {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            tokens.push(Token::Op(c));
+            i += 1;
+        }
+    }
+
+    if !matches!(tokens.last(), None | Some(Token::Newline)) {
+        tokens.push(Token::Newline);
+    }
+
+    tokens
+}
+
+/// Read a string literal whose opening quote is at `start`.
+///
+/// Returns the token and the offset just past the closing quote.
+fn read_string(chars: &[char], start: usize, prefix: &str) -> (Token, usize) {
+    let quote = chars[start];
+    let triple = chars.get(start + 1) == Some(&quote) && chars.get(start + 2) == Some(&quote);
+    let raw = prefix.contains('r');
+
+    let mut i = if triple { start + 3 } else { start + 1 };
+    let mut value = String::new();
+    let mut simple = !prefix.contains('b') && !prefix.contains('f');
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c == '\\' {
+            // Escape sequences make the value non-trivial to derive unless
+            // the string is raw. Either way, the next character never
+            // terminates the string.
+            if !raw {
+                simple = false;
+            }
+            value.push(c);
+            if let Some(next) = chars.get(i + 1) {
+                value.push(*next);
+            }
+            i += 2;
+        } else if c == quote
+            && (!triple || (chars.get(i + 1) == Some(&quote) && chars.get(i + 2) == Some(&quote)))
+        {
+            i += if triple { 3 } else { 1 };
+            break;
+        } else if c == '\n' && !triple {
+            // Unterminated string. Stop at the end of the line.
+            simple = false;
+            break;
+        } else {
+            value.push(c);
+            i += 1;
+        }
+    }
+
+    (Token::Str(if simple { Some(value) } else { None }), i)
+}
+
+/// How an import was expressed in Python source code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImportKind {
+    /// An `import X` statement.
+    Import,
+    /// A `from X import Y` statement.
+    FromImport,
+    /// A call to `importlib.import_module()` or `__import__()` with a string literal.
+    Dynamic,
+}
+
+/// Describes an import performed by Python source code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PythonImport {
+    /// The fully qualified name of the module being imported.
+    ///
+    /// Relative imports are resolved against the importing module.
+    pub module: String,
+
+    /// Names imported from the module by a `from X import ...` statement.
+    ///
+    /// Names may refer to submodules of `module` or to attributes defined by it.
+    /// `*` is recorded verbatim.
+    pub names: Vec<String>,
+
+    /// How the import was performed.
+    pub kind: ImportKind,
+}
+
+/// Resolve a relative module name to an absolute one.
+///
+/// `level` is the number of leading dots. Returns `None` if the relative
+/// reference goes beyond the top-level package.
+pub(crate) fn resolve_relative_module(package: &str, level: usize, name: &str) -> Option<String> {
+    if level == 0 {
+        return Some(name.to_string());
+    }
+
+    let mut parts = if package.is_empty() {
+        vec![]
+    } else {
+        package.split('.').collect::<Vec<_>>()
+    };
+
+    if parts.len() < level {
+        return None;
+    }
+
+    parts.truncate(parts.len() - (level - 1));
+
+    if !name.is_empty() {
+        parts.push(name);
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join("."))
+    }
+}
+
+/// Read a dotted name starting at token offset `i`.
+///
+/// Returns the name and the offset of the token following it.
+fn read_dotted_name(tokens: &[Token], mut i: usize) -> Option<(String, usize)> {
+    let mut parts = vec![];
+
+    loop {
+        match tokens.get(i) {
+            Some(Token::Name(name)) => {
+                parts.push(name.clone());
+                i += 1;
+            }
+            _ => return None,
+        }
+
+        if tokens.get(i) == Some(&Token::Op('.')) {
+            i += 1;
+        } else {
+            return Some((parts.join("."), i));
+        }
+    }
+}
+
+/// Find import statements and literal dynamic imports in Python source code.
+///
+/// `module` and `is_package` describe the module the source code belongs to.
+/// They are used to resolve relative imports. Relative imports that can't be
+/// resolved are ignored.
+///
+/// This performs purely lexical analysis. Imports in unreachable code paths
+/// are reported. Imports whose module names are computed at run-time are not.
+pub fn find_imports(source: &[u8], module: &str, is_package: bool) -> Result<Vec<PythonImport>> {
+    let source = decode_python_source(source);
+    let tokens = tokenize(&source);
+
+    let package = if is_package {
+        module
+    } else if let Some(idx) = module.rfind('.') {
+        &module[0..idx]
+    } else {
+        ""
+    };
+
+    let mut res = vec![];
+    let mut depth = 0usize;
+    let mut statement_start = true;
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let at_statement_start = statement_start;
+        statement_start = false;
+
+        match &tokens[i] {
+            Token::Newline => {
+                statement_start = true;
+                i += 1;
+            }
+            Token::Op(c) => {
+                match c {
+                    '(' | '[' | '{' => depth += 1,
+                    ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                    ';' | ':' if depth == 0 => statement_start = true,
+                    _ => {}
+                }
+                i += 1;
+            }
+            Token::Name(name) if at_statement_start && name == "import" => {
+                i += 1;
+
+                while let Some((name, next)) = read_dotted_name(&tokens, i) {
+                    res.push(PythonImport {
+                        module: name,
+                        names: vec![],
+                        kind: ImportKind::Import,
+                    });
+                    i = next;
+
+                    if tokens.get(i) == Some(&Token::Name("as".to_string())) {
+                        i += 2;
+                    }
+
+                    if tokens.get(i) == Some(&Token::Op(',')) {
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            Token::Name(name) if at_statement_start && name == "from" => {
+                i += 1;
+
+                let mut level = 0;
+                while tokens.get(i) == Some(&Token::Op('.')) {
+                    level += 1;
+                    i += 1;
+                }
+
+                let name = match read_dotted_name(&tokens, i) {
+                    Some((name, next)) if name != "import" => {
+                        i = next;
+                        name
+                    }
+                    _ => "".to_string(),
+                };
+
+                if tokens.get(i) != Some(&Token::Name("import".to_string())) {
+                    continue;
+                }
+                i += 1;
+
+                let parenthesized = tokens.get(i) == Some(&Token::Op('('));
+                if parenthesized {
+                    i += 1;
+                }
+
+                let mut names = vec![];
+                loop {
+                    match tokens.get(i) {
+                        Some(Token::Op('*')) => {
+                            names.push("*".to_string());
+                            i += 1;
+                        }
+                        Some(Token::Name(name)) => {
+                            names.push(name.clone());
+                            i += 1;
+
+                            if tokens.get(i) == Some(&Token::Name("as".to_string())) {
+                                i += 2;
+                            }
+                        }
+                        _ => break,
+                    }
+
+                    if tokens.get(i) == Some(&Token::Op(',')) {
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                }
+
+                if parenthesized && tokens.get(i) == Some(&Token::Op(')')) {
+                    i += 1;
+                }
+
+                if let Some(module) = resolve_relative_module(package, level, &name) {
+                    res.push(PythonImport {
+                        module,
+                        names,
+                        kind: ImportKind::FromImport,
+                    });
+                }
+            }
+            Token::Name(name) if name == "import_module" || name == "__import__" => {
+                let dunder = name == "__import__";
+                i += 1;
+
+                if tokens.get(i) != Some(&Token::Op('(')) {
+                    continue;
+                }
+
+                let target = match tokens.get(i + 1) {
+                    Some(Token::Str(Some(target))) => target.clone(),
+                    _ => continue,
+                };
+
+                // `import_module(".name", anchor)` resolves relative to `anchor`.
+                let (level, target) = if dunder {
+                    (0, target.as_str())
+                } else {
+                    let stripped = target.trim_start_matches('.');
+                    (target.len() - stripped.len(), stripped)
+                };
+
+                // Relative names require an explicit anchor package.
+                let resolved = if level == 0 {
+                    Some(target.to_string())
+                } else {
+                    let anchor = match (tokens.get(i + 2), tokens.get(i + 3)) {
+                        (Some(Token::Op(',')), Some(Token::Str(Some(anchor)))) => {
+                            Some(anchor.as_str())
+                        }
+                        (Some(Token::Op(',')), Some(Token::Name(anchor)))
+                            if anchor == "__name__" =>
+                        {
+                            Some(module)
+                        }
+                        (Some(Token::Op(',')), Some(Token::Name(anchor)))
+                            if anchor == "__package__" =>
+                        {
+                            Some(package)
+                        }
+                        _ => None,
+                    };
+
+                    anchor.and_then(|anchor| resolve_relative_module(anchor, level, target))
+                };
+
+                if let Some(module) = resolved {
+                    if !module.is_empty() {
+                        res.push(PythonImport {
+                            module,
+                            names: vec![],
+                            kind: ImportKind::Dynamic,
+                        });
+                    }
+                }
+            }
+            _ => {
+                i += 1;
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+static RE_FILE_PATH: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(
+        r"\b(?:os\.path\.(?:dirname|abspath|realpath|join|split)|(?:pathlib\.)?Path)\(\s*__file__",
+    )
+    .unwrap()
+});
+
+static RE_RESOURCE_FILENAME: Lazy<regex::Regex> =
+    Lazy::new(|| regex::Regex::new(r"\bresource_filename\s*\(").unwrap());
+
+static RE_IMPORTLIB_RESOURCES_PATH: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(
+        r"\b(?:importlib\.resources|importlib_resources|resources)\.(?:path|as_file)\s*\(",
+    )
+    .unwrap()
+});
+
+static RE_CTYPES_LOAD: Lazy<regex::Regex> = Lazy::new(|| {
+    regex::Regex::new(r"\b(?:CDLL|PyDLL|WinDLL|OleDLL|LoadLibrary)\s*\((.*)").unwrap()
+});
+
+/// A construct in Python source code that requires resources to exist as files.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum FilesystemDependencyKind {
+    /// Usage of `__file__`, which isn't set for in-memory modules.
+    DunderFile,
+    /// A call to `pkg_resources.resource_filename()`.
+    PkgResourcesFilename,
+    /// A call to `importlib.resources.path()` or `as_file()`.
+    ImportlibResourcesPath,
+    /// Loading a shared library via `ctypes` from a path relative to the module.
+    CtypesRelativeLibrary,
+}
+
+/// Describes code depending on Python resources existing on the filesystem.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FilesystemDependency {
+    /// The kind of dependency.
+    pub kind: FilesystemDependencyKind,
+
+    /// 1-based line number of the finding, if known.
+    pub line: Option<usize>,
+
+    /// Human readable explanation of the finding.
+    pub reason: String,
+}
+
+/// Strip a trailing comment from a line of Python source code.
+///
+/// `#` characters inside string literals are preserved.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+
+        match (quote, c) {
+            (_, '\\') => escaped = true,
+            (None, '#') => return &line[0..i],
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+    }
+
+    line
+}
+
+/// Blank out the content of string literals in a line of Python source code.
+///
+/// Quote characters are preserved so the line retains its structure.
+fn mask_string_literals(line: &str) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut quote = None;
+    let mut escaped = false;
+
+    for c in line.chars() {
+        if escaped {
+            escaped = false;
+            res.push(' ');
+            continue;
+        }
+
+        match (quote, c) {
+            (Some(_), '\\') => {
+                escaped = true;
+                res.push(' ');
+            }
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                res.push(c);
+            }
+            (Some(q), c) if q == c => {
+                quote = None;
+                res.push(c);
+            }
+            (Some(_), _) => res.push(' '),
+            (None, c) => res.push(c),
+        }
+    }
+
+    res
+}
+
+/// Whether an argument to a `ctypes` library loader refers to a relative path.
+fn ctypes_argument_is_relative(argument: &str) -> bool {
+    let argument = argument.trim_start();
+    let code = mask_string_literals(argument);
+
+    // Paths derived from the module's location or joined onto a base
+    // directory, such as `os.path.join(HERE, "libfoo.so")`.
+    if code.contains("__file__") || code.contains("dirname") || code.contains("join(") {
+        return true;
+    }
+
+    let quote = match argument.chars().next() {
+        Some(c) if c == '\'' || c == '"' => c,
+        _ => return false,
+    };
+
+    let literal = match argument[1..].find(quote) {
+        Some(end) => &argument[1..end + 1],
+        None => return false,
+    };
+
+    let has_separator = literal.contains('/') || literal.contains('\\');
+    let is_absolute = literal.starts_with('/')
+        || literal.starts_with('\\')
+        || literal.chars().nth(1) == Some(':');
+
+    has_separator && !is_absolute
+}
+
+/// Find constructs in Python source code that require files on the filesystem.
+///
+/// This performs line-based lexical analysis and is intended to flag code
+/// that will likely fail when its package is loaded from memory. It
+/// can have false positives and false negatives.
+pub fn find_filesystem_dependencies(source: &[u8]) -> Result<Vec<FilesystemDependency>> {
+    let source = decode_python_source(source);
+
+    let mut res = vec![];
+
+    for (i, line) in source.lines().enumerate() {
+        let line = strip_comment(line);
+        let code = mask_string_literals(line);
+        let lineno = Some(i + 1);
+
+        if RE_FILE_PATH.is_match(&code) {
+            res.push(FilesystemDependency {
+                kind: FilesystemDependencyKind::DunderFile,
+                line: lineno,
+                reason: "derives a filesystem path from __file__".to_string(),
+            });
+        } else if code.contains("__file__") {
+            res.push(FilesystemDependency {
+                kind: FilesystemDependencyKind::DunderFile,
+                line: lineno,
+                reason: "references __file__".to_string(),
+            });
+        }
+
+        if RE_RESOURCE_FILENAME.is_match(line) {
+            res.push(FilesystemDependency {
+                kind: FilesystemDependencyKind::PkgResourcesFilename,
+                line: lineno,
+                reason:
+                    "calls pkg_resources.resource_filename(), which requires resources to be files"
+                        .to_string(),
+            });
+        }
+
+        if RE_IMPORTLIB_RESOURCES_PATH.is_match(line) {
+            res.push(FilesystemDependency {
+                kind: FilesystemDependencyKind::ImportlibResourcesPath,
+                line: lineno,
+                reason: "obtains a filesystem path to a resource via importlib.resources"
+                    .to_string(),
+            });
+        }
+
+        if let Some(captures) = RE_CTYPES_LOAD.captures(line) {
+            if ctypes_argument_is_relative(&captures[1]) {
+                res.push(FilesystemDependency {
+                    kind: FilesystemDependencyKind::CtypesRelativeLibrary,
+                    line: lineno,
+                    reason: "loads a shared library via ctypes from a relative path".to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imports(source: &str, module: &str, is_package: bool) -> Vec<(String, Vec<String>)> {
+        find_imports(source.as_bytes(), module, is_package)
+            .unwrap()
+            .into_iter()
+            .map(|i| (i.module, i.names))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_imports_absolute() {
+        assert_eq!(
+            imports("import os\nimport foo.bar as baz, sys\n", "m", false),
+            vec![
+                ("os".to_string(), vec![]),
+                ("foo.bar".to_string(), vec![]),
+                ("sys".to_string(), vec![]),
+            ]
+        );
+        assert_eq!(
+            imports(
+                "from os import path, sep as s\nfrom a.b import (\n    c,\n    d,\n)\n",
+                "m",
+                false
+            ),
+            vec![
+                (
+                    "os".to_string(),
+                    vec!["path".to_string(), "sep".to_string()]
+                ),
+                ("a.b".to_string(), vec!["c".to_string(), "d".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_imports_relative() {
+        assert_eq!(
+            imports("from . import x\nfrom .y import z\n", "pkg.mod", false),
+            vec![
+                ("pkg".to_string(), vec!["x".to_string()]),
+                ("pkg.y".to_string(), vec!["z".to_string()]),
+            ]
+        );
+        assert_eq!(
+            imports("from .. import x\nfrom .y import *\n", "a.b", true),
+            vec![
+                ("a".to_string(), vec!["x".to_string()]),
+                ("a.b.y".to_string(), vec!["*".to_string()]),
+            ]
+        );
+        // Beyond the top-level package is ignored.
+        assert!(imports("from .. import x\n", "a", false).is_empty());
+    }
+
+    #[test]
+    fn test_find_imports_statement_contexts() {
+        assert_eq!(
+            imports(
+                "def f():\n    import a\nx = 1; import b\ntry: import c\nexcept ImportError: pass\n",
+                "m",
+                false
+            ),
+            vec![
+                ("a".to_string(), vec![]),
+                ("b".to_string(), vec![]),
+                ("c".to_string(), vec![]),
+            ]
+        );
+
+        // Not import statements.
+        assert!(imports(
+            "# import a\ns = 'import b'\nt = \"\"\"\nimport c\n\"\"\"\nraise X from y\n",
+            "m",
+            false
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_find_imports_dynamic() {
+        assert_eq!(
+            imports(
+                "importlib.import_module('a.b')\n__import__(\"c\")\nimport_module(name)\n",
+                "m",
+                false
+            ),
+            vec![("a.b".to_string(), vec![]), ("c".to_string(), vec![])]
+        );
+        assert_eq!(
+            imports(
+                "import_module('.x', __name__)\nimport_module('.y', __package__)\nimport_module('..z', 'a.b')\n",
+                "pkg.mod",
+                false
+            ),
+            vec![
+                ("pkg.mod.x".to_string(), vec![]),
+                ("pkg.y".to_string(), vec![]),
+                ("a.z".to_string(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_filesystem_dependencies() -> Result<()> {
+        let source = br##"import os
+HERE = os.path.dirname(__file__)  # __file__ in comment
+print(__file__)
+# pkg_resources.resource_filename("ignored", "comment")
+data = pkg_resources.resource_filename(__name__, "data.txt")
+with importlib.resources.path("pkg", "data.txt") as p:
+    pass
+lib = ctypes.CDLL("./libfoo.so")
+lib = ctypes.CDLL(os.path.join(HERE, "libfoo.so"))
+lib = ctypes.CDLL("libc.so.6")
+lib = ctypes.cdll.LoadLibrary("/usr/lib/libfoo.so")
+s = "# not a comment __file__"
+lib = ctypes.CDLL("lib__file__.so")
+"##;
+
+        let found = find_filesystem_dependencies(source)?
+            .into_iter()
+            .map(|d| (d.kind, d.line.unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            found,
+            vec![
+                (FilesystemDependencyKind::DunderFile, 2),
+                (FilesystemDependencyKind::DunderFile, 3),
+                (FilesystemDependencyKind::PkgResourcesFilename, 5),
+                (FilesystemDependencyKind::ImportlibResourcesPath, 6),
+                (FilesystemDependencyKind::CtypesRelativeLibrary, 8),
+                (FilesystemDependencyKind::CtypesRelativeLibrary, 9),
+            ]
+        );
+
+        Ok(())
+    }
 }
@@ -24,7 +24,12 @@ use {
 };
 
 /// An optimization level for Python bytecode.
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(
+    feature = "serialization",
+    derive(serde::Deserialize, serde::Serialize),
+    serde(try_from = "i32", into = "i32")
+)]
 pub enum BytecodeOptimizationLevel {
     Zero,
     One,
@@ -7,12 +7,18 @@
 use {
     crate::{
         bytecode::{
-            compute_bytecode_header, BytecodeHeaderMode, CompileMode, PythonBytecodeCompiler,
+            compute_bytecode_header, BytecodeHeaderMode, CompileMode, CompileRequest,
+            PythonBytecodeCompiler,
         },
         libpython::LibPythonBuildContext,
         location::{AbstractResourceLocation, ConcreteResourceLocation},
+        marshal::{
+            bytecode_has_dunder_file, find_bytecode_filesystem_dependencies, BytecodeVersion,
+        },
+        module_graph::{ModuleDependencyGraph, ModuleReachability},
         module_util::{packages_from_module_name, resolve_path_for_module},
-        python_source::has_dunder_file,
+        policy::{apply_resource_placement_rules, ResourcePlacementRule},
+        python_source::{find_filesystem_dependencies, has_dunder_file, FilesystemDependency},
         resource::{
             BytecodeOptimizationLevel, PythonExtensionModule, PythonModuleBytecode,
             PythonModuleBytecodeFromSource, PythonModuleSource, PythonPackageDistributionResource,
@@ -46,6 +52,38 @@ pub enum PythonModuleBytecodeProvider {
     FromSource(FileData),
 }
 
+/// A `PythonBytecodeCompiler` that serves results compiled ahead of time.
+///
+/// Requests not compiled ahead of time are forwarded to the wrapped compiler.
+struct PrecompiledBytecodeCompiler<'a> {
+    compiler: &'a mut dyn PythonBytecodeCompiler,
+    bytecode: HashMap<(String, BytecodeOptimizationLevel, CompileMode), Vec<u8>>,
+}
+
+impl<'a> PythonBytecodeCompiler for PrecompiledBytecodeCompiler<'a> {
+    fn get_magic_number(&self) -> u32 {
+        self.compiler.get_magic_number()
+    }
+
+    fn compile(
+        &mut self,
+        source: &[u8],
+        filename: &str,
+        optimize: BytecodeOptimizationLevel,
+        output_mode: CompileMode,
+    ) -> Result<Vec<u8>> {
+        if let Some(bytecode) = self
+            .bytecode
+            .remove(&(filename.to_string(), optimize, output_mode))
+        {
+            Ok(bytecode)
+        } else {
+            self.compiler
+                .compile(source, filename, optimize, output_mode)
+        }
+    }
+}
+
 /// Represents a Python resource entry before it is packaged.
 ///
 /// Instances hold the same fields as `Resource` except fields holding
@@ -97,6 +135,143 @@ impl PrePackagedResource {
             || self.is_extension_module
     }
 
+    /// Whether this resource holds a Python module's code.
+    ///
+    /// Adding a package or distribution resource registers a package without
+    /// code. This returns false for such entries.
+    pub fn has_module_code(&self) -> bool {
+        self.is_builtin_extension_module
+            || self.is_frozen_module
+            || self.is_extension_module
+            || self.is_namespace_package
+            || self.in_memory_source.is_some()
+            || self.in_memory_bytecode.is_some()
+            || self.in_memory_bytecode_opt1.is_some()
+            || self.in_memory_bytecode_opt2.is_some()
+            || self.relative_path_module_source.is_some()
+            || self.relative_path_bytecode.is_some()
+            || self.relative_path_bytecode_opt1.is_some()
+            || self.relative_path_bytecode_opt2.is_some()
+    }
+
+    /// Whether this resource holds package resources.
+    fn has_package_resources(&self) -> bool {
+        self.in_memory_resources.is_some() || self.relative_path_package_resources.is_some()
+    }
+
+    /// Obtain the data of a package distribution resource by name.
+    fn distribution_resource(&self, name: &str) -> Option<&FileData> {
+        self.in_memory_distribution_resources
+            .as_ref()
+            .and_then(|resources| resources.get(name))
+            .or_else(|| {
+                self.relative_path_distribution_resources
+                    .as_ref()
+                    .and_then(|resources| resources.get(name).map(|(_, data)| data))
+            })
+    }
+
+    /// Resolve the top-level modules provided by this resource's package distribution.
+    ///
+    /// Names are read from `top_level.txt` if present, else derived from the
+    /// paths in `RECORD`. If neither is present, the distribution name with
+    /// `-` normalized to `_` is assumed. Returns an empty set if this
+    /// resource holds no distribution resources.
+    pub fn distribution_top_level_modules(&self) -> Result<BTreeSet<String>> {
+        if self.in_memory_distribution_resources.is_none()
+            && self.relative_path_distribution_resources.is_none()
+        {
+            return Ok(BTreeSet::new());
+        }
+
+        if let Some(data) = self.distribution_resource("top_level.txt") {
+            return Ok(String::from_utf8_lossy(&data.resolve()?)
+                .lines()
+                .map(|line| line.trim().replace('/', "."))
+                .filter(|line| !line.is_empty())
+                .collect());
+        }
+
+        if let Some(data) = self.distribution_resource("RECORD") {
+            return Ok(String::from_utf8_lossy(&data.resolve()?)
+                .lines()
+                .filter_map(|line| {
+                    let path = line.split(',').next()?.trim_matches('"');
+                    let first = path.split('/').next()?;
+
+                    if first.is_empty()
+                        || first == ".."
+                        || first == "__pycache__"
+                        || first.ends_with(".dist-info")
+                        || first.ends_with(".egg-info")
+                        || first.ends_with(".data")
+                        || first.ends_with(".pth")
+                    {
+                        None
+                    } else {
+                        first.split('.').next().map(|x| x.to_string())
+                    }
+                })
+                .collect());
+        }
+
+        Ok(std::iter::once(self.name.replace('-', "_")).collect())
+    }
+
+    /// Obtain requests to compile bytecode from source for this resource.
+    ///
+    /// The requests are identical to the ones `to_resource()` issues to its
+    /// compiler.
+    pub fn bytecode_compile_requests(&self) -> Result<Vec<CompileRequest>> {
+        let mut res = vec![];
+
+        for (provider, optimize, output_mode) in [
+            (
+                self.in_memory_bytecode.as_ref(),
+                BytecodeOptimizationLevel::Zero,
+                CompileMode::Bytecode,
+            ),
+            (
+                self.in_memory_bytecode_opt1.as_ref(),
+                BytecodeOptimizationLevel::One,
+                CompileMode::Bytecode,
+            ),
+            (
+                self.in_memory_bytecode_opt2.as_ref(),
+                BytecodeOptimizationLevel::Two,
+                CompileMode::Bytecode,
+            ),
+            (
+                self.relative_path_bytecode.as_ref().map(|(_, _, p)| p),
+                BytecodeOptimizationLevel::Zero,
+                CompileMode::PycUncheckedHash,
+            ),
+            (
+                self.relative_path_bytecode_opt1.as_ref().map(|(_, _, p)| p),
+                BytecodeOptimizationLevel::One,
+                CompileMode::PycUncheckedHash,
+            ),
+            (
+                self.relative_path_bytecode_opt2.as_ref().map(|(_, _, p)| p),
+                BytecodeOptimizationLevel::Two,
+                CompileMode::PycUncheckedHash,
+            ),
+        ]
+        .iter()
+        {
+            if let Some(PythonModuleBytecodeProvider::FromSource(location)) = provider {
+                res.push(CompileRequest {
+                    source: location.resolve()?,
+                    filename: self.name.clone(),
+                    optimize: *optimize,
+                    output_mode: *output_mode,
+                });
+            }
+        }
+
+        Ok(res)
+    }
+
     /// Convert the instance to a `Resource`.
     ///
     /// This will compile bytecode from source code using the specified compiler.
@@ -589,6 +764,105 @@ impl PythonResourceAddCollectionContext {
         self.optimize_level_one = other.optimize_level_one;
         self.optimize_level_two = other.optimize_level_two;
     }
+
+    /// Describe the values of this instance.
+    pub fn describe(&self) -> String {
+        format!(
+            "include: {}; location: {}; location fallback: {}; store source: {}; \
+            bytecode optimization levels 0/1/2: {}/{}/{}",
+            self.include,
+            self.location.to_string(),
+            describe_location_fallback(&self.location_fallback),
+            self.store_source,
+            self.optimize_level_zero,
+            self.optimize_level_one,
+            self.optimize_level_two,
+        )
+    }
+
+    /// Describe the fields of `other` that differ from `self`.
+    pub fn describe_changes(&self, other: &Self) -> Vec<String> {
+        let mut changes = vec![];
+
+        if self.include != other.include {
+            changes.push(format!("include changed to {}", other.include));
+        }
+        if self.location != other.location {
+            changes.push(format!(
+                "location changed to {}",
+                other.location.to_string()
+            ));
+        }
+        if self.location_fallback != other.location_fallback {
+            changes.push(format!(
+                "location fallback changed to {}",
+                describe_location_fallback(&other.location_fallback)
+            ));
+        }
+        if self.store_source != other.store_source {
+            changes.push(format!("store source changed to {}", other.store_source));
+        }
+        if self.optimize_level_zero != other.optimize_level_zero {
+            changes.push(format!(
+                "bytecode optimization level 0 changed to {}",
+                other.optimize_level_zero
+            ));
+        }
+        if self.optimize_level_one != other.optimize_level_one {
+            changes.push(format!(
+                "bytecode optimization level 1 changed to {}",
+                other.optimize_level_one
+            ));
+        }
+        if self.optimize_level_two != other.optimize_level_two {
+            changes.push(format!(
+                "bytecode optimization level 2 changed to {}",
+                other.optimize_level_two
+            ));
+        }
+
+        changes
+    }
+}
+
+/// Describe the kind of a resource for the decision log.
+fn resource_kind(resource: &PythonResource) -> String {
+    match resource {
+        PythonResource::ModuleSource(_) => "module source".to_string(),
+        PythonResource::ModuleBytecodeRequest(module) => format!(
+            "module bytecode (optimization level {})",
+            i32::from(module.optimize_level)
+        ),
+        PythonResource::ModuleBytecode(module) => format!(
+            "module bytecode (optimization level {})",
+            i32::from(module.optimize_level)
+        ),
+        PythonResource::PackageResource(_) => "package resource".to_string(),
+        PythonResource::PackageDistributionResource(_) => {
+            "package distribution resource".to_string()
+        }
+        PythonResource::ExtensionModule(_) => "extension module".to_string(),
+        PythonResource::EggFile(_) => "egg file".to_string(),
+        PythonResource::PathExtension(_) => "path extension".to_string(),
+        PythonResource::File(_) => "file".to_string(),
+    }
+}
+
+fn describe_location_fallback(location: &Option<ConcreteResourceLocation>) -> String {
+    match location {
+        Some(location) => location.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+/// Records something that influenced how a resource was added to a collection.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ResourceDecision {
+    /// Entity making the decision. e.g. `policy` or the name of a callback.
+    pub actor: String,
+
+    /// Human readable description of the decision.
+    pub message: String,
 }
 
 /// Describes the state of licensing for resources in a given resources collection.
@@ -674,6 +948,15 @@ pub struct PythonResourceCollector {
 
     /// Collection of software components which are licensed.
     licensed_components: LicensedComponents,
+
+    /// Rules applied to add contexts when adding resources.
+    resource_placement_rules: Vec<ResourcePlacementRule>,
+
+    /// Decisions influencing how resources were added, keyed by resource name.
+    decision_log: BTreeMap<String, Vec<ResourceDecision>>,
+
+    /// Names of modules and packages added from the standard library.
+    stdlib_modules: BTreeSet<String>,
 }
 
 impl PythonResourceCollector {
@@ -699,7 +982,97 @@ impl PythonResourceCollector {
             resources: BTreeMap::new(),
             cache_tag: cache_tag.to_string(),
             licensed_components: LicensedComponents::default(),
+            resource_placement_rules: vec![],
+            decision_log: BTreeMap::new(),
+            stdlib_modules: BTreeSet::new(),
+        }
+    }
+
+    /// Obtain the resource placement rules applied by `*_with_context()` methods.
+    pub fn resource_placement_rules(&self) -> &[ResourcePlacementRule] {
+        &self.resource_placement_rules
+    }
+
+    /// Set the resource placement rules applied by `*_with_context()` methods.
+    ///
+    /// Matching rules are applied to the add context passed into these
+    /// methods, so they take precedence over its values.
+    pub fn set_resource_placement_rules(&mut self, rules: Vec<ResourcePlacementRule>) {
+        self.resource_placement_rules = rules;
+    }
+
+    /// Obtain the log of decisions made about resources, keyed by resource name.
+    ///
+    /// Names are those from `PythonResource::full_name()`.
+    pub fn resource_decision_log(&self) -> &BTreeMap<String, Vec<ResourceDecision>> {
+        &self.decision_log
+    }
+
+    /// Obtain the decisions recorded for a named resource.
+    pub fn resource_decisions(&self, name: &str) -> Option<&[ResourceDecision]> {
+        self.decision_log.get(name).map(|x| x.as_slice())
+    }
+
+    /// Record a decision about a named resource.
+    ///
+    /// This can be used by callers to record decisions made outside this
+    /// collector, such as the removal of a resource by a filter.
+    pub fn record_resource_decision(
+        &mut self,
+        name: &str,
+        actor: impl ToString,
+        message: impl ToString,
+    ) {
+        self.decision_log
+            .entry(name.to_string())
+            .or_default()
+            .push(ResourceDecision {
+                actor: actor.to_string(),
+                message: message.to_string(),
+            });
+    }
+
+    /// Apply resource placement rules to an add context.
+    ///
+    /// Changes made by rules and the exclusion of the resource are recorded
+    /// in the decision log.
+    fn resolve_add_context<'a>(
+        &mut self,
+        resource: &PythonResource,
+        add_context: &'a PythonResourceAddCollectionContext,
+    ) -> Cow<'a, PythonResourceAddCollectionContext> {
+        let name = resource.full_name();
+
+        let add_context = apply_resource_placement_rules(
+            &self.resource_placement_rules,
+            resource,
+            add_context,
+            self.decision_log.entry(name.clone()).or_default(),
+        );
+
+        // Extension modules required by the interpreter are always added.
+        let required = matches!(resource, PythonResource::ExtensionModule(em) if em.required);
+
+        if !add_context.include {
+            if required {
+                self.record_resource_decision(
+                    &name,
+                    "collector",
+                    "extension module added even though include is false because the interpreter requires it",
+                );
+            } else {
+                self.record_resource_decision(
+                    &name,
+                    "collector",
+                    format!(
+                        "{} excluded because include is false",
+                        resource_kind(resource)
+                    ),
+                );
+            }
         }
+
+        add_context
     }
 
     /// Obtain locations that resources can be loaded from.
@@ -745,9 +1118,9 @@ impl PythonResourceCollector {
     /// Apply a filter function on resources in this collection and mutate in place.
     ///
     /// If the filter function returns true, the item will be preserved.
-    pub fn filter_resources_mut<F>(&mut self, filter: F) -> Result<()>
+    pub fn filter_resources_mut<F>(&mut self, mut filter: F) -> Result<()>
     where
-        F: Fn(&PrePackagedResource) -> bool,
+        F: FnMut(&PrePackagedResource) -> bool,
     {
         self.resources = self
             .resources
@@ -819,6 +1192,26 @@ impl PythonResourceCollector {
         Ok(report)
     }
 
+    /// Obtain licensed software components relevant to this collection.
+    ///
+    /// Components for Python packages are only included if the package has
+    /// resources in this collection. All other components are included.
+    pub fn licensed_components(&self) -> LicensedComponents {
+        let all_packages = self.all_top_level_module_names();
+
+        let mut res = LicensedComponents::default();
+
+        for component in self.licensed_components.iter_components() {
+            if component.flavor() != &ComponentFlavor::PythonPackage
+                || all_packages.contains(component.name())
+            {
+                res.add_component(component.clone());
+            }
+        }
+
+        res
+    }
+
     /// Register a licensed software component to this collection.
     pub fn add_licensed_component(&mut self, component: LicensedComponent) -> Result<()> {
         self.licensed_components.add_component(component);
@@ -845,6 +1238,10 @@ impl PythonResourceCollector {
         entry.is_module = true;
         entry.is_package = module.is_package;
 
+        if module.is_stdlib {
+            self.stdlib_modules.insert(module.name.clone());
+        }
+
         match location {
             ConcreteResourceLocation::InMemory => {
                 entry.in_memory_source = Some(module.source.clone());
@@ -873,6 +1270,8 @@ impl PythonResourceCollector {
         module: &PythonModuleSource,
         add_context: &PythonResourceAddCollectionContext,
     ) -> Result<()> {
+        let add_context = self.resolve_add_context(&module.into(), add_context);
+
         if !add_context.include {
             return Ok(());
         }
@@ -938,6 +1337,10 @@ impl PythonResourceCollector {
         entry.is_module = true;
         entry.is_package = module.is_package;
 
+        if module.is_stdlib {
+            self.stdlib_modules.insert(module.name.clone());
+        }
+
         // TODO having to resolve the FileData here is a bit unfortunate.
         // We could invent a better type to allow the I/O to remain lazy.
         let bytecode =
@@ -985,6 +1388,8 @@ impl PythonResourceCollector {
         module: &PythonModuleBytecode,
         add_context: &PythonResourceAddCollectionContext,
     ) -> Result<()> {
+        let add_context = self.resolve_add_context(&module.into(), add_context);
+
         if !add_context.include {
             return Ok(());
         }
@@ -1045,6 +1450,10 @@ impl PythonResourceCollector {
         entry.is_module = true;
         entry.is_package = module.is_package;
 
+        if module.is_stdlib {
+            self.stdlib_modules.insert(module.name.clone());
+        }
+
         let bytecode = PythonModuleBytecodeProvider::FromSource(module.source.clone());
 
         match location {
@@ -1091,6 +1500,8 @@ impl PythonResourceCollector {
         module: &PythonModuleBytecodeFromSource,
         add_context: &PythonResourceAddCollectionContext,
     ) -> Result<()> {
+        let add_context = self.resolve_add_context(&module.into(), add_context);
+
         if !add_context.include {
             return Ok(());
         }
@@ -1154,6 +1565,10 @@ impl PythonResourceCollector {
         entry.is_module = true;
         entry.is_package = true;
 
+        if resource.is_stdlib {
+            self.stdlib_modules.insert(resource.leaf_package.clone());
+        }
+
         match location {
             ConcreteResourceLocation::InMemory => {
                 if entry.in_memory_resources.is_none() {
@@ -1193,6 +1608,8 @@ impl PythonResourceCollector {
         resource: &PythonPackageResource,
         add_context: &PythonResourceAddCollectionContext,
     ) -> Result<()> {
+        let add_context = self.resolve_add_context(&resource.into(), add_context);
+
         if !add_context.include {
             return Ok(());
         }
@@ -1264,6 +1681,8 @@ impl PythonResourceCollector {
         resource: &PythonPackageDistributionResource,
         add_context: &PythonResourceAddCollectionContext,
     ) -> Result<()> {
+        let add_context = self.resolve_add_context(&resource.into(), add_context);
+
         if !add_context.include {
             return Ok(());
         }
@@ -1282,10 +1701,11 @@ impl PythonResourceCollector {
         extension_module: &PythonExtensionModule,
         add_context: &PythonResourceAddCollectionContext,
     ) -> Result<Option<LibPythonBuildContext>> {
-        // TODO consult this attribute (it isn't set for built-ins for some reason)
-        //if !add_context.include {
-        //    return Ok(None);
-        // }
+        let add_context = self.resolve_add_context(&extension_module.into(), add_context);
+
+        if !add_context.include && !extension_module.required {
+            return Ok(None);
+        }
 
         // Whether we can load extension modules as standalone shared library files.
         let can_load_standalone = self
@@ -1416,6 +1836,11 @@ impl PythonResourceCollector {
             }
 
             self.add_builtin_python_extension_module(extension_module)?;
+            self.record_resource_decision(
+                &extension_module.name,
+                "collector",
+                "extension module added as a built-in linked into libpython",
+            );
 
             Ok(Some(build_context))
         } else {
@@ -1433,6 +1858,14 @@ impl PythonResourceCollector {
             };
 
             self.add_python_extension_module(extension_module, &location)?;
+            self.record_resource_decision(
+                &extension_module.name,
+                "collector",
+                format!(
+                    "extension module added as a shared library to {}",
+                    location.to_string()
+                ),
+            );
 
             Ok(None)
         }
@@ -1628,6 +2061,8 @@ impl PythonResourceCollector {
         file: &File,
         add_context: &PythonResourceAddCollectionContext,
     ) -> Result<()> {
+        let add_context = self.resolve_add_context(&file.into(), add_context);
+
         if !add_context.include {
             return Ok(());
         }
@@ -1645,104 +2080,77 @@ impl PythonResourceCollector {
         location: &ConcreteResourceLocation,
         fallback_location: &Option<ConcreteResourceLocation>,
     ) -> Result<()> {
-        match resource {
-            PythonResource::ModuleSource(module) => {
-                match self
-                    .add_python_module_source(module, location)
-                    .with_context(|| format!("adding PythonModuleSource<{}>", module.name))
-                {
-                    Ok(()) => Ok(()),
-                    Err(err) => {
-                        if let Some(location) = fallback_location {
-                            self.add_python_module_source(module, location)
-                        } else {
-                            Err(err)
-                        }
-                    }
-                }
-            }
-            PythonResource::ModuleBytecodeRequest(module) => {
-                match self
-                    .add_python_module_bytecode_from_source(module, location)
-                    .with_context(|| {
-                        format!("adding PythonModuleBytecodeFromSource<{}>", module.name)
-                    }) {
-                    Ok(()) => Ok(()),
-                    Err(err) => {
-                        if let Some(location) = fallback_location {
-                            self.add_python_module_bytecode_from_source(module, location)
-                        } else {
-                            Err(err)
-                        }
-                    }
-                }
-            }
-            PythonResource::ModuleBytecode(module) => {
-                match self
-                    .add_python_module_bytecode(module, location)
-                    .with_context(|| format!("adding PythonModuleBytecode<{}>", module.name))
-                {
-                    Ok(()) => Ok(()),
-                    Err(err) => {
-                        if let Some(location) = fallback_location {
-                            self.add_python_module_bytecode(module, location)
-                        } else {
-                            Err(err)
-                        }
-                    }
-                }
-            }
-            PythonResource::PackageResource(resource) => {
-                match self
-                    .add_python_package_resource(resource, location)
-                    .with_context(|| {
-                        format!(
-                            "adding PythonPackageResource<{}, {}>",
-                            resource.leaf_package, resource.relative_name
-                        )
-                    }) {
-                    Ok(()) => Ok(()),
-                    Err(err) => {
-                        if let Some(location) = fallback_location {
-                            self.add_python_package_resource(resource, location)
-                        } else {
-                            Err(err)
-                        }
-                    }
-                }
+        match self.add_python_resource_to_location(resource, location) {
+            Ok(()) => {
+                self.record_resource_decision(
+                    &resource.full_name(),
+                    "collector",
+                    format!(
+                        "{} added to {}",
+                        resource_kind(resource),
+                        location.to_string()
+                    ),
+                );
+
+                Ok(())
             }
-            PythonResource::PackageDistributionResource(resource) => {
-                match self
-                    .add_python_package_distribution_resource(resource, location)
-                    .with_context(|| {
+            Err(err) => {
+                if let Some(fallback) = fallback_location {
+                    self.add_python_resource_to_location(resource, fallback)?;
+                    self.record_resource_decision(
+                        &resource.full_name(),
+                        "collector",
                         format!(
-                            "adding PythonPackageDistributionResource<{}, {}>",
-                            resource.package, resource.name
-                        )
-                    }) {
-                    Ok(()) => Ok(()),
-                    Err(err) => {
-                        if let Some(location) = fallback_location {
-                            self.add_python_package_distribution_resource(resource, location)
-                        } else {
-                            Err(err)
-                        }
-                    }
+                            "{} added to fallback location {} because adding to {} failed: {}",
+                            resource_kind(resource),
+                            fallback.to_string(),
+                            location.to_string(),
+                            err
+                        ),
+                    );
+
+                    Ok(())
+                } else {
+                    Err(err)
                 }
             }
-            PythonResource::File(file) => match self
+        }
+    }
+
+    fn add_python_resource_to_location(
+        &mut self,
+        resource: &PythonResource,
+        location: &ConcreteResourceLocation,
+    ) -> Result<()> {
+        match resource {
+            PythonResource::ModuleSource(module) => self
+                .add_python_module_source(module, location)
+                .with_context(|| format!("adding PythonModuleSource<{}>", module.name)),
+            PythonResource::ModuleBytecodeRequest(module) => self
+                .add_python_module_bytecode_from_source(module, location)
+                .with_context(|| format!("adding PythonModuleBytecodeFromSource<{}>", module.name)),
+            PythonResource::ModuleBytecode(module) => self
+                .add_python_module_bytecode(module, location)
+                .with_context(|| format!("adding PythonModuleBytecode<{}>", module.name)),
+            PythonResource::PackageResource(resource) => self
+                .add_python_package_resource(resource, location)
+                .with_context(|| {
+                    format!(
+                        "adding PythonPackageResource<{}, {}>",
+                        resource.leaf_package, resource.relative_name
+                    )
+                }),
+            PythonResource::PackageDistributionResource(resource) => self
+                .add_python_package_distribution_resource(resource, location)
+                .with_context(|| {
+                    format!(
+                        "adding PythonPackageDistributionResource<{}, {}>",
+                        resource.package, resource.name
+                    )
+                }),
+            PythonResource::File(file) => self
                 .add_file_data(file, location)
-                .with_context(|| format!("adding File<{}>", file.path.display()))
-            {
-                Ok(()) => Ok(()),
-                Err(err) => {
-                    if let Some(location) = fallback_location {
-                        self.add_file_data(file, location)
-                    } else {
-                        Err(err)
-                    }
-                }
-            },
+                .with_context(|| format!("adding File<{}>", file.path.display())),
             _ => Err(anyhow!("PythonResource variant not yet supported")),
         }
     }
@@ -1761,26 +2169,24 @@ impl PythonResourceCollector {
                 }
             }
 
-            if let Some(PythonModuleBytecodeProvider::FromSource(location)) =
-                &module.in_memory_bytecode
-            {
-                if has_dunder_file(&location.resolve()?)? {
-                    res.insert(name.clone());
-                }
-            }
-
-            if let Some(PythonModuleBytecodeProvider::FromSource(location)) =
-                &module.in_memory_bytecode_opt1
-            {
-                if has_dunder_file(&location.resolve()?)? {
-                    res.insert(name.clone());
-                }
-            }
+            for provider in &[
+                &module.in_memory_bytecode,
+                &module.in_memory_bytecode_opt1,
+                &module.in_memory_bytecode_opt2,
+            ] {
+                let found = match provider {
+                    Some(PythonModuleBytecodeProvider::FromSource(location)) => {
+                        has_dunder_file(&location.resolve()?)?
+                    }
+                    Some(PythonModuleBytecodeProvider::Provided(location))
+                        if BytecodeVersion::from_cache_tag(&self.cache_tag).is_some() =>
+                    {
+                        bytecode_has_dunder_file(&location.resolve()?).unwrap_or(false)
+                    }
+                    _ => false,
+                };
 
-            if let Some(PythonModuleBytecodeProvider::FromSource(location)) =
-                &module.in_memory_bytecode_opt2
-            {
-                if has_dunder_file(&location.resolve()?)? {
+                if found {
                     res.insert(name.clone());
                 }
             }
@@ -1789,38 +2195,374 @@ impl PythonResourceCollector {
         Ok(res)
     }
 
-    /// Compiles resources into a finalized collection.
-    ///
-    /// This will take all resources collected so far and convert them into
-    /// a collection of `Resource` plus extra file install rules.
+    /// Resolve the data of a module that can be statically analyzed.
     ///
-    /// Missing parent packages will be added automatically.
-    pub fn compile_resources(
+    /// Returns the source code of the module, if available. Otherwise returns
+    /// the verbatim bytecode of the module, if available and if we know how
+    /// to decode it.
+    fn analyzable_module_data<'a>(
         &self,
-        compiler: &mut dyn PythonBytecodeCompiler,
-    ) -> Result<CompiledResourcesCollection> {
-        let mut input_resources = self.resources.clone();
-        populate_parent_packages(&mut input_resources).context("populating parent packages")?;
+        resource: &'a PrePackagedResource,
+    ) -> (Option<&'a FileData>, Option<&'a FileData>) {
+        let providers = [
+            resource.in_memory_bytecode.as_ref(),
+            resource.in_memory_bytecode_opt1.as_ref(),
+            resource.in_memory_bytecode_opt2.as_ref(),
+            resource
+                .relative_path_bytecode
+                .as_ref()
+                .map(|(_, _, provider)| provider),
+            resource
+                .relative_path_bytecode_opt1
+                .as_ref()
+                .map(|(_, _, provider)| provider),
+            resource
+                .relative_path_bytecode_opt2
+                .as_ref()
+                .map(|(_, _, provider)| provider),
+        ];
+
+        let source = resource
+            .in_memory_source
+            .as_ref()
+            .or_else(|| {
+                resource
+                    .relative_path_module_source
+                    .as_ref()
+                    .map(|(_, data)| data)
+            })
+            .or_else(|| {
+                providers.iter().find_map(|provider| match provider {
+                    Some(PythonModuleBytecodeProvider::FromSource(data)) => Some(data),
+                    _ => None,
+                })
+            });
 
-        let mut resources = BTreeMap::new();
-        let mut extra_files = Vec::new();
+        if source.is_some() {
+            return (source, None);
+        }
 
-        for (name, resource) in &input_resources {
-            let (entry, installs) = resource
-                .to_resource(compiler)
-                .with_context(|| format!("converting {} to resource", name))?;
+        // Modules only available as bytecode are analyzed if we know how to
+        // decode their bytecode.
+        let bytecode = if BytecodeVersion::from_cache_tag(&self.cache_tag).is_some() {
+            providers.iter().find_map(|provider| match provider {
+                Some(PythonModuleBytecodeProvider::Provided(data)) => Some(data),
+                _ => None,
+            })
+        } else {
+            None
+        };
 
-            for install in installs {
-                extra_files.push(install);
+        (None, bytecode)
+    }
+
+    /// Find code depending on Python resources existing on the filesystem.
+    ///
+    /// This extends `find_dunder_file()` to other constructs, like
+    /// `pkg_resources.resource_filename()`, that will likely fail if a
+    /// package is loaded from memory.
+    ///
+    /// Returns a mapping of top-level package name to module name to findings.
+    /// Only modules having findings are present. Standard library modules
+    /// are not analyzed.
+    pub fn find_filesystem_dependencies(
+        &self,
+    ) -> Result<BTreeMap<String, BTreeMap<String, Vec<FilesystemDependency>>>> {
+        let mut res: BTreeMap<String, BTreeMap<String, Vec<FilesystemDependency>>> =
+            BTreeMap::new();
+
+        for (name, resource) in &self.resources {
+            if !resource.is_module || self.stdlib_modules.contains(name) {
+                continue;
             }
 
-            resources.insert(name.clone(), entry);
+            let findings = match self.analyzable_module_data(resource) {
+                (Some(source), _) => find_filesystem_dependencies(&source.resolve()?)
+                    .with_context(|| format!("analyzing source of {}", name))?,
+                // Bytecode we fail to decode is treated as opaque.
+                (None, Some(bytecode)) => {
+                    find_bytecode_filesystem_dependencies(&bytecode.resolve()?).unwrap_or_default()
+                }
+                (None, None) => vec![],
+            };
+
+            if !findings.is_empty() {
+                let package = name.split('.').next().unwrap_or(name).to_string();
+
+                res.entry(package)
+                    .or_default()
+                    .insert(name.clone(), findings);
+            }
         }
 
-        Ok(CompiledResourcesCollection {
-            resources,
-            extra_files,
-        })
+        Ok(res)
+    }
+
+    /// Move the in-memory modules and package resources of a package to the filesystem.
+    ///
+    /// `package` and all its sub-packages and modules are relocated to
+    /// filesystem-relative locations under `prefix`. Other resources, like
+    /// extension modules and distribution metadata, are left as is. So are
+    /// standard library modules.
+    ///
+    /// Returns the names of resources that were relocated.
+    pub fn relocate_package_to_filesystem(
+        &mut self,
+        package: &str,
+        prefix: &str,
+    ) -> Result<Vec<String>> {
+        if !self
+            .allowed_locations
+            .contains(&AbstractResourceLocation::RelativePath)
+        {
+            return Err(anyhow!(
+                "cannot relocate {} because filesystem-relative resources are not allowed",
+                package
+            ));
+        }
+
+        let child_prefix = format!("{}.", package);
+        let mut relocated = vec![];
+
+        for (name, entry) in self.resources.iter_mut() {
+            if (name != package && !name.starts_with(&child_prefix))
+                || self.stdlib_modules.contains(name)
+            {
+                continue;
+            }
+
+            let mut moved = false;
+
+            if let Some(source) = entry.in_memory_source.take() {
+                entry.relative_path_module_source = Some((prefix.to_string(), source));
+                moved = true;
+            }
+
+            for (in_memory, relative) in [
+                (
+                    &mut entry.in_memory_bytecode,
+                    &mut entry.relative_path_bytecode,
+                ),
+                (
+                    &mut entry.in_memory_bytecode_opt1,
+                    &mut entry.relative_path_bytecode_opt1,
+                ),
+                (
+                    &mut entry.in_memory_bytecode_opt2,
+                    &mut entry.relative_path_bytecode_opt2,
+                ),
+            ] {
+                if let Some(provider) = in_memory.take() {
+                    *relative = Some((prefix.to_string(), self.cache_tag.clone(), provider));
+                    moved = true;
+                }
+            }
+
+            if let Some(resources) = entry.in_memory_resources.take() {
+                let relative = entry
+                    .relative_path_package_resources
+                    .get_or_insert_with(BTreeMap::new);
+
+                for (relative_name, data) in resources {
+                    let path = PythonPackageResource {
+                        leaf_package: name.clone(),
+                        relative_name: relative_name.clone(),
+                        data: data.clone(),
+                        is_stdlib: false,
+                        is_test: false,
+                    }
+                    .resolve_path(prefix);
+
+                    relative.insert(relative_name, (path, data));
+                }
+
+                moved = true;
+            }
+
+            if moved {
+                relocated.push(name.clone());
+            }
+        }
+
+        for name in &relocated {
+            self.record_resource_decision(
+                name,
+                "collector",
+                format!(
+                    "relocated to filesystem-relative:{} because package {} depends on the filesystem",
+                    prefix, package
+                ),
+            );
+        }
+
+        Ok(relocated)
+    }
+
+    /// Construct a `ModuleDependencyGraph` from modules in this collection.
+    ///
+    /// Modules having source code, either directly or as the input to bytecode
+    /// compilation, have their imports analyzed. So do modules only available
+    /// as CPython 3.8 or 3.9 bytecode. Other modules, such as extension modules
+    /// and modules with bytecode we can't decode, are registered without any
+    /// imports. Entries only holding package or distribution resources aren't
+    /// modules and are ignored.
+    pub fn module_dependency_graph(&self) -> Result<ModuleDependencyGraph> {
+        let mut graph = ModuleDependencyGraph::default();
+
+        for (name, resource) in &self.resources {
+            if !resource.is_python_resource() || !resource.has_module_code() {
+                continue;
+            }
+
+            let (source, bytecode) = self.analyzable_module_data(resource);
+
+            if let Some(source) = source {
+                graph
+                    .add_module_source(name, resource.is_package, &source.resolve()?)
+                    .with_context(|| format!("analyzing imports of {}", name))?;
+            } else if let Some(bytecode) = bytecode {
+                // Bytecode we fail to decode is treated as opaque.
+                if graph
+                    .add_module_bytecode(name, resource.is_package, &bytecode.resolve()?)
+                    .is_err()
+                {
+                    graph.add_module(name, resource.is_package);
+                }
+            } else {
+                graph.add_module(name, resource.is_package);
+            }
+        }
+
+        Ok(graph)
+    }
+
+    /// Remove resources of modules found unreachable by a reachability analysis.
+    ///
+    /// Unreachable modules are removed along with every resource attached to
+    /// them. Package resources of removed packages are removed as well, as
+    /// are package distributions all of whose top-level modules are removed.
+    /// Builtin and frozen modules are always retained.
+    ///
+    /// Returns the names of removed resources.
+    pub fn remove_unreachable_resources(
+        &mut self,
+        reachability: &ModuleReachability,
+    ) -> Result<Vec<String>> {
+        let removed_modules = self
+            .resources
+            .iter()
+            .filter(|(name, resource)| {
+                resource.has_module_code()
+                    && !resource.is_builtin_extension_module
+                    && !resource.is_frozen_module
+                    && reachability.unreachable.contains(*name)
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<BTreeSet<_>>();
+
+        let is_removed = |name: &str| {
+            removed_modules.contains(name)
+                || packages_from_module_name(name)
+                    .iter()
+                    .any(|package| removed_modules.contains(package))
+        };
+
+        let mut removed = vec![];
+
+        for (name, resource) in &self.resources {
+            let remove = if resource.has_module_code() {
+                removed_modules.contains(name)
+            } else if resource.has_package_resources() && is_removed(name) {
+                true
+            } else {
+                let modules = resource
+                    .distribution_top_level_modules()
+                    .with_context(|| format!("resolving top-level modules of {}", name))?;
+
+                modules.iter().any(|module| is_removed(module))
+                    && modules.iter().all(|module| {
+                        is_removed(module)
+                            || !self
+                                .resources
+                                .get(module)
+                                .map(|r| r.has_module_code())
+                                .unwrap_or(false)
+                    })
+            };
+
+            if remove {
+                removed.push(name.clone());
+            }
+        }
+
+        for name in &removed {
+            self.resources.remove(name);
+        }
+
+        Ok(removed)
+    }
+
+    /// Compiles resources into a finalized collection.
+    ///
+    /// This will take all resources collected so far and convert them into
+    /// a collection of `Resource` plus extra file install rules.
+    ///
+    /// Missing parent packages will be added automatically.
+    pub fn compile_resources(
+        &self,
+        compiler: &mut dyn PythonBytecodeCompiler,
+    ) -> Result<CompiledResourcesCollection> {
+        let mut input_resources = self.resources.clone();
+        populate_parent_packages(&mut input_resources).context("populating parent packages")?;
+
+        // Compile all bytecode up front so compilers capable of compiling in
+        // parallel can do so.
+        let mut requests = vec![];
+        for (name, resource) in &input_resources {
+            requests.extend(
+                resource
+                    .bytecode_compile_requests()
+                    .with_context(|| format!("resolving bytecode compile requests for {}", name))?,
+            );
+        }
+
+        let compiled = compiler
+            .compile_many(&requests)
+            .context("compiling Python bytecode")?;
+
+        let mut compiler = PrecompiledBytecodeCompiler {
+            compiler,
+            bytecode: requests
+                .into_iter()
+                .zip(compiled)
+                .map(|(request, bytecode)| {
+                    (
+                        (request.filename, request.optimize, request.output_mode),
+                        bytecode,
+                    )
+                })
+                .collect(),
+        };
+
+        let mut resources = BTreeMap::new();
+        let mut extra_files = Vec::new();
+
+        for (name, resource) in &input_resources {
+            let (entry, installs) = resource
+                .to_resource(&mut compiler)
+                .with_context(|| format!("converting {} to resource", name))?;
+
+            for install in installs {
+                extra_files.push(install);
+            }
+
+            resources.insert(name.clone(), entry);
+        }
+
+        Ok(CompiledResourcesCollection {
+            resources,
+            extra_files,
+        })
     }
 }
 
@@ -1828,7 +2570,10 @@ impl PythonResourceCollector {
 mod tests {
     use {
         super::*,
-        crate::resource::{LibraryDependency, PythonPackageDistributionResourceFlavor},
+        crate::{
+            python_source::FilesystemDependencyKind,
+            resource::{LibraryDependency, PythonPackageDistributionResourceFlavor},
+        },
         tugger_file_manifest::FileEntry,
     };
 
@@ -3237,6 +3982,185 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_add_with_context_placement_rules() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![
+                AbstractResourceLocation::InMemory,
+                AbstractResourceLocation::RelativePath,
+            ],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+        r.set_resource_placement_rules(vec![
+            ResourcePlacementRule {
+                pattern: Some("foo.*".to_string()),
+                location: Some(ConcreteResourceLocation::RelativePath("lib".to_string())),
+                ..ResourcePlacementRule::default()
+            },
+            ResourcePlacementRule {
+                pattern: Some("bar".to_string()),
+                include: Some(false),
+                ..ResourcePlacementRule::default()
+            },
+        ]);
+
+        let add_context = PythonResourceAddCollectionContext {
+            include: true,
+            location: ConcreteResourceLocation::InMemory,
+            location_fallback: None,
+            store_source: true,
+            optimize_level_zero: false,
+            optimize_level_one: false,
+            optimize_level_two: false,
+        };
+
+        for name in &["foo", "bar", "baz"] {
+            r.add_python_module_source_with_context(
+                &PythonModuleSource {
+                    name: name.to_string(),
+                    source: FileData::Memory(vec![42]),
+                    is_package: false,
+                    cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                    is_stdlib: false,
+                    is_test: false,
+                },
+                &add_context,
+            )?;
+        }
+
+        assert_eq!(r.resources.len(), 2);
+        assert_eq!(
+            r.resources.get("foo"),
+            Some(&PrePackagedResource {
+                is_module: true,
+                name: "foo".to_string(),
+                relative_path_module_source: Some(("lib".to_string(), FileData::Memory(vec![42]))),
+                ..PrePackagedResource::default()
+            })
+        );
+        assert_eq!(
+            r.resources.get("baz"),
+            Some(&PrePackagedResource {
+                is_module: true,
+                name: "baz".to_string(),
+                in_memory_source: Some(FileData::Memory(vec![42])),
+                ..PrePackagedResource::default()
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_context_describe() {
+        let add_context = PythonResourceAddCollectionContext {
+            include: true,
+            location: ConcreteResourceLocation::InMemory,
+            location_fallback: None,
+            store_source: false,
+            optimize_level_zero: true,
+            optimize_level_one: false,
+            optimize_level_two: false,
+        };
+
+        let mut other = add_context.clone();
+        other.location_fallback = Some(ConcreteResourceLocation::RelativePath("lib".to_string()));
+        other.store_source = true;
+
+        assert_eq!(
+            add_context.describe(),
+            "include: true; location: in-memory; location fallback: none; store source: false; \
+            bytecode optimization levels 0/1/2: true/false/false"
+        );
+        assert_eq!(
+            add_context.describe_changes(&other),
+            vec![
+                "location fallback changed to filesystem-relative:lib".to_string(),
+                "store source changed to true".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resource_decision_log() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+        r.set_resource_placement_rules(vec![ResourcePlacementRule {
+            pattern: Some("bar".to_string()),
+            include: Some(false),
+            ..ResourcePlacementRule::default()
+        }]);
+
+        let add_context = PythonResourceAddCollectionContext {
+            include: true,
+            location: ConcreteResourceLocation::RelativePath("lib".to_string()),
+            location_fallback: Some(ConcreteResourceLocation::InMemory),
+            store_source: true,
+            optimize_level_zero: false,
+            optimize_level_one: false,
+            optimize_level_two: false,
+        };
+
+        for name in &["foo", "bar"] {
+            r.record_resource_decision(name, "policy", "defaults");
+            r.add_python_module_source_with_context(
+                &PythonModuleSource {
+                    name: name.to_string(),
+                    source: FileData::Memory(vec![42]),
+                    is_package: false,
+                    cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                    is_stdlib: false,
+                    is_test: false,
+                },
+                &add_context,
+            )?;
+        }
+        r.record_resource_decision("foo", "filter", "kept");
+
+        let decisions = |r: &PythonResourceCollector, name: &str| {
+            r.resource_decisions(name)
+                .unwrap()
+                .iter()
+                .map(|d| (d.actor.clone(), d.message.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        let foo = decisions(&r, "foo");
+        assert_eq!(foo.len(), 3);
+        assert_eq!(foo[0], ("policy".to_string(), "defaults".to_string()));
+        assert_eq!(foo[1].0, "collector");
+        assert!(foo[1]
+            .1
+            .starts_with("module source added to fallback location in-memory because adding to filesystem-relative:lib failed"));
+        assert_eq!(foo[2], ("filter".to_string(), "kept".to_string()));
+
+        assert_eq!(
+            decisions(&r, "bar"),
+            vec![
+                ("policy".to_string(), "defaults".to_string()),
+                (
+                    "resource placement rule #0 (bar)".to_string(),
+                    "include changed to false".to_string()
+                ),
+                (
+                    "collector".to_string(),
+                    "module source excluded because include is false".to_string()
+                ),
+            ]
+        );
+        assert!(r.resource_decisions("baz").is_none());
+
+        Ok(())
+    }
+
     #[test]
     fn test_add_in_memory_bytecode_module() -> Result<()> {
         let mut r = PythonResourceCollector::new(
@@ -3345,6 +4269,122 @@ mod tests {
         Ok(())
     }
 
+    /// A compiler that only supports compiling in batches.
+    struct BatchBytecodeCompiler {
+        batches: usize,
+    }
+
+    impl PythonBytecodeCompiler for BatchBytecodeCompiler {
+        fn get_magic_number(&self) -> u32 {
+            42
+        }
+
+        fn compile(
+            &mut self,
+            _source: &[u8],
+            _filename: &str,
+            _optimize: BytecodeOptimizationLevel,
+            _output_mode: CompileMode,
+        ) -> Result<Vec<u8>> {
+            Err(anyhow!("compile() should not be called"))
+        }
+
+        fn compile_many(&mut self, requests: &[CompileRequest]) -> Result<Vec<Vec<u8>>> {
+            self.batches += 1;
+
+            Ok(requests
+                .iter()
+                .map(|r| {
+                    format!(
+                        "{}:{}:{:?}",
+                        r.filename,
+                        i32::from(r.optimize),
+                        r.output_mode
+                    )
+                    .into_bytes()
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_compile_resources_batch() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![
+                AbstractResourceLocation::InMemory,
+                AbstractResourceLocation::RelativePath,
+            ],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+
+        for (name, level) in &[
+            ("foo", BytecodeOptimizationLevel::Zero),
+            ("foo", BytecodeOptimizationLevel::Two),
+            ("bar", BytecodeOptimizationLevel::One),
+        ] {
+            r.add_python_module_bytecode_from_source(
+                &PythonModuleBytecodeFromSource {
+                    name: name.to_string(),
+                    source: FileData::Memory(vec![42]),
+                    optimize_level: *level,
+                    is_package: false,
+                    cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                    is_stdlib: false,
+                    is_test: false,
+                },
+                &ConcreteResourceLocation::InMemory,
+            )?;
+        }
+        r.add_python_module_bytecode_from_source(
+            &PythonModuleBytecodeFromSource {
+                name: "baz".to_string(),
+                source: FileData::Memory(vec![42]),
+                optimize_level: BytecodeOptimizationLevel::Zero,
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::RelativePath("prefix".to_string()),
+        )?;
+
+        let mut compiler = BatchBytecodeCompiler { batches: 0 };
+        let resources = r.compile_resources(&mut compiler)?;
+        assert_eq!(compiler.batches, 1);
+
+        let foo = resources.resources.get("foo").unwrap();
+        assert_eq!(
+            foo.in_memory_bytecode,
+            Some(Cow::Owned(b"foo:0:Bytecode".to_vec()))
+        );
+        assert_eq!(foo.in_memory_bytecode_opt1, None);
+        assert_eq!(
+            foo.in_memory_bytecode_opt2,
+            Some(Cow::Owned(b"foo:2:Bytecode".to_vec()))
+        );
+        assert_eq!(
+            resources
+                .resources
+                .get("bar")
+                .unwrap()
+                .in_memory_bytecode_opt1,
+            Some(Cow::Owned(b"bar:1:Bytecode".to_vec()))
+        );
+        assert_eq!(
+            resources.extra_files,
+            vec![(
+                PathBuf::from("prefix/__pycache__/baz.cpython-39.pyc"),
+                FileData::Memory(b"baz:0:PycUncheckedHash".to_vec()),
+                false
+            )]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_add_module_bytecode_with_context() -> Result<()> {
         let mut r = PythonResourceCollector::new(
@@ -4168,6 +5208,72 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_add_python_extension_module_include() -> Result<()> {
+        let mut c = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![AbstractResourceLocation::InMemory],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+        c.set_resource_placement_rules(vec![ResourcePlacementRule {
+            pattern: Some("_*".to_string()),
+            is_extension_module: Some(true),
+            include: Some(false),
+            ..ResourcePlacementRule::default()
+        }]);
+
+        let add_context = PythonResourceAddCollectionContext {
+            include: true,
+            location: ConcreteResourceLocation::InMemory,
+            location_fallback: None,
+            store_source: false,
+            optimize_level_zero: false,
+            optimize_level_one: false,
+            optimize_level_two: false,
+        };
+
+        let mut em = PythonExtensionModule {
+            name: "_io".to_string(),
+            init_fn: Some("PyInit__io".to_string()),
+            extension_file_suffix: "".to_string(),
+            shared_library: None,
+            object_file_data: vec![],
+            is_package: false,
+            link_libraries: vec![],
+            is_stdlib: true,
+            builtin_default: true,
+            required: true,
+            variant: None,
+            license: None,
+        };
+
+        // Required extension modules are added despite include being false.
+        c.add_python_extension_module_with_context(&em, &add_context)?;
+        assert!(c.resources.contains_key("_io"));
+
+        em.name = "_json".to_string();
+        em.init_fn = Some("PyInit__json".to_string());
+        em.required = false;
+
+        c.add_python_extension_module_with_context(&em, &add_context)?;
+        assert!(!c.resources.contains_key("_json"));
+        assert!(c
+            .resource_decisions("_json")
+            .unwrap()
+            .iter()
+            .any(|d| d.message.contains("include is false")));
+
+        em.name = "json_ext".to_string();
+        em.init_fn = Some("PyInit_json_ext".to_string());
+
+        c.add_python_extension_module_with_context(&em, &add_context)?;
+        assert!(c.resources.contains_key("json_ext"));
+
+        Ok(())
+    }
+
     #[test]
     fn test_add_in_memory_python_extension_module_shared_library() -> Result<()> {
         let em = PythonExtensionModule {
@@ -4715,4 +5821,335 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_filesystem_dependencies() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![
+                AbstractResourceLocation::InMemory,
+                AbstractResourceLocation::RelativePath,
+            ],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+
+        for (name, is_package, source) in &[
+            ("foo", true, ""),
+            (
+                "foo.data",
+                false,
+                "import pkg_resources\nDATA = pkg_resources.resource_filename(__name__, 'x')\n",
+            ),
+            ("bar", false, "import os\n"),
+        ] {
+            r.add_python_module_source(
+                &PythonModuleSource {
+                    name: name.to_string(),
+                    source: FileData::Memory(Vec::from(*source)),
+                    is_package: *is_package,
+                    cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                    is_stdlib: false,
+                    is_test: false,
+                },
+                &ConcreteResourceLocation::InMemory,
+            )?;
+        }
+        r.add_python_package_resource(
+            &PythonPackageResource {
+                leaf_package: "foo".to_string(),
+                relative_name: "x".to_string(),
+                data: FileData::Memory(vec![42]),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::InMemory,
+        )?;
+        // Standard library modules are never analyzed or relocated.
+        r.add_python_module_source(
+            &PythonModuleSource {
+                name: "foo.stdlib".to_string(),
+                source: FileData::Memory(b"HERE = os.path.dirname(__file__)\n".to_vec()),
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: true,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::InMemory,
+        )?;
+
+        let report = r.find_filesystem_dependencies()?;
+        assert_eq!(report.keys().collect::<Vec<_>>(), vec!["foo"]);
+        let findings = report.get("foo").unwrap().get("foo.data").unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].kind,
+            FilesystemDependencyKind::PkgResourcesFilename
+        );
+        assert_eq!(findings[0].line, Some(2));
+
+        assert_eq!(
+            r.relocate_package_to_filesystem("foo", "lib")?,
+            vec!["foo".to_string(), "foo.data".to_string()]
+        );
+        assert_eq!(
+            r.resources.get("foo"),
+            Some(&PrePackagedResource {
+                name: "foo".to_string(),
+                is_module: true,
+                is_package: true,
+                relative_path_module_source: Some(("lib".to_string(), FileData::Memory(vec![]))),
+                relative_path_package_resources: Some(
+                    [(
+                        "x".to_string(),
+                        (PathBuf::from("lib/foo/x"), FileData::Memory(vec![42]))
+                    )]
+                    .iter()
+                    .cloned()
+                    .collect()
+                ),
+                ..PrePackagedResource::default()
+            })
+        );
+        assert!(r.resources.get("bar").unwrap().in_memory_source.is_some());
+        assert!(r
+            .resources
+            .get("foo.stdlib")
+            .unwrap()
+            .in_memory_source
+            .is_some());
+        assert_eq!(
+            r.resource_decisions("foo.data").unwrap()[0].message,
+            "relocated to filesystem-relative:lib because package foo depends on the filesystem"
+        );
+
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+        assert!(r.relocate_package_to_filesystem("foo", "lib").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_licensed_components() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+
+        r.add_python_module_source(
+            &PythonModuleSource {
+                name: "foo.bar".to_string(),
+                source: FileData::Memory(vec![]),
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::InMemory,
+        )?;
+
+        let mut component = LicensedComponent::new_spdx("foo", "MIT")?;
+        component.set_flavor(ComponentFlavor::PythonPackage);
+        component.set_version("1.0");
+        r.add_licensed_component(component)?;
+
+        let mut component = LicensedComponent::new_spdx("missing", "MIT")?;
+        component.set_flavor(ComponentFlavor::PythonPackage);
+        r.add_licensed_component(component)?;
+
+        let mut component = LicensedComponent::new_spdx("libfoo", "MIT")?;
+        component.set_flavor(ComponentFlavor::Library);
+        r.add_licensed_component(component)?;
+
+        let components = r.licensed_components();
+        assert_eq!(
+            components
+                .iter_components()
+                .map(|c| (c.name(), c.version()))
+                .collect::<Vec<_>>(),
+            vec![("foo", Some("1.0")), ("libfoo", None)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_module_dependency_graph() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+
+        r.add_python_module_source(
+            &PythonModuleSource {
+                name: "app".to_string(),
+                source: FileData::Memory(Vec::from("from . import cli")),
+                is_package: true,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::InMemory,
+        )?;
+        r.add_python_module_bytecode_from_source(
+            &PythonModuleBytecodeFromSource {
+                name: "app.cli".to_string(),
+                source: FileData::Memory(Vec::from("import json")),
+                optimize_level: BytecodeOptimizationLevel::Zero,
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::InMemory,
+        )?;
+        r.add_python_module_bytecode(
+            &PythonModuleBytecode::new(
+                "json",
+                BytecodeOptimizationLevel::Zero,
+                true,
+                DEFAULT_CACHE_TAG,
+                &[],
+            ),
+            &ConcreteResourceLocation::InMemory,
+        )?;
+        r.add_python_module_source(
+            &PythonModuleSource {
+                name: "unused".to_string(),
+                source: FileData::Memory(vec![]),
+                is_package: false,
+                cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                is_stdlib: false,
+                is_test: false,
+            },
+            &ConcreteResourceLocation::InMemory,
+        )?;
+
+        let graph = r.module_dependency_graph()?;
+        let res = graph.reachability(vec!["app"], &[]);
+
+        assert_eq!(
+            res.reachable,
+            ["app", "app.cli", "json"]
+                .iter()
+                .map(|x| x.to_string())
+                .collect()
+        );
+        assert_eq!(
+            res.unreachable,
+            ["unused"].iter().map(|x| x.to_string()).collect()
+        );
+        assert_eq!(res.opaque, ["json"].iter().map(|x| x.to_string()).collect());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_unreachable_resources() -> Result<()> {
+        let mut r = PythonResourceCollector::new(
+            vec![AbstractResourceLocation::InMemory],
+            vec![],
+            false,
+            false,
+            DEFAULT_CACHE_TAG,
+        );
+
+        for (name, source) in &[("app", "import foo"), ("unused", ""), ("unused.sub", "")] {
+            r.add_python_module_source(
+                &PythonModuleSource {
+                    name: name.to_string(),
+                    source: FileData::Memory(Vec::from(*source)),
+                    is_package: *name != "unused.sub",
+                    cache_tag: DEFAULT_CACHE_TAG.to_string(),
+                    is_stdlib: false,
+                    is_test: false,
+                },
+                &ConcreteResourceLocation::InMemory,
+            )?;
+        }
+
+        // Data in packages without code.
+        for leaf_package in &["app.data", "unused.data"] {
+            r.add_python_package_resource(
+                &PythonPackageResource {
+                    leaf_package: leaf_package.to_string(),
+                    relative_name: "data.txt".to_string(),
+                    data: FileData::Memory(vec![42]),
+                    is_stdlib: false,
+                    is_test: false,
+                },
+                &ConcreteResourceLocation::InMemory,
+            )?;
+        }
+
+        for (package, name, data) in &[
+            (
+                "app-dist",
+                "RECORD",
+                "app/__init__.py,,\napp-1.0.dist-info/METADATA,,\n",
+            ),
+            ("Unused-Dist", "top_level.txt", "unused\n"),
+            ("unused", "METADATA", ""),
+            ("other", "METADATA", ""),
+        ] {
+            r.add_python_package_distribution_resource(
+                &PythonPackageDistributionResource {
+                    location: PythonPackageDistributionResourceFlavor::DistInfo,
+                    package: package.to_string(),
+                    version: "1.0".to_string(),
+                    name: name.to_string(),
+                    data: FileData::Memory(Vec::from(*data)),
+                },
+                &ConcreteResourceLocation::InMemory,
+            )?;
+        }
+
+        assert_eq!(
+            r.resources
+                .get("app-dist")
+                .unwrap()
+                .distribution_top_level_modules()?,
+            ["app"].iter().map(|x| x.to_string()).collect()
+        );
+        assert_eq!(
+            r.resources
+                .get("other")
+                .unwrap()
+                .distribution_top_level_modules()?,
+            ["other"].iter().map(|x| x.to_string()).collect()
+        );
+
+        let graph = r.module_dependency_graph()?;
+        assert_eq!(
+            graph.module_names().collect::<Vec<_>>(),
+            vec!["app", "unused", "unused.sub"]
+        );
+
+        let reachability = graph.reachability(vec!["app"], &[]);
+        let removed = r.remove_unreachable_resources(&reachability)?;
+
+        assert_eq!(
+            removed,
+            vec!["Unused-Dist", "unused", "unused.data", "unused.sub"]
+        );
+        assert_eq!(
+            r.resources.keys().collect::<Vec<_>>(),
+            vec!["app", "app-dist", "app.data", "other"]
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,383 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Interact with Python source distributions (sdists). */
+
+use {
+    crate::package_metadata::PythonPackageMetadata,
+    anyhow::{anyhow, Context, Result},
+    std::{
+        io::Read,
+        path::{Component, Path, PathBuf},
+    },
+    tugger_file_manifest::{FileEntry, FileManifest},
+    zip::ZipArchive,
+};
+
+const S_IXUSR: u32 = 64;
+
+/// Compression/archive formats of source distributions.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SourceDistributionFormat {
+    /// A gzip compressed tar archive (`.tar.gz` or `.tgz`).
+    TarGz,
+    /// An uncompressed tar archive (`.tar`).
+    Tar,
+    /// A zip archive (`.zip`).
+    Zip,
+}
+
+impl SourceDistributionFormat {
+    /// Resolve the format of a source distribution from its filename.
+    pub fn from_basename(basename: &str) -> Option<Self> {
+        if basename.ends_with(".tar.gz") || basename.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if basename.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if basename.ends_with(".zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+/// Represents a Python source distribution archive.
+///
+/// Source distributions contain a single top-level `<name>-<version>`
+/// directory holding the source tree and a `PKG-INFO` metadata file. Files
+/// are indexed relative to this directory.
+pub struct SourceDistributionArchive {
+    files: FileManifest,
+    root_directory: String,
+}
+
+impl SourceDistributionArchive {
+    /// Construct an instance from a generic reader.
+    ///
+    /// `basename` is the filename of the archive. It is used to determine
+    /// the archive format.
+    pub fn from_reader<R>(reader: R, basename: &str) -> Result<Self>
+    where
+        R: std::io::Read + std::io::Seek,
+    {
+        let format = SourceDistributionFormat::from_basename(basename)
+            .ok_or_else(|| anyhow!("unknown source distribution format: {}", basename))?;
+
+        let entries = match format {
+            SourceDistributionFormat::TarGz => {
+                read_tar_entries(flate2::read::GzDecoder::new(reader))?
+            }
+            SourceDistributionFormat::Tar => read_tar_entries(reader)?,
+            SourceDistributionFormat::Zip => read_zip_entries(reader)?,
+        };
+
+        Self::from_entries(entries)
+    }
+
+    /// Construct an instance from a filesystem path.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let fh = std::fs::File::open(path)
+            .with_context(|| format!("opening {} for sdist reading", path.display()))?;
+
+        let reader = std::io::BufReader::new(fh);
+        let basename = path
+            .file_name()
+            .ok_or_else(|| anyhow!("could not derive file name"))?
+            .to_string_lossy();
+
+        Self::from_reader(reader, &basename)
+    }
+
+    fn from_entries(entries: Vec<(PathBuf, FileEntry)>) -> Result<Self> {
+        let mut root_directory = None;
+        let mut files = FileManifest::default();
+
+        for (path, entry) in entries {
+            // Archives created by e.g. `tar -C dir .` prefix members with `./`.
+            let normalized = path
+                .components()
+                .filter(|c| *c != Component::CurDir)
+                .collect::<PathBuf>();
+
+            if normalized
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+            {
+                return Err(anyhow!("illegal path in sdist: {}", path.display()));
+            }
+
+            let mut components = normalized.components();
+
+            let root = match components.next() {
+                Some(Component::Normal(root)) => root.to_string_lossy().to_string(),
+                _ => return Err(anyhow!("illegal path in sdist: {}", path.display())),
+            };
+
+            match &root_directory {
+                Some(existing) if existing != &root => {
+                    return Err(anyhow!(
+                        "sdist has multiple top-level directories: {} and {}",
+                        existing,
+                        root
+                    ));
+                }
+                Some(_) => {}
+                None => {
+                    root_directory = Some(root);
+                }
+            }
+
+            let relative = components.as_path();
+            if relative.as_os_str().is_empty() {
+                return Err(anyhow!(
+                    "sdist has file outside top-level directory: {}",
+                    path.display()
+                ));
+            }
+
+            files
+                .add_file_entry(relative, entry)
+                .with_context(|| format!("indexing {}", path.display()))?;
+        }
+
+        Ok(Self {
+            files,
+            root_directory: root_directory.ok_or_else(|| anyhow!("sdist is empty"))?,
+        })
+    }
+
+    /// The name of the top-level directory in the archive.
+    pub fn root_directory(&self) -> &str {
+        &self.root_directory
+    }
+
+    /// Files in the source tree, relative to the top-level directory.
+    pub fn files(&self) -> &FileManifest {
+        &self.files
+    }
+
+    /// Obtain the `PKG-INFO` content as a parsed object.
+    pub fn metadata(&self) -> Result<PythonPackageMetadata> {
+        let file = self
+            .files
+            .get("PKG-INFO")
+            .ok_or_else(|| anyhow!("PKG-INFO does not exist"))?;
+
+        PythonPackageMetadata::from_metadata(&file.data.resolve()?)
+    }
+
+    /// The package name, as defined by `PKG-INFO`.
+    pub fn name(&self) -> Result<String> {
+        Ok(self
+            .metadata()?
+            .name()
+            .ok_or_else(|| anyhow!("Name not found in PKG-INFO"))?
+            .to_string())
+    }
+
+    /// The package version, as defined by `PKG-INFO`.
+    pub fn version(&self) -> Result<String> {
+        Ok(self
+            .metadata()?
+            .version()
+            .ok_or_else(|| anyhow!("Version not found in PKG-INFO"))?
+            .to_string())
+    }
+
+    /// Obtain the content of `pyproject.toml`, if present.
+    pub fn pyproject_toml(&self) -> Result<Option<Vec<u8>>> {
+        if let Some(file) = self.files.get("pyproject.toml") {
+            Ok(Some(file.data.resolve()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Whether the source tree has a `setup.py` file.
+    pub fn has_setup_py(&self) -> bool {
+        self.files.has_path("setup.py")
+    }
+
+    /// Write the source tree to a directory.
+    ///
+    /// Files are written directly into `dest`, without the top-level
+    /// directory.
+    pub fn extract_to(&self, dest: &Path) -> Result<()> {
+        self.files
+            .materialize_files(dest)
+            .with_context(|| format!("extracting sdist to {}", dest.display()))?;
+
+        Ok(())
+    }
+}
+
+fn read_tar_entries(reader: impl Read) -> Result<Vec<(PathBuf, FileEntry)>> {
+    let mut archive = tar::Archive::new(reader);
+    let mut res = vec![];
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+
+        // Directories are implied by file paths. Links aren't supported by
+        // our file representation and aren't common in sdists.
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let path = entry.path()?.to_path_buf();
+        let executable = entry.header().mode()? & S_IXUSR != 0;
+
+        let mut buffer = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut buffer)?;
+
+        res.push((
+            path,
+            FileEntry {
+                data: buffer.into(),
+                executable,
+            },
+        ));
+    }
+
+    Ok(res)
+}
+
+fn read_zip_entries<R>(reader: R) -> Result<Vec<(PathBuf, FileEntry)>>
+where
+    R: std::io::Read + std::io::Seek,
+{
+    let mut archive = ZipArchive::new(reader)?;
+    let mut res = vec![];
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+
+        if file.is_dir() {
+            continue;
+        }
+
+        let mut buffer = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut buffer)?;
+
+        res.push((
+            PathBuf::from(file.name()),
+            FileEntry {
+                data: buffer.into(),
+                executable: file.unix_mode().unwrap_or(0) & S_IXUSR != 0,
+            },
+        ));
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Write};
+
+    const PKG_INFO: &[u8] = b"Metadata-Version: 2.1\nName: foo\nVersion: 1.0\n";
+
+    fn tar_gz_sdist(files: &[(&str, &[u8], u32)]) -> Result<Vec<u8>> {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            vec![],
+            flate2::Compression::default(),
+        ));
+
+        for (path, data, mode) in files {
+            // Names are written verbatim since `set_path()` normalizes them.
+            let mut header = tar::Header::new_gnu();
+            header.as_gnu_mut().unwrap().name[0..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(data.len() as u64);
+            header.set_mode(*mode);
+            header.set_cksum();
+            builder.append(&header, *data)?;
+        }
+
+        Ok(builder.into_inner()?.finish()?)
+    }
+
+    #[test]
+    fn test_tar_gz() -> Result<()> {
+        let data = tar_gz_sdist(&[
+            ("foo-1.0/PKG-INFO", PKG_INFO, 0o644),
+            ("foo-1.0/pyproject.toml", b"[build-system]\n", 0o644),
+            ("foo-1.0/foo/__init__.py", b"", 0o644),
+            ("foo-1.0/bin/tool", b"#!/bin/sh\n", 0o755),
+        ])?;
+
+        let sdist =
+            SourceDistributionArchive::from_reader(std::io::Cursor::new(data), "foo-1.0.tar.gz")?;
+
+        assert_eq!(sdist.root_directory(), "foo-1.0");
+        assert_eq!(sdist.name()?, "foo");
+        assert_eq!(sdist.version()?, "1.0");
+        assert_eq!(sdist.pyproject_toml()?, Some(b"[build-system]\n".to_vec()));
+        assert!(!sdist.has_setup_py());
+        assert!(sdist.files().has_path("foo/__init__.py"));
+        assert!(sdist.files().get("bin/tool").unwrap().executable);
+        assert!(!sdist.files().get("PKG-INFO").unwrap().executable);
+
+        let td = tempfile::Builder::new()
+            .prefix("python-packaging-test")
+            .tempdir()?;
+        sdist.extract_to(td.path())?;
+        assert!(td.path().join("foo").join("__init__.py").exists());
+        assert_eq!(std::fs::read(td.path().join("PKG-INFO"))?, PKG_INFO);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip() -> Result<()> {
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        writer.start_file("foo-1.0/PKG-INFO", zip::write::FileOptions::default())?;
+        writer.write_all(PKG_INFO)?;
+        writer.start_file("foo-1.0/setup.py", zip::write::FileOptions::default())?;
+        writer.write_all(b"")?;
+        let data = writer.finish()?.into_inner();
+
+        let sdist =
+            SourceDistributionArchive::from_reader(std::io::Cursor::new(data), "foo-1.0.zip")?;
+
+        assert_eq!(sdist.name()?, "foo");
+        assert!(sdist.has_setup_py());
+        assert_eq!(sdist.pyproject_toml()?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_current_dir_prefix() -> Result<()> {
+        let data = tar_gz_sdist(&[
+            ("./foo-1.0/PKG-INFO", PKG_INFO, 0o644),
+            ("./foo-1.0/./foo/__init__.py", b"", 0o644),
+        ])?;
+
+        let sdist =
+            SourceDistributionArchive::from_reader(std::io::Cursor::new(data), "foo-1.0.tar.gz")?;
+
+        assert_eq!(sdist.root_directory(), "foo-1.0");
+        assert_eq!(sdist.name()?, "foo");
+        assert!(sdist.files().has_path("foo/__init__.py"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiple_roots() -> Result<()> {
+        let data = tar_gz_sdist(&[
+            ("foo-1.0/PKG-INFO", PKG_INFO, 0o644),
+            ("bar/setup.py", b"", 0o644),
+        ])?;
+
+        assert!(SourceDistributionArchive::from_reader(
+            std::io::Cursor::new(data),
+            "foo-1.0.tar.gz"
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
@@ -41,6 +41,7 @@ static RELEASE_ORDER: Lazy<Vec<&'static str>> = Lazy::new(|| {
         "tugger-licensing",
         "tugger-licensing-net",
         "tugger-rpm",
+        "tugger-squashfs",
         "tugger-snapcraft",
         "tugger-apple-bundle",
         "tugger-apple-codesign",
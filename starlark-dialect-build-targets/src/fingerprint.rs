@@ -0,0 +1,373 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Fingerprinting of target inputs to support incremental builds.
+
+A target's fingerprint is a set of digests of everything known to influence
+the result of building it: files and directories that were read, environment
+variables, and named values such as tool versions. Fingerprints
+are persisted after a target is built, along with a snapshot of its output
+directory. If a later build computes an identical fingerprint and the
+outputs haven't been modified, building the target can be skipped.
+
+Only inputs that are explicitly recorded are tracked. Code reading inputs
+must record them via [crate::record_target_input]. This includes the files
+defining targets: resolved Starlark values are not part of fingerprints.
+*/
+
+use {
+    crate::{ResolvedTarget, RunMode},
+    anyhow::{Context, Result},
+    serde::{Deserialize, Serialize},
+    sha2::Digest,
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+};
+
+/// An input influencing the result of building a target.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum TargetInput {
+    /// A file or directory tree on the filesystem.
+    ///
+    /// Content is hashed when the fingerprint is computed.
+    Path(PathBuf),
+
+    /// An environment variable.
+    ///
+    /// The value is read when the fingerprint is computed.
+    EnvVar(String),
+
+    /// An arbitrary named value.
+    Value { name: String, value: String },
+}
+
+impl TargetInput {
+    /// Human readable description of this input.
+    ///
+    /// This is used as the key in fingerprints and in log messages.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Path(path) => format!("path {}", path.display()),
+            Self::EnvVar(name) => format!("environment variable {}", name),
+            Self::Value { name, .. } => name.clone(),
+        }
+    }
+
+    /// Compute the digest of this input's current state.
+    pub fn digest(&self) -> Result<String> {
+        match self {
+            Self::Path(path) => path_digest(path),
+            Self::EnvVar(name) => Ok(match std::env::var_os(name) {
+                Some(value) => {
+                    hex::encode(sha2::Sha256::digest(value.to_string_lossy().as_bytes()))
+                }
+                None => "unset".to_string(),
+            }),
+            Self::Value { value, .. } => Ok(hex::encode(sha2::Sha256::digest(value.as_bytes()))),
+        }
+    }
+}
+
+/// Compute the digest of a file or directory tree.
+fn path_digest(path: &Path) -> Result<String> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok("missing".to_string()),
+    };
+
+    let mut hasher = sha2::Sha256::new();
+
+    if metadata.is_dir() {
+        let mut entries = std::fs::read_dir(path)
+            .with_context(|| format!("reading directory {}", path.display()))?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            hasher.update(entry.file_name().to_string_lossy().as_bytes());
+            hasher.update(b"\0");
+            hasher.update(path_digest(&entry.path())?.as_bytes());
+            hasher.update(b"\n");
+        }
+    } else if metadata.file_type().is_symlink() {
+        hasher.update(b"symlink\0");
+        hasher.update(std::fs::read_link(path)?.to_string_lossy().as_bytes());
+    } else {
+        hasher.update(&std::fs::read(path).with_context(|| format!("reading {}", path.display()))?);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Describe every file in a directory tree by its size and modification time.
+fn snapshot_outputs(root: &Path) -> Result<BTreeMap<String, String>> {
+    let mut res = BTreeMap::new();
+
+    if !root.is_dir() {
+        return Ok(res);
+    }
+
+    let mut pending = vec![root.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        for entry in std::fs::read_dir(&dir)
+            .with_context(|| format!("reading directory {}", dir.display()))?
+        {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+
+            if metadata.is_dir() {
+                pending.push(entry.path());
+                continue;
+            }
+
+            let mtime = metadata
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+
+            let path = entry.path();
+            let rel = path
+                .strip_prefix(root)
+                .with_context(|| format!("{} is not under {}", path.display(), root.display()))?
+                .display()
+                .to_string();
+
+            res.insert(rel, format!("{}:{}", metadata.len(), mtime));
+        }
+    }
+
+    Ok(res)
+}
+
+/// The fingerprint of a target's inputs and the result of building it.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TargetFingerprint {
+    /// Digests of inputs, keyed by input description.
+    pub inputs: BTreeMap<String, String>,
+
+    /// Directory the built target was written to.
+    output_path: Option<PathBuf>,
+
+    /// Path to run, if the built target is runnable.
+    run_path: Option<PathBuf>,
+
+    /// Snapshot of files in `output_path` after building.
+    outputs: BTreeMap<String, String>,
+}
+
+impl TargetFingerprint {
+    /// Compute a fingerprint from the current state of inputs.
+    pub fn from_inputs<'a>(inputs: impl Iterator<Item = &'a TargetInput>) -> Result<Self> {
+        let mut res = Self::default();
+
+        for input in inputs {
+            res.inputs.insert(input.describe(), input.digest()?);
+        }
+
+        Ok(res)
+    }
+
+    /// Read a fingerprint persisted by [Self::write_path].
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+
+        serde_json::from_slice(&data).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Persist this fingerprint to a file.
+    pub fn write_path(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+
+    /// A single digest representing all inputs.
+    pub fn digest(&self) -> String {
+        let mut hasher = sha2::Sha256::new();
+
+        for (key, digest) in &self.inputs {
+            hasher.update(key.as_bytes());
+            hasher.update(b"\0");
+            hasher.update(digest.as_bytes());
+            hasher.update(b"\n");
+        }
+
+        hex::encode(hasher.finalize())
+    }
+
+    /// Record the result of building the target.
+    ///
+    /// This snapshots the target's output directory, so it should be called
+    /// after building has finished.
+    pub fn set_built_target(&mut self, target: &ResolvedTarget) -> Result<()> {
+        self.output_path = Some(target.output_path.clone());
+        self.run_path = match &target.run_mode {
+            RunMode::None => None,
+            RunMode::Path { path } => Some(path.clone()),
+        };
+        self.outputs = snapshot_outputs(&target.output_path)?;
+
+        Ok(())
+    }
+
+    /// The result of building the target, if it was built.
+    pub fn built_target(&self) -> Option<ResolvedTarget> {
+        self.output_path.as_ref().map(|output_path| ResolvedTarget {
+            run_mode: match &self.run_path {
+                Some(path) => RunMode::Path { path: path.clone() },
+                None => RunMode::None,
+            },
+            output_path: output_path.clone(),
+        })
+    }
+
+    /// Obtain reasons why a target having this previously persisted fingerprint
+    /// needs to be built again, given its `current` fingerprint.
+    ///
+    /// An empty result means the target is up to date.
+    pub fn rebuild_reasons(&self, current: &Self) -> Result<Vec<String>> {
+        let mut reasons = vec![];
+
+        for (key, digest) in &current.inputs {
+            match self.inputs.get(key) {
+                Some(previous) if previous == digest => {}
+                Some(_) => reasons.push(format!("{} changed", key)),
+                None => reasons.push(format!("{} is a new input", key)),
+            }
+        }
+
+        for key in self.inputs.keys() {
+            if !current.inputs.contains_key(key) {
+                reasons.push(format!("{} is no longer an input", key));
+            }
+        }
+
+        if !reasons.is_empty() {
+            return Ok(reasons);
+        }
+
+        match &self.output_path {
+            Some(output_path) => {
+                if !output_path.exists() {
+                    reasons.push(format!("{} does not exist", output_path.display()));
+                } else if snapshot_outputs(output_path)? != self.outputs {
+                    reasons.push(format!("{} was modified", output_path.display()));
+                }
+            }
+            None => reasons.push("target was never built".to_string()),
+        }
+
+        Ok(reasons)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rebuild_reasons() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("starlark-dialect-build-targets-")
+            .tempdir()?;
+
+        let input_path = td.path().join("input.txt");
+        let output_path = td.path().join("out");
+        std::fs::write(&input_path, b"foo")?;
+        std::fs::create_dir(&output_path)?;
+        std::fs::write(output_path.join("result"), b"result")?;
+
+        let inputs = [
+            TargetInput::Path(input_path.clone()),
+            TargetInput::Value {
+                name: "value".to_string(),
+                value: "1".to_string(),
+            },
+        ];
+
+        let mut previous = TargetFingerprint::from_inputs(inputs.iter())?;
+        assert_eq!(
+            previous.rebuild_reasons(&previous)?,
+            vec!["target was never built".to_string()]
+        );
+
+        previous.set_built_target(&ResolvedTarget {
+            run_mode: RunMode::None,
+            output_path: output_path.clone(),
+        })?;
+
+        let fingerprint_path = td.path().join("fingerprint.json");
+        previous.write_path(&fingerprint_path)?;
+        let previous = TargetFingerprint::from_path(&fingerprint_path)?;
+        assert_eq!(
+            previous.built_target().unwrap().output_path,
+            output_path.clone()
+        );
+
+        let current = TargetFingerprint::from_inputs(inputs.iter())?;
+        assert!(previous.rebuild_reasons(&current)?.is_empty());
+        assert_eq!(current.digest(), previous.digest());
+
+        std::fs::write(&input_path, b"bar")?;
+        let current = TargetFingerprint::from_inputs(inputs.iter())?;
+        assert_eq!(
+            previous.rebuild_reasons(&current)?,
+            vec![format!("path {} changed", input_path.display())]
+        );
+        assert_ne!(current.digest(), previous.digest());
+
+        std::fs::write(&input_path, b"foo")?;
+        std::fs::write(output_path.join("result"), b"modified")?;
+        let current = TargetFingerprint::from_inputs(inputs.iter())?;
+        assert_eq!(
+            previous.rebuild_reasons(&current)?,
+            vec![format!("{} was modified", output_path.display())]
+        );
+
+        let current = TargetFingerprint::from_inputs(inputs[1..].iter())?;
+        assert_eq!(
+            previous.rebuild_reasons(&current)?,
+            vec![format!(
+                "path {} is no longer an input",
+                input_path.display()
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_digest() -> Result<()> {
+        let td = tempfile::Builder::new()
+            .prefix("starlark-dialect-build-targets-")
+            .tempdir()?;
+
+        let input = TargetInput::Path(td.path().to_path_buf());
+        let empty = input.digest()?;
+
+        std::fs::create_dir(td.path().join("sub"))?;
+        std::fs::write(td.path().join("sub").join("file"), b"foo")?;
+        let populated = input.digest()?;
+        assert_ne!(empty, populated);
+
+        std::fs::write(td.path().join("sub").join("file"), b"bar")?;
+        assert_ne!(input.digest()?, populated);
+
+        assert_eq!(
+            TargetInput::Path(td.path().join("missing")).digest()?,
+            "missing"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Export registered targets and their dependencies. */
+
+use {crate::EnvironmentContext, anyhow::Result, serde::Serialize, std::fmt::Write};
+
+/// Describes a registered target.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct TargetNode {
+    /// Name of the target.
+    pub name: String,
+
+    /// Names of targets this one depends on.
+    pub depends: Vec<String>,
+
+    /// Whether this is the default target.
+    pub default: bool,
+
+    /// Whether this is the default target in build script mode.
+    pub default_build_script: bool,
+
+    /// Starlark type of the value the target resolved to.
+    ///
+    /// `None` if the target hasn't been resolved.
+    pub value_type: Option<String>,
+}
+
+/// The graph of registered targets.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct TargetGraph {
+    /// Targets in the order they were registered.
+    pub targets: Vec<TargetNode>,
+}
+
+impl From<&EnvironmentContext> for TargetGraph {
+    fn from(context: &EnvironmentContext) -> Self {
+        let targets = context
+            .targets_order()
+            .iter()
+            .filter_map(|name| {
+                context.get_target(name).map(|target| TargetNode {
+                    name: name.clone(),
+                    depends: target.depends.clone(),
+                    default: context.default_target() == Some(name.as_str()),
+                    default_build_script: context.default_build_script_target.as_deref()
+                        == Some(name.as_str()),
+                    value_type: target
+                        .resolved_value
+                        .as_ref()
+                        .map(|v| v.get_type().to_string()),
+                })
+            })
+            .collect();
+
+        Self { targets }
+    }
+}
+
+/// Quote a string as a DOT identifier.
+fn dot_quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl TargetGraph {
+    /// Render the graph in Graphviz DOT format.
+    ///
+    /// Edges point from a target to the targets it depends on. The default
+    /// target is drawn bold and the default build script target is dashed.
+    pub fn to_dot(&self) -> String {
+        let mut s = String::new();
+
+        writeln!(s, "digraph targets {{").unwrap();
+        writeln!(s, "  node [shape=box];").unwrap();
+
+        for target in &self.targets {
+            let mut label = target.name.clone();
+            if let Some(value_type) = &target.value_type {
+                label.push_str(&format!("\n{}", value_type));
+            }
+
+            let mut styles = vec![];
+            if target.default {
+                styles.push("bold");
+            }
+            if target.default_build_script {
+                styles.push("dashed");
+            }
+
+            write!(
+                s,
+                "  {} [label={}",
+                dot_quote(&target.name),
+                dot_quote(&label).replace('\n', "\\n")
+            )
+            .unwrap();
+            if !styles.is_empty() {
+                write!(s, ", style={}", dot_quote(&styles.join(","))).unwrap();
+            }
+            writeln!(s, "];").unwrap();
+        }
+
+        for target in &self.targets {
+            for depend in &target.depends {
+                writeln!(s, "  {} -> {};", dot_quote(&target.name), dot_quote(depend)).unwrap();
+            }
+        }
+
+        writeln!(s, "}}").unwrap();
+
+        s
+    }
+
+    /// Render the graph as JSON.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{get_context_value, testutil::*},
+    };
+
+    #[test]
+    fn test_target_graph() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+        env.eval("def foo(): return 'foo'")?;
+        env.eval("def bar(foo): pass")?;
+        env.eval("register_target('foo', foo, default_build_script=True)")?;
+        env.eval("register_target('bar', bar, depends=['foo'], default=True)")?;
+        env.eval("resolve_target('foo')")?;
+
+        let context_value = get_context_value(&env.type_values).unwrap();
+        let context = context_value.downcast_ref::<EnvironmentContext>().unwrap();
+        let graph = TargetGraph::from(&*context);
+
+        assert_eq!(
+            graph.targets,
+            vec![
+                TargetNode {
+                    name: "foo".to_string(),
+                    depends: vec![],
+                    default: false,
+                    default_build_script: true,
+                    value_type: Some("string".to_string()),
+                },
+                TargetNode {
+                    name: "bar".to_string(),
+                    depends: vec!["foo".to_string()],
+                    default: true,
+                    default_build_script: false,
+                    value_type: None,
+                },
+            ]
+        );
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph targets {\n  node [shape=box];\n  \"foo\" [label=\"foo\\nstring\", style=\"dashed\"];\n  \"bar\" [label=\"bar\", style=\"bold\"];\n  \"bar\" -> \"foo\";\n}\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json()?)?;
+        assert_eq!(json["targets"][1]["depends"][0], "foo");
+        assert_eq!(json["targets"][1]["value_type"], serde_json::Value::Null);
+
+        Ok(())
+    }
+}
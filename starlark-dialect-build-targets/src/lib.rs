@@ -2,10 +2,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+pub mod fingerprint;
+pub mod graph;
 pub mod testutil;
 
 use {
-    anyhow::{anyhow, Result},
+    crate::fingerprint::{TargetFingerprint, TargetInput},
+    anyhow::{anyhow, Context, Result},
     linked_hash_map::LinkedHashMap,
     path_dedot::ParseDot,
     slog::warn,
@@ -24,7 +27,7 @@ use {
     },
     std::{
         borrow::Cow,
-        collections::{BTreeMap, HashMap},
+        collections::{BTreeMap, BTreeSet, HashMap},
         os::raw::c_ulong,
         path::{Path, PathBuf},
     },
@@ -87,6 +90,70 @@ impl From<ResolvedTarget> for ResolvedTargetValue {
     }
 }
 
+/// A function building a target that can run on any thread.
+///
+/// It receives the logger to use for output from the build.
+pub type BuildJob = Box<dyn FnOnce(&slog::Logger) -> Result<ResolvedTarget> + Send>;
+
+/// Holds a `BuildJob` returned by a value's `prepare_build()` method.
+///
+/// Values implementing `prepare_build(target)` do the parts of building
+/// requiring access to Starlark state when it is called and defer the rest
+/// to the returned job. This allows targets to be built concurrently.
+pub struct BuildJobValue {
+    job: Option<BuildJob>,
+}
+
+impl BuildJobValue {
+    pub fn new(job: BuildJob) -> Self {
+        Self { job: Some(job) }
+    }
+}
+
+impl TypedValue for BuildJobValue {
+    type Holder = Mutable<BuildJobValue>;
+    const TYPE: &'static str = "BuildJob";
+
+    fn values_for_descendant_check_and_freeze(&self) -> Box<dyn Iterator<Item = Value>> {
+        Box::new(std::iter::empty())
+    }
+}
+
+/// A slog Drain prefixing messages with a target name before forwarding them.
+struct TargetLogDrain {
+    logger: slog::Logger,
+    target: String,
+}
+
+impl slog::Drain for TargetLogDrain {
+    type Ok = ();
+    type Err = slog::Never;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        values: &slog::OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        let record_static = slog::RecordStatic {
+            location: record.location(),
+            tag: record.tag(),
+            level: record.level(),
+        };
+
+        // Logging through the Drain impl retains context attached to loggers
+        // derived from ours.
+        slog::Drain::log(
+            &self.logger,
+            &slog::Record::new(
+                &record_static,
+                &format_args!("[{}] {}", self.target, record.msg()),
+                record.kv(),
+            ),
+            values,
+        )
+    }
+}
+
 /// Represents a registered target in the Starlark environment.
 #[derive(Debug, Clone)]
 pub struct Target {
@@ -103,6 +170,9 @@ pub struct Target {
     ///
     /// TODO consider making this an Arc<T> so we don't have to clone it.
     pub built_target: Option<ResolvedTarget>,
+
+    /// Inputs recorded while resolving this target.
+    pub inputs: BTreeSet<TargetInput>,
 }
 
 /// Holds execution context for a Starlark environment.
@@ -141,6 +211,20 @@ pub struct EnvironmentContext {
     ///
     /// This will change the default target to resolve.
     pub build_script_mode: bool,
+
+    /// Whether to skip building targets whose inputs and outputs are unchanged.
+    incremental: bool,
+
+    /// Whether to build targets even if they are up to date.
+    force_build: bool,
+
+    /// Targets currently being resolved, innermost last.
+    resolving_targets: Vec<String>,
+
+    /// Inputs recorded outside of resolving a target.
+    ///
+    /// These influence every target.
+    global_inputs: BTreeSet<TargetInput>,
 }
 
 impl EnvironmentContext {
@@ -158,6 +242,10 @@ impl EnvironmentContext {
             resolve_targets: None,
             default_build_script_target: None,
             build_script_mode: false,
+            incremental: false,
+            force_build: false,
+            resolving_targets: vec![],
+            global_inputs: BTreeSet::new(),
         }
     }
 
@@ -199,15 +287,102 @@ impl EnvironmentContext {
         self.target_build_path_prefix = prefix.map(|p| p.as_ref().to_path_buf());
     }
 
+    /// Obtain the directory holding the build directories of targets.
+    pub fn targets_build_path(&self) -> PathBuf {
+        if let Some(prefix) = &self.target_build_path_prefix {
+            self.build_path.join(prefix)
+        } else {
+            self.build_path.clone()
+        }
+    }
+
     /// Obtain the directory to use to build a named target.
     pub fn target_build_path(&self, target: &str) -> PathBuf {
+        self.targets_build_path().join(target)
+    }
+
+    /// Obtain the path of the file holding the fingerprint of a named target.
+    pub fn target_fingerprint_path(&self, target: &str) -> PathBuf {
+        let path = self.build_path.join(".fingerprints");
+
         if let Some(prefix) = &self.target_build_path_prefix {
-            self.build_path.join(prefix).join(target)
+            path.join(prefix).join(format!("{}.json", target))
         } else {
-            self.build_path.join(target)
+            path.join(format!("{}.json", target))
         }
     }
 
+    /// Set whether building targets whose fingerprint is unchanged is skipped.
+    ///
+    /// Only enable this if every input influencing targets is recorded via
+    /// [Self::record_input]. Otherwise stale outputs may be reused.
+    pub fn set_incremental(&mut self, value: bool) {
+        self.incremental = value;
+    }
+
+    /// Set whether to build targets even if they are up to date.
+    ///
+    /// Fingerprints are still written when this is set.
+    pub fn set_force_build(&mut self, value: bool) {
+        self.force_build = value;
+    }
+
+    /// Record an input influencing the build.
+    ///
+    /// The input is attributed to the target currently being resolved. If no
+    /// target is being resolved, it is attributed to all targets.
+    pub fn record_input(&mut self, input: TargetInput) {
+        let inputs = match self.resolving_targets.last() {
+            Some(target) => self.targets.get_mut(target).map(|t| &mut t.inputs),
+            None => None,
+        };
+
+        if let Some(inputs) = inputs {
+            inputs.insert(input);
+        } else {
+            self.global_inputs.insert(input);
+        }
+    }
+
+    /// Obtain all inputs influencing a named target.
+    ///
+    /// This includes global inputs and a digest of the inputs of each target
+    /// it depends on.
+    pub fn target_inputs(&self, target: &str) -> Result<BTreeSet<TargetInput>> {
+        let entry = self
+            .get_target(target)
+            .ok_or_else(|| anyhow!("target {} does not exist", target))?;
+
+        let mut inputs = self.global_inputs.clone();
+        inputs.extend(entry.inputs.iter().cloned());
+
+        for depend in &entry.depends {
+            let fingerprint = TargetFingerprint::from_inputs(self.target_inputs(depend)?.iter())?;
+
+            inputs.insert(TargetInput::Value {
+                name: format!("target {}", depend),
+                value: fingerprint.digest(),
+            });
+        }
+
+        Ok(inputs)
+    }
+
+    /// Collect the names of all targets a named target depends on, directly or indirectly.
+    fn collect_depends(&self, target: &str, depends: &mut BTreeSet<String>) -> Result<()> {
+        let entry = self
+            .get_target(target)
+            .ok_or_else(|| anyhow!("target {} does not exist", target))?;
+
+        for depend in &entry.depends {
+            if depends.insert(depend.clone()) {
+                self.collect_depends(depend, depends)?;
+            }
+        }
+
+        Ok(())
+    }
+
     /// Obtain all registered targets.
     pub fn targets(&self) -> &BTreeMap<String, Target> {
         &self.targets
@@ -258,6 +433,7 @@ impl EnvironmentContext {
                 depends,
                 resolved_value: None,
                 built_target: None,
+                inputs: BTreeSet::new(),
             },
         );
 
@@ -843,6 +1019,13 @@ fn starlark_resolve_target(
         )?);
     }
 
+    // Inputs recorded while the callable runs are attributed to this target.
+    get_context_value(type_values)?
+        .downcast_mut::<EnvironmentContext>()?
+        .ok_or(ValueError::IncorrectParameterType)?
+        .resolving_targets
+        .push(target.clone());
+
     let res = target_entry.callable.call(
         call_stack,
         type_values,
@@ -850,7 +1033,7 @@ fn starlark_resolve_target(
         LinkedHashMap::new(),
         None,
         None,
-    )?;
+    );
 
     // TODO consider replacing the target's callable with a new function that returns the
     // resolved value. This will ensure a target function is only ever called once.
@@ -862,6 +1045,9 @@ fn starlark_resolve_target(
         .downcast_mut::<EnvironmentContext>()?
         .ok_or(ValueError::IncorrectParameterType)?;
 
+    context.resolving_targets.pop();
+    let res = res?;
+
     if let Some(target_entry) = context.get_target_mut(&target) {
         target_entry.resolved_value = Some(res.clone());
     }
@@ -869,6 +1055,37 @@ fn starlark_resolve_target(
     Ok(res)
 }
 
+/// Resolve a registered target in a Starlark environment.
+///
+/// Returns the value returned by the target's callable. See `resolve_target()`
+/// in Starlark for semantics.
+pub fn resolve_target_value(
+    type_values: &TypeValues,
+    call_stack: &mut CallStack,
+    target: &str,
+) -> Result<Value> {
+    starlark_resolve_target(type_values, call_stack, target.to_string()).map_err(|e| match e {
+        ValueError::DiagnosedError(d) => {
+            anyhow!("error resolving target {}: {}", target, d.message)
+        }
+        e => anyhow!("error resolving target {}: {:?}", target, e),
+    })
+}
+
+/// Record an input influencing the build in a Starlark environment.
+///
+/// See [EnvironmentContext::record_input].
+pub fn record_target_input(type_values: &TypeValues, input: TargetInput) -> Result<(), ValueError> {
+    let raw_context = get_context_value(type_values)?;
+    let mut context = raw_context
+        .downcast_mut::<EnvironmentContext>()?
+        .ok_or(ValueError::IncorrectParameterType)?;
+
+    context.record_input(input);
+
+    Ok(())
+}
+
 /// resolve_targets()
 fn starlark_resolve_targets(type_values: &TypeValues, call_stack: &mut CallStack) -> ValueResult {
     let resolve_target_fn = type_values
@@ -995,14 +1212,29 @@ pub fn populate_environment(
     Ok(())
 }
 
-/// Build a registered target in a Starlark environment.
-pub fn build_target(
-    _env: &mut Environment,
+/// How building a target will proceed.
+enum PreparedBuild {
+    /// The target doesn't need to be built.
+    Built(ResolvedTarget),
+
+    /// The target is built by running a job, which may run on any thread.
+    Job(BuildJob, Option<TargetFingerprint>),
+
+    /// The target is built by calling the `build()` method of its value.
+    Value(Value, Option<TargetFingerprint>),
+}
+
+/// Determine how to build a resolved target.
+///
+/// This consults the target's fingerprint and calls the `prepare_build()`
+/// method of its value, if available. It must run on the thread evaluating
+/// Starlark.
+fn prepare_build(
     type_values: &TypeValues,
     call_stack: &mut CallStack,
     target: &str,
-) -> Result<ResolvedTarget> {
-    let resolved_value = {
+) -> Result<PreparedBuild> {
+    let (resolved_value, fingerprint) = {
         let context_value = get_context_value(type_values)
             .map_err(|_| anyhow!("unable to resolve context value"))?;
         let context = context_value
@@ -1011,7 +1243,7 @@ pub fn build_target(
 
         let v = if let Some(t) = context.get_target(target) {
             if let Some(t) = &t.built_target {
-                return Ok(t.clone());
+                return Ok(PreparedBuild::Built(t.clone()));
             }
 
             if let Some(v) = &t.resolved_value {
@@ -1023,9 +1255,61 @@ pub fn build_target(
             return Err(anyhow!("target {} is not resolved", target));
         };
 
-        v
+        // The resolved value itself isn't fingerprinted: the repr of most
+        // custom types doesn't describe their content. Code defining and
+        // configuring targets must be recorded as inputs instead.
+        let fingerprint = if context.incremental {
+            let inputs = context.target_inputs(target)?;
+            let fingerprint = TargetFingerprint::from_inputs(inputs.iter())?;
+
+            if let Some(built) = check_fingerprint(&context, target, &fingerprint)? {
+                drop(context);
+                set_built_target(type_values, target, &built)?;
+
+                return Ok(PreparedBuild::Built(built));
+            }
+
+            Some(fingerprint)
+        } else {
+            None
+        };
+
+        (v, fingerprint)
     };
 
+    if let Some(prepare) = type_values.get_type_value(&resolved_value, "prepare_build") {
+        let job_value = prepare
+            .call(
+                call_stack,
+                type_values,
+                vec![resolved_value, Value::from(target)],
+                LinkedHashMap::new(),
+                None,
+                None,
+            )
+            .map_err(|e| anyhow!("error calling prepare_build(): {:?}", e))?;
+
+        let job = job_value
+            .downcast_mut::<BuildJobValue>()
+            .map_err(|_| anyhow!("unable to obtain build job"))?
+            .ok_or_else(|| anyhow!("prepare_build() did not return a BuildJob"))?
+            .job
+            .take()
+            .ok_or_else(|| anyhow!("build job for target {} already consumed", target))?;
+
+        Ok(PreparedBuild::Job(job, fingerprint))
+    } else {
+        Ok(PreparedBuild::Value(resolved_value, fingerprint))
+    }
+}
+
+/// Build a target by calling the `build()` method of its value.
+fn build_value(
+    type_values: &TypeValues,
+    call_stack: &mut CallStack,
+    target: &str,
+    resolved_value: Value,
+) -> Result<ResolvedTarget> {
     let build = type_values
         .get_type_value(&resolved_value, "build")
         .ok_or_else(|| anyhow!("{} does not implement build()", resolved_value.get_type()))?;
@@ -1045,6 +1329,276 @@ pub fn build_target(
         .downcast_ref::<ResolvedTargetValue>()
         .unwrap();
 
+    Ok(resolved_target.inner.clone())
+}
+
+/// Record the result of building a target.
+fn finish_build(
+    type_values: &TypeValues,
+    target: &str,
+    built: &ResolvedTarget,
+    fingerprint: Option<TargetFingerprint>,
+) -> Result<()> {
+    if let Some(mut fingerprint) = fingerprint {
+        let context_value = get_context_value(type_values)
+            .map_err(|e| anyhow!("unable to resolve context: {:?}", e))?;
+        let context = context_value
+            .downcast_ref::<EnvironmentContext>()
+            .ok_or_else(|| anyhow!("context has incorrect type"))?;
+
+        fingerprint.set_built_target(built)?;
+        fingerprint.write_path(&context.target_fingerprint_path(target))?;
+    }
+
+    set_built_target(type_values, target, built)
+}
+
+/// Build a registered target in a Starlark environment.
+///
+/// If incremental building is enabled and the target's fingerprint and
+/// outputs are unchanged since it was last built, the previous result is
+/// returned without building.
+pub fn build_target(
+    _env: &mut Environment,
+    type_values: &TypeValues,
+    call_stack: &mut CallStack,
+    target: &str,
+) -> Result<ResolvedTarget> {
+    let (built, fingerprint) = match prepare_build(type_values, call_stack, target)? {
+        PreparedBuild::Built(built) => return Ok(built),
+        PreparedBuild::Job(job, fingerprint) => {
+            let logger = get_context_value(type_values)
+                .map_err(|_| anyhow!("unable to resolve context value"))?
+                .downcast_ref::<EnvironmentContext>()
+                .ok_or_else(|| anyhow!("context has incorrect type"))?
+                .logger()
+                .clone();
+
+            (job(&logger)?, fingerprint)
+        }
+        PreparedBuild::Value(value, fingerprint) => (
+            build_value(type_values, call_stack, target, value)?,
+            fingerprint,
+        ),
+    };
+
+    finish_build(type_values, target, &built, fingerprint)?;
+
+    Ok(built)
+}
+
+/// Build multiple registered targets, running up to `jobs` builds concurrently.
+///
+/// A target is only built once the other targets in `targets` it depends on
+/// (directly or indirectly) have been built. Targets whose value has a
+/// `prepare_build()` method are built on worker threads. Other targets are
+/// built on the calling thread. When `jobs` is greater than 1, log messages
+/// from worker threads are prefixed with the target name.
+///
+/// A failure doesn't prevent building targets not depending on the failed
+/// target. Once all buildable targets are built, the error of the first
+/// failed target in `targets` order is returned. So the outcome doesn't
+/// depend on the order in which concurrent builds finish.
+pub fn build_targets(
+    _env: &mut Environment,
+    type_values: &TypeValues,
+    call_stack: &mut CallStack,
+    targets: &[String],
+    jobs: usize,
+) -> Result<Vec<ResolvedTarget>> {
+    let jobs = jobs.max(1);
+
+    let mut order = vec![];
+    for target in targets {
+        if !order.contains(target) {
+            order.push(target.clone());
+        }
+    }
+    let mut pending = order.clone();
+
+    let (logger, depends) = {
+        let context_value = get_context_value(type_values)
+            .map_err(|_| anyhow!("unable to resolve context value"))?;
+        let context = context_value
+            .downcast_ref::<EnvironmentContext>()
+            .ok_or_else(|| anyhow!("context has incorrect type"))?;
+
+        let mut depends = HashMap::new();
+        for target in &pending {
+            let mut seen = BTreeSet::new();
+            context.collect_depends(target, &mut seen)?;
+            seen.retain(|t| pending.contains(t));
+            depends.insert(target.clone(), seen);
+        }
+
+        (context.logger().clone(), depends)
+    };
+
+    let mut results: HashMap<String, Result<ResolvedTarget>> = HashMap::new();
+    let mut fingerprints = HashMap::new();
+    let mut running = 0;
+    let (sender, receiver) = std::sync::mpsc::channel();
+
+    loop {
+        while running < jobs {
+            let ready = pending.iter().position(|target| {
+                depends[target]
+                    .iter()
+                    .all(|depend| matches!(results.get(depend), Some(Ok(_))))
+            });
+
+            let target = match ready {
+                Some(index) => pending.remove(index),
+                None => break,
+            };
+
+            match prepare_build(type_values, call_stack, &target) {
+                Ok(PreparedBuild::Built(built)) => {
+                    results.insert(target, Ok(built));
+                }
+                Ok(PreparedBuild::Job(job, fingerprint)) => {
+                    let logger = if jobs > 1 {
+                        slog::Logger::root(
+                            TargetLogDrain {
+                                logger: logger.clone(),
+                                target: target.clone(),
+                            },
+                            slog::o!(),
+                        )
+                    } else {
+                        logger.clone()
+                    };
+
+                    fingerprints.insert(target.clone(), fingerprint);
+                    let sender = sender.clone();
+                    running += 1;
+
+                    std::thread::spawn(move || {
+                        // A panicking job must still report back. Otherwise we'd
+                        // wait on it forever.
+                        let res =
+                            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| job(&logger)))
+                                .unwrap_or_else(|payload| {
+                                    let message = if let Some(s) = payload.downcast_ref::<&str>() {
+                                        s.to_string()
+                                    } else if let Some(s) = payload.downcast_ref::<String>() {
+                                        s.clone()
+                                    } else {
+                                        "unknown cause".to_string()
+                                    };
+
+                                    Err(anyhow!("build job panicked: {}", message))
+                                });
+                        // The receiver only goes away if the calling thread panicked.
+                        let _ = sender.send((target, res));
+                    });
+                }
+                Ok(PreparedBuild::Value(value, fingerprint)) => {
+                    let res =
+                        build_value(type_values, call_stack, &target, value).and_then(|built| {
+                            finish_build(type_values, &target, &built, fingerprint)?;
+                            Ok(built)
+                        });
+                    results.insert(target, res);
+                }
+                Err(e) => {
+                    results.insert(target, Err(e));
+                }
+            }
+        }
+
+        if running == 0 {
+            break;
+        }
+
+        let (target, res) = receiver
+            .recv()
+            .map_err(|_| anyhow!("build thread exited unexpectedly"))?;
+        running -= 1;
+
+        let fingerprint = fingerprints.remove(&target).flatten();
+        let res = res.and_then(|built| {
+            finish_build(type_values, &target, &built, fingerprint)?;
+            Ok(built)
+        });
+
+        results.insert(target, res);
+    }
+
+    for target in &pending {
+        warn!(
+            logger,
+            "not building target {} because a target it depends on failed", target
+        );
+    }
+
+    let mut failed = order
+        .iter()
+        .filter(|target| matches!(results.get(*target), Some(Err(_))));
+
+    if let Some(first) = failed.next() {
+        // The first error is returned. Log the others so they aren't lost.
+        for target in failed {
+            if let Some(Err(e)) = results.get(target) {
+                slog::error!(logger, "error building target {}: {:#}", target, e);
+            }
+        }
+
+        if let Some(Err(e)) = results.remove(first) {
+            return Err(e).with_context(|| format!("building target {}", first));
+        }
+    }
+
+    Ok(order
+        .iter()
+        .filter_map(|target| results.remove(target).and_then(Result::ok))
+        .collect())
+}
+
+/// Compare a target's fingerprint against the one persisted when it was last built.
+///
+/// Returns the previous result of building the target if it is up to date.
+/// Otherwise logs why the target needs to be built.
+fn check_fingerprint(
+    context: &EnvironmentContext,
+    target: &str,
+    fingerprint: &TargetFingerprint,
+) -> Result<Option<ResolvedTarget>> {
+    let path = context.target_fingerprint_path(target);
+
+    let reasons = if context.force_build {
+        vec!["building was forced".to_string()]
+    } else if !path.exists() {
+        vec!["target has no fingerprint from a previous build".to_string()]
+    } else {
+        match TargetFingerprint::from_path(&path) {
+            Ok(previous) => {
+                let reasons = previous.rebuild_reasons(fingerprint)?;
+
+                if reasons.is_empty() {
+                    if let Some(built) = previous.built_target() {
+                        warn!(
+                            context.logger(),
+                            "target {} is up to date; not building", target
+                        );
+                        return Ok(Some(built));
+                    }
+                }
+
+                reasons
+            }
+            Err(e) => vec![format!("{:?}", e)],
+        }
+    };
+
+    for reason in reasons {
+        warn!(context.logger(), "building target {}: {}", target, reason);
+    }
+
+    Ok(None)
+}
+
+fn set_built_target(type_values: &TypeValues, target: &str, built: &ResolvedTarget) -> Result<()> {
     let context_value = get_context_value(type_values)
         .map_err(|e| anyhow!("unable to resolve context: {:?}", e))?;
     let mut context = context_value
@@ -1052,9 +1606,9 @@ pub fn build_target(
         .map_err(|_| anyhow!("unable to obtain mutable context"))?
         .ok_or_else(|| anyhow!("context has incorrect type"))?;
 
-    context.get_target_mut(target).unwrap().built_target = Some(resolved_target.inner.clone());
+    context.get_target_mut(target).unwrap().built_target = Some(built.clone());
 
-    Ok(resolved_target.inner.clone())
+    Ok(())
 }
 
 /// Runs a named target.
@@ -1090,6 +1644,90 @@ pub fn run_target(
 mod test {
     use super::*;
     use crate::testutil::*;
+    use {once_cell::sync::Lazy, std::sync::Mutex};
+
+    /// Events emitted by `TestBuild` jobs.
+    static EVENTS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(vec![]));
+
+    struct TestBuild {
+        fail: bool,
+        panic: bool,
+    }
+
+    impl TypedValue for TestBuild {
+        type Holder = Mutable<TestBuild>;
+        const TYPE: &'static str = "TestBuild";
+
+        fn values_for_descendant_check_and_freeze(&self) -> Box<dyn Iterator<Item = Value>> {
+            Box::new(std::iter::empty())
+        }
+    }
+
+    starlark_module! { test_build_module =>
+        test_build(fail: bool = false, panic: bool = false) {
+            Ok(Value::new(TestBuild { fail, panic }))
+        }
+
+        TestBuild.prepare_build(this, target: String) {
+            let fail = this.downcast_ref::<TestBuild>().unwrap().fail;
+            let panic = this.downcast_ref::<TestBuild>().unwrap().panic;
+
+            Ok(Value::new(BuildJobValue::new(Box::new(move |_logger: &slog::Logger| {
+                EVENTS.lock().unwrap().push(format!("start {}", target));
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                EVENTS.lock().unwrap().push(format!("end {}", target));
+
+                if panic {
+                    panic!("{} panicked", target);
+                }
+
+                if fail {
+                    Err(anyhow!("{} failed", target))
+                } else {
+                    Ok(ResolvedTarget {
+                        run_mode: RunMode::None,
+                        output_path: PathBuf::from(target),
+                    })
+                }
+            }))))
+        }
+    }
+
+    fn test_build_env() -> Result<StarlarkEnvironment> {
+        let mut env = StarlarkEnvironment::new()?;
+        test_build_module(&mut env.env, &mut env.type_values);
+
+        Ok(env)
+    }
+
+    fn build(env: &mut StarlarkEnvironment, targets: &[&str], jobs: usize) -> Result<Vec<String>> {
+        let targets = targets.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        for target in &targets {
+            env.eval(&format!("resolve_target('{}')", target))?;
+        }
+
+        let mut call_stack = CallStack::default();
+        Ok(build_targets(
+            &mut env.env,
+            &env.type_values,
+            &mut call_stack,
+            &targets,
+            jobs,
+        )?
+        .into_iter()
+        .map(|t| t.output_path.display().to_string())
+        .collect())
+    }
+
+    fn events(prefix: &str) -> Vec<String> {
+        EVENTS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| str::contains(e, prefix))
+            .cloned()
+            .collect()
+    }
 
     #[test]
     fn test_register_target() -> Result<()> {
@@ -1138,4 +1776,177 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn test_target_inputs() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+        env.eval("def foo(): pass")?;
+        env.eval("def bar(): pass")?;
+        env.eval("register_target('foo', foo)")?;
+        env.eval("register_target('bar', bar, depends=['foo'])")?;
+
+        let context_value = get_context_value(&env.type_values).unwrap();
+        let mut context = context_value
+            .downcast_mut::<EnvironmentContext>()
+            .unwrap()
+            .unwrap();
+
+        let global = TargetInput::EnvVar("PATH".to_string());
+        let foo = TargetInput::Value {
+            name: "foo".to_string(),
+            value: "1".to_string(),
+        };
+
+        context.record_input(global.clone());
+        context.resolving_targets.push("foo".to_string());
+        context.record_input(foo.clone());
+        context.resolving_targets.pop();
+
+        let foo_inputs = context.target_inputs("foo")?;
+        assert_eq!(
+            foo_inputs,
+            vec![global.clone(), foo]
+                .into_iter()
+                .collect::<BTreeSet<_>>()
+        );
+
+        let bar_inputs = context.target_inputs("bar")?;
+        assert_eq!(bar_inputs.len(), 2);
+        assert!(bar_inputs.contains(&global));
+        assert!(bar_inputs.contains(&TargetInput::Value {
+            name: "target foo".to_string(),
+            value: TargetFingerprint::from_inputs(foo_inputs.iter())?.digest(),
+        }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_targets_parallel() -> Result<()> {
+        let mut env = test_build_env()?;
+        env.eval("def ok(*args): return test_build()")?;
+        env.eval("register_target('par_a', ok)")?;
+        env.eval("register_target('par_b', ok)")?;
+        env.eval("register_target('par_c', ok, depends=['par_a'])")?;
+
+        assert_eq!(
+            build(&mut env, &["par_c", "par_a", "par_b"], 4)?,
+            vec!["par_c", "par_a", "par_b"]
+        );
+
+        let events = events("par_");
+        let index = |e: &str| events.iter().position(|x| x == e).unwrap();
+
+        // Independent targets run concurrently.
+        assert!(index("start par_b") < index("end par_a"));
+        // Dependencies finish before dependents start.
+        assert!(index("end par_a") < index("start par_c"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_targets_failure() -> Result<()> {
+        let mut env = test_build_env()?;
+        env.eval("def ok(*args): return test_build()")?;
+        env.eval("def fail(*args): return test_build(fail=True)")?;
+        env.eval("register_target('fail_a', ok)")?;
+        env.eval("register_target('fail_b', fail)")?;
+        env.eval("register_target('fail_c', ok, depends=['fail_b'])")?;
+        env.eval("register_target('fail_d', fail)")?;
+
+        let err = build(&mut env, &["fail_c", "fail_d", "fail_a", "fail_b"], 4).unwrap_err();
+        assert_eq!(err.to_string(), "building target fail_d");
+        assert_eq!(
+            format!("{:#}", err),
+            "building target fail_d: fail_d failed"
+        );
+
+        // Targets not depending on a failed target are still built.
+        assert!(events("fail_").contains(&"end fail_a".to_string()));
+        assert!(!events("fail_").contains(&"start fail_c".to_string()));
+
+        let context_value = get_context_value(&env.type_values).unwrap();
+        let context = context_value.downcast_ref::<EnvironmentContext>().unwrap();
+        assert!(context.get_target("fail_a").unwrap().built_target.is_some());
+        assert!(context.get_target("fail_c").unwrap().built_target.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_targets_panic() -> Result<()> {
+        let mut env = test_build_env()?;
+        env.eval("def ok(*args): return test_build()")?;
+        env.eval("def boom(*args): return test_build(panic=True)")?;
+        env.eval("register_target('panic_a', ok)")?;
+        env.eval("register_target('panic_b', boom)")?;
+
+        let err = build(&mut env, &["panic_a", "panic_b"], 2).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "building target panic_b: build job panicked: panic_b panicked"
+        );
+        assert!(events("panic_").contains(&"end panic_a".to_string()));
+
+        Ok(())
+    }
+
+    /// A drain recording messages and key-value pairs.
+    #[derive(Clone, Default)]
+    struct CapturingDrain {
+        records: std::sync::Arc<Mutex<Vec<String>>>,
+    }
+
+    impl slog::Drain for CapturingDrain {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(
+            &self,
+            record: &slog::Record,
+            values: &slog::OwnedKVList,
+        ) -> Result<Self::Ok, Self::Err> {
+            struct Collector(Vec<String>);
+
+            impl slog::Serializer for Collector {
+                fn emit_arguments(
+                    &mut self,
+                    key: slog::Key,
+                    val: &std::fmt::Arguments,
+                ) -> slog::Result {
+                    self.0.push(format!("{}={}", key, val));
+                    Ok(())
+                }
+            }
+
+            let mut collector = Collector(vec![record.msg().to_string()]);
+            slog::KV::serialize(&record.kv(), record, &mut collector).unwrap();
+            slog::KV::serialize(values, record, &mut collector).unwrap();
+            self.records.lock().unwrap().push(collector.0.join(" "));
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_target_log_drain_keeps_context() {
+        let drain = CapturingDrain::default();
+        let logger = slog::Logger::root(
+            TargetLogDrain {
+                logger: slog::Logger::root(drain.clone(), slog::o!("root" => "1")),
+                target: "foo".to_string(),
+            },
+            slog::o!(),
+        );
+
+        let child = logger.new(slog::o!("step" => "compile"));
+        warn!(child, "hello"; "file" => "x.rs");
+
+        let records = drain.records.lock().unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(records[0].starts_with("[foo] hello file=x.rs"));
+        assert!(str::contains(&records[0], "step=compile"));
+        assert!(str::contains(&records[0], "root=1"));
+    }
 }
@@ -4,8 +4,8 @@
 
 use {
     crate::UndefinedSymbol,
-    byteorder::ReadBytesExt,
-    std::{ffi::CStr, os::raw::c_char},
+    anyhow::Result,
+    byteorder::{BigEndian, LittleEndian, ReadBytesExt},
 };
 
 #[repr(C)]
@@ -40,6 +40,95 @@ struct Elf64_Vernaux {
     vna_next: u32,
 }
 
+/// Read a `u16` at an offset using the byte order of an ELF file.
+fn read_u16(data: &[u8], offset: usize, little_endian: bool) -> Option<u16> {
+    let mut reader = std::io::Cursor::new(data.get(offset..)?);
+
+    if little_endian {
+        reader.read_u16::<LittleEndian>().ok()
+    } else {
+        reader.read_u16::<BigEndian>().ok()
+    }
+}
+
+/// Read a `u32` at an offset using the byte order of an ELF file.
+fn read_u32(data: &[u8], offset: usize, little_endian: bool) -> Option<u32> {
+    let mut reader = std::io::Cursor::new(data.get(offset..)?);
+
+    if little_endian {
+        reader.read_u32::<LittleEndian>().ok()
+    } else {
+        reader.read_u32::<BigEndian>().ok()
+    }
+}
+
+/// Parse the content of a `SHT_GNU_VERNEED` section.
+///
+/// Records have the same layout in 32-bit and 64-bit ELF files. Parsing
+/// stops at the first record extending past the end of `data`.
+fn parse_verneed(
+    data: &[u8],
+    count: usize,
+    little_endian: bool,
+) -> Vec<(Elf64_Verneed, Vec<Elf64_Vernaux>)> {
+    let mut res = vec![];
+    let mut offset = 0usize;
+
+    for _ in 0..count {
+        let record = match (|| {
+            Some(Elf64_Verneed {
+                vn_version: read_u16(data, offset, little_endian)?,
+                vn_cnt: read_u16(data, offset + 2, little_endian)?,
+                vn_file: read_u32(data, offset + 4, little_endian)?,
+                vn_aux: read_u32(data, offset + 8, little_endian)?,
+                vn_next: read_u32(data, offset + 12, little_endian)?,
+            })
+        })() {
+            Some(record) => record,
+            None => break,
+        };
+
+        let mut vernauxes = vec![];
+        let mut aux_offset = offset + record.vn_aux as usize;
+
+        for _ in 0..record.vn_cnt {
+            let aux = match (|| {
+                Some(Elf64_Vernaux {
+                    vna_hash: read_u32(data, aux_offset, little_endian)?,
+                    vna_flags: read_u16(data, aux_offset + 4, little_endian)?,
+                    vna_other: read_u16(data, aux_offset + 6, little_endian)?,
+                    vna_name: read_u32(data, aux_offset + 8, little_endian)?,
+                    vna_next: read_u32(data, aux_offset + 12, little_endian)?,
+                })
+            })() {
+                Some(aux) => aux,
+                None => break,
+            };
+
+            aux_offset += aux.vna_next as usize;
+            vernauxes.push(aux);
+        }
+
+        let next = record.vn_next as usize;
+        res.push((record, vernauxes));
+
+        if next == 0 {
+            break;
+        }
+        offset += next;
+    }
+
+    res
+}
+
+/// Read a NUL terminated string at an offset of a string table.
+fn read_string(data: &[u8], offset: usize) -> Option<String> {
+    let data = data.get(offset..)?;
+    let end = data.iter().position(|c| *c == 0)?;
+
+    Some(String::from_utf8_lossy(&data[..end]).into_owned())
+}
+
 fn resolve_verneed(
     verneed_entries: &[(Elf64_Verneed, Vec<Elf64_Vernaux>)],
     names_data: &[u8],
@@ -52,15 +141,9 @@ fn resolve_verneed(
                 continue;
             }
 
-            let filename_ptr = unsafe { names_data.as_ptr().add(verneed.vn_file as usize) };
-            let filename = unsafe { CStr::from_ptr(filename_ptr as *const c_char) };
-
-            let depend_ptr = unsafe { names_data.as_ptr().add(vernaux.vna_name as usize) };
-            let depend = unsafe { CStr::from_ptr(depend_ptr as *const c_char) };
-
             return (
-                Some(filename.to_string_lossy().into_owned()),
-                Some(depend.to_string_lossy().into_owned()),
+                read_string(names_data, verneed.vn_file as usize),
+                read_string(names_data, vernaux.vna_name as usize),
             );
         }
     }
@@ -70,8 +153,8 @@ fn resolve_verneed(
 
 /// Find undefined dynamic symbols in an ELF binary.
 ///
-/// Will also resolve the filename and symbol version, if available.
-#[allow(clippy::cast_ptr_alignment)]
+/// Will also resolve the filename and symbol version, if available. Both 32-bit
+/// and 64-bit ELF files of either byte order are supported.
 pub fn find_undefined_elf_symbols(buffer: &[u8], elf: &goblin::elf::Elf) -> Vec<UndefinedSymbol> {
     let mut verneed_entries: Vec<(Elf64_Verneed, Vec<Elf64_Vernaux>)> = Vec::new();
     let mut versym: Vec<u16> = Vec::new();
@@ -84,11 +167,9 @@ pub fn find_undefined_elf_symbols(buffer: &[u8], elf: &goblin::elf::Elf) -> Vec<
                     .file_range()
                     .expect("SHT_GNU_VERSYM missing file range")];
 
-                let mut reader = std::io::Cursor::new(data);
-
-                while let Ok(value) = reader.read_u16::<byteorder::NativeEndian>() {
-                    versym.push(value);
-                }
+                versym.extend(
+                    (0..data.len() / 2).filter_map(|i| read_u16(data, i * 2, elf.little_endian)),
+                );
             }
             goblin::elf::section_header::SHT_GNU_VERNEED => {
                 verneed_names_section = section_header.sh_link;
@@ -97,28 +178,13 @@ pub fn find_undefined_elf_symbols(buffer: &[u8], elf: &goblin::elf::Elf) -> Vec<
                     .file_range()
                     .expect("SHT_GNU_VERNEED missing file range")];
 
-                let mut ptr = data.as_ptr();
-
-                for _ in 0..elf.dynamic.as_ref().unwrap().info.verneednum {
-                    let record: Elf64_Verneed = unsafe { std::ptr::read(ptr as *const _) };
-
-                    // Stash pointer to next Verneed record.
-                    let next_record = unsafe { ptr.add(record.vn_next as usize) };
-
-                    let mut vernaux: Vec<Elf64_Vernaux> = Vec::new();
+                let count = elf
+                    .dynamic
+                    .as_ref()
+                    .map(|dynamic| dynamic.info.verneednum)
+                    .unwrap_or_default();
 
-                    ptr = unsafe { ptr.add(record.vn_aux as usize) };
-
-                    for _ in 0..record.vn_cnt {
-                        let aux: Elf64_Vernaux = unsafe { std::ptr::read(ptr as *const _) };
-                        vernaux.push(aux.clone());
-                        ptr = unsafe { ptr.add(aux.vna_next as usize) };
-                    }
-
-                    verneed_entries.push((record.clone(), vernaux));
-
-                    ptr = next_record;
-                }
+                verneed_entries.extend(parse_verneed(data, count as usize, elf.little_endian));
             }
             _ => {}
         }
@@ -134,7 +200,8 @@ pub fn find_undefined_elf_symbols(buffer: &[u8], elf: &goblin::elf::Elf) -> Vec<
     let mut versym_iter = versym.iter();
 
     for sym in elf.dynsyms.iter() {
-        let versym = *versym_iter.next().unwrap();
+        // Binaries without symbol versioning have no versym section.
+        let versym = versym_iter.next().copied().unwrap_or(0);
 
         if sym.is_import() {
             let name = dynstrtab.get(sym.st_name).unwrap().unwrap();
@@ -160,3 +227,140 @@ pub fn find_undefined_elf_symbols(buffer: &[u8], elf: &goblin::elf::Elf) -> Vec<
 
     res
 }
+
+/// Find the newest glibc version required by an ELF binary.
+///
+/// This is the highest `GLIBC_*` version of any undefined symbol. 32-bit and
+/// 64-bit binaries are supported. Returns `None` if the data isn't an ELF
+/// binary or it doesn't reference versioned glibc symbols, e.g. because it is
+/// statically linked.
+pub fn find_required_glibc_version(data: &[u8]) -> Result<Option<String>> {
+    let elf = match goblin::Object::parse(data)? {
+        goblin::Object::Elf(elf) => elf,
+        _ => return Ok(None),
+    };
+
+    if elf.dynamic.is_none() || elf.section_headers.is_empty() {
+        return Ok(None);
+    }
+
+    let symbols = find_undefined_elf_symbols(data, &elf);
+
+    let mut res: Option<(version_compare::Version, &str)> = None;
+
+    for symbol in &symbols {
+        let version = match symbol
+            .version
+            .as_ref()
+            .and_then(|v| v.strip_prefix("GLIBC_"))
+            // Ignore GLIBC_PRIVATE and similar.
+            .filter(|v| v.starts_with(|c: char| c.is_ascii_digit()))
+        {
+            Some(version) => version,
+            None => continue,
+        };
+
+        let parsed = match version_compare::Version::from(version) {
+            Some(v) => v,
+            None => continue,
+        };
+
+        if res.as_ref().map(|(max, _)| &parsed > max).unwrap_or(true) {
+            res = Some((parsed, version));
+        }
+    }
+
+    Ok(res.map(|(_, version)| version.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a `SHT_GNU_VERNEED` section with 1 record having 2 auxiliary entries.
+    fn verneed_data(little_endian: bool) -> Vec<u8> {
+        let u16_bytes = |v: u16| {
+            if little_endian {
+                v.to_le_bytes().to_vec()
+            } else {
+                v.to_be_bytes().to_vec()
+            }
+        };
+        let u32_bytes = |v: u32| {
+            if little_endian {
+                v.to_le_bytes().to_vec()
+            } else {
+                v.to_be_bytes().to_vec()
+            }
+        };
+
+        let mut data = vec![];
+        // Elf64_Verneed: version, cnt, file, aux, next.
+        data.extend(u16_bytes(1));
+        data.extend(u16_bytes(2));
+        data.extend(u32_bytes(0));
+        data.extend(u32_bytes(16));
+        data.extend(u32_bytes(0));
+        // Elf64_Vernaux: hash, flags, other, name, next.
+        data.extend(u32_bytes(0));
+        data.extend(u16_bytes(0));
+        data.extend(u16_bytes(2));
+        data.extend(u32_bytes(10));
+        data.extend(u32_bytes(16));
+        data.extend(u32_bytes(0));
+        data.extend(u16_bytes(0));
+        data.extend(u16_bytes(3));
+        data.extend(u32_bytes(22));
+        data.extend(u32_bytes(0));
+
+        data
+    }
+
+    #[test]
+    fn test_parse_verneed() {
+        let names = b"libc.so.6\0GLIBC_2.2.5\0GLIBC_2.17\0";
+
+        for little_endian in &[true, false] {
+            let data = verneed_data(*little_endian);
+            let entries = parse_verneed(&data, 1, *little_endian);
+
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].1.len(), 2);
+            assert_eq!(
+                resolve_verneed(&entries, names, 2),
+                (
+                    Some("libc.so.6".to_string()),
+                    Some("GLIBC_2.2.5".to_string())
+                )
+            );
+            assert_eq!(
+                resolve_verneed(&entries, names, 3),
+                (
+                    Some("libc.so.6".to_string()),
+                    Some("GLIBC_2.17".to_string())
+                )
+            );
+            assert_eq!(resolve_verneed(&entries, names, 4), (None, None));
+
+            // Truncated data doesn't panic.
+            let entries = parse_verneed(&data[0..20], 1, *little_endian);
+            assert_eq!(entries[0].1.len(), 0);
+            assert!(parse_verneed(&data[0..8], 1, *little_endian).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_find_required_glibc_version() -> Result<()> {
+        assert_eq!(find_required_glibc_version(&[b'#'; 64])?, None);
+
+        // The running test binary is dynamically linked against glibc on
+        // GNU/Linux.
+        if cfg!(all(target_os = "linux", target_env = "gnu")) {
+            let data = std::fs::read(std::env::current_exe()?)?;
+            let version = find_required_glibc_version(&data)?.expect("glibc version");
+            assert!(version.starts_with("2."), "{}", version);
+        }
+
+        Ok(())
+    }
+}
@@ -11,8 +11,9 @@ use {
     crate::ControlFile,
     os_str_bytes::OsStrBytes,
     std::{
+        collections::{BTreeMap, BTreeSet},
         io::{BufWriter, Cursor, Read, Write},
-        path::Path,
+        path::{Path, PathBuf},
         time::SystemTime,
     },
     tugger_file_manifest::{FileEntry, FileManifest, FileManifestError},
@@ -57,6 +58,7 @@ impl From<FileManifestError> for DebError {
 }
 
 /// Compression format to apply to `.deb` files.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum DebCompression {
     /// Do not compress contents of `.deb` files.
     Uncompressed,
@@ -122,6 +124,9 @@ pub struct DebBuilder<'control> {
     /// Files to install as part of the package.
     install_files: FileManifest,
 
+    /// Symlinks to install as part of the package, mapped to their targets.
+    install_symlinks: BTreeMap<PathBuf, PathBuf>,
+
     mtime: Option<SystemTime>,
 }
 
@@ -132,6 +137,7 @@ impl<'control> DebBuilder<'control> {
             control_builder: ControlTarBuilder::new(control_file),
             compression: DebCompression::Gzip,
             install_files: FileManifest::default(),
+            install_symlinks: BTreeMap::new(),
             mtime: None,
         }
     }
@@ -199,6 +205,16 @@ impl<'control> DebBuilder<'control> {
         Ok(self)
     }
 
+    /// Register a symlink as to be installed by this package.
+    ///
+    /// `path` is relative to the filesystem root, like with [Self::install_file].
+    /// `target` is written to the symlink verbatim.
+    pub fn install_symlink(mut self, path: impl AsRef<Path>, target: impl AsRef<Path>) -> Self {
+        self.install_symlinks
+            .insert(path.as_ref().to_path_buf(), target.as_ref().to_path_buf());
+        self
+    }
+
     /// Write `.deb` file content to a writer.
     ///
     /// This effectively materialized the `.deb` package somewhere.
@@ -234,7 +250,12 @@ impl<'control> DebBuilder<'control> {
 
         // Third entry is a data.tar with file content.
         let mut data_writer = BufWriter::new(Vec::new());
-        write_deb_tar(&mut data_writer, &self.install_files, self.mtime())?;
+        write_deb_tar_with_symlinks(
+            &mut data_writer,
+            &self.install_files,
+            &self.install_symlinks,
+            self.mtime(),
+        )?;
         let data_tar = data_writer.into_inner()?;
         let data_tar = self
             .compression
@@ -438,6 +459,18 @@ pub fn write_deb_tar<W: Write>(
     writer: W,
     files: &FileManifest,
     mtime: u64,
+) -> Result<(), DebError> {
+    write_deb_tar_with_symlinks(writer, files, &BTreeMap::new(), mtime)
+}
+
+/// Write a tar archive containing files and symlinks suitable for inclusion in a `.deb` archive.
+///
+/// `symlinks` maps the relative path of each symlink to its target.
+pub fn write_deb_tar_with_symlinks<W: Write>(
+    writer: W,
+    files: &FileManifest,
+    symlinks: &BTreeMap<PathBuf, PathBuf>,
+    mtime: u64,
 ) -> Result<(), DebError> {
     let mut builder = tar::Builder::new(writer);
 
@@ -450,7 +483,20 @@ pub fn write_deb_tar<W: Write>(
     builder.append(&header, &*vec![])?;
 
     // And entries for each directory in the tree.
-    for directory in files.relative_directories() {
+    let mut directories = files
+        .relative_directories()
+        .into_iter()
+        .collect::<BTreeSet<_>>();
+    for path in symlinks.keys() {
+        directories.extend(
+            path.ancestors()
+                .skip(1)
+                .filter(|p| !p.as_os_str().is_empty())
+                .map(|p| p.to_path_buf()),
+        );
+    }
+
+    for directory in directories {
         let mut header = new_tar_header(mtime)?;
         set_header_path(&mut builder, &mut header, &directory, true)?;
         header.set_mode(0o755);
@@ -471,6 +517,17 @@ pub fn write_deb_tar<W: Write>(
         builder.append(&header, &*data)?;
     }
 
+    for (rel_path, target) in symlinks {
+        let mut header = new_tar_header(mtime)?;
+        set_header_path(&mut builder, &mut header, rel_path, false)?;
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_link_name(target)?;
+        header.set_mode(0o777);
+        header.set_size(0);
+        header.set_cksum();
+        builder.append(&header, &*vec![])?;
+    }
+
     builder.finish()?;
 
     Ok(())
@@ -594,6 +651,58 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_write_data_tar_symlink() -> Result<()> {
+        let mut manifest = FileManifest::default();
+        manifest.add_file_entry(
+            "usr/lib/myapp/myapp",
+            FileEntry {
+                data: vec![42].into(),
+                executable: true,
+            },
+        )?;
+
+        let mut symlinks = BTreeMap::new();
+        symlinks.insert(
+            PathBuf::from("usr/bin/myapp"),
+            PathBuf::from("/usr/lib/myapp/myapp"),
+        );
+
+        let mut buffer = vec![];
+        write_deb_tar_with_symlinks(&mut buffer, &manifest, &symlinks, 2)?;
+
+        let mut archive = tar::Archive::new(std::io::Cursor::new(buffer));
+
+        let entries = archive
+            .entries()?
+            .map(|entry| {
+                let entry = entry?;
+                Ok((
+                    entry.path()?.to_path_buf(),
+                    entry.link_name()?.map(|p| p.to_path_buf()),
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            entries,
+            vec![
+                (PathBuf::from("./"), None),
+                (PathBuf::from("./usr/"), None),
+                (PathBuf::from("./usr/bin/"), None),
+                (PathBuf::from("./usr/lib/"), None),
+                (PathBuf::from("./usr/lib/myapp/"), None),
+                (PathBuf::from("./usr/lib/myapp/myapp"), None),
+                (
+                    PathBuf::from("./usr/bin/myapp"),
+                    Some(PathBuf::from("/usr/lib/myapp/myapp"))
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_write_deb() -> Result<()> {
         let mut control_para = ControlParagraph::default();
@@ -11,5 +11,8 @@ pub use {
     control::{
         ControlError, ControlField, ControlFieldValue, ControlFile, ControlParagraph, SourceControl,
     },
-    deb::{write_deb_tar, ControlTarBuilder, DebBuilder, DebError},
+    deb::{
+        write_deb_tar, write_deb_tar_with_symlinks, ControlTarBuilder, DebBuilder, DebCompression,
+        DebError,
+    },
 };
@@ -2,6 +2,8 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+pub mod sbom;
+
 use {
     anyhow::{anyhow, Result},
     spdx::{ExceptionId, Expression, LicenseId},
@@ -84,6 +86,9 @@ pub struct LicensedComponent {
     /// Type of component.
     flavor: ComponentFlavor,
 
+    /// Version of this software component.
+    version: Option<String>,
+
     /// The type of license.
     license: LicenseFlavor,
 
@@ -120,6 +125,7 @@ impl LicensedComponent {
         Ok(Self {
             name: name.to_string(),
             flavor: ComponentFlavor::Generic,
+            version: None,
             license,
             source_location: SourceLocation::NotSet,
             license_texts: vec![],
@@ -131,6 +137,7 @@ impl LicensedComponent {
         Self {
             name: name.to_string(),
             flavor: ComponentFlavor::Generic,
+            version: None,
             license: LicenseFlavor::None,
             source_location: SourceLocation::NotSet,
             license_texts: vec![],
@@ -142,6 +149,7 @@ impl LicensedComponent {
         Self {
             name: name.to_string(),
             flavor: ComponentFlavor::Generic,
+            version: None,
             license: LicenseFlavor::PublicDomain,
             source_location: SourceLocation::NotSet,
             license_texts: vec![],
@@ -153,6 +161,7 @@ impl LicensedComponent {
         Self {
             name: name.to_string(),
             flavor: ComponentFlavor::Generic,
+            version: None,
             license: LicenseFlavor::Unknown(terms),
             source_location: SourceLocation::NotSet,
             license_texts: vec![],
@@ -174,6 +183,16 @@ impl LicensedComponent {
         self.flavor = flavor;
     }
 
+    /// Obtain the version of this component, if known.
+    pub fn version(&self) -> Option<&str> {
+        self.version.as_deref()
+    }
+
+    /// Set the version of this component.
+    pub fn set_version(&mut self, version: impl ToString) {
+        self.version = Some(version.to_string());
+    }
+
     /// Obtain the flavor of license for this component.
     pub fn license(&self) -> &LicenseFlavor {
         &self.license
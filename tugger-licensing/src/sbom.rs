@@ -0,0 +1,583 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Software bill of materials (SBOM) generation.
+
+This module can express a [LicensedComponents] as machine-readable
+SPDX 2.2 (JSON and tag-value) and CycloneDX 1.3 (JSON) documents.
+*/
+
+use {
+    crate::{
+        ComponentFlavor, LicenseFlavor, LicensedComponent, LicensedComponents, SourceLocation,
+    },
+    anyhow::Result,
+    chrono::{DateTime, SecondsFormat, Utc},
+    serde_json::{json, Value},
+    std::{collections::BTreeSet, convert::TryFrom, fmt::Write},
+};
+
+const SPDX_NOASSERTION: &str = "NOASSERTION";
+
+/// Describes a supported SBOM document format.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SbomFormat {
+    /// SPDX 2.2 JSON.
+    SpdxJson,
+    /// SPDX 2.2 tag-value.
+    SpdxTagValue,
+    /// CycloneDX 1.3 JSON.
+    CycloneDxJson,
+}
+
+impl std::fmt::Display for SbomFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::SpdxJson => "spdx-json",
+            Self::SpdxTagValue => "spdx-tag-value",
+            Self::CycloneDxJson => "cyclonedx-json",
+        })
+    }
+}
+
+impl TryFrom<&str> for SbomFormat {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "spdx-json" => Ok(Self::SpdxJson),
+            "spdx-tag-value" => Ok(Self::SpdxTagValue),
+            "cyclonedx-json" => Ok(Self::CycloneDxJson),
+            _ => Err(format!("{} is not a valid SBOM format", value)),
+        }
+    }
+}
+
+impl SbomFormat {
+    /// The conventional file name suffix for documents of this format.
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            Self::SpdxJson => "spdx.json",
+            Self::SpdxTagValue => "spdx",
+            Self::CycloneDxJson => "cdx.json",
+        }
+    }
+}
+
+/// Metadata describing an SBOM document.
+#[derive(Clone, Debug)]
+pub struct SbomMetadata {
+    /// Name of the document.
+    ///
+    /// This is typically the name of the software being described.
+    pub name: String,
+
+    /// URI uniquely identifying this document.
+    ///
+    /// Used as the SPDX `DocumentNamespace`.
+    pub namespace: String,
+
+    /// UUID uniquely identifying this document.
+    ///
+    /// Used to derive the CycloneDX `serialNumber`.
+    pub uuid: Option<String>,
+
+    /// When the document was created.
+    pub created: DateTime<Utc>,
+
+    /// Name of the tool that created the document.
+    pub tool_name: String,
+
+    /// Version of the tool that created the document.
+    pub tool_version: String,
+}
+
+impl ComponentFlavor {
+    fn spdx_id_prefix(&self) -> &'static str {
+        match self {
+            Self::Generic => "Generic",
+            Self::Library => "Library",
+            Self::RustCrate => "RustCrate",
+            Self::PythonPackage => "PythonPackage",
+        }
+    }
+}
+
+/// Obtain a package URL (purl) for a component, if one can be derived.
+pub fn component_purl(component: &LicensedComponent) -> Option<String> {
+    let (purl_type, name) = match component.flavor() {
+        ComponentFlavor::PythonPackage => {
+            ("pypi", component.name().to_lowercase().replace('_', "-"))
+        }
+        ComponentFlavor::RustCrate => ("cargo", component.name().to_string()),
+        ComponentFlavor::Generic | ComponentFlavor::Library => return None,
+    };
+
+    Some(if let Some(version) = component.version() {
+        format!("pkg:{}/{}@{}", purl_type, name, version)
+    } else {
+        format!("pkg:{}/{}", purl_type, name)
+    })
+}
+
+/// Derive unique SPDX element identifiers for every component.
+///
+/// Identifiers may only contain letters, numbers, `.`, and `-`. Names
+/// colliding after normalization receive a numeric suffix.
+fn component_ids(components: &LicensedComponents) -> Vec<(String, &LicensedComponent)> {
+    let mut seen = BTreeSet::new();
+
+    components
+        .iter_components()
+        .map(|component| {
+            let base = format!(
+                "SPDXRef-{}-{}",
+                component.flavor().spdx_id_prefix(),
+                component
+                    .name()
+                    .chars()
+                    .map(|c| {
+                        if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                            c
+                        } else {
+                            '-'
+                        }
+                    })
+                    .collect::<String>()
+            );
+
+            let mut id = base.clone();
+            let mut counter = 2;
+            while seen.contains(&id) {
+                id = format!("{}-{}", base, counter);
+                counter += 1;
+            }
+            seen.insert(id.clone());
+
+            (id, component)
+        })
+        .collect()
+}
+
+fn spdx_download_location(component: &LicensedComponent) -> &str {
+    match component.source_location() {
+        SourceLocation::Url(url) => url,
+        SourceLocation::NotSet => SPDX_NOASSERTION,
+    }
+}
+
+/// Resolve the SPDX declared license and an optional license comment for a component.
+///
+/// SPDX license expressions may only reference SPDX license identifiers. So
+/// licenses not expressible as SPDX are recorded as `NOASSERTION` with the
+/// original license terms preserved in the comment.
+fn spdx_license_declared(component: &LicensedComponent) -> (String, Option<String>) {
+    match component.license() {
+        LicenseFlavor::Spdx(expression) => (expression.to_string(), None),
+        LicenseFlavor::OtherExpression(expression) => (
+            SPDX_NOASSERTION.to_string(),
+            Some(format!("license expression: {}", expression)),
+        ),
+        LicenseFlavor::PublicDomain => (
+            SPDX_NOASSERTION.to_string(),
+            Some("public domain".to_string()),
+        ),
+        LicenseFlavor::Unknown(terms) => (
+            SPDX_NOASSERTION.to_string(),
+            Some(format!("license terms: {}", terms.join(", "))),
+        ),
+        LicenseFlavor::None => ("NONE".to_string(), None),
+    }
+}
+
+fn format_timestamp(value: &DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Produce an SPDX 2.2 JSON document describing components.
+pub fn spdx_json_document(
+    components: &LicensedComponents,
+    metadata: &SbomMetadata,
+) -> Result<String> {
+    let ids = component_ids(components);
+
+    let packages = ids
+        .iter()
+        .map(|(id, component)| {
+            let (license_declared, license_comments) = spdx_license_declared(component);
+
+            let mut package = json!({
+                "SPDXID": id,
+                "name": component.name(),
+                "downloadLocation": spdx_download_location(component),
+                "filesAnalyzed": false,
+                "licenseConcluded": SPDX_NOASSERTION,
+                "licenseDeclared": license_declared,
+                "copyrightText": SPDX_NOASSERTION,
+                "comment": component.flavor().to_string(),
+            });
+
+            let object = package.as_object_mut().unwrap();
+
+            if let Some(version) = component.version() {
+                object.insert("versionInfo".to_string(), json!(version));
+            }
+            if let Some(comments) = license_comments {
+                object.insert("licenseComments".to_string(), json!(comments));
+            }
+            if let Some(purl) = component_purl(component) {
+                object.insert(
+                    "externalRefs".to_string(),
+                    json!([{
+                        "referenceCategory": "PACKAGE-MANAGER",
+                        "referenceType": "purl",
+                        "referenceLocator": purl,
+                    }]),
+                );
+            }
+
+            package
+        })
+        .collect::<Vec<_>>();
+
+    let relationships = ids
+        .iter()
+        .map(|(id, _)| {
+            json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": id,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let document = json!({
+        "spdxVersion": "SPDX-2.2",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": metadata.name,
+        "documentNamespace": metadata.namespace,
+        "creationInfo": {
+            "created": format_timestamp(&metadata.created),
+            "creators": [format!("Tool: {}-{}", metadata.tool_name, metadata.tool_version)],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    });
+
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Produce an SPDX 2.2 tag-value document describing components.
+pub fn spdx_tag_value_document(
+    components: &LicensedComponents,
+    metadata: &SbomMetadata,
+) -> Result<String> {
+    let mut text = String::new();
+
+    writeln!(&mut text, "SPDXVersion: SPDX-2.2")?;
+    writeln!(&mut text, "DataLicense: CC0-1.0")?;
+    writeln!(&mut text, "SPDXID: SPDXRef-DOCUMENT")?;
+    writeln!(&mut text, "DocumentName: {}", metadata.name)?;
+    writeln!(&mut text, "DocumentNamespace: {}", metadata.namespace)?;
+    writeln!(
+        &mut text,
+        "Creator: Tool: {}-{}",
+        metadata.tool_name, metadata.tool_version
+    )?;
+    writeln!(
+        &mut text,
+        "Created: {}",
+        format_timestamp(&metadata.created)
+    )?;
+
+    for (id, component) in component_ids(components) {
+        let (license_declared, license_comments) = spdx_license_declared(component);
+
+        writeln!(&mut text)?;
+        writeln!(&mut text, "PackageName: {}", component.name())?;
+        writeln!(&mut text, "SPDXID: {}", id)?;
+        if let Some(version) = component.version() {
+            writeln!(&mut text, "PackageVersion: {}", version)?;
+        }
+        writeln!(
+            &mut text,
+            "PackageDownloadLocation: {}",
+            spdx_download_location(component)
+        )?;
+        writeln!(&mut text, "FilesAnalyzed: false")?;
+        writeln!(&mut text, "PackageLicenseConcluded: {}", SPDX_NOASSERTION)?;
+        writeln!(&mut text, "PackageLicenseDeclared: {}", license_declared)?;
+        if let Some(comments) = license_comments {
+            writeln!(
+                &mut text,
+                "PackageLicenseComments: <text>{}</text>",
+                comments
+            )?;
+        }
+        writeln!(&mut text, "PackageCopyrightText: {}", SPDX_NOASSERTION)?;
+        writeln!(
+            &mut text,
+            "PackageComment: <text>{}</text>",
+            component.flavor().to_string()
+        )?;
+        if let Some(purl) = component_purl(component) {
+            writeln!(&mut text, "ExternalRef: PACKAGE-MANAGER purl {}", purl)?;
+        }
+        writeln!(&mut text, "Relationship: SPDXRef-DOCUMENT DESCRIBES {}", id)?;
+    }
+
+    Ok(text)
+}
+
+fn cyclonedx_licenses(component: &LicensedComponent) -> Option<Value> {
+    match component.license() {
+        LicenseFlavor::Spdx(expression) => Some(json!([{ "expression": expression.to_string() }])),
+        LicenseFlavor::OtherExpression(expression) => {
+            Some(json!([{ "license": { "name": expression.to_string() } }]))
+        }
+        LicenseFlavor::PublicDomain => Some(json!([{ "license": { "name": "Public Domain" } }])),
+        LicenseFlavor::Unknown(terms) => Some(Value::Array(
+            terms
+                .iter()
+                .map(|term| json!({ "license": { "name": term } }))
+                .collect(),
+        )),
+        LicenseFlavor::None => None,
+    }
+}
+
+/// Produce a CycloneDX 1.3 JSON document describing components.
+pub fn cyclonedx_json_document(
+    components: &LicensedComponents,
+    metadata: &SbomMetadata,
+) -> Result<String> {
+    let components = component_ids(components)
+        .into_iter()
+        .map(|(id, component)| {
+            let mut value = json!({
+                "type": "library",
+                "bom-ref": id,
+                "name": component.name(),
+            });
+
+            let object = value.as_object_mut().unwrap();
+
+            if let Some(version) = component.version() {
+                object.insert("version".to_string(), json!(version));
+            }
+            if let Some(licenses) = cyclonedx_licenses(component) {
+                object.insert("licenses".to_string(), licenses);
+            }
+            if let Some(purl) = component_purl(component) {
+                object.insert("purl".to_string(), json!(purl));
+            }
+            if let SourceLocation::Url(url) = component.source_location() {
+                object.insert(
+                    "externalReferences".to_string(),
+                    json!([{ "type": "distribution", "url": url }]),
+                );
+            }
+
+            value
+        })
+        .collect::<Vec<_>>();
+
+    let mut document = json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.3",
+        "version": 1,
+        "metadata": {
+            "timestamp": format_timestamp(&metadata.created),
+            "tools": [{
+                "name": metadata.tool_name,
+                "version": metadata.tool_version,
+            }],
+            "component": {
+                "type": "application",
+                "name": metadata.name,
+            },
+        },
+        "components": components,
+    });
+
+    if let Some(uuid) = &metadata.uuid {
+        document.as_object_mut().unwrap().insert(
+            "serialNumber".to_string(),
+            json!(format!("urn:uuid:{}", uuid)),
+        );
+    }
+
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Produce an SBOM document of the specified format describing components.
+pub fn sbom_document(
+    components: &LicensedComponents,
+    metadata: &SbomMetadata,
+    format: SbomFormat,
+) -> Result<String> {
+    match format {
+        SbomFormat::SpdxJson => spdx_json_document(components, metadata),
+        SbomFormat::SpdxTagValue => spdx_tag_value_document(components, metadata),
+        SbomFormat::CycloneDxJson => cyclonedx_json_document(components, metadata),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone};
+
+    fn components() -> Result<LicensedComponents> {
+        let mut components = LicensedComponents::default();
+
+        let mut c = LicensedComponent::new_spdx("python", "Python-2.0")?;
+        c.set_flavor(ComponentFlavor::Library);
+        c.set_version("3.9.1");
+        components.add_component(c);
+
+        let mut c = LicensedComponent::new_spdx("foo_bar", "MIT OR Apache-2.0")?;
+        c.set_flavor(ComponentFlavor::PythonPackage);
+        c.set_version("1.0");
+        c.set_source_location(SourceLocation::Url("https://example.com/foo".to_string()));
+        components.add_component(c);
+
+        let mut c = LicensedComponent::new_unknown("weird", vec!["Custom".to_string()]);
+        c.set_flavor(ComponentFlavor::PythonPackage);
+        components.add_component(c);
+
+        let mut c = LicensedComponent::new_spdx("anyhow", "Apache-2.0 OR MIT")?;
+        c.set_flavor(ComponentFlavor::RustCrate);
+        components.add_component(c);
+
+        Ok(components)
+    }
+
+    fn metadata() -> SbomMetadata {
+        SbomMetadata {
+            name: "myapp".to_string(),
+            namespace: "https://example.com/spdx/myapp".to_string(),
+            uuid: Some("00000000-0000-0000-0000-000000000000".to_string()),
+            created: Utc.ymd(2021, 1, 2).and_hms(3, 4, 5),
+            tool_name: "tool".to_string(),
+            tool_version: "0.1".to_string(),
+        }
+    }
+
+    #[test]
+    fn format_parse() {
+        for format in &[
+            SbomFormat::SpdxJson,
+            SbomFormat::SpdxTagValue,
+            SbomFormat::CycloneDxJson,
+        ] {
+            assert_eq!(
+                SbomFormat::try_from(format.to_string().as_str()),
+                Ok(*format)
+            );
+        }
+        assert!(SbomFormat::try_from("bad").is_err());
+    }
+
+    #[test]
+    fn purl() -> Result<()> {
+        let components = components()?;
+        let purls = components
+            .iter_components()
+            .filter_map(component_purl)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            purls,
+            vec!["pkg:cargo/anyhow", "pkg:pypi/foo-bar@1.0", "pkg:pypi/weird"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn spdx_json() -> Result<()> {
+        let doc: Value = serde_json::from_str(&spdx_json_document(&components()?, &metadata())?)?;
+
+        assert_eq!(doc["spdxVersion"], "SPDX-2.2");
+        assert_eq!(doc["creationInfo"]["created"], "2021-01-02T03:04:05Z");
+        assert_eq!(doc["creationInfo"]["creators"][0], "Tool: tool-0.1");
+
+        let packages = doc["packages"].as_array().unwrap();
+        assert_eq!(packages.len(), 4);
+        assert_eq!(packages[1]["SPDXID"], "SPDXRef-PythonPackage-foo-bar");
+        assert_eq!(packages[1]["versionInfo"], "1.0");
+        assert_eq!(packages[1]["licenseDeclared"], "MIT OR Apache-2.0");
+        assert_eq!(packages[1]["downloadLocation"], "https://example.com/foo");
+        assert_eq!(packages[2]["name"], "python");
+        assert_eq!(packages[2]["versionInfo"], "3.9.1");
+        assert_eq!(packages[3]["licenseDeclared"], "NOASSERTION");
+        assert_eq!(packages[3]["licenseComments"], "license terms: Custom");
+
+        assert_eq!(doc["relationships"].as_array().unwrap().len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn spdx_tag_value() -> Result<()> {
+        let text = spdx_tag_value_document(&components()?, &metadata())?;
+
+        assert!(text.starts_with("SPDXVersion: SPDX-2.2\n"));
+        assert!(text.contains("\nCreated: 2021-01-02T03:04:05Z\n"));
+        assert!(text.contains(
+            "\nPackageName: foo_bar\nSPDXID: SPDXRef-PythonPackage-foo-bar\nPackageVersion: 1.0\n"
+        ));
+        assert!(text.contains("\nExternalRef: PACKAGE-MANAGER purl pkg:pypi/foo-bar@1.0\n"));
+        assert_eq!(text.matches("Relationship: ").count(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn component_ids_unique() -> Result<()> {
+        let mut components = LicensedComponents::default();
+        components.add_component(LicensedComponent::new_none("foo-bar"));
+        components.add_component(LicensedComponent::new_none("foo_bar"));
+
+        let ids = component_ids(&components)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec!["SPDXRef-Generic-foo-bar", "SPDXRef-Generic-foo-bar-2"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn cyclonedx_json() -> Result<()> {
+        let doc: Value =
+            serde_json::from_str(&cyclonedx_json_document(&components()?, &metadata())?)?;
+
+        assert_eq!(doc["bomFormat"], "CycloneDX");
+        assert_eq!(
+            doc["serialNumber"],
+            "urn:uuid:00000000-0000-0000-0000-000000000000"
+        );
+        assert_eq!(doc["metadata"]["component"]["name"], "myapp");
+
+        let components = doc["components"].as_array().unwrap();
+        assert_eq!(components.len(), 4);
+        assert_eq!(
+            components[0]["licenses"][0]["expression"],
+            "Apache-2.0 OR MIT"
+        );
+        assert_eq!(components[1]["purl"], "pkg:pypi/foo-bar@1.0");
+        assert_eq!(
+            components[1]["externalReferences"][0]["url"],
+            "https://example.com/foo"
+        );
+        assert_eq!(components[3]["licenses"][0]["license"]["name"], "Custom");
+
+        Ok(())
+    }
+}
@@ -3,24 +3,61 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
+    crate::{
+        RpmCompression, RpmDependency, RpmDependencyOperator, RpmFileAttributes, RpmScriptlet,
+    },
     anyhow::{anyhow, Context, Result},
-    std::path::{Path, PathBuf},
-    tugger_file_manifest::FileManifest,
+    filetime::FileTime,
+    libflate::gzip::HeaderBuilder,
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+    tugger_file_manifest::{FileEntry, FileManifest},
 };
 
 #[cfg(target_family = "unix")]
-use rpm::{RPMFileOptions, RPMPackage};
+use rpm::{Compressor, Dependency, RPMFileOptions, RPMPackage};
+
+/// File type bits of the mode of a regular file.
+const MODE_REGULAR_FILE: u32 = 0o100_000;
+
+/// File type bits of the mode of a symlink.
+const MODE_SYMLINK: u32 = 0o120_000;
+
+impl From<&RpmDependency> for Dependency {
+    fn from(dependency: &RpmDependency) -> Self {
+        let name = dependency.name.as_str();
+
+        match &dependency.version {
+            None => Dependency::any(name),
+            Some((RpmDependencyOperator::Less, version)) => Dependency::less(name, version),
+            Some((RpmDependencyOperator::LessEqual, version)) => Dependency::less_eq(name, version),
+            Some((RpmDependencyOperator::Equal, version)) => Dependency::eq(name, version),
+            Some((RpmDependencyOperator::GreaterEqual, version)) => {
+                Dependency::greater_eq(name, version)
+            }
+            Some((RpmDependencyOperator::Greater, version)) => Dependency::greater(name, version),
+        }
+    }
+}
 
 /// Create RPMs.
 ///
 /// This is a thin wrapper around rpm::RPMBuilder which provides some
 /// minor quality of life improvements, such as handling of
 /// `FileManifest` instances.
+///
+/// The RPM header doesn't record the build host or build time. Given a fixed
+/// [Self::mtime], the same inputs produce the same RPM.
 pub struct RpmBuilder {
     inner: rpm::RPMBuilder,
 
     build_path: PathBuf,
     files: FileManifest,
+    file_attributes: BTreeMap<PathBuf, RpmFileAttributes>,
+    symlinks: BTreeMap<PathBuf, PathBuf>,
+    mtime: Option<u64>,
 }
 
 impl AsMut<rpm::RPMBuilder> for RpmBuilder {
@@ -45,27 +82,187 @@ impl RpmBuilder {
             inner,
             build_path: build_path.as_ref().to_path_buf(),
             files: FileManifest::default(),
+            file_attributes: BTreeMap::new(),
+            symlinks: BTreeMap::new(),
+            mtime: None,
         }
     }
 
+    /// Set the release number of the package.
+    pub fn release(mut self, release: u16) -> Self {
+        self.inner = self.inner.release(release);
+        self
+    }
+
+    /// Set the epoch of the package.
+    pub fn epoch(mut self, epoch: i32) -> Self {
+        self.inner = self.inner.epoch(epoch);
+        self
+    }
+
+    /// Set the compression format of the payload.
+    ///
+    /// The gzip header doesn't record a modified time.
+    pub fn compression(mut self, compression: RpmCompression) -> Result<Self> {
+        let compressor = match compression {
+            RpmCompression::None => Compressor::None(vec![]),
+            RpmCompression::Gzip => Compressor::Gzip(
+                libflate::gzip::Encoder::with_options(
+                    vec![],
+                    libflate::gzip::EncodeOptions::new()
+                        .header(HeaderBuilder::new().modification_time(0).finish()),
+                )
+                .context("creating gzip compressor")?,
+            ),
+        };
+        self.inner = self.inner.compression(compressor);
+        Ok(self)
+    }
+
+    /// Add a dependency to the `Requires` of the package.
+    pub fn requires(mut self, dependency: &RpmDependency) -> Self {
+        self.inner = self.inner.requires(dependency.into());
+        self
+    }
+
+    /// Add a dependency to the `Provides` of the package.
+    pub fn provides(mut self, dependency: &RpmDependency) -> Self {
+        self.inner = self.inner.provides(dependency.into());
+        self
+    }
+
+    /// Add a dependency to the `Conflicts` of the package.
+    pub fn conflicts(mut self, dependency: &RpmDependency) -> Self {
+        self.inner = self.inner.conflicts(dependency.into());
+        self
+    }
+
+    /// Add a dependency to the `Obsoletes` of the package.
+    pub fn obsoletes(mut self, dependency: &RpmDependency) -> Self {
+        self.inner = self.inner.obsoletes(dependency.into());
+        self
+    }
+
+    /// Set the content of a scriptlet.
+    pub fn scriptlet(mut self, scriptlet: RpmScriptlet, content: &str) -> Self {
+        self.inner = match scriptlet {
+            RpmScriptlet::Pre => self.inner.pre_install_script(content),
+            RpmScriptlet::Post => self.inner.post_install_script(content),
+            RpmScriptlet::PreUn => self.inner.pre_uninstall_script(content),
+            RpmScriptlet::PostUn => self.inner.post_uninstall_script(content),
+        };
+        self
+    }
+
+    /// Add an entry to the changelog.
+    ///
+    /// `time` is in seconds since the UNIX epoch.
+    pub fn changelog_entry(mut self, author: &str, entry: &str, time: i32) -> Self {
+        self.inner = self.inner.add_changelog_entry(author, entry, time);
+        self
+    }
+
+    /// Set the modified time of installed files, in seconds since the UNIX epoch.
+    ///
+    /// If not set, files have the time they were staged in the build path.
+    pub fn mtime(mut self, mtime: u64) -> Result<Self> {
+        if mtime > i32::MAX as u64 {
+            return Err(anyhow!(
+                "modified time {} is not representable in an RPM",
+                mtime
+            ));
+        }
+
+        self.mtime = Some(mtime);
+        Ok(self)
+    }
+
+    /// Stage the content of a file at `path` for the internal RPMBuilder.
+    ///
+    /// `RPMBuilder` reads content and modified times from the filesystem.
+    fn stage_file(&self, path: &Path, data: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+        std::fs::write(path, data).with_context(|| format!("writing {}", path.display()))?;
+
+        if let Some(mtime) = self.mtime {
+            filetime::set_file_mtime(path, FileTime::from_unix_time(mtime as i64, 0))
+                .with_context(|| format!("setting modified time of {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Register a file to be installed by this package.
+    ///
+    /// `path` is relative to the filesystem root. e.g. `usr/bin/myapp`.
+    pub fn install_file(
+        mut self,
+        path: impl AsRef<Path>,
+        entry: impl Into<FileEntry>,
+        attributes: RpmFileAttributes,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+
+        self.files.add_file_entry(path, entry)?;
+        self.file_attributes.insert(path.to_path_buf(), attributes);
+
+        Ok(self)
+    }
+
+    /// Register a symlink to be installed by this package.
+    ///
+    /// `path` is relative to the filesystem root, like with [Self::install_file].
+    /// `target` is written to the symlink verbatim.
+    pub fn install_symlink(mut self, path: impl AsRef<Path>, target: impl AsRef<Path>) -> Self {
+        self.symlinks
+            .insert(path.as_ref().to_path_buf(), target.as_ref().to_path_buf());
+        self
+    }
+
     /// Populate registered files with the internal RPMBuilder.
+    ///
+    /// Files are staged under `files/` of the build path and symlink targets
+    /// under `symlinks/`.
     pub fn populate_files(mut self) -> Result<Self> {
-        self.files
-            .materialize_files(&self.build_path)
-            .context("writing RPM data files")?;
+        let files_path = self.build_path.join("files");
 
         for (rel_path, content) in self.files.iter_entries() {
-            let real_path = self.build_path.join(rel_path);
+            let real_path = files_path.join(rel_path);
+            self.stage_file(
+                &real_path,
+                &content
+                    .data
+                    .resolve()
+                    .with_context(|| format!("resolving {}", rel_path.display()))?,
+            )?;
+
+            let attributes = self
+                .file_attributes
+                .get(rel_path)
+                .cloned()
+                .unwrap_or_default();
 
-            let mut options = RPMFileOptions::new(rel_path.display().to_string());
+            let mut options = RPMFileOptions::new(format!("/{}", rel_path.display()));
 
-            if content.executable {
+            if let Some(mode) = attributes.mode {
+                options = options.mode((MODE_REGULAR_FILE | mode) as i32);
+            } else if content.executable {
                 options = options.mode(0o100_775);
+            } else {
+                options = options.mode(0o100_664);
+            }
+            if let Some(user) = attributes.user {
+                options = options.user(user);
+            }
+            if let Some(group) = attributes.group {
+                options = options.group(group);
+            }
+            if attributes.config {
+                options = options.is_config();
             }
-
-            // TODO support additional attributes, such as owner/group.
-            // TODO make deterministic by modifying upstream to allow control
-            // over what add_data() does.
 
             self.inner = self
                 .inner
@@ -74,6 +271,27 @@ impl RpmBuilder {
                 .context("registering file with RPM")?;
         }
 
+        // The payload content of a symlink is its target. RPMBuilder in
+        // rpm-rs 0.6 can only read payload content from a file, so the target
+        // is staged in one.
+        let symlinks_path = self.build_path.join("symlinks");
+        for (i, (rel_path, target)) in self.symlinks.iter().enumerate() {
+            let target = target.display().to_string();
+
+            let real_path = symlinks_path.join(i.to_string());
+            self.stage_file(&real_path, target.as_bytes())?;
+
+            let options = RPMFileOptions::new(format!("/{}", rel_path.display()))
+                .mode((MODE_SYMLINK | 0o777) as i32)
+                .symlink(target);
+
+            self.inner = self
+                .inner
+                .with_file(&real_path, options)
+                .map_err(|e| anyhow!("error registering symlink with RPMBuilder: {}", e))
+                .context("registering symlink with RPM")?;
+        }
+
         Ok(self)
     }
 
@@ -112,3 +330,139 @@ impl RpmBuilder {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() -> Result<()> {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("tugger-rpm-test-")
+            .tempdir()?;
+
+        let package = RpmBuilder::new(
+            temp_dir.path(),
+            "myapp",
+            "0.1",
+            "MPL-2.0",
+            "x86_64",
+            "My app",
+        )
+        .release(2)
+        .compression(RpmCompression::Gzip)?
+        .requires(&"glibc >= 2.17".parse()?)
+        .provides(&"myapp-bin".parse()?)
+        .scriptlet(RpmScriptlet::Post, "exit 0")
+        .changelog_entry("Me <me@example.com>", "- Initial release", 0)
+        .install_file(
+            "usr/lib/myapp/myapp",
+            FileEntry {
+                data: vec![42].into(),
+                executable: true,
+            },
+            RpmFileAttributes::default(),
+        )?
+        .install_file(
+            "etc/myapp.conf",
+            FileEntry {
+                data: b"key = value\n".to_vec().into(),
+                executable: false,
+            },
+            RpmFileAttributes {
+                mode: Some(0o600),
+                user: Some("myapp".to_string()),
+                config: true,
+                ..RpmFileAttributes::default()
+            },
+        )?
+        .install_symlink("usr/bin/myapp", "/usr/lib/myapp/myapp")
+        .build()?;
+
+        let mut buffer = vec![];
+        package.write(&mut buffer).map_err(|e| anyhow!("{}", e))?;
+
+        let package =
+            RPMPackage::parse(&mut std::io::Cursor::new(buffer)).map_err(|e| anyhow!("{}", e))?;
+        let header = &package.metadata.header;
+        assert_eq!(header.get_name().map_err(|e| anyhow!("{}", e))?, "myapp");
+        assert_eq!(header.get_release().map_err(|e| anyhow!("{}", e))?, "2");
+        assert_eq!(
+            header
+                .get_payload_compressor()
+                .map_err(|e| anyhow!("{}", e))?,
+            "gzip"
+        );
+        assert_eq!(
+            header
+                .get_file_checksums()
+                .map_err(|e| anyhow!("{}", e))?
+                .len(),
+            3
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_deterministic() -> Result<()> {
+        let build = || -> Result<Vec<u8>> {
+            let temp_dir = tempfile::Builder::new()
+                .prefix("tugger-rpm-test-")
+                .tempdir()?;
+
+            let package = RpmBuilder::new(
+                temp_dir.path(),
+                "myapp",
+                "0.1",
+                "MPL-2.0",
+                "x86_64",
+                "My app",
+            )
+            .compression(RpmCompression::Gzip)?
+            .mtime(1000)?
+            .install_file(
+                "usr/lib/myapp/myapp",
+                FileEntry {
+                    data: vec![42].into(),
+                    executable: true,
+                },
+                RpmFileAttributes::default(),
+            )?
+            .install_file(
+                "usr/share/myapp/data",
+                FileEntry {
+                    data: vec![43].into(),
+                    executable: false,
+                },
+                RpmFileAttributes::default(),
+            )?
+            .install_symlink("usr/bin/myapp", "/usr/lib/myapp/myapp")
+            .build()?;
+
+            assert_eq!(
+                std::fs::read(temp_dir.path().join("symlinks").join("0"))?,
+                b"/usr/lib/myapp/myapp"
+            );
+
+            let mut buffer = vec![];
+            package.write(&mut buffer).map_err(|e| anyhow!("{}", e))?;
+
+            Ok(buffer)
+        };
+
+        let first = build()?;
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        let second = build()?;
+
+        assert_eq!(first, second, "setting mtime makes output deterministic");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mtime_range() {
+        let builder = RpmBuilder::new("build", "myapp", "0.1", "MPL-2.0", "x86_64", "My app");
+        assert!(builder.mtime(i32::MAX as u64 + 1).is_err());
+    }
+}
@@ -4,6 +4,10 @@
 
 #[cfg(target_family = "unix")]
 mod builder;
+mod metadata;
 
 #[cfg(target_family = "unix")]
 pub use builder::RpmBuilder;
+pub use metadata::{
+    RpmCompression, RpmDependency, RpmDependencyOperator, RpmFileAttributes, RpmScriptlet,
+};
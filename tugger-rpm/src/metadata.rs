@@ -0,0 +1,190 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Platform independent descriptions of RPM package metadata. */
+
+use {
+    anyhow::{anyhow, Error, Result},
+    std::str::FromStr,
+};
+
+/// Attributes of a file installed by an RPM.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RpmFileAttributes {
+    /// Permission bits of the file, e.g. `0o644`.
+    ///
+    /// If not set, derived from whether the file is executable.
+    pub mode: Option<u32>,
+
+    /// Owner of the file. Defaults to `root`.
+    pub user: Option<String>,
+
+    /// Group of the file. Defaults to `root`.
+    pub group: Option<String>,
+
+    /// Whether the file is a configuration file.
+    ///
+    /// Modifications to configuration files are preserved on upgrade.
+    pub config: bool,
+}
+
+/// Version comparison in a dependency.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RpmDependencyOperator {
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+}
+
+impl RpmDependencyOperator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Equal => "=",
+            Self::GreaterEqual => ">=",
+            Self::Greater => ">",
+        }
+    }
+}
+
+/// A dependency on another package or capability, e.g. `glibc >= 2.17`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RpmDependency {
+    pub name: String,
+
+    /// Constraint on the version. Any version matches if `None`.
+    pub version: Option<(RpmDependencyOperator, String)>,
+}
+
+impl FromStr for RpmDependency {
+    type Err = Error;
+
+    /// Parse an expression consisting of a name, optionally followed by one of
+    /// `<`, `<=`, `=`, `>=`, or `>` and a version.
+    fn from_str(s: &str) -> Result<Self> {
+        let parts = s.split_whitespace().collect::<Vec<_>>();
+
+        let operator = |op: &str| match op {
+            "<" => Some(RpmDependencyOperator::Less),
+            "<=" => Some(RpmDependencyOperator::LessEqual),
+            "=" => Some(RpmDependencyOperator::Equal),
+            ">=" => Some(RpmDependencyOperator::GreaterEqual),
+            ">" => Some(RpmDependencyOperator::Greater),
+            _ => None,
+        };
+
+        match parts.as_slice() {
+            [name] => Ok(Self {
+                name: name.to_string(),
+                version: None,
+            }),
+            [name, op, version] if operator(op).is_some() => Ok(Self {
+                name: name.to_string(),
+                version: Some((operator(op).unwrap(), version.to_string())),
+            }),
+            _ => Err(anyhow!(
+                "invalid dependency {}; expected <name> [<|<=|=|>=|> <version>]",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for RpmDependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some((op, version)) => write!(f, "{} {} {}", self.name, op.as_str(), version),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+/// A script executed when a package is installed or removed.
+///
+/// Named like the corresponding sections in `.spec` files.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum RpmScriptlet {
+    /// `%pre`, executed before installing.
+    Pre,
+    /// `%post`, executed after installing.
+    Post,
+    /// `%preun`, executed before uninstalling.
+    PreUn,
+    /// `%postun`, executed after uninstalling.
+    PostUn,
+}
+
+impl FromStr for RpmScriptlet {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pre" => Ok(Self::Pre),
+            "post" => Ok(Self::Post),
+            "preun" => Ok(Self::PreUn),
+            "postun" => Ok(Self::PostUn),
+            _ => Err(anyhow!(
+                "unknown scriptlet {}; expected one of pre, post, preun, postun",
+                s
+            )),
+        }
+    }
+}
+
+/// Compression format of the payload of an RPM.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RpmCompression {
+    None,
+    Gzip,
+}
+
+impl FromStr for RpmCompression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            _ => Err(anyhow!(
+                "unsupported RPM compression {}; expected none or gzip",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_dependency() -> Result<()> {
+        assert_eq!(
+            "glibc".parse::<RpmDependency>()?,
+            RpmDependency {
+                name: "glibc".to_string(),
+                version: None
+            }
+        );
+        assert_eq!(
+            "glibc >= 2.17".parse::<RpmDependency>()?,
+            RpmDependency {
+                name: "glibc".to_string(),
+                version: Some((RpmDependencyOperator::GreaterEqual, "2.17".to_string()))
+            }
+        );
+        assert_eq!(
+            " glibc  >=  2.17 ".parse::<RpmDependency>()?.to_string(),
+            "glibc >= 2.17"
+        );
+        assert!("glibc => 2.17".parse::<RpmDependency>().is_err());
+        assert!("glibc >=".parse::<RpmDependency>().is_err());
+        assert!("".parse::<RpmDependency>().is_err());
+
+        Ok(())
+    }
+}
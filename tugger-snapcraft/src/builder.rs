@@ -36,6 +36,9 @@ pub struct SnapcraftInvocation {
 ///
 /// When we `build()`, we materialize all the files into a build directory and invoke
 /// `snapcraft` repeatedly until we're complete.
+///
+/// Snaps consisting of prebuilt files can instead be produced without `snapcraft`
+/// via `write_snap()`.
 #[derive(Clone, Debug, PartialEq)]
 pub struct SnapcraftBuilder<'a> {
     pub(crate) snap: Snapcraft<'a>,
@@ -5,6 +5,7 @@
 /*! Functionality for the Snapcraft packaging format. */
 
 mod builder;
+mod native;
 mod yaml;
 
 pub use {
@@ -0,0 +1,549 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Build snaps without invoking `snapcraft`.
+
+A snap is a SquashFS image holding the snap's files and a `meta/snap.yaml`
+describing it. For snaps consisting of prebuilt files, there is no need to
+run `snapcraft`: we derive `snap.yaml` from the `snapcraft.yaml` model and
+write the image directly.
+*/
+
+use {
+    crate::{
+        builder::SnapcraftBuilder,
+        yaml::{Architecture, Confinement, Daemon, Grade, RestartCondition, SnapApp, Type},
+    },
+    anyhow::{anyhow, Context, Result},
+    serde::Serialize,
+    std::{
+        borrow::Cow,
+        collections::{BTreeMap, HashMap},
+        io::{BufWriter, Write},
+        path::Path,
+    },
+    tugger_file_manifest::FileEntry,
+    tugger_squashfs::{SquashfsBuilder, SquashfsCompression},
+};
+
+/// Plugins whose parts can be built natively.
+const NATIVE_PLUGINS: &[&str] = &["dump", "nil"];
+
+/// Represents an `apps.<app-name>` entry in a `meta/snap.yaml`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct SnapYamlApp<'a> {
+    command: Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    autostart: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    command_chain: Vec<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    common_id: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daemon: Option<Daemon>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    environment: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    plugs: Vec<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    slots: Vec<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_command: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    post_stop_command: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_timeout: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timer: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    restart_condition: Option<RestartCondition>,
+    #[serde(flatten)]
+    passthrough: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+}
+
+impl<'a> SnapYamlApp<'a> {
+    fn from_app(name: &str, app: &SnapApp<'a>) -> Result<Self> {
+        if !app.extensions.is_empty() {
+            return Err(anyhow!(
+                "app {} uses extensions, which require snapcraft",
+                name
+            ));
+        }
+        if !app.socket.is_empty() || app.socket_mode.is_some() || app.listen_stream.is_some() {
+            return Err(anyhow!(
+                "app {} defines sockets, which native builds do not support",
+                name
+            ));
+        }
+
+        Ok(Self {
+            command: app
+                .command
+                .clone()
+                .ok_or_else(|| anyhow!("app {} does not define a command", name))?,
+            autostart: app.autostart.clone(),
+            command_chain: app.command_chain.clone(),
+            common_id: app.common_id.clone(),
+            daemon: app.daemon.clone(),
+            environment: app.environment.clone().into_iter().collect(),
+            plugs: app.plugs.clone(),
+            slots: app.slots.clone(),
+            stop_command: app.stop_command.clone(),
+            post_stop_command: app.post_stop_command.clone(),
+            stop_timeout: app.stop_timeout.clone(),
+            timer: app.timer.clone(),
+            restart_condition: app.restart_condition.clone(),
+            passthrough: app.passthrough.clone().into_iter().collect(),
+        })
+    }
+}
+
+/// Represents a `meta/snap.yaml` file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct SnapYaml<'a> {
+    name: Cow<'a, str>,
+    version: Cow<'a, str>,
+    summary: Cow<'a, str>,
+    description: Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base: Option<Cow<'a, str>>,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    snap_type: Option<Type>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confinement: Option<Confinement>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    grade: Option<Grade>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    license: Option<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    architectures: Vec<Architecture>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    assumes: Vec<Cow<'a, str>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    apps: BTreeMap<Cow<'a, str>, SnapYamlApp<'a>>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    plugs: Interfaces<'a>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    slots: Interfaces<'a>,
+    #[serde(flatten)]
+    passthrough: BTreeMap<Cow<'a, str>, Cow<'a, str>>,
+}
+
+/// Resolve the snap architecture of the machine running this code.
+///
+/// Like `snapcraft`, native builds target this architecture if the snap
+/// does not define `architectures`.
+fn host_architecture() -> Result<Architecture> {
+    match std::env::consts::ARCH {
+        "x86_64" => Ok(Architecture::Amd64),
+        "x86" => Ok(Architecture::I386),
+        "aarch64" => Ok(Architecture::Arm64),
+        "arm" => Ok(Architecture::Armhf),
+        "powerpc64" if cfg!(target_endian = "little") => Ok(Architecture::Ppc64el),
+        "s390x" => Ok(Architecture::S390x),
+        arch => Err(anyhow!(
+            "snaps do not support the {} host architecture; define architectures",
+            arch
+        )),
+    }
+}
+
+/// Obtain the name of an architecture as it appears in snap metadata.
+fn architecture_name(arch: &Architecture) -> Result<String> {
+    serde_yaml::to_value(arch)?
+        .as_str()
+        .map(|s| s.to_string())
+        .ok_or_else(|| anyhow!("architecture {:?} does not serialize to a string", arch))
+}
+
+type Interfaces<'a> = BTreeMap<Cow<'a, str>, BTreeMap<Cow<'a, str>, Cow<'a, str>>>;
+
+/// Convert `plugs` or `slots` definitions to a map with a stable order.
+fn sorted_interfaces<'a>(
+    value: &HashMap<Cow<'a, str>, HashMap<Cow<'a, str>, Cow<'a, str>>>,
+) -> Interfaces<'a> {
+    value
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone().into_iter().collect()))
+        .collect()
+}
+
+impl<'a> SnapcraftBuilder<'a> {
+    /// Architectures the snap runs on.
+    ///
+    /// Falls back to the host architecture if the snap does not define any.
+    fn run_on_architectures(&self) -> Result<Vec<Architecture>> {
+        let architectures = match &self.snap.architectures {
+            Some(architectures) if !architectures.run_on.is_empty() => architectures.run_on.clone(),
+            Some(architectures) => architectures.build_on.clone(),
+            None => vec![],
+        };
+
+        if architectures.is_empty() {
+            Ok(vec![host_architecture()?])
+        } else {
+            Ok(architectures)
+        }
+    }
+
+    /// Ensure the snap can be built without `snapcraft`.
+    ///
+    /// Parts must use the `dump` or `nil` plugins and cannot fetch or
+    /// rearrange files: the registered files are the content of the snap.
+    fn validate_native(&self) -> Result<()> {
+        if self.snap.adopt_info.is_some() {
+            return Err(anyhow!("adopt-info requires snapcraft"));
+        }
+
+        let mut parts = self.snap.parts.iter().collect::<Vec<_>>();
+        parts.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (name, part) in parts {
+            let plugin = part.plugin.as_deref().unwrap_or("nil");
+
+            if !NATIVE_PLUGINS.contains(&plugin) {
+                return Err(anyhow!(
+                    "part {} uses the {} plugin; native builds only support {}",
+                    name,
+                    plugin,
+                    NATIVE_PLUGINS.join(", ")
+                ));
+            }
+
+            if part.source.as_deref().unwrap_or(".") != "."
+                || part.source_type.is_some()
+                || !part.stage_packages.is_empty()
+                || !part.stage_snaps.is_empty()
+                || !part.organize.is_empty()
+                || !part.filesets.is_empty()
+                || !part.stage.is_empty()
+                || !part.prime.is_empty()
+                || part.override_build.is_some()
+                || part.override_prime.is_some()
+                || part.override_pull.is_some()
+                || part.override_stage.is_some()
+            {
+                return Err(anyhow!(
+                    "part {} fetches, filters or rearranges files, which requires snapcraft",
+                    name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Obtain the content of the `meta/snap.yaml` file for a native build.
+    pub fn snap_yaml(&self) -> Result<String> {
+        self.validate_native()?;
+
+        let snap = &self.snap;
+
+        let mut apps = BTreeMap::new();
+        for (name, app) in &snap.apps {
+            apps.insert(name.clone(), SnapYamlApp::from_app(name, app)?);
+        }
+
+        let yaml = SnapYaml {
+            name: snap.name.clone(),
+            version: snap.version.clone(),
+            summary: snap.summary.clone(),
+            description: snap.description.clone(),
+            title: snap.title.clone(),
+            base: snap.base.clone(),
+            snap_type: snap.snap_type.clone(),
+            confinement: snap.confinement.clone(),
+            grade: snap.grade.clone(),
+            license: snap.license.clone(),
+            architectures: self.run_on_architectures()?,
+            assumes: snap.assumes.clone(),
+            apps,
+            plugs: sorted_interfaces(&snap.plugs),
+            slots: sorted_interfaces(&snap.slots),
+            passthrough: snap.passthrough.clone().into_iter().collect(),
+        };
+
+        serde_yaml::to_string(&yaml).context("serializing snap.yaml")
+    }
+
+    /// Obtain the filename of the `.snap` file produced by a native build.
+    ///
+    /// This follows `snapcraft`'s `<name>_<version>_<arch>.snap` convention.
+    pub fn snap_filename(&self) -> Result<String> {
+        let architectures = self.run_on_architectures()?;
+
+        let arch = match architectures.as_slice() {
+            [arch] => architecture_name(arch)?,
+            _ => "multi".to_string(),
+        };
+
+        Ok(format!(
+            "{}_{}_{}.snap",
+            self.snap.name, self.snap.version, arch
+        ))
+    }
+
+    /// Obtain a SquashFS builder holding the content of the snap.
+    ///
+    /// The registered files form the root of the snap. `meta/snap.yaml` is
+    /// derived from the `Snapcraft` instance. The icon and app desktop files
+    /// are copied to `meta/gui/`. `mtime` is the modified time of all
+    /// entries, in seconds since the UNIX epoch.
+    pub fn to_squashfs_builder(&self, mtime: u32) -> Result<SquashfsBuilder> {
+        let snap_yaml = self.snap_yaml()?;
+
+        let mut builder = SquashfsBuilder::new(SquashfsCompression::Xz);
+        builder.set_mtime(mtime);
+        builder.add_manifest(&self.install_files)?;
+
+        let mut add_meta = |path: String, entry: FileEntry| -> Result<()> {
+            builder
+                .add_file_entry(&path, entry)
+                .with_context(|| format!("adding {}", path))
+        };
+
+        add_meta(
+            "meta/snap.yaml".to_string(),
+            FileEntry {
+                data: snap_yaml.into_bytes().into(),
+                executable: false,
+            },
+        )?;
+
+        if let Some(icon) = &self.snap.icon {
+            let entry = self
+                .install_files
+                .get(icon.as_ref())
+                .ok_or_else(|| anyhow!("icon {} is not an installed file", icon))?;
+            let extension = Path::new(icon.as_ref())
+                .extension()
+                .and_then(|e| e.to_str())
+                .ok_or_else(|| anyhow!("icon {} has no file extension", icon))?;
+
+            add_meta(format!("meta/gui/icon.{}", extension), entry.clone())?;
+        }
+
+        let mut apps = self.snap.apps.iter().collect::<Vec<_>>();
+        apps.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (name, app) in apps {
+            if let Some(desktop) = &app.desktop {
+                let entry = self.install_files.get(desktop.as_ref()).ok_or_else(|| {
+                    anyhow!(
+                        "desktop file {} of app {} is not an installed file",
+                        desktop,
+                        name
+                    )
+                })?;
+
+                add_meta(format!("meta/gui/{}.desktop", name), entry.clone())?;
+            }
+        }
+
+        Ok(builder)
+    }
+
+    /// Write a `.snap` file without invoking `snapcraft`.
+    pub fn write_snap(&self, dest_path: impl AsRef<Path>, mtime: u32) -> Result<()> {
+        let dest_path = dest_path.as_ref();
+
+        let builder = self.to_squashfs_builder(mtime)?;
+
+        let fh = std::fs::File::create(dest_path)
+            .with_context(|| format!("creating {}", dest_path.display()))?;
+        let mut writer = BufWriter::new(fh);
+        builder
+            .write(&mut writer)
+            .with_context(|| format!("writing {}", dest_path.display()))?;
+        writer
+            .flush()
+            .with_context(|| format!("flushing {}", dest_path.display()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{Architectures, SnapPart, Snapcraft},
+        tugger_common::testutil::*,
+    };
+
+    fn builder() -> Result<SnapcraftBuilder<'static>> {
+        let mut snap = Snapcraft::new(
+            "myapp".into(),
+            "0.1".into(),
+            "summary".into(),
+            "description".into(),
+        );
+        snap.base = Some("core20".into());
+        snap.confinement = Some(Confinement::Strict);
+        snap.grade = Some(Grade::Stable);
+        snap.icon = Some("myapp.png".into());
+        snap.add_part(
+            "myapp".into(),
+            SnapPart {
+                plugin: Some("dump".into()),
+                source: Some(".".into()),
+                ..SnapPart::default()
+            },
+        );
+        snap.add_app(
+            "myapp".into(),
+            SnapApp {
+                command: Some("bin/myapp".into()),
+                plugs: vec!["home".into(), "network".into()],
+                environment: [("LANG".into(), "C.UTF-8".into())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+                desktop: Some("share/myapp.desktop".into()),
+                ..SnapApp::default()
+            },
+        );
+
+        let mut manifest = tugger_file_manifest::FileManifest::default();
+        for (path, executable) in &[
+            ("bin/myapp", true),
+            ("myapp.png", false),
+            ("share/myapp.desktop", false),
+        ] {
+            manifest.add_file_entry(
+                path,
+                FileEntry {
+                    data: path.as_bytes().to_vec().into(),
+                    executable: *executable,
+                },
+            )?;
+        }
+
+        SnapcraftBuilder::new(snap).install_manifest(&manifest)
+    }
+
+    #[test]
+    fn test_host_architecture() -> Result<()> {
+        let arch = host_architecture()?;
+
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(arch, Architecture::Amd64);
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(arch, Architecture::Arm64);
+
+        assert_ne!(arch, Architecture::All);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snap_yaml() -> Result<()> {
+        let builder = builder()?;
+
+        assert_eq!(
+            builder.snap_yaml()?,
+            format!(
+                "---\n\
+            name: myapp\n\
+            version: \"0.1\"\n\
+            summary: summary\n\
+            description: description\n\
+            base: core20\n\
+            confinement: strict\n\
+            grade: stable\n\
+            architectures:\n  \
+              - {}\n\
+            apps:\n  \
+              myapp:\n    \
+                command: bin/myapp\n    \
+                environment:\n      \
+                  LANG: C.UTF-8\n    \
+                plugs:\n      \
+                  - home\n      \
+                  - network\n",
+                architecture_name(&host_architecture()?)?
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_snap_filename() -> Result<()> {
+        let mut builder = builder()?;
+        assert_eq!(
+            builder.snap_filename()?,
+            format!(
+                "myapp_0.1_{}.snap",
+                architecture_name(&host_architecture()?)?
+            )
+        );
+
+        builder.snap.architectures = Some(Architectures {
+            build_on: vec![Architecture::Amd64],
+            run_on: vec![],
+        });
+        assert_eq!(builder.snap_filename()?, "myapp_0.1_amd64.snap");
+        assert!(builder.snap_yaml()?.contains("architectures:\n  - amd64\n"));
+
+        builder.snap.architectures = Some(Architectures {
+            build_on: vec![Architecture::Amd64],
+            run_on: vec![Architecture::Amd64, Architecture::Arm64],
+        });
+        assert_eq!(builder.snap_filename()?, "myapp_0.1_multi.snap");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_native() -> Result<()> {
+        let mut builder = builder()?;
+        builder.snap.add_part(
+            "other".into(),
+            SnapPart {
+                plugin: Some("python".into()),
+                ..SnapPart::default()
+            },
+        );
+        assert!(builder.snap_yaml().is_err());
+
+        let mut builder = self::builder()?;
+        builder.snap.add_part(
+            "other".into(),
+            SnapPart {
+                plugin: Some("dump".into()),
+                stage_packages: vec!["libfoo".into()],
+                ..SnapPart::default()
+            },
+        );
+        assert!(builder.snap_yaml().is_err());
+
+        let mut builder = self::builder()?;
+        builder.snap.add_app("other".into(), SnapApp::default());
+        assert!(builder.snap_yaml().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_snap() -> Result<()> {
+        let builder = builder()?;
+
+        let path = DEFAULT_TEMP_DIR.path().join("test_write_snap.snap");
+        builder.write_snap(&path, 0)?;
+
+        let data = std::fs::read(&path)?;
+        assert_eq!(&data[0..4], b"hsqs");
+
+        let mut missing_icon = builder.clone();
+        missing_icon.snap.icon = Some("missing.png".into());
+        assert!(missing_icon.write_snap(&path, 0).is_err());
+
+        Ok(())
+    }
+}
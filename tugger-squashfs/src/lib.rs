@@ -0,0 +1,16 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Write SquashFS filesystem images.
+
+This crate implements a writer for version 4.0 of the SquashFS format, as
+supported by the Linux kernel and `squashfuse`. Only the subset of the
+format needed to represent regular files, directories, and symlinks is
+implemented: fragments, extended attributes, and NFS export tables are not
+written.
+*/
+
+mod writer;
+
+pub use writer::{SquashfsBuilder, SquashfsCompression};
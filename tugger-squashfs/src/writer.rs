@@ -0,0 +1,1087 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    anyhow::{anyhow, Context, Result},
+    std::{
+        collections::BTreeMap,
+        io::{Seek, SeekFrom, Write},
+        path::{Component, Path},
+    },
+    tugger_file_manifest::{FileEntry, FileManifest},
+};
+
+const SQUASHFS_MAGIC: u32 = 0x7371_7368;
+const SUPERBLOCK_SIZE: usize = 96;
+const METADATA_BLOCK_SIZE: usize = 8192;
+const METADATA_UNCOMPRESSED: u16 = 0x8000;
+const DATA_BLOCK_UNCOMPRESSED: u32 = 1 << 24;
+const INVALID_TABLE: u64 = u64::MAX;
+const INVALID_FRAGMENT: u32 = u32::MAX;
+const INVALID_XATTR: u32 = u32::MAX;
+const DEVICE_BLOCK_SIZE: u64 = 4096;
+const MAX_NAME_LENGTH: usize = 256;
+const MAX_DIRECTORY_HEADER_ENTRIES: usize = 256;
+
+const FLAG_NO_FRAGMENTS: u16 = 0x0010;
+const FLAG_NO_XATTRS: u16 = 0x0200;
+
+const INODE_BASIC_DIRECTORY: u16 = 1;
+const INODE_BASIC_FILE: u16 = 2;
+const INODE_BASIC_SYMLINK: u16 = 3;
+const INODE_EXTENDED_DIRECTORY: u16 = 8;
+const INODE_EXTENDED_FILE: u16 = 9;
+
+/// Compression algorithm used for data and metadata blocks.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SquashfsCompression {
+    /// zlib compression. Supported by every SquashFS implementation.
+    Gzip,
+    /// XZ compression. Produces smaller images at the cost of speed.
+    Xz,
+}
+
+impl SquashfsCompression {
+    fn id(&self) -> u16 {
+        match self {
+            Self::Gzip => 1,
+            Self::Xz => 4,
+        }
+    }
+
+    fn compress(&self, data: &[u8], block_size: u32) -> Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(vec![], flate2::Compression::best());
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+            Self::Xz => {
+                // The kernel decoder sizes its dictionary to the block size
+                // unless compressor options are present. So constrain the
+                // encoder to match.
+                let mut options = xz2::stream::LzmaOptions::new_preset(6)?;
+                options.dict_size(block_size);
+                let mut filters = xz2::stream::Filters::new();
+                filters.lzma2(&options);
+                let stream =
+                    xz2::stream::Stream::new_stream_encoder(&filters, xz2::stream::Check::Crc32)?;
+
+                let mut encoder = xz2::write::XzEncoder::new_stream(vec![], stream);
+                encoder.write_all(data)?;
+                Ok(encoder.finish()?)
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for SquashfsCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(Self::Gzip),
+            "xz" => Ok(Self::Xz),
+            _ => Err(format!(
+                "{} is not a valid squashfs compression; use gzip or xz",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Directory(BTreeMap<String, Node>),
+    File(FileEntry),
+    Symlink(String),
+}
+
+impl Node {
+    /// Number of inodes in this node, including itself.
+    fn inode_count(&self) -> u32 {
+        match self {
+            Self::Directory(children) => {
+                1 + children.values().map(|c| c.inode_count()).sum::<u32>()
+            }
+            Self::File(_) | Self::Symlink(_) => 1,
+        }
+    }
+}
+
+/// Builds a SquashFS filesystem image.
+///
+/// All entries are owned by uid/gid 0. Files have mode 0644, or 0755 if
+/// executable. Directories have mode 0755. Every inode has the same
+/// modification time. Entries are written in sorted order, so the same
+/// input always produces a byte identical image.
+#[derive(Clone, Debug)]
+pub struct SquashfsBuilder {
+    compression: SquashfsCompression,
+    block_log: u16,
+    mtime: u32,
+    root: BTreeMap<String, Node>,
+}
+
+impl SquashfsBuilder {
+    /// Create a new, empty image using the given compression.
+    pub fn new(compression: SquashfsCompression) -> Self {
+        Self {
+            compression,
+            block_log: 17,
+            mtime: 0,
+            root: BTreeMap::new(),
+        }
+    }
+
+    /// The size of data blocks, in bytes.
+    pub fn block_size(&self) -> u32 {
+        1 << self.block_log
+    }
+
+    /// Set the size of data blocks.
+    ///
+    /// Must be a power of 2 between 4 KiB and 1 MiB.
+    pub fn set_block_size(&mut self, size: u32) -> Result<()> {
+        if !size.is_power_of_two() || !(4096..=1024 * 1024).contains(&size) {
+            return Err(anyhow!(
+                "block size must be a power of 2 between 4096 and 1048576; got {}",
+                size
+            ));
+        }
+
+        self.block_log = size.trailing_zeros() as u16;
+
+        Ok(())
+    }
+
+    /// Set the modification time of all inodes, in seconds since UNIX epoch.
+    pub fn set_mtime(&mut self, mtime: u32) {
+        self.mtime = mtime;
+    }
+
+    fn parent_directory(&mut self, components: &[String]) -> Result<&mut BTreeMap<String, Node>> {
+        let mut dir = &mut self.root;
+
+        for component in components {
+            let node = dir
+                .entry(component.clone())
+                .or_insert_with(|| Node::Directory(BTreeMap::new()));
+
+            dir = match node {
+                Node::Directory(children) => children,
+                _ => return Err(anyhow!("{} is not a directory", components.join("/"))),
+            };
+        }
+
+        Ok(dir)
+    }
+
+    fn insert(&mut self, path: &Path, node: Node) -> Result<()> {
+        let mut components = path_components(path)?;
+        let name = components
+            .pop()
+            .ok_or_else(|| anyhow!("cannot add empty path to squashfs image"))?;
+
+        let parent = self.parent_directory(&components)?;
+
+        match (parent.get(&name), &node) {
+            (Some(Node::Directory(_)), Node::Directory(_)) => Ok(()),
+            (Some(_), _) => Err(anyhow!("{} already exists in image", path.display())),
+            (None, _) => {
+                parent.insert(name, node);
+                Ok(())
+            }
+        }
+    }
+
+    /// Add a file at the given path.
+    ///
+    /// Parent directories are created automatically.
+    pub fn add_file_entry(&mut self, path: impl AsRef<Path>, entry: FileEntry) -> Result<()> {
+        self.insert(path.as_ref(), Node::File(entry))
+    }
+
+    /// Add all files from a `FileManifest`, relative to the image root.
+    pub fn add_manifest(&mut self, manifest: &FileManifest) -> Result<()> {
+        for (path, entry) in manifest.iter_entries() {
+            self.add_file_entry(path, entry.clone())?;
+        }
+
+        Ok(())
+    }
+
+    /// Add a symlink at `path` pointing to `target`.
+    pub fn add_symlink(&mut self, path: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<()> {
+        let target = target
+            .as_ref()
+            .to_str()
+            .ok_or_else(|| anyhow!("symlink target is not valid UTF-8"))?
+            .to_string();
+
+        if target.is_empty() {
+            return Err(anyhow!("symlink target cannot be empty"));
+        }
+
+        self.insert(path.as_ref(), Node::Symlink(target))
+    }
+
+    /// Add an empty directory at the given path.
+    pub fn add_directory(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.insert(path.as_ref(), Node::Directory(BTreeMap::new()))
+    }
+
+    /// Write the image to a writer.
+    ///
+    /// The image is written starting at the writer's current position, which
+    /// allows appending images to existing content (such as an AppImage
+    /// runtime). Returns the number of bytes written, including padding.
+    pub fn write<W: Write + Seek>(&self, writer: &mut W) -> Result<u64> {
+        let base = writer.stream_position()?;
+
+        writer.write_all(&[0u8; SUPERBLOCK_SIZE])?;
+
+        let mut image = ImageWriter {
+            writer,
+            position: SUPERBLOCK_SIZE as u64,
+            compression: self.compression,
+            block_size: self.block_size(),
+            mtime: self.mtime,
+            inodes: MetadataWriter::new(self.compression),
+            directories: MetadataWriter::new(self.compression),
+        };
+
+        let inode_count = Node::Directory(self.root.clone()).inode_count();
+        let (root_inode_ref, root_number) =
+            image.write_directory(&self.root, 1, inode_count + 1)?;
+        assert_eq!(root_number, inode_count);
+
+        let inodes = std::mem::replace(&mut image.inodes, MetadataWriter::new(self.compression));
+        let inode_table_start = image.position;
+        image.write_raw(&inodes.finish()?)?;
+
+        let directories = std::mem::replace(
+            &mut image.directories,
+            MetadataWriter::new(self.compression),
+        );
+        let directory_table_start = image.position;
+        image.write_raw(&directories.finish()?)?;
+
+        // Everything is owned by id 0, so the id table has a single entry.
+        let mut ids = MetadataWriter::new(self.compression);
+        ids.write(&0u32.to_le_bytes())?;
+        let id_block_start = image.position;
+        image.write_raw(&ids.finish()?)?;
+
+        let id_table_start = image.position;
+        image.write_raw(&id_block_start.to_le_bytes())?;
+
+        let bytes_used = image.position;
+        let padding = (DEVICE_BLOCK_SIZE - bytes_used % DEVICE_BLOCK_SIZE) % DEVICE_BLOCK_SIZE;
+        image.write_raw(&vec![0u8; padding as usize])?;
+        let total = image.position;
+
+        let mut superblock = Vec::with_capacity(SUPERBLOCK_SIZE);
+        superblock.extend_from_slice(&SQUASHFS_MAGIC.to_le_bytes());
+        superblock.extend_from_slice(&inode_count.to_le_bytes());
+        superblock.extend_from_slice(&self.mtime.to_le_bytes());
+        superblock.extend_from_slice(&self.block_size().to_le_bytes());
+        // Fragment count.
+        superblock.extend_from_slice(&0u32.to_le_bytes());
+        superblock.extend_from_slice(&self.compression.id().to_le_bytes());
+        superblock.extend_from_slice(&self.block_log.to_le_bytes());
+        superblock.extend_from_slice(&(FLAG_NO_FRAGMENTS | FLAG_NO_XATTRS).to_le_bytes());
+        // Id count.
+        superblock.extend_from_slice(&1u16.to_le_bytes());
+        // Version 4.0.
+        superblock.extend_from_slice(&4u16.to_le_bytes());
+        superblock.extend_from_slice(&0u16.to_le_bytes());
+        superblock.extend_from_slice(&root_inode_ref.to_le_bytes());
+        superblock.extend_from_slice(&bytes_used.to_le_bytes());
+        superblock.extend_from_slice(&id_table_start.to_le_bytes());
+        // Extended attributes table.
+        superblock.extend_from_slice(&INVALID_TABLE.to_le_bytes());
+        superblock.extend_from_slice(&inode_table_start.to_le_bytes());
+        superblock.extend_from_slice(&directory_table_start.to_le_bytes());
+        // There is no fragment table. Point it at the id table's metadata
+        // block, like mksquashfs does.
+        superblock.extend_from_slice(&id_block_start.to_le_bytes());
+        // Export table.
+        superblock.extend_from_slice(&INVALID_TABLE.to_le_bytes());
+        assert_eq!(superblock.len(), SUPERBLOCK_SIZE);
+
+        writer.seek(SeekFrom::Start(base))?;
+        writer.write_all(&superblock)?;
+        writer.seek(SeekFrom::Start(base + total))?;
+
+        Ok(total)
+    }
+}
+
+fn path_components(path: &Path) -> Result<Vec<String>> {
+    let mut components = vec![];
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => {
+                let name = name
+                    .to_str()
+                    .ok_or_else(|| anyhow!("{} is not valid UTF-8", path.display()))?;
+
+                if name.len() > MAX_NAME_LENGTH {
+                    return Err(anyhow!(
+                        "{} has a component longer than {} bytes",
+                        path.display(),
+                        MAX_NAME_LENGTH
+                    ));
+                }
+
+                components.push(name.to_string());
+            }
+            Component::RootDir | Component::CurDir => {}
+            _ => {
+                return Err(anyhow!(
+                    "{} is not a valid path in a squashfs image",
+                    path.display()
+                ))
+            }
+        }
+    }
+
+    Ok(components)
+}
+
+/// Accumulates data into compressed metadata blocks.
+struct MetadataWriter {
+    compression: SquashfsCompression,
+    pending: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl MetadataWriter {
+    fn new(compression: SquashfsCompression) -> Self {
+        Self {
+            compression,
+            pending: vec![],
+            output: vec![],
+        }
+    }
+
+    /// Obtain the (block start, offset) where the next write will land.
+    fn position(&self) -> (u32, u16) {
+        (self.output.len() as u32, self.pending.len() as u16)
+    }
+
+    /// Obtain the reference to the next write, as stored in inode references.
+    fn reference(&self) -> u64 {
+        let (block, offset) = self.position();
+
+        ((block as u64) << 16) | offset as u64
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.pending.extend_from_slice(data);
+
+        while self.pending.len() >= METADATA_BLOCK_SIZE {
+            let remaining = self.pending.split_off(METADATA_BLOCK_SIZE);
+            let block = std::mem::replace(&mut self.pending, remaining);
+            self.flush_block(&block)?;
+        }
+
+        Ok(())
+    }
+
+    fn flush_block(&mut self, block: &[u8]) -> Result<()> {
+        // Metadata blocks are always 8 KiB, which is within every valid
+        // dictionary size.
+        let compressed = self
+            .compression
+            .compress(block, METADATA_BLOCK_SIZE as u32)?;
+
+        if compressed.len() < block.len() {
+            self.output
+                .extend_from_slice(&(compressed.len() as u16).to_le_bytes());
+            self.output.extend_from_slice(&compressed);
+        } else {
+            self.output
+                .extend_from_slice(&(block.len() as u16 | METADATA_UNCOMPRESSED).to_le_bytes());
+            self.output.extend_from_slice(block);
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>> {
+        if !self.pending.is_empty() {
+            let block = std::mem::take(&mut self.pending);
+            self.flush_block(&block)?;
+        }
+
+        Ok(self.output)
+    }
+}
+
+struct DirectoryEntry<'a> {
+    name: &'a str,
+    inode_ref: u64,
+    inode_number: u32,
+    inode_type: u16,
+}
+
+struct ImageWriter<'a, W: Write> {
+    writer: &'a mut W,
+    /// Position relative to the start of the image.
+    position: u64,
+    compression: SquashfsCompression,
+    block_size: u32,
+    mtime: u32,
+    inodes: MetadataWriter,
+    directories: MetadataWriter,
+}
+
+impl<'a, W: Write> ImageWriter<'a, W> {
+    fn write_raw(&mut self, data: &[u8]) -> Result<()> {
+        self.writer.write_all(data)?;
+        self.position += data.len() as u64;
+
+        Ok(())
+    }
+
+    fn inode_header(&self, inode_type: u16, mode: u16, inode_number: u32) -> Vec<u8> {
+        let mut header = Vec::with_capacity(16);
+        header.extend_from_slice(&inode_type.to_le_bytes());
+        header.extend_from_slice(&mode.to_le_bytes());
+        // uid and gid indices into the id table.
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&self.mtime.to_le_bytes());
+        header.extend_from_slice(&inode_number.to_le_bytes());
+
+        header
+    }
+
+    fn write_file(&mut self, path: &str, entry: &FileEntry, inode_number: u32) -> Result<u64> {
+        let data = entry
+            .data
+            .resolve()
+            .with_context(|| format!("resolving data for {}", path))?;
+
+        let blocks_start = self.position;
+        let mut block_sizes = vec![];
+
+        for chunk in data.chunks(self.block_size as usize) {
+            let compressed = self.compression.compress(chunk, self.block_size)?;
+
+            if compressed.len() < chunk.len() {
+                block_sizes.push(compressed.len() as u32);
+                self.write_raw(&compressed)?;
+            } else {
+                block_sizes.push(chunk.len() as u32 | DATA_BLOCK_UNCOMPRESSED);
+                self.write_raw(chunk)?;
+            }
+        }
+
+        let mode = if entry.executable { 0o755 } else { 0o644 };
+        let inode_ref = self.inodes.reference();
+
+        let mut inode = if blocks_start <= u32::MAX as u64 && data.len() <= u32::MAX as usize {
+            let mut inode = self.inode_header(INODE_BASIC_FILE, mode, inode_number);
+            inode.extend_from_slice(&(blocks_start as u32).to_le_bytes());
+            inode.extend_from_slice(&INVALID_FRAGMENT.to_le_bytes());
+            inode.extend_from_slice(&0u32.to_le_bytes());
+            inode.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            inode
+        } else {
+            let mut inode = self.inode_header(INODE_EXTENDED_FILE, mode, inode_number);
+            inode.extend_from_slice(&blocks_start.to_le_bytes());
+            inode.extend_from_slice(&(data.len() as u64).to_le_bytes());
+            // Sparse bytes.
+            inode.extend_from_slice(&0u64.to_le_bytes());
+            // Link count.
+            inode.extend_from_slice(&1u32.to_le_bytes());
+            inode.extend_from_slice(&INVALID_FRAGMENT.to_le_bytes());
+            inode.extend_from_slice(&0u32.to_le_bytes());
+            inode.extend_from_slice(&INVALID_XATTR.to_le_bytes());
+            inode
+        };
+
+        for size in block_sizes {
+            inode.extend_from_slice(&size.to_le_bytes());
+        }
+
+        self.inodes.write(&inode)?;
+
+        Ok(inode_ref)
+    }
+
+    fn write_symlink(&mut self, target: &str, inode_number: u32) -> Result<u64> {
+        let inode_ref = self.inodes.reference();
+
+        let mut inode = self.inode_header(INODE_BASIC_SYMLINK, 0o777, inode_number);
+        // Link count.
+        inode.extend_from_slice(&1u32.to_le_bytes());
+        inode.extend_from_slice(&(target.len() as u32).to_le_bytes());
+        inode.extend_from_slice(target.as_bytes());
+
+        self.inodes.write(&inode)?;
+
+        Ok(inode_ref)
+    }
+
+    /// Write a directory and all its descendants.
+    ///
+    /// Inodes are numbered in post-order starting at `first_number`, so
+    /// children are written before their parent and the directory receives
+    /// the highest number in its subtree. Returns the directory's inode
+    /// reference and number.
+    fn write_directory(
+        &mut self,
+        children: &BTreeMap<String, Node>,
+        first_number: u32,
+        parent_number: u32,
+    ) -> Result<(u64, u32)> {
+        let own_number = first_number + children.values().map(|c| c.inode_count()).sum::<u32>();
+
+        let mut next_number = first_number;
+        let mut entries = Vec::with_capacity(children.len());
+        let mut subdirectories = 0;
+
+        for (name, child) in children {
+            let (inode_ref, inode_number, inode_type) = match child {
+                Node::Directory(grandchildren) => {
+                    let (inode_ref, number) =
+                        self.write_directory(grandchildren, next_number, own_number)?;
+                    subdirectories += 1;
+                    (inode_ref, number, INODE_BASIC_DIRECTORY)
+                }
+                Node::File(entry) => (
+                    self.write_file(name, entry, next_number)?,
+                    next_number,
+                    INODE_BASIC_FILE,
+                ),
+                Node::Symlink(target) => (
+                    self.write_symlink(target, next_number)?,
+                    next_number,
+                    INODE_BASIC_SYMLINK,
+                ),
+            };
+
+            next_number = inode_number + 1;
+
+            entries.push(DirectoryEntry {
+                name,
+                inode_ref,
+                inode_number,
+                inode_type,
+            });
+        }
+
+        assert_eq!(next_number, own_number);
+
+        let (listing_block, listing_offset) = self.directories.position();
+        let listing = directory_listing(&entries);
+        self.directories.write(&listing)?;
+
+        // The listing size accounts for the implicit `.` and `..` entries.
+        let listing_size = listing.len() as u32 + 3;
+        let link_count = 2 + subdirectories;
+
+        let inode_ref = self.inodes.reference();
+
+        let inode = if listing_size <= u16::MAX as u32 {
+            let mut inode = self.inode_header(INODE_BASIC_DIRECTORY, 0o755, own_number);
+            inode.extend_from_slice(&listing_block.to_le_bytes());
+            inode.extend_from_slice(&(link_count as u32).to_le_bytes());
+            inode.extend_from_slice(&(listing_size as u16).to_le_bytes());
+            inode.extend_from_slice(&listing_offset.to_le_bytes());
+            inode.extend_from_slice(&parent_number.to_le_bytes());
+            inode
+        } else {
+            let mut inode = self.inode_header(INODE_EXTENDED_DIRECTORY, 0o755, own_number);
+            inode.extend_from_slice(&(link_count as u32).to_le_bytes());
+            inode.extend_from_slice(&listing_size.to_le_bytes());
+            inode.extend_from_slice(&listing_block.to_le_bytes());
+            inode.extend_from_slice(&parent_number.to_le_bytes());
+            // Directory index count.
+            inode.extend_from_slice(&0u16.to_le_bytes());
+            inode.extend_from_slice(&listing_offset.to_le_bytes());
+            inode.extend_from_slice(&INVALID_XATTR.to_le_bytes());
+            inode
+        };
+
+        self.inodes.write(&inode)?;
+
+        Ok((inode_ref, own_number))
+    }
+}
+
+/// Serialize directory entries into headers and entry records.
+///
+/// A header groups up to 256 entries whose inodes live in the same metadata
+/// block and whose inode numbers are within an `i16` of the header's.
+fn directory_listing(entries: &[DirectoryEntry]) -> Vec<u8> {
+    let mut listing = vec![];
+    let mut start = 0;
+
+    while start < entries.len() {
+        let block = (entries[start].inode_ref >> 16) as u32;
+        let base_number = entries[start].inode_number;
+
+        let mut end = start;
+        while end < entries.len()
+            && end - start < MAX_DIRECTORY_HEADER_ENTRIES
+            && (entries[end].inode_ref >> 16) as u32 == block
+            && (entries[end].inode_number as i64 - base_number as i64).abs() <= i16::MAX as i64
+        {
+            end += 1;
+        }
+
+        listing.extend_from_slice(&((end - start - 1) as u32).to_le_bytes());
+        listing.extend_from_slice(&block.to_le_bytes());
+        listing.extend_from_slice(&base_number.to_le_bytes());
+
+        for entry in &entries[start..end] {
+            listing.extend_from_slice(&((entry.inode_ref & 0xffff) as u16).to_le_bytes());
+            listing.extend_from_slice(
+                &((entry.inode_number as i64 - base_number as i64) as i16).to_le_bytes(),
+            );
+            listing.extend_from_slice(&entry.inode_type.to_le_bytes());
+            listing.extend_from_slice(&((entry.name.len() - 1) as u16).to_le_bytes());
+            listing.extend_from_slice(entry.name.as_bytes());
+        }
+
+        start = end;
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{convert::TryInto, io::Cursor, io::Read},
+    };
+
+    fn u16_at(data: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(data[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(data: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// A minimal reader used to verify images round trip.
+    struct Reader {
+        image: Vec<u8>,
+        compression: u16,
+        inodes: Vec<u8>,
+        /// Maps metadata block offsets to offsets in `inodes`.
+        inode_blocks: BTreeMap<u32, usize>,
+        directories: Vec<u8>,
+        directory_blocks: BTreeMap<u32, usize>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Entry {
+        File(Vec<u8>, u16),
+        Symlink(String),
+        Directory,
+    }
+
+    fn decompress(compression: u16, data: &[u8]) -> Vec<u8> {
+        let mut res = vec![];
+        match compression {
+            1 => {
+                flate2::read::ZlibDecoder::new(data)
+                    .read_to_end(&mut res)
+                    .unwrap();
+            }
+            4 => {
+                xz2::read::XzDecoder::new(data)
+                    .read_to_end(&mut res)
+                    .unwrap();
+            }
+            _ => panic!("unknown compression"),
+        }
+        res
+    }
+
+    impl Reader {
+        fn new(image: Vec<u8>) -> Self {
+            assert_eq!(u32_at(&image, 0), SQUASHFS_MAGIC);
+            assert_eq!(u16_at(&image, 28), 4);
+            let compression = u16_at(&image, 20);
+            let block_size = u32_at(&image, 12);
+            assert_eq!(1 << u16_at(&image, 22), block_size);
+
+            let inode_table = u64_at(&image, 64) as usize;
+            let directory_table = u64_at(&image, 72) as usize;
+            let id_table = u64_at(&image, 48) as usize;
+            let id_block = u64_at(&image, id_table) as usize;
+
+            let (inodes, inode_blocks) =
+                Self::read_metadata(&image, compression, inode_table, directory_table);
+            let (directories, directory_blocks) =
+                Self::read_metadata(&image, compression, directory_table, id_block);
+
+            let (ids, _) = Self::read_metadata(&image, compression, id_block, id_table);
+            assert_eq!(ids, vec![0, 0, 0, 0]);
+            assert_eq!(u64_at(&image, 40) as usize, id_table + 8);
+            assert_eq!(image.len() % 4096, 0);
+
+            Self {
+                image,
+                compression,
+                inodes,
+                inode_blocks,
+                directories,
+                directory_blocks,
+            }
+        }
+
+        fn read_metadata(
+            image: &[u8],
+            compression: u16,
+            start: usize,
+            end: usize,
+        ) -> (Vec<u8>, BTreeMap<u32, usize>) {
+            let mut res = vec![];
+            let mut blocks = BTreeMap::new();
+            let mut pos = start;
+
+            while pos < end {
+                blocks.insert((pos - start) as u32, res.len());
+                let header = u16_at(image, pos);
+                let size = (header & !METADATA_UNCOMPRESSED) as usize;
+                let data = &image[pos + 2..pos + 2 + size];
+                if header & METADATA_UNCOMPRESSED != 0 {
+                    res.extend_from_slice(data);
+                } else {
+                    let block = decompress(compression, data);
+                    assert!(block.len() <= METADATA_BLOCK_SIZE);
+                    res.extend(block);
+                }
+                pos += 2 + size;
+            }
+
+            assert_eq!(pos, end);
+            (res, blocks)
+        }
+
+        fn inode_offset(&self, inode_ref: u64) -> usize {
+            self.inode_blocks[&((inode_ref >> 16) as u32)] + (inode_ref & 0xffff) as usize
+        }
+
+        fn walk(&self, inode_ref: u64, prefix: &str, out: &mut BTreeMap<String, Entry>) {
+            let offset = self.inode_offset(inode_ref);
+            let inode_type = u16_at(&self.inodes, offset);
+            assert_eq!(inode_type, INODE_BASIC_DIRECTORY);
+
+            let block = u32_at(&self.inodes, offset + 16);
+            let size = u16_at(&self.inodes, offset + 24) as usize - 3;
+            let block_offset = u16_at(&self.inodes, offset + 26) as usize;
+
+            if size == 0 {
+                return;
+            }
+
+            let start = self.directory_blocks[&block] + block_offset;
+            let listing = &self.directories[start..start + size];
+
+            let mut pos = 0;
+            while pos < listing.len() {
+                let count = u32_at(listing, pos) as usize + 1;
+                let entries_block = u32_at(listing, pos + 4) as u64;
+                pos += 12;
+
+                for _ in 0..count {
+                    let entry_offset = u16_at(listing, pos) as u64;
+                    let name_size = u16_at(listing, pos + 6) as usize + 1;
+                    let name =
+                        String::from_utf8(listing[pos + 8..pos + 8 + name_size].to_vec()).unwrap();
+                    pos += 8 + name_size;
+
+                    let path = format!("{}{}", prefix, name);
+                    let child_ref = (entries_block << 16) | entry_offset;
+                    let child = self.inode_offset(child_ref);
+
+                    match u16_at(&self.inodes, child) {
+                        INODE_BASIC_DIRECTORY => {
+                            out.insert(path.clone(), Entry::Directory);
+                            self.walk(child_ref, &format!("{}/", path), out);
+                        }
+                        INODE_BASIC_FILE => {
+                            let mode = u16_at(&self.inodes, child + 2);
+                            let mut data_pos = u32_at(&self.inodes, child + 16) as usize;
+                            let file_size = u32_at(&self.inodes, child + 28) as usize;
+
+                            let mut data = vec![];
+                            let mut size_pos = child + 32;
+                            while data.len() < file_size {
+                                let size = u32_at(&self.inodes, size_pos);
+                                size_pos += 4;
+                                let len = (size & !DATA_BLOCK_UNCOMPRESSED) as usize;
+                                let raw = &self.image[data_pos..data_pos + len];
+                                if size & DATA_BLOCK_UNCOMPRESSED != 0 {
+                                    data.extend_from_slice(raw);
+                                } else {
+                                    data.extend(decompress(self.compression, raw));
+                                }
+                                data_pos += len;
+                            }
+                            assert_eq!(data.len(), file_size);
+
+                            out.insert(path, Entry::File(data, mode));
+                        }
+                        INODE_BASIC_SYMLINK => {
+                            let size = u32_at(&self.inodes, child + 20) as usize;
+                            let target = String::from_utf8(
+                                self.inodes[child + 24..child + 24 + size].to_vec(),
+                            )
+                            .unwrap();
+                            out.insert(path, Entry::Symlink(target));
+                        }
+                        t => panic!("unexpected inode type {}", t),
+                    }
+                }
+            }
+        }
+
+        fn entries(&self) -> BTreeMap<String, Entry> {
+            let mut res = BTreeMap::new();
+            self.walk(u64_at(&self.image, 32), "", &mut res);
+            res
+        }
+    }
+
+    fn write_image(builder: &SquashfsBuilder) -> Result<Vec<u8>> {
+        let mut cursor = Cursor::new(vec![]);
+        let size = builder.write(&mut cursor)?;
+        let data = cursor.into_inner();
+        assert_eq!(size as usize, data.len());
+
+        Ok(data)
+    }
+
+    #[test]
+    fn test_empty() -> Result<()> {
+        let builder = SquashfsBuilder::new(SquashfsCompression::Gzip);
+        let image = write_image(&builder)?;
+
+        assert_eq!(u32_at(&image, 4), 1);
+        assert!(Reader::new(image).entries().is_empty());
+
+        Ok(())
+    }
+
+    /// Data spanning multiple 4096 byte blocks that doesn't compress well.
+    fn large_data() -> Vec<u8> {
+        (0..20000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect::<Vec<_>>()
+    }
+
+    /// A builder with files, a symlink and an empty directory.
+    fn sample_builder(compression: SquashfsCompression) -> Result<SquashfsBuilder> {
+        let mut builder = SquashfsBuilder::new(compression);
+        builder.set_block_size(4096)?;
+        builder.set_mtime(42);
+
+        let mut manifest = FileManifest::default();
+        manifest.add_file_entry(
+            "bin/app",
+            FileEntry {
+                data: vec![42; 10000].into(),
+                executable: true,
+            },
+        )?;
+        manifest.add_file_entry(
+            "lib/data.bin",
+            FileEntry {
+                data: large_data().into(),
+                executable: false,
+            },
+        )?;
+        manifest.add_file_entry(
+            "empty",
+            FileEntry {
+                data: vec![].into(),
+                executable: false,
+            },
+        )?;
+        builder.add_manifest(&manifest)?;
+        builder.add_symlink("AppRun", "bin/app")?;
+        builder.add_directory("share/empty")?;
+
+        Ok(builder)
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        for compression in &[SquashfsCompression::Gzip, SquashfsCompression::Xz] {
+            let builder = sample_builder(*compression)?;
+
+            let image = write_image(&builder)?;
+            assert_eq!(u32_at(&image, 4), 9);
+            assert_eq!(u32_at(&image, 8), 42);
+
+            let entries = Reader::new(image).entries();
+            assert_eq!(
+                entries.keys().collect::<Vec<_>>(),
+                vec![
+                    "AppRun",
+                    "bin",
+                    "bin/app",
+                    "empty",
+                    "lib",
+                    "lib/data.bin",
+                    "share",
+                    "share/empty"
+                ]
+            );
+            assert_eq!(entries["AppRun"], Entry::Symlink("bin/app".to_string()));
+            assert_eq!(entries["bin/app"], Entry::File(vec![42; 10000], 0o755));
+            assert_eq!(entries["lib/data.bin"], Entry::File(large_data(), 0o644));
+            assert_eq!(entries["empty"], Entry::File(vec![], 0o644));
+        }
+
+        Ok(())
+    }
+
+    /// Verify images can be extracted by `unsquashfs` from squashfs-tools.
+    ///
+    /// This requires `unsquashfs` to be installed. Run it via
+    /// `cargo test -- --ignored`.
+    #[cfg(unix)]
+    #[test]
+    #[ignore = "requires unsquashfs from squashfs-tools"]
+    fn test_unsquashfs() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::Builder::new()
+            .prefix("tugger-squashfs-test-")
+            .tempdir()?;
+
+        for compression in &[SquashfsCompression::Gzip, SquashfsCompression::Xz] {
+            let image_path = temp_dir.path().join(format!("{:?}.squashfs", compression));
+            let dest_path = temp_dir.path().join(format!("{:?}", compression));
+
+            std::fs::write(&image_path, write_image(&sample_builder(*compression)?)?)?;
+
+            let output = std::process::Command::new("unsquashfs")
+                .arg("-no-progress")
+                .arg("-d")
+                .arg(&dest_path)
+                .arg(&image_path)
+                .output()
+                .context("running unsquashfs")?;
+
+            assert!(
+                output.status.success(),
+                "unsquashfs failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+
+            let mode = |path: &str| -> Result<u32> {
+                Ok(std::fs::symlink_metadata(dest_path.join(path))?
+                    .permissions()
+                    .mode()
+                    & 0o7777)
+            };
+
+            assert_eq!(std::fs::read(dest_path.join("bin/app"))?, vec![42; 10000]);
+            assert_eq!(mode("bin/app")?, 0o755);
+            assert_eq!(std::fs::read(dest_path.join("lib/data.bin"))?, large_data());
+            assert_eq!(mode("lib/data.bin")?, 0o644);
+            assert!(std::fs::read(dest_path.join("empty"))?.is_empty());
+            assert_eq!(
+                std::fs::read_link(dest_path.join("AppRun"))?,
+                Path::new("bin/app")
+            );
+            assert!(dest_path.join("share/empty").is_dir());
+            assert_eq!(std::fs::read_dir(dest_path.join("share/empty"))?.count(), 0);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_many_entries() -> Result<()> {
+        // Exercises multiple metadata blocks and directory headers.
+        let mut builder = SquashfsBuilder::new(SquashfsCompression::Gzip);
+        for i in 0..1000 {
+            builder.add_file_entry(
+                format!("dir/file-{:04}", i),
+                FileEntry {
+                    data: format!("{}", i).into_bytes().into(),
+                    executable: false,
+                },
+            )?;
+        }
+
+        let entries = Reader::new(write_image(&builder)?).entries();
+        assert_eq!(entries.len(), 1001);
+        assert_eq!(
+            entries["dir/file-0999"],
+            Entry::File(b"999".to_vec(), 0o644)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deterministic() -> Result<()> {
+        let mut builder = SquashfsBuilder::new(SquashfsCompression::Xz);
+        builder.add_file_entry(
+            "a/b",
+            FileEntry {
+                data: b"hello".to_vec().into(),
+                executable: false,
+            },
+        )?;
+
+        assert_eq!(write_image(&builder)?, write_image(&builder)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid() -> Result<()> {
+        let mut builder = SquashfsBuilder::new(SquashfsCompression::Gzip);
+        let entry = FileEntry {
+            data: b"hello".to_vec().into(),
+            executable: false,
+        };
+
+        builder.add_file_entry("a", entry.clone())?;
+        assert!(builder.add_file_entry("a", entry.clone()).is_err());
+        assert!(builder.add_file_entry("a/b", entry.clone()).is_err());
+        assert!(builder.add_file_entry("../b", entry).is_err());
+        assert!(builder.set_block_size(1000).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_append() -> Result<()> {
+        let builder = SquashfsBuilder::new(SquashfsCompression::Gzip);
+
+        let mut cursor = Cursor::new(b"prefix".to_vec());
+        cursor.seek(SeekFrom::End(0))?;
+        let size = builder.write(&mut cursor)?;
+        let data = cursor.into_inner();
+
+        assert_eq!(&data[0..6], b"prefix");
+        assert_eq!(data.len() as u64, 6 + size);
+        assert_eq!(u32_at(&data, 6), SQUASHFS_MAGIC);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,419 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Produce AppImage application bundles.
+
+See <https://docs.appimage.org/reference/appdir.html> for the AppDir layout
+and <https://github.com/AppImage/AppImageSpec> for the file format.
+
+An AppImage is an ELF runtime executable followed by a SquashFS image of an
+AppDir. The runtime is not built here: callers provide a prebuilt one (e.g.
+`runtime-x86_64` from the AppImageKit project) as a local file.
+*/
+
+use {
+    anyhow::{anyhow, Context, Result},
+    std::{
+        collections::BTreeMap,
+        convert::TryFrom,
+        io::Write,
+        path::{Path, PathBuf},
+    },
+    tugger_file_manifest::{FileEntry, FileManifest},
+    tugger_squashfs::{SquashfsBuilder, SquashfsCompression},
+};
+
+/// Extensions of icon files supported by the AppDir specification.
+const ICON_EXTENSIONS: &[&str] = &["png", "svg", "xpm"];
+
+/// Builds AppImage files.
+#[derive(Clone, Debug)]
+pub struct AppImageBuilder {
+    name: String,
+    files: FileManifest,
+    exec: Option<PathBuf>,
+    icon: Option<(String, FileEntry)>,
+    categories: Vec<String>,
+    desktop_entries: BTreeMap<String, String>,
+    minimum_glibc_version: Option<String>,
+    compression: SquashfsCompression,
+    mtime: u32,
+}
+
+impl AppImageBuilder {
+    /// Create a new instance for an application.
+    ///
+    /// `name` is used as the application's display name and as the base name
+    /// of the `.desktop` file and icon in the AppDir.
+    pub fn new(name: impl ToString) -> Result<Self> {
+        let name = name.to_string();
+
+        if name.is_empty() || name.contains('/') {
+            return Err(anyhow!("{:?} is not a valid AppImage name", name));
+        }
+
+        Ok(Self {
+            name,
+            files: FileManifest::default(),
+            exec: None,
+            icon: None,
+            categories: vec!["Utility".to_string()],
+            desktop_entries: BTreeMap::new(),
+            minimum_glibc_version: None,
+            compression: SquashfsCompression::Gzip,
+            mtime: 0,
+        })
+    }
+
+    /// The name of the application.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Add files from a manifest to the AppDir.
+    pub fn add_manifest(mut self, manifest: &FileManifest) -> Result<Self> {
+        self.files.add_manifest(manifest)?;
+
+        Ok(self)
+    }
+
+    /// Set the path of the executable, relative to the AppDir, that `AppRun` runs.
+    pub fn exec(mut self, path: impl AsRef<Path>) -> Self {
+        self.exec = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the icon of the application from a local file.
+    ///
+    /// The file must be a PNG, SVG, or XPM image.
+    pub fn icon_path(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .filter(|e| ICON_EXTENSIONS.contains(&e.as_str()))
+            .ok_or_else(|| {
+                anyhow!(
+                    "icon {} must have one of the extensions: {}",
+                    path.display(),
+                    ICON_EXTENSIONS.join(", ")
+                )
+            })?;
+
+        let entry = FileEntry {
+            data: std::fs::read(path)
+                .with_context(|| format!("reading {}", path.display()))?
+                .into(),
+            executable: false,
+        };
+
+        self.icon = Some((extension, entry));
+
+        Ok(self)
+    }
+
+    /// Set the categories of the application in the `.desktop` file.
+    pub fn categories(mut self, categories: Vec<String>) -> Self {
+        self.categories = categories;
+        self
+    }
+
+    /// Set an additional key in the `[Desktop Entry]` section of the `.desktop` file.
+    pub fn desktop_entry(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.desktop_entries
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Set the oldest glibc version the AppImage should run on.
+    ///
+    /// Binaries requiring a newer version are reported by `glibc_warnings()`.
+    pub fn minimum_glibc_version(mut self, version: impl ToString) -> Result<Self> {
+        let version = version.to_string();
+
+        if !version.starts_with(|c: char| c.is_ascii_digit())
+            || version_compare::Version::from(&version).is_none()
+        {
+            return Err(anyhow!("{} is not a valid glibc version", version));
+        }
+
+        self.minimum_glibc_version = Some(version);
+
+        Ok(self)
+    }
+
+    /// Set the compression of the SquashFS image.
+    ///
+    /// The runtime must support the chosen compression.
+    pub fn compression(mut self, compression: SquashfsCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set the modified time of AppDir entries, in seconds since the UNIX epoch.
+    pub fn mtime(mut self, mtime: u64) -> Result<Self> {
+        self.mtime = u32::try_from(mtime)
+            .map_err(|_| anyhow!("mtime {} is out of range for SquashFS", mtime))?;
+
+        Ok(self)
+    }
+
+    /// Obtain the content of the `.desktop` file.
+    pub fn desktop_file(&self) -> Result<String> {
+        let exec = self
+            .exec
+            .as_ref()
+            .ok_or_else(|| anyhow!("AppImage executable not defined"))?;
+        let exec = exec
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("{} is not a valid executable path", exec.display()))?;
+
+        let mut entries = BTreeMap::new();
+        entries.insert("Type".to_string(), "Application".to_string());
+        entries.insert("Name".to_string(), self.name.clone());
+        entries.insert("Exec".to_string(), exec.to_string());
+        entries.insert("Icon".to_string(), self.name.clone());
+        if !self.categories.is_empty() {
+            entries.insert(
+                "Categories".to_string(),
+                format!("{};", self.categories.join(";")),
+            );
+        }
+        entries.extend(self.desktop_entries.clone());
+
+        let mut res = "[Desktop Entry]\n".to_string();
+        for (key, value) in entries {
+            res.push_str(&format!("{}={}\n", key, value));
+        }
+
+        Ok(res)
+    }
+
+    /// Obtain a SquashFS builder holding the AppDir.
+    pub fn appdir(&self) -> Result<SquashfsBuilder> {
+        let exec = self
+            .exec
+            .as_ref()
+            .ok_or_else(|| anyhow!("AppImage executable not defined"))?;
+        if !self.files.has_path(exec) {
+            return Err(anyhow!(
+                "AppImage executable {} is not in the AppDir",
+                exec.display()
+            ));
+        }
+
+        let (icon_extension, icon) = self
+            .icon
+            .as_ref()
+            .ok_or_else(|| anyhow!("AppImage icon not defined"))?;
+        let icon_filename = format!("{}.{}", self.name, icon_extension);
+
+        let mut builder = SquashfsBuilder::new(self.compression);
+        builder.set_mtime(self.mtime);
+        builder.add_manifest(&self.files)?;
+        builder.add_symlink("AppRun", exec)?;
+        builder.add_file_entry(
+            format!("{}.desktop", self.name),
+            FileEntry {
+                data: self.desktop_file()?.into_bytes().into(),
+                executable: false,
+            },
+        )?;
+        builder.add_file_entry(&icon_filename, icon.clone())?;
+        builder.add_symlink(".DirIcon", &icon_filename)?;
+
+        Ok(builder)
+    }
+
+    /// Find binaries requiring a newer glibc than the stated minimum.
+    ///
+    /// Returns human readable descriptions of each offending file. Returns
+    /// an empty list if no minimum version is defined.
+    pub fn glibc_warnings(&self) -> Result<Vec<String>> {
+        let minimum = match &self.minimum_glibc_version {
+            Some(version) => version,
+            None => return Ok(vec![]),
+        };
+        let minimum_version = version_compare::Version::from(minimum)
+            .ok_or_else(|| anyhow!("{} is not a valid glibc version", minimum))?;
+
+        let mut res = vec![];
+
+        for (path, entry) in self.files.iter_entries() {
+            let data = entry
+                .data
+                .resolve()
+                .with_context(|| format!("reading {}", path.display()))?;
+
+            if !data.starts_with(b"\x7fELF") {
+                continue;
+            }
+
+            let required = tugger_binary_analysis::find_required_glibc_version(&data)
+                .with_context(|| format!("analyzing {}", path.display()))?;
+
+            if let Some(required) = required {
+                if let Some(version) = version_compare::Version::from(&required) {
+                    if version > minimum_version {
+                        res.push(format!(
+                            "{} requires glibc {}, which is newer than the minimum {}",
+                            path.display(),
+                            required,
+                            minimum
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Write an AppImage using the runtime at `runtime_path`.
+    pub fn write_appimage(&self, runtime_path: &Path, dest_path: &Path) -> Result<()> {
+        let runtime = std::fs::read(runtime_path)
+            .with_context(|| format!("reading runtime {}", runtime_path.display()))?;
+        if !runtime.starts_with(b"\x7fELF") {
+            return Err(anyhow!(
+                "runtime {} is not an ELF executable",
+                runtime_path.display()
+            ));
+        }
+
+        let appdir = self.appdir()?;
+
+        let mut fh = std::fs::File::create(dest_path)
+            .with_context(|| format!("creating {}", dest_path.display()))?;
+
+        {
+            let mut writer = std::io::BufWriter::new(&mut fh);
+            writer.write_all(&runtime)?;
+
+            // The runtime locates the image at the end of its own ELF data.
+            appdir
+                .write(&mut writer)
+                .with_context(|| format!("writing {}", dest_path.display()))?;
+
+            writer.flush()?;
+        }
+
+        tugger_file_manifest::set_executable(&mut fh)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tugger_common::testutil::*};
+
+    fn builder() -> Result<AppImageBuilder> {
+        let icon_path = DEFAULT_TEMP_DIR.path().join("appimage-icon.png");
+        std::fs::write(&icon_path, b"not really a png")?;
+
+        let mut manifest = FileManifest::default();
+        manifest.add_file_entry(
+            "usr/bin/myapp",
+            FileEntry {
+                data: b"#!/bin/sh\n".to_vec().into(),
+                executable: true,
+            },
+        )?;
+
+        AppImageBuilder::new("myapp")?
+            .add_manifest(&manifest)?
+            .exec("usr/bin/myapp")
+            .icon_path(&icon_path)
+    }
+
+    #[test]
+    fn test_desktop_file() -> Result<()> {
+        let builder = builder()?
+            .categories(vec!["Development".to_string(), "Utility".to_string()])
+            .desktop_entry("Comment", "My application");
+
+        assert_eq!(
+            builder.desktop_file()?,
+            "[Desktop Entry]\n\
+            Categories=Development;Utility;\n\
+            Comment=My application\n\
+            Exec=myapp\n\
+            Icon=myapp\n\
+            Name=myapp\n\
+            Type=Application\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validation() -> Result<()> {
+        assert!(AppImageBuilder::new("").is_err());
+        assert!(AppImageBuilder::new("a/b").is_err());
+        assert!(AppImageBuilder::new("myapp")?
+            .icon_path("icon.bmp")
+            .is_err());
+        assert!(AppImageBuilder::new("myapp")?.appdir().is_err());
+        assert!(builder()?.exec("missing").appdir().is_err());
+        assert!(builder()?.minimum_glibc_version("").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_appimage() -> Result<()> {
+        let runtime_path = DEFAULT_TEMP_DIR.path().join("appimage-runtime");
+        std::fs::write(&runtime_path, b"\x7fELF runtime")?;
+        let not_elf_path = DEFAULT_TEMP_DIR.path().join("appimage-not-elf");
+        std::fs::write(&not_elf_path, b"runtime")?;
+
+        let builder = builder()?;
+        let dest_path = DEFAULT_TEMP_DIR.path().join("myapp.AppImage");
+        builder.write_appimage(&runtime_path, &dest_path)?;
+
+        let data = std::fs::read(&dest_path)?;
+        assert!(data.starts_with(b"\x7fELF runtime"));
+        assert_eq!(&data[12..16], b"hsqs");
+        assert_eq!((data.len() - 12) % 4096, 0);
+
+        assert!(builder.write_appimage(&not_elf_path, &dest_path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_glibc_warnings() -> Result<()> {
+        // Use the running test binary as a sample ELF file.
+        let exe = std::env::current_exe()?;
+        let data = std::fs::read(&exe)?;
+        let required = if data.starts_with(b"\x7fELF") {
+            tugger_binary_analysis::find_required_glibc_version(&data)?
+        } else {
+            None
+        };
+
+        let mut manifest = FileManifest::default();
+        manifest.add_file_entry(
+            "usr/bin/test",
+            FileEntry {
+                data: exe.into(),
+                executable: true,
+            },
+        )?;
+
+        let builder = builder()?.add_manifest(&manifest)?;
+        assert!(builder.glibc_warnings()?.is_empty());
+
+        let builder = builder.minimum_glibc_version("2.2")?;
+        assert_eq!(
+            builder.glibc_warnings()?.len(),
+            if required.is_some() { 1 } else { 0 }
+        );
+
+        Ok(())
+    }
+}
@@ -19,5 +19,27 @@ Rust crate and utility. It just happens to be developed alongside
 PyOxidizer.
 */
 
+pub mod appimage;
+pub mod oci;
 pub mod starlark;
 pub mod tarball;
+pub mod zipfile;
+
+use anyhow::{anyhow, Result};
+
+/// Obtain the value of the `SOURCE_DATE_EPOCH` environment variable.
+///
+/// This variable is the standard way of requesting reproducible timestamps
+/// in build output. See <https://reproducible-builds.org/specs/source-date-epoch/>.
+/// Returns an error if the variable is set to something other than an integer.
+pub fn source_date_epoch() -> Result<Option<u64>> {
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) => value
+            .trim()
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|_| anyhow!("SOURCE_DATE_EPOCH is not a valid integer: {}", value)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(e) => Err(anyhow!("error reading SOURCE_DATE_EPOCH: {}", e)),
+    }
+}
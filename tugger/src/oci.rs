@@ -0,0 +1,621 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Produce container images in the OCI image layout format.
+
+See <https://github.com/opencontainers/image-spec> for the specification.
+Image layouts can be consumed by tools like `skopeo` and `podman`, which can
+also copy them to registries or a Docker daemon.
+*/
+
+use {
+    crate::tarball::write_tarball_from_manifest,
+    anyhow::{anyhow, Context, Result},
+    serde::{Deserialize, Serialize},
+    sha2::Digest,
+    std::{
+        collections::BTreeMap,
+        io::Write,
+        path::{Path, PathBuf},
+    },
+    tugger_file_manifest::FileManifest,
+};
+
+pub const MEDIA_TYPE_IMAGE_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+pub const MEDIA_TYPE_IMAGE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const MEDIA_TYPE_IMAGE_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+pub const MEDIA_TYPE_IMAGE_LAYER_GZIP: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+/// Annotation holding the name of a reference (e.g. a tag) to a manifest.
+pub const ANNOTATION_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// The platform an image runs on.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+/// Reference to content in a blob store.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Descriptor {
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImageIndex {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "mediaType", default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub manifests: Vec<Descriptor>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ImageManifest {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "mediaType", default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub config: Descriptor,
+    pub layers: Vec<Descriptor>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// Execution parameters of containers created from an image.
+///
+/// Fields not modeled here are preserved in `extra`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ContainerConfig {
+    #[serde(rename = "User", default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(rename = "Env", default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<Vec<String>>,
+    #[serde(
+        rename = "Entrypoint",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(rename = "Cmd", default, skip_serializing_if = "Option::is_none")]
+    pub cmd: Option<Vec<String>>,
+    #[serde(
+        rename = "WorkingDir",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub working_dir: Option<String>,
+    #[serde(rename = "Labels", default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RootFs {
+    #[serde(rename = "type")]
+    pub fs_type: String,
+    pub diff_ids: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct History {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+/// Configuration of an image.
+///
+/// Fields not modeled here are preserved in `extra`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ImageConfig {
+    pub architecture: String,
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+    #[serde(default)]
+    pub config: ContainerConfig,
+    pub rootfs: RootFs,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<History>,
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+fn sha256_digest(data: &[u8]) -> String {
+    format!("sha256:{}", hex::encode(sha2::Sha256::digest(data)))
+}
+
+/// Resolve the path of a blob in an image layout directory.
+fn blob_path(layout_path: &Path, digest: &str) -> Result<PathBuf> {
+    let mut parts = digest.splitn(2, ':');
+
+    match (parts.next(), parts.next()) {
+        (Some(algorithm), Some(encoded))
+            if !algorithm.is_empty()
+                && !encoded.is_empty()
+                && algorithm
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+                && encoded.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            Ok(layout_path.join("blobs").join(algorithm).join(encoded))
+        }
+        _ => Err(anyhow!("invalid digest: {}", digest)),
+    }
+}
+
+/// Write content to the blob store of an image layout directory.
+fn write_blob(layout_path: &Path, media_type: &str, data: &[u8]) -> Result<Descriptor> {
+    let digest = sha256_digest(data);
+    let path = blob_path(layout_path, &digest)?;
+
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(&path, data).with_context(|| format!("writing {}", path.display()))?;
+
+    Ok(Descriptor {
+        media_type: media_type.to_string(),
+        digest,
+        size: data.len() as u64,
+        platform: None,
+        annotations: BTreeMap::new(),
+    })
+}
+
+fn read_blob(layout_path: &Path, descriptor: &Descriptor) -> Result<Vec<u8>> {
+    let path = blob_path(layout_path, &descriptor.digest)?;
+
+    let data = std::fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+    if descriptor.digest.starts_with("sha256:") && sha256_digest(&data) != descriptor.digest {
+        return Err(anyhow!("digest of {} does not match", path.display()));
+    }
+
+    Ok(data)
+}
+
+/// An image in an image layout directory.
+#[derive(Clone, Debug)]
+pub struct OciImage {
+    pub manifest: ImageManifest,
+    pub config: ImageConfig,
+}
+
+impl OciImage {
+    /// Read the image for a platform from an image layout directory.
+    ///
+    /// If the layout contains a single image, it is used. Otherwise the image
+    /// for `platform` is used.
+    pub fn from_layout(layout_path: &Path, platform: &Platform) -> Result<Self> {
+        let index_path = layout_path.join("index.json");
+        let index: ImageIndex = serde_json::from_slice(
+            &std::fs::read(&index_path)
+                .with_context(|| format!("reading {}", index_path.display()))?,
+        )
+        .with_context(|| format!("parsing {}", index_path.display()))?;
+
+        let descriptor = match index.manifests.as_slice() {
+            [descriptor] => descriptor,
+            manifests => manifests
+                .iter()
+                .find(|d| match &d.platform {
+                    Some(p) => {
+                        p.architecture == platform.architecture
+                            && p.os == platform.os
+                            && (platform.variant.is_none() || p.variant == platform.variant)
+                    }
+                    None => false,
+                })
+                .ok_or_else(|| {
+                    anyhow!(
+                        "no image for {}/{} in {}",
+                        platform.os,
+                        platform.architecture,
+                        layout_path.display()
+                    )
+                })?,
+        };
+
+        if descriptor.media_type == MEDIA_TYPE_IMAGE_INDEX {
+            return Err(anyhow!(
+                "nested image indexes in {} are not supported",
+                layout_path.display()
+            ));
+        }
+
+        let manifest: ImageManifest = serde_json::from_slice(&read_blob(layout_path, descriptor)?)
+            .context("parsing image manifest")?;
+        let config: ImageConfig =
+            serde_json::from_slice(&read_blob(layout_path, &manifest.config)?)
+                .context("parsing image config")?;
+
+        Ok(Self { manifest, config })
+    }
+}
+
+/// Builds container images in the OCI image layout format.
+///
+/// Files are added to a single layer, which is optionally stacked on a base
+/// image. Output is deterministic: building the same inputs twice yields the
+/// same digests.
+#[derive(Clone, Debug)]
+pub struct OciImageBuilder {
+    platform: Platform,
+    base_image: Option<PathBuf>,
+    files: FileManifest,
+    entrypoint: Option<Vec<String>>,
+    cmd: Option<Vec<String>>,
+    env: BTreeMap<String, String>,
+    labels: BTreeMap<String, String>,
+    working_dir: Option<String>,
+    user: Option<String>,
+    tag: Option<String>,
+    mtime: u64,
+}
+
+impl OciImageBuilder {
+    /// Create a new instance for a platform.
+    ///
+    /// `architecture` and `os` use Go's `GOARCH` and `GOOS` values. e.g.
+    /// `amd64` and `linux`.
+    pub fn new(architecture: &str, os: &str) -> Self {
+        Self {
+            platform: Platform {
+                architecture: architecture.to_string(),
+                os: os.to_string(),
+                variant: None,
+            },
+            base_image: None,
+            files: FileManifest::default(),
+            entrypoint: None,
+            cmd: None,
+            env: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            working_dir: None,
+            user: None,
+            tag: None,
+            mtime: 0,
+        }
+    }
+
+    /// The platform of the image.
+    pub fn platform(&self) -> &Platform {
+        &self.platform
+    }
+
+    /// Set the variant of the CPU. e.g. `v7` for `arm`.
+    pub fn variant(mut self, variant: impl ToString) -> Self {
+        self.platform.variant = Some(variant.to_string());
+        self
+    }
+
+    /// Stack the image on an image in an image layout directory.
+    pub fn base_image(mut self, layout_path: impl AsRef<Path>) -> Self {
+        self.base_image = Some(layout_path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Add files to the image.
+    pub fn add_manifest(mut self, manifest: &FileManifest) -> Result<Self> {
+        self.files.add_manifest(manifest)?;
+        Ok(self)
+    }
+
+    /// Set the command executed when a container starts.
+    ///
+    /// This clears any `Cmd` inherited from the base image, like Docker does.
+    pub fn entrypoint(mut self, entrypoint: Vec<String>) -> Self {
+        self.entrypoint = Some(entrypoint);
+        self
+    }
+
+    /// Set the default arguments to the entrypoint.
+    pub fn cmd(mut self, cmd: Vec<String>) -> Self {
+        self.cmd = Some(cmd);
+        self
+    }
+
+    /// Set an environment variable, overriding a value from the base image.
+    pub fn env(mut self, name: impl ToString, value: impl ToString) -> Self {
+        self.env.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Set a label, overriding a value from the base image.
+    pub fn label(mut self, name: impl ToString, value: impl ToString) -> Self {
+        self.labels.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Set the working directory of containers.
+    pub fn working_dir(mut self, path: impl ToString) -> Self {
+        self.working_dir = Some(path.to_string());
+        self
+    }
+
+    /// Set the user containers run as.
+    pub fn user(mut self, user: impl ToString) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Set the reference name (e.g. a tag like `1.0`) of the image in the index.
+    pub fn tag(mut self, tag: impl ToString) -> Self {
+        self.tag = Some(tag.to_string());
+        self
+    }
+
+    /// Set the modified time of files in the layer, in seconds since the UNIX epoch.
+    pub fn mtime(mut self, mtime: u64) -> Self {
+        self.mtime = mtime;
+        self
+    }
+
+    /// Produce the layer tarball.
+    ///
+    /// Returns the uncompressed digest (the `diff_id`) and the gzip compressed
+    /// tarball.
+    fn layer(&self) -> Result<(String, Vec<u8>)> {
+        let mut tar_data = vec![];
+        write_tarball_from_manifest(&mut tar_data, &self.files, None, self.mtime)?;
+
+        let mut encoder = flate2::GzBuilder::new()
+            .mtime(0)
+            .write(vec![], flate2::Compression::default());
+        encoder.write_all(&tar_data)?;
+
+        Ok((sha256_digest(&tar_data), encoder.finish()?))
+    }
+
+    /// Resolve the image configuration, applying our settings to `config`.
+    fn resolve_config(&self, mut config: ImageConfig, diff_id: String) -> ImageConfig {
+        config.architecture = self.platform.architecture.clone();
+        config.os = self.platform.os.clone();
+        config.variant = self.platform.variant.clone();
+
+        let container = &mut config.config;
+
+        if !self.env.is_empty() {
+            let mut env = container
+                .env
+                .take()
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|entry| {
+                    let name = entry.split('=').next().unwrap_or_default();
+                    !self.env.contains_key(name)
+                })
+                .collect::<Vec<_>>();
+            env.extend(self.env.iter().map(|(k, v)| format!("{}={}", k, v)));
+            container.env = Some(env);
+        }
+
+        if let Some(entrypoint) = &self.entrypoint {
+            container.entrypoint = Some(entrypoint.clone());
+            container.cmd = None;
+        }
+        if let Some(cmd) = &self.cmd {
+            container.cmd = Some(cmd.clone());
+        }
+
+        if !self.labels.is_empty() {
+            let mut labels = container.labels.take().unwrap_or_default();
+            labels.extend(self.labels.clone());
+            container.labels = Some(labels);
+        }
+
+        if let Some(working_dir) = &self.working_dir {
+            container.working_dir = Some(working_dir.clone());
+        }
+        if let Some(user) = &self.user {
+            container.user = Some(user.clone());
+        }
+
+        config.rootfs.fs_type = "layers".to_string();
+        config.rootfs.diff_ids.push(diff_id);
+        config.history.push(History {
+            created_by: Some("tugger".to_string()),
+            extra: BTreeMap::new(),
+        });
+
+        config
+    }
+
+    /// Write the image to an image layout directory.
+    ///
+    /// The directory should not exist or be empty. Returns the descriptor of
+    /// the image manifest.
+    pub fn write_layout(&self, layout_path: impl AsRef<Path>) -> Result<Descriptor> {
+        let layout_path = layout_path.as_ref();
+
+        std::fs::create_dir_all(layout_path)
+            .with_context(|| format!("creating {}", layout_path.display()))?;
+        std::fs::write(
+            layout_path.join("oci-layout"),
+            br#"{"imageLayoutVersion":"1.0.0"}"#,
+        )?;
+
+        let (base_config, mut layers) = if let Some(base_path) = &self.base_image {
+            let base = OciImage::from_layout(base_path, &self.platform)
+                .with_context(|| format!("reading base image from {}", base_path.display()))?;
+
+            if base.config.architecture != self.platform.architecture
+                || base.config.os != self.platform.os
+            {
+                return Err(anyhow!(
+                    "base image is for {}/{}; expected {}/{}",
+                    base.config.os,
+                    base.config.architecture,
+                    self.platform.os,
+                    self.platform.architecture
+                ));
+            }
+
+            for layer in &base.manifest.layers {
+                let source = blob_path(base_path, &layer.digest)?;
+                let dest = blob_path(layout_path, &layer.digest)?;
+                std::fs::create_dir_all(dest.parent().unwrap())?;
+                std::fs::copy(&source, &dest)
+                    .with_context(|| format!("copying {}", source.display()))?;
+            }
+
+            (base.config, base.manifest.layers)
+        } else {
+            (ImageConfig::default(), vec![])
+        };
+
+        let (diff_id, layer_data) = self.layer()?;
+        layers.push(write_blob(
+            layout_path,
+            MEDIA_TYPE_IMAGE_LAYER_GZIP,
+            &layer_data,
+        )?);
+
+        let config = self.resolve_config(base_config, diff_id);
+        let config_descriptor = write_blob(
+            layout_path,
+            MEDIA_TYPE_IMAGE_CONFIG,
+            &serde_json::to_vec(&config)?,
+        )?;
+
+        let manifest = ImageManifest {
+            schema_version: 2,
+            media_type: Some(MEDIA_TYPE_IMAGE_MANIFEST.to_string()),
+            config: config_descriptor,
+            layers,
+            annotations: BTreeMap::new(),
+        };
+        let mut manifest_descriptor = write_blob(
+            layout_path,
+            MEDIA_TYPE_IMAGE_MANIFEST,
+            &serde_json::to_vec(&manifest)?,
+        )?;
+        manifest_descriptor.platform = Some(self.platform.clone());
+        if let Some(tag) = &self.tag {
+            manifest_descriptor
+                .annotations
+                .insert(ANNOTATION_REF_NAME.to_string(), tag.clone());
+        }
+
+        let index = ImageIndex {
+            schema_version: 2,
+            media_type: Some(MEDIA_TYPE_IMAGE_INDEX.to_string()),
+            manifests: vec![manifest_descriptor.clone()],
+        };
+        std::fs::write(layout_path.join("index.json"), serde_json::to_vec(&index)?)?;
+
+        Ok(manifest_descriptor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tugger_common::testutil::*, tugger_file_manifest::FileEntry};
+
+    fn builder() -> Result<OciImageBuilder> {
+        let mut manifest = FileManifest::default();
+        manifest.add_file_entry(
+            "app/myapp",
+            FileEntry {
+                data: vec![42].into(),
+                executable: true,
+            },
+        )?;
+
+        Ok(OciImageBuilder::new("amd64", "linux")
+            .add_manifest(&manifest)?
+            .entrypoint(vec!["/app/myapp".to_string()])
+            .env("PATH", "/app")
+            .label("org.example", "value")
+            .tag("1.0"))
+    }
+
+    #[test]
+    fn test_write_layout() -> Result<()> {
+        let first_path = DEFAULT_TEMP_DIR.path().join("oci_test_write_layout_0");
+        let second_path = DEFAULT_TEMP_DIR.path().join("oci_test_write_layout_1");
+
+        let first = builder()?.write_layout(&first_path)?;
+        let second = builder()?.write_layout(&second_path)?;
+        assert_eq!(first.digest, second.digest, "output is deterministic");
+        assert_eq!(first.annotations.get(ANNOTATION_REF_NAME).unwrap(), "1.0");
+
+        let image = OciImage::from_layout(&first_path, builder()?.platform())?;
+        assert_eq!(image.manifest.layers.len(), 1);
+        assert_eq!(
+            image.manifest.layers[0].media_type,
+            MEDIA_TYPE_IMAGE_LAYER_GZIP
+        );
+        assert_eq!(image.config.rootfs.diff_ids.len(), 1);
+        assert_eq!(
+            image.config.config.entrypoint,
+            Some(vec!["/app/myapp".to_string()])
+        );
+        assert_eq!(image.config.config.env, Some(vec!["PATH=/app".to_string()]));
+
+        let index = std::fs::read_to_string(first_path.join("index.json"))?;
+        assert!(index.contains(MEDIA_TYPE_IMAGE_MANIFEST));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_base_image() -> Result<()> {
+        let base_path = DEFAULT_TEMP_DIR.path().join("oci_test_base_image_base");
+        let path = DEFAULT_TEMP_DIR.path().join("oci_test_base_image");
+
+        builder()?
+            .cmd(vec!["--help".to_string()])
+            .env("LANG", "C.UTF-8")
+            .write_layout(&base_path)?;
+
+        OciImageBuilder::new("amd64", "linux")
+            .base_image(&base_path)
+            .env("PATH", "/usr/bin")
+            .label("org.example.other", "other")
+            .write_layout(&path)?;
+
+        let image = OciImage::from_layout(&path, builder()?.platform())?;
+        assert_eq!(image.manifest.layers.len(), 2);
+        for layer in &image.manifest.layers {
+            assert!(blob_path(&path, &layer.digest)?.exists());
+        }
+        assert_eq!(image.config.rootfs.diff_ids.len(), 2);
+        assert_eq!(image.config.history.len(), 2);
+        assert_eq!(
+            image.config.config.env,
+            Some(vec![
+                "LANG=C.UTF-8".to_string(),
+                "PATH=/usr/bin".to_string()
+            ])
+        );
+        assert_eq!(image.config.config.cmd, Some(vec!["--help".to_string()]));
+        assert_eq!(image.config.config.labels.unwrap().len(), 2);
+
+        assert!(OciImageBuilder::new("arm64", "linux")
+            .base_image(&base_path)
+            .write_layout(DEFAULT_TEMP_DIR.path().join("oci_test_base_image_arm64"))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_blob_path() {
+        assert!(blob_path(Path::new("/"), "sha256:abc").is_ok());
+        assert!(blob_path(Path::new("/"), "sha256:../../etc").is_err());
+        assert!(blob_path(Path::new("/"), "abc").is_err());
+    }
+}
@@ -0,0 +1,405 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::{
+        appimage::AppImageBuilder,
+        starlark::{
+            common::{add_manifest_with_prefix, mtime_from_arg, resolve_mtime},
+            file_resource::FileManifestValue,
+        },
+    },
+    anyhow::{Context, Result},
+    starlark::{
+        environment::TypeValues,
+        values::{
+            error::{RuntimeError, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE},
+            none::NoneType,
+            {Mutable, TypedValue, Value, ValueResult},
+        },
+        {
+            starlark_fun, starlark_module, starlark_parse_param_type, starlark_signature,
+            starlark_signature_extraction, starlark_signatures,
+        },
+    },
+    starlark_dialect_build_targets::{
+        fingerprint::TargetInput, get_context_value, optional_str_arg, record_target_input,
+        required_list_arg, EnvironmentContext, ResolvedTarget, ResolvedTargetValue, RunMode,
+    },
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+    tugger_file_manifest::FileManifest,
+    tugger_squashfs::SquashfsCompression,
+};
+
+fn to_runtime_error(err: anyhow::Error, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: "TUGGER_APPIMAGE_BUILDER",
+        message: format!("{:?}", err),
+        label: label.to_string(),
+    })
+}
+
+fn to_parameter_error(message: String, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+        message,
+        label: label.to_string(),
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct AppImageBuilderValue {
+    /// Name of the application.
+    pub name: String,
+    /// Name of the AppImage file to write.
+    pub filename: String,
+    /// Path to the AppImage runtime executable.
+    pub runtime_path: PathBuf,
+    /// Files in the AppDir.
+    pub files: FileManifest,
+    /// Path of the executable run by `AppRun`, relative to the AppDir.
+    pub exec: Option<String>,
+    pub icon_path: Option<PathBuf>,
+    pub categories: Option<Vec<String>>,
+    pub desktop_entries: BTreeMap<String, String>,
+    pub minimum_glibc_version: Option<String>,
+    pub compression: SquashfsCompression,
+    /// Modified time of AppDir entries, in seconds since the UNIX epoch.
+    pub mtime: Option<u64>,
+}
+
+impl TypedValue for AppImageBuilderValue {
+    type Holder = Mutable<AppImageBuilderValue>;
+    const TYPE: &'static str = "AppImageBuilder";
+
+    fn values_for_descendant_check_and_freeze(&self) -> Box<dyn Iterator<Item = Value>> {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl AppImageBuilderValue {
+    pub fn new(name: String, runtime_path: PathBuf, filename: Option<String>) -> Result<Self> {
+        // Validates the name.
+        AppImageBuilder::new(&name)?;
+
+        Ok(Self {
+            filename: filename.unwrap_or_else(|| format!("{}.AppImage", name)),
+            name,
+            runtime_path,
+            files: FileManifest::default(),
+            exec: None,
+            icon_path: None,
+            categories: None,
+            desktop_entries: BTreeMap::new(),
+            minimum_glibc_version: None,
+            compression: SquashfsCompression::Gzip,
+            mtime: None,
+        })
+    }
+
+    /// Add files from a `FileManifest` to the AppDir under a directory.
+    pub fn add_manifest(&mut self, manifest: &FileManifest, prefix: &str) -> Result<()> {
+        add_manifest_with_prefix(&mut self.files, manifest, prefix)
+    }
+
+    /// Convert to an `AppImageBuilder`.
+    pub fn to_appimage_builder(&self) -> Result<AppImageBuilder> {
+        let mut builder = AppImageBuilder::new(&self.name)?
+            .add_manifest(&self.files)?
+            .compression(self.compression)
+            .mtime(resolve_mtime(self.mtime)?)?;
+
+        if let Some(exec) = &self.exec {
+            builder = builder.exec(exec);
+        }
+        if let Some(path) = &self.icon_path {
+            builder = builder.icon_path(path)?;
+        }
+        if let Some(categories) = &self.categories {
+            builder = builder.categories(categories.clone());
+        }
+        for (key, value) in &self.desktop_entries {
+            builder = builder.desktop_entry(key, value);
+        }
+        if let Some(version) = &self.minimum_glibc_version {
+            builder = builder.minimum_glibc_version(version)?;
+        }
+
+        Ok(builder)
+    }
+
+    pub fn new_from_args(
+        type_values: &TypeValues,
+        name: String,
+        runtime_path: String,
+        filename: &Value,
+    ) -> ValueResult {
+        let filename = optional_str_arg("filename", filename)?;
+        let runtime_path = PathBuf::from(runtime_path);
+
+        record_target_input(type_values, TargetInput::Path(runtime_path.clone()))?;
+
+        Ok(Value::new(
+            Self::new(name, runtime_path, filename)
+                .map_err(|e| to_parameter_error(e.to_string(), "AppImageBuilder()"))?,
+        ))
+    }
+
+    pub fn add_manifest_starlark(
+        &mut self,
+        manifest: FileManifestValue,
+        prefix: String,
+    ) -> ValueResult {
+        self.add_manifest(&manifest.manifest, &prefix)
+            .map_err(|e| to_runtime_error(e, "add_manifest()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_exec_starlark(&mut self, path: String) -> ValueResult {
+        self.exec = Some(path.trim_start_matches('/').to_string());
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_icon_starlark(&mut self, type_values: &TypeValues, path: String) -> ValueResult {
+        let path = PathBuf::from(path);
+
+        record_target_input(type_values, TargetInput::Path(path.clone()))?;
+        self.icon_path = Some(path);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_categories_starlark(&mut self, categories: &Value) -> ValueResult {
+        required_list_arg("categories", "string", categories)?;
+
+        self.categories = Some(categories.iter()?.iter().map(|x| x.to_string()).collect());
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_desktop_entry_starlark(&mut self, key: String, value: String) -> ValueResult {
+        self.desktop_entries.insert(key, value);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_minimum_glibc_version_starlark(&mut self, version: String) -> ValueResult {
+        AppImageBuilder::new(&self.name)
+            .and_then(|builder| builder.minimum_glibc_version(&version))
+            .map_err(|e| to_parameter_error(e.to_string(), "set_minimum_glibc_version()"))?;
+
+        self.minimum_glibc_version = Some(version);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_compression_starlark(&mut self, compression: String) -> ValueResult {
+        self.compression = compression
+            .parse::<SquashfsCompression>()
+            .map_err(|e| to_parameter_error(e, "set_compression()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_mtime_starlark(&mut self, mtime: i64) -> ValueResult {
+        self.mtime = Some(mtime_from_arg(mtime, "set_mtime()")?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn build(&self, type_values: &TypeValues, target: String) -> ValueResult {
+        let context_value = get_context_value(type_values)?;
+        let context = context_value
+            .downcast_ref::<EnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        let output_path = context.target_build_path(&target).join(&self.filename);
+
+        (|| -> Result<()> {
+            let builder = self.to_appimage_builder()?;
+
+            for warning in builder.glibc_warnings()? {
+                slog::warn!(context.logger(), "{}", warning);
+            }
+
+            if let Some(parent) = output_path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("creating {}", parent.display()))?;
+            }
+
+            builder.write_appimage(&self.runtime_path, &output_path)
+        })()
+        .map_err(|e| to_runtime_error(e, "build()"))?;
+
+        slog::warn!(context.logger(), "wrote {}", output_path.display());
+
+        Ok(Value::new(ResolvedTargetValue {
+            inner: ResolvedTarget {
+                run_mode: RunMode::None,
+                output_path,
+            },
+        }))
+    }
+
+    /// Write the AppImage to a filesystem path.
+    pub fn write_to_path(&self, path: &Path) -> Result<()> {
+        self.to_appimage_builder()?
+            .write_appimage(&self.runtime_path, path)
+    }
+}
+
+starlark_module! { appimage_builder_module =>
+    #[allow(non_snake_case)]
+    AppImageBuilder(env env, name: String, runtime_path: String, filename = NoneType::None) {
+        AppImageBuilderValue::new_from_args(env, name, runtime_path, &filename)
+    }
+
+    #[allow(non_snake_case)]
+    AppImageBuilder.add_manifest(
+        this,
+        manifest: FileManifestValue,
+        prefix: String = "".to_string()
+    ) {
+        let mut this = this.downcast_mut::<AppImageBuilderValue>().unwrap().unwrap();
+        this.add_manifest_starlark(manifest, prefix)
+    }
+
+    #[allow(non_snake_case)]
+    AppImageBuilder.set_exec(this, path: String) {
+        let mut this = this.downcast_mut::<AppImageBuilderValue>().unwrap().unwrap();
+        this.set_exec_starlark(path)
+    }
+
+    #[allow(non_snake_case)]
+    AppImageBuilder.set_icon(env env, this, path: String) {
+        let mut this = this.downcast_mut::<AppImageBuilderValue>().unwrap().unwrap();
+        this.set_icon_starlark(env, path)
+    }
+
+    #[allow(non_snake_case)]
+    AppImageBuilder.set_categories(this, categories) {
+        let mut this = this.downcast_mut::<AppImageBuilderValue>().unwrap().unwrap();
+        this.set_categories_starlark(&categories)
+    }
+
+    #[allow(non_snake_case)]
+    AppImageBuilder.set_desktop_entry(this, key: String, value: String) {
+        let mut this = this.downcast_mut::<AppImageBuilderValue>().unwrap().unwrap();
+        this.set_desktop_entry_starlark(key, value)
+    }
+
+    #[allow(non_snake_case)]
+    AppImageBuilder.set_minimum_glibc_version(this, version: String) {
+        let mut this = this.downcast_mut::<AppImageBuilderValue>().unwrap().unwrap();
+        this.set_minimum_glibc_version_starlark(version)
+    }
+
+    #[allow(non_snake_case)]
+    AppImageBuilder.set_compression(this, compression: String) {
+        let mut this = this.downcast_mut::<AppImageBuilderValue>().unwrap().unwrap();
+        this.set_compression_starlark(compression)
+    }
+
+    #[allow(non_snake_case)]
+    AppImageBuilder.set_mtime(this, mtime: i64) {
+        let mut this = this.downcast_mut::<AppImageBuilderValue>().unwrap().unwrap();
+        this.set_mtime_starlark(mtime)
+    }
+
+    #[allow(non_snake_case)]
+    AppImageBuilder.build(env env, this, target: String) {
+        let this = this.downcast_ref::<AppImageBuilderValue>().unwrap();
+        this.build(env, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, crate::starlark::testutil::*, tugger_common::testutil::*,
+        tugger_file_manifest::FileEntry,
+    };
+
+    #[test]
+    fn test_new() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval("AppImageBuilder('myapp', 'runtime-x86_64')")?;
+        assert_eq!(v.get_type(), "AppImageBuilder");
+        let builder = v.downcast_ref::<AppImageBuilderValue>().unwrap();
+        assert_eq!(builder.filename, "myapp.AppImage");
+        assert_eq!(builder.compression, SquashfsCompression::Gzip);
+
+        let v = env.eval(
+            "AppImageBuilder('myapp', 'runtime-x86_64', filename = 'myapp-x86_64.AppImage')",
+        )?;
+        let builder = v.downcast_ref::<AppImageBuilderValue>().unwrap();
+        assert_eq!(builder.filename, "myapp-x86_64.AppImage");
+
+        assert!(env.eval("AppImageBuilder('my/app', 'runtime')").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let runtime_path = DEFAULT_TEMP_DIR.path().join("appimage_builder_runtime");
+        std::fs::write(&runtime_path, b"\x7fELF runtime")?;
+        let icon_path = DEFAULT_TEMP_DIR.path().join("appimage_builder_icon.svg");
+        std::fs::write(&icon_path, b"<svg/>")?;
+
+        let v = env.eval(&format!(
+            "app = AppImageBuilder('myapp', {:?}); app",
+            runtime_path.display().to_string()
+        ))?;
+        let m = env.eval("m = FileManifest(); m")?;
+        m.downcast_mut::<FileManifestValue>()
+            .unwrap()
+            .unwrap()
+            .manifest
+            .add_file_entry(
+                "bin/myapp",
+                FileEntry {
+                    data: vec![42].into(),
+                    executable: true,
+                },
+            )?;
+
+        env.eval("app.add_manifest(m, 'usr')")?;
+        env.eval("app.set_exec('usr/bin/myapp')")?;
+        env.eval(&format!(
+            "app.set_icon({:?})",
+            icon_path.display().to_string()
+        ))?;
+        env.eval("app.set_categories(['Development'])")?;
+        env.eval("app.set_desktop_entry('Comment', 'My application')")?;
+        env.eval("app.set_minimum_glibc_version('2.17')")?;
+        env.eval("app.set_compression('xz')")?;
+        env.eval("app.set_mtime(1000)")?;
+        assert!(env.eval("app.set_minimum_glibc_version('latest')").is_err());
+        assert!(env.eval("app.set_compression('lz4')").is_err());
+        assert!(env.eval("app.set_categories([1])").is_err());
+
+        let builder = v.downcast_ref::<AppImageBuilderValue>().unwrap();
+        assert!(builder.files.has_path("usr/bin/myapp"));
+        assert_eq!(builder.compression, SquashfsCompression::Xz);
+
+        let desktop = builder.to_appimage_builder()?.desktop_file()?;
+        assert!(str::contains(&desktop, "Categories=Development;\n"));
+        assert!(str::contains(&desktop, "Comment=My application\n"));
+
+        let path = DEFAULT_TEMP_DIR.path().join("appimage_builder_test_write");
+        builder.write_to_path(&path)?;
+        assert!(std::fs::read(&path)?.starts_with(b"\x7fELF runtime"));
+
+        Ok(())
+    }
+}
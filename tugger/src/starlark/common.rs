@@ -0,0 +1,145 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Functionality shared by Starlark types producing files from `FileManifest` instances. */
+
+use {
+    crate::source_date_epoch,
+    anyhow::{Context, Result},
+    starlark::{
+        environment::TypeValues,
+        values::{
+            error::{RuntimeError, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE},
+            Value, ValueResult,
+        },
+    },
+    starlark_dialect_build_targets::{
+        get_context_value, EnvironmentContext, ResolvedTarget, ResolvedTargetValue, RunMode,
+    },
+    std::path::Path,
+    tugger_file_manifest::FileManifest,
+};
+
+/// Add files in a manifest to another manifest under a directory prefix.
+pub(crate) fn add_manifest_with_prefix(
+    dest: &mut FileManifest,
+    manifest: &FileManifest,
+    prefix: &str,
+) -> Result<()> {
+    let prefix = Path::new(prefix.trim_start_matches('/'));
+
+    for (path, entry) in manifest.iter_entries() {
+        dest.add_file_entry(prefix.join(path), entry.clone())
+            .with_context(|| format!("adding {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Resolve the modified time of archive members.
+///
+/// An explicit time takes precedence over `SOURCE_DATE_EPOCH`. The UNIX
+/// epoch is used if neither is set.
+pub(crate) fn resolve_mtime(mtime: Option<u64>) -> Result<u64> {
+    Ok(match mtime {
+        Some(mtime) => mtime,
+        None => source_date_epoch()?.unwrap_or(0),
+    })
+}
+
+/// Validate a Starlark `mtime` argument, in seconds since the UNIX epoch.
+pub(crate) fn mtime_from_arg(mtime: i64, label: &str) -> Result<u64, ValueError> {
+    if mtime < 0 {
+        Err(ValueError::Runtime(RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: "mtime must not be before the UNIX epoch".to_string(),
+            label: label.to_string(),
+        }))
+    } else {
+        Ok(mtime as u64)
+    }
+}
+
+/// Write an archive to a target's build directory and resolve the target.
+///
+/// Errors from `write` are reported with the error code `code`.
+pub(crate) fn build_archive(
+    type_values: &TypeValues,
+    code: &'static str,
+    target: &str,
+    filename: &str,
+    write: impl FnOnce(&Path) -> Result<()>,
+) -> ValueResult {
+    let context_value = get_context_value(type_values)?;
+    let context = context_value
+        .downcast_ref::<EnvironmentContext>()
+        .ok_or(ValueError::IncorrectParameterType)?;
+
+    let output_path = context.target_build_path(target).join(filename);
+
+    (|| -> Result<()> {
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+        }
+
+        write(&output_path)
+    })()
+    .map_err(|e| {
+        ValueError::Runtime(RuntimeError {
+            code,
+            message: format!("{:?}", e),
+            label: "build()".to_string(),
+        })
+    })?;
+
+    slog::warn!(context.logger(), "wrote {}", output_path.display());
+
+    Ok(Value::new(ResolvedTargetValue {
+        inner: ResolvedTarget {
+            run_mode: RunMode::None,
+            output_path,
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, tugger_file_manifest::FileEntry};
+
+    #[test]
+    fn test_add_manifest_with_prefix() -> Result<()> {
+        let mut manifest = FileManifest::default();
+        manifest.add_file_entry(
+            "bin/myapp",
+            FileEntry {
+                data: vec![42].into(),
+                executable: true,
+            },
+        )?;
+
+        let mut dest = FileManifest::default();
+        add_manifest_with_prefix(&mut dest, &manifest, "/opt/myapp")?;
+        add_manifest_with_prefix(&mut dest, &manifest, "")?;
+
+        assert_eq!(
+            dest.iter_entries()
+                .map(|(path, _)| path.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Path::new("bin/myapp").to_path_buf(),
+                Path::new("opt/myapp/bin/myapp").to_path_buf()
+            ]
+        );
+        assert!(dest.get("opt/myapp/bin/myapp").unwrap().executable);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_mtime_from_arg() {
+        assert_eq!(mtime_from_arg(1000, "set_mtime()").unwrap(), 1000);
+        assert!(mtime_from_arg(-1, "set_mtime()").is_err());
+    }
+}
@@ -0,0 +1,573 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::starlark::file_resource::FileManifestValue,
+    anyhow::{anyhow, Context, Result},
+    starlark::{
+        environment::TypeValues,
+        values::{
+            error::{RuntimeError, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE},
+            none::NoneType,
+            {Mutable, TypedValue, Value, ValueResult},
+        },
+        {
+            starlark_fun, starlark_module, starlark_parse_param_type, starlark_signature,
+            starlark_signature_extraction, starlark_signatures,
+        },
+    },
+    starlark_dialect_build_targets::{
+        get_context_value, optional_type_arg, EnvironmentContext, ResolvedTarget,
+        ResolvedTargetValue, RunMode,
+    },
+    std::{
+        borrow::Cow,
+        collections::BTreeMap,
+        io::Write,
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    },
+    tugger_debian::{ControlFile, ControlParagraph, DebBuilder, DebCompression},
+    tugger_file_manifest::{FileEntry, FileManifest},
+};
+
+/// Names of maintainer scripts that can be added to packages.
+const MAINTAINER_SCRIPTS: &[&str] = &["config", "postinst", "postrm", "preinst", "prerm"];
+
+fn to_runtime_error(err: anyhow::Error, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: "TUGGER_DEBIAN_PACKAGE_BUILDER",
+        message: format!("{:?}", err),
+        label: label.to_string(),
+    })
+}
+
+fn to_parameter_error(message: String, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+        message,
+        label: label.to_string(),
+    })
+}
+
+/// Resolve the `DebCompression` for a format name and optional level.
+fn resolve_compression(format: &str, level: Option<i64>) -> Result<DebCompression> {
+    match (format, level) {
+        ("none", None) => Ok(DebCompression::Uncompressed),
+        ("gzip", None) => Ok(DebCompression::Gzip),
+        ("xz", None) => Ok(DebCompression::Xz(6)),
+        ("xz", Some(level)) if (0..=9).contains(&level) => Ok(DebCompression::Xz(level as u32)),
+        ("zstd", None) => Ok(DebCompression::Zstandard(3)),
+        ("zstd", Some(level)) if (1..=22).contains(&level) => {
+            Ok(DebCompression::Zstandard(level as i32))
+        }
+        ("none", Some(_)) | ("gzip", Some(_)) => Err(anyhow!(
+            "compression format {} does not accept a level",
+            format
+        )),
+        ("xz", Some(level)) | ("zstd", Some(level)) => Err(anyhow!(
+            "compression level {} is not valid for {}",
+            level,
+            format
+        )),
+        _ => Err(anyhow!(
+            "unknown compression format {}; expected one of none, gzip, xz, zstd",
+            format
+        )),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DebianPackageBuilderValue {
+    /// Fields in the `control` file, in the order they will be written.
+    pub control_fields: Vec<(String, String)>,
+    /// Files to install, relative to the filesystem root.
+    pub install_files: FileManifest,
+    /// Symlinks to install, relative to the filesystem root, mapped to their targets.
+    pub install_symlinks: BTreeMap<PathBuf, PathBuf>,
+    /// Maintainer scripts to add to the control archive.
+    pub maintainer_scripts: FileManifest,
+    pub compression: DebCompression,
+    /// Modified time of archive members. The current time is used if not set.
+    pub mtime: Option<SystemTime>,
+}
+
+impl TypedValue for DebianPackageBuilderValue {
+    type Holder = Mutable<DebianPackageBuilderValue>;
+    const TYPE: &'static str = "DebianPackageBuilder";
+
+    fn values_for_descendant_check_and_freeze(&self) -> Box<dyn Iterator<Item = Value>> {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl DebianPackageBuilderValue {
+    pub fn new(
+        package: String,
+        version: String,
+        architecture: String,
+        maintainer: String,
+        description: String,
+    ) -> Self {
+        Self {
+            control_fields: vec![
+                ("Package".to_string(), package),
+                ("Version".to_string(), version),
+                ("Architecture".to_string(), architecture),
+                ("Maintainer".to_string(), maintainer),
+                ("Description".to_string(), description),
+            ],
+            install_files: FileManifest::default(),
+            install_symlinks: BTreeMap::new(),
+            maintainer_scripts: FileManifest::default(),
+            compression: DebCompression::Gzip,
+            mtime: None,
+        }
+    }
+
+    /// Obtain the value of a control field.
+    pub fn control_field(&self, name: &str) -> Option<&str> {
+        self.control_fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Set the value of a control field, replacing an existing value.
+    pub fn set_control_field(&mut self, name: impl ToString, value: impl ToString) {
+        let name = name.to_string();
+        let value = value.to_string();
+
+        if let Some(entry) = self.control_fields.iter_mut().find(|(k, _)| k == &name) {
+            entry.1 = value;
+        } else {
+            self.control_fields.push((name, value));
+        }
+    }
+
+    /// Add files from a `FileManifest` to be installed under a directory.
+    ///
+    /// `install_prefix` is an absolute path like `/usr/lib/myapp`.
+    pub fn add_manifest(&mut self, manifest: &FileManifest, install_prefix: &str) -> Result<()> {
+        let prefix = Path::new(install_prefix.trim_start_matches('/'));
+
+        for (path, entry) in manifest.iter_entries() {
+            self.install_files
+                .add_file_entry(prefix.join(path), entry.clone())
+                .with_context(|| format!("adding {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Add a symlink to be installed.
+    pub fn add_symlink(&mut self, path: &str, target: &str) {
+        self.install_symlinks.insert(
+            PathBuf::from(path.trim_start_matches('/')),
+            PathBuf::from(target),
+        );
+    }
+
+    /// Add a maintainer script, such as `postinst`.
+    pub fn add_maintainer_script(&mut self, name: &str, content: &str) -> Result<()> {
+        if !MAINTAINER_SCRIPTS.contains(&name) {
+            return Err(anyhow!(
+                "{} is not a maintainer script; expected one of {}",
+                name,
+                MAINTAINER_SCRIPTS.join(", ")
+            ));
+        }
+
+        self.maintainer_scripts.add_file_entry(
+            name,
+            FileEntry {
+                data: content.as_bytes().to_vec().into(),
+                executable: true,
+            },
+        )?;
+
+        Ok(())
+    }
+
+    /// The filename of the `.deb` file, following Debian conventions.
+    pub fn deb_filename(&self) -> String {
+        format!(
+            "{}_{}_{}.deb",
+            self.control_field("Package").unwrap_or_default(),
+            self.control_field("Version").unwrap_or_default(),
+            self.control_field("Architecture").unwrap_or_default()
+        )
+    }
+
+    /// Convert to a `DebBuilder` from the tugger-debian crate.
+    pub fn to_deb_builder(&self) -> Result<DebBuilder<'static>> {
+        let mut paragraph = ControlParagraph::default();
+        for (name, value) in &self.control_fields {
+            paragraph
+                .add_field_from_string(Cow::Owned(name.clone()), Cow::Owned(value.clone()))
+                .map_err(|e| anyhow!("invalid control field {}: {}", name, e))?;
+        }
+
+        let mut control = ControlFile::default();
+        control.add_paragraph(paragraph);
+
+        let mut builder = DebBuilder::new(control)
+            .set_compression(self.compression)
+            .set_mtime(self.mtime);
+
+        for (path, entry) in self.maintainer_scripts.iter_entries() {
+            builder = builder.extra_control_tar_file(path, entry.clone())?;
+        }
+
+        for (path, entry) in self.install_files.iter_entries() {
+            builder = builder
+                .install_file(path, entry.clone())
+                .with_context(|| format!("adding {}", path.display()))?;
+        }
+
+        for (path, target) in &self.install_symlinks {
+            builder = builder.install_symlink(path, target);
+        }
+
+        Ok(builder)
+    }
+
+    /// Write the `.deb` file to a writer.
+    pub fn write_deb(&self, writer: &mut impl Write) -> Result<()> {
+        self.to_deb_builder()?
+            .write(writer)
+            .context("writing .deb file")?;
+
+        Ok(())
+    }
+
+    pub fn new_from_args(
+        package: String,
+        version: String,
+        architecture: String,
+        maintainer: String,
+        description: String,
+    ) -> ValueResult {
+        Ok(Value::new(Self::new(
+            package,
+            version,
+            architecture,
+            maintainer,
+            description,
+        )))
+    }
+
+    pub fn set_control_field_starlark(&mut self, name: String, value: String) -> ValueResult {
+        self.set_control_field(name, value);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_manifest_starlark(
+        &mut self,
+        manifest: FileManifestValue,
+        install_prefix: String,
+    ) -> ValueResult {
+        self.add_manifest(&manifest.manifest, &install_prefix)
+            .map_err(|e| to_runtime_error(e, "add_manifest()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_symlink_starlark(&mut self, path: String, target: String) -> ValueResult {
+        self.add_symlink(&path, &target);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_maintainer_script_starlark(&mut self, name: String, content: String) -> ValueResult {
+        self.add_maintainer_script(&name, &content)
+            .map_err(|e| to_parameter_error(e.to_string(), "add_maintainer_script()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_compression_starlark(&mut self, format: String, level: &Value) -> ValueResult {
+        optional_type_arg("level", "int", level)?;
+
+        let level = match level.get_type() {
+            "int" => Some(level.to_int()?),
+            _ => None,
+        };
+
+        self.compression = resolve_compression(&format, level)
+            .map_err(|e| to_parameter_error(e.to_string(), "set_compression()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_mtime_starlark(&mut self, mtime: i64) -> ValueResult {
+        if mtime < 0 {
+            return Err(to_parameter_error(
+                "mtime must not be before the UNIX epoch".to_string(),
+                "set_mtime()",
+            ));
+        }
+
+        self.mtime = Some(SystemTime::UNIX_EPOCH + Duration::from_secs(mtime as u64));
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn build(&self, type_values: &TypeValues, target: String) -> ValueResult {
+        let context_value = get_context_value(type_values)?;
+        let context = context_value
+            .downcast_ref::<EnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        let output_path = context.target_build_path(&target);
+        let deb_path = output_path.join(self.deb_filename());
+
+        (|| -> Result<()> {
+            std::fs::create_dir_all(&output_path)
+                .with_context(|| format!("creating {}", output_path.display()))?;
+
+            let mut fh = std::fs::File::create(&deb_path)
+                .with_context(|| format!("creating {}", deb_path.display()))?;
+            self.write_deb(&mut fh)
+        })()
+        .map_err(|e| to_runtime_error(e, "build()"))?;
+
+        slog::warn!(context.logger(), "wrote {}", deb_path.display());
+
+        Ok(Value::new(ResolvedTargetValue {
+            inner: ResolvedTarget {
+                run_mode: RunMode::None,
+                output_path,
+            },
+        }))
+    }
+}
+
+starlark_module! { debian_package_builder_module =>
+    #[allow(non_snake_case)]
+    DebianPackageBuilder(
+        package: String,
+        version: String,
+        architecture: String,
+        maintainer: String,
+        description: String
+    ) {
+        DebianPackageBuilderValue::new_from_args(package, version, architecture, maintainer, description)
+    }
+
+    #[allow(non_snake_case)]
+    DebianPackageBuilder.set_control_field(this, name: String, value: String) {
+        let mut this = this.downcast_mut::<DebianPackageBuilderValue>().unwrap().unwrap();
+        this.set_control_field_starlark(name, value)
+    }
+
+    #[allow(non_snake_case)]
+    DebianPackageBuilder.add_manifest(
+        this,
+        manifest: FileManifestValue,
+        install_prefix: String = "/".to_string()
+    ) {
+        let mut this = this.downcast_mut::<DebianPackageBuilderValue>().unwrap().unwrap();
+        this.add_manifest_starlark(manifest, install_prefix)
+    }
+
+    #[allow(non_snake_case)]
+    DebianPackageBuilder.add_symlink(this, path: String, target: String) {
+        let mut this = this.downcast_mut::<DebianPackageBuilderValue>().unwrap().unwrap();
+        this.add_symlink_starlark(path, target)
+    }
+
+    #[allow(non_snake_case)]
+    DebianPackageBuilder.add_maintainer_script(this, name: String, content: String) {
+        let mut this = this.downcast_mut::<DebianPackageBuilderValue>().unwrap().unwrap();
+        this.add_maintainer_script_starlark(name, content)
+    }
+
+    #[allow(non_snake_case)]
+    DebianPackageBuilder.set_compression(this, format: String, level = NoneType::None) {
+        let mut this = this.downcast_mut::<DebianPackageBuilderValue>().unwrap().unwrap();
+        this.set_compression_starlark(format, &level)
+    }
+
+    #[allow(non_snake_case)]
+    DebianPackageBuilder.set_mtime(this, mtime: i64) {
+        let mut this = this.downcast_mut::<DebianPackageBuilderValue>().unwrap().unwrap();
+        this.set_mtime_starlark(mtime)
+    }
+
+    #[allow(non_snake_case)]
+    DebianPackageBuilder.build(env env, this, target: String) {
+        let this = this.downcast_ref::<DebianPackageBuilderValue>().unwrap();
+        this.build(env, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::starlark::testutil::*, std::io::Read};
+
+    #[test]
+    fn test_new() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval(
+            "DebianPackageBuilder('myapp', '0.1', 'amd64', 'Me <me@example.com>', 'My app')",
+        )?;
+        assert_eq!(v.get_type(), "DebianPackageBuilder");
+
+        let builder = v.downcast_ref::<DebianPackageBuilderValue>().unwrap();
+        assert_eq!(builder.control_field("Package"), Some("myapp"));
+        assert_eq!(builder.deb_filename(), "myapp_0.1_amd64.deb");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_control_field() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v =
+            env.eval("deb = DebianPackageBuilder('myapp', '0.1', 'amd64', 'Me', 'My app'); deb")?;
+        env.eval("deb.set_control_field('Version', '0.2')")?;
+        env.eval("deb.set_control_field('Depends', 'libc6')")?;
+
+        let builder = v.downcast_ref::<DebianPackageBuilderValue>().unwrap();
+        assert_eq!(builder.control_field("Version"), Some("0.2"));
+        assert_eq!(builder.control_fields.last().unwrap().0, "Depends");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_manifest() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v =
+            env.eval("deb = DebianPackageBuilder('myapp', '0.1', 'amd64', 'Me', 'My app'); deb")?;
+        let m = env.eval("m = FileManifest(); m")?;
+        m.downcast_mut::<FileManifestValue>()
+            .unwrap()
+            .unwrap()
+            .manifest
+            .add_file_entry(
+                "myapp",
+                FileEntry {
+                    data: vec![42].into(),
+                    executable: true,
+                },
+            )?;
+
+        env.eval("deb.add_manifest(m, '/usr/lib/myapp')")?;
+        env.eval("deb.add_manifest(m)")?;
+        env.eval("deb.add_symlink('/usr/bin/myapp', '/usr/lib/myapp/myapp')")?;
+
+        let builder = v.downcast_ref::<DebianPackageBuilderValue>().unwrap();
+        assert!(builder.install_files.get("usr/lib/myapp/myapp").is_some());
+        assert!(builder.install_files.get("myapp").is_some());
+        assert_eq!(
+            builder.install_symlinks.get(Path::new("usr/bin/myapp")),
+            Some(&PathBuf::from("/usr/lib/myapp/myapp"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_compression() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v =
+            env.eval("deb = DebianPackageBuilder('myapp', '0.1', 'amd64', 'Me', 'My app'); deb")?;
+
+        env.eval("deb.set_compression('xz')")?;
+        assert_eq!(
+            v.downcast_ref::<DebianPackageBuilderValue>()
+                .unwrap()
+                .compression,
+            DebCompression::Xz(6)
+        );
+
+        env.eval("deb.set_compression('zstd', level = 19)")?;
+        assert_eq!(
+            v.downcast_ref::<DebianPackageBuilderValue>()
+                .unwrap()
+                .compression,
+            DebCompression::Zstandard(19)
+        );
+
+        assert!(env.eval("deb.set_compression('gzip', level = 3)").is_err());
+        assert!(env.eval("deb.set_compression('xz', level = 10)").is_err());
+        assert!(env.eval("deb.set_compression('bzip2')").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_maintainer_script() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v =
+            env.eval("deb = DebianPackageBuilder('myapp', '0.1', 'amd64', 'Me', 'My app'); deb")?;
+
+        env.eval("deb.add_maintainer_script('postinst', '#!/bin/sh\\nexit 0\\n')")?;
+        assert!(env
+            .eval("deb.add_maintainer_script('install', 'exit 0')")
+            .is_err());
+
+        let builder = v.downcast_ref::<DebianPackageBuilderValue>().unwrap();
+        assert_eq!(
+            builder.maintainer_scripts.get("postinst"),
+            Some(&FileEntry {
+                data: b"#!/bin/sh\nexit 0\n".to_vec().into(),
+                executable: true,
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_deb() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v =
+            env.eval("deb = DebianPackageBuilder('myapp', '0.1', 'amd64', 'Me', 'My app'); deb")?;
+        env.eval("deb.set_compression('none')")?;
+        env.eval("deb.set_mtime(1000)")?;
+        env.eval("deb.add_symlink('/usr/bin/myapp', '/usr/lib/myapp/myapp')")?;
+
+        let builder = v.downcast_ref::<DebianPackageBuilderValue>().unwrap();
+
+        let mut first = vec![];
+        builder.write_deb(&mut first)?;
+        let mut second = vec![];
+        builder.write_deb(&mut second)?;
+        assert_eq!(first, second, "setting mtime makes output deterministic");
+
+        let mut archive = ar::Archive::new(std::io::Cursor::new(first));
+        archive.next_entry().unwrap()?;
+
+        let mut control_tar = vec![];
+        archive
+            .next_entry()
+            .unwrap()?
+            .read_to_end(&mut control_tar)?;
+        let mut control_archive = tar::Archive::new(std::io::Cursor::new(control_tar));
+        let mut control = String::new();
+        for entry in control_archive.entries()? {
+            let mut entry = entry?;
+            if entry.path()? == Path::new("./control") {
+                entry.read_to_string(&mut control)?;
+            }
+        }
+
+        assert_eq!(
+            control,
+            "Package: myapp\nVersion: 0.1\nArchitecture: amd64\nMaintainer: Me\nDescription: My app\n\n"
+        );
+
+        Ok(())
+    }
+}
@@ -17,8 +17,9 @@ use {
         },
     },
     starlark_dialect_build_targets::{
-        get_context_value, optional_list_arg, optional_str_arg, required_list_arg,
-        EnvironmentContext, ResolvedTarget, ResolvedTargetValue, RunMode,
+        fingerprint::TargetInput, get_context_value, optional_list_arg, optional_str_arg,
+        record_target_input, required_list_arg, EnvironmentContext, ResolvedTarget,
+        ResolvedTargetValue, RunMode,
     },
     std::{
         collections::HashSet,
@@ -142,6 +143,7 @@ impl FileManifestValue {
     /// FileManifest.add_path(path, strip_prefix, force_read=False)
     pub fn add_path(
         &mut self,
+        type_values: &TypeValues,
         path: String,
         strip_prefix: String,
         force_read: bool,
@@ -149,6 +151,8 @@ impl FileManifestValue {
         let path = Path::new(&path);
         let strip_prefix = Path::new(&strip_prefix);
 
+        record_target_input(type_values, TargetInput::Path(path.to_path_buf()))?;
+
         if force_read {
             self.manifest.add_path_memory(path, strip_prefix)
         } else {
@@ -246,6 +250,12 @@ fn starlark_glob(
         }
     }
 
+    drop(context);
+
+    for path in &result {
+        record_target_input(type_values, TargetInput::Path(path.clone()))?;
+    }
+
     let mut manifest = FileManifest::default();
 
     for path in result {
@@ -301,9 +311,15 @@ starlark_module! { file_resource_module =>
         this.add_manifest(other)
     }
 
-    FileManifest.add_path(this, path: String, strip_prefix: String, force_read: bool = false) {
+    FileManifest.add_path(
+        env env,
+        this,
+        path: String,
+        strip_prefix: String,
+        force_read: bool = false
+    ) {
         let mut this = this.downcast_mut::<FileManifestValue>().unwrap().unwrap();
-        this.add_path(path, strip_prefix, force_read)
+        this.add_path(env, path, strip_prefix, force_read)
     }
 
     FileManifest.build(env env, this, target: String) {
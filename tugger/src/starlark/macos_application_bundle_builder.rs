@@ -18,7 +18,8 @@ use {
         },
     },
     starlark_dialect_build_targets::{
-        get_context_value, EnvironmentContext, ResolvedTarget, ResolvedTargetValue, RunMode,
+        fingerprint::TargetInput, get_context_value, record_target_input, EnvironmentContext,
+        ResolvedTarget, ResolvedTargetValue, RunMode,
     },
     std::path::PathBuf,
     tugger_apple_bundle::MacOsApplicationBundleBuilder,
@@ -63,9 +64,12 @@ impl MacOsApplicationBundleBuilderValue {
         Ok(Value::new(MacOsApplicationBundleBuilderValue { inner }))
     }
 
-    pub fn add_icon(&mut self, path: String) -> ValueResult {
+    pub fn add_icon(&mut self, type_values: &TypeValues, path: String) -> ValueResult {
+        let path = PathBuf::from(path);
+        record_target_input(type_values, TargetInput::Path(path.clone()))?;
+
         self.inner
-            .add_icon(FileData::from(PathBuf::from(path)))
+            .add_icon(FileData::from(path))
             .map_err(|e| to_runtime_error(e, "add_icon()"))?;
 
         Ok(Value::new(NoneType::None))
@@ -185,9 +189,9 @@ starlark_module! { macos_application_bundle_builder_module =>
     }
 
     #[allow(non_snake_case)]
-    MacOsApplicationBundleBuilder.add_icon(this, path: String) {
+    MacOsApplicationBundleBuilder.add_icon(env env, this, path: String) {
         let mut this = this.downcast_mut::<MacOsApplicationBundleBuilderValue>().unwrap().unwrap();
-        this.add_icon(path)
+        this.add_icon(env, path)
     }
 
     #[allow(non_snake_case)]
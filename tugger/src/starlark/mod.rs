@@ -8,14 +8,21 @@ The `starlark` module and related sub-modules define the
 Tugger.
 */
 
+pub mod appimage_builder;
+mod common;
+pub mod debian_package_builder;
 pub mod file_resource;
 pub mod macos_application_bundle_builder;
+pub mod oci_image_builder;
+pub mod rpm_builder;
 pub mod snapcraft;
+pub mod tarball_builder;
 #[cfg(test)]
 mod testutil;
 pub mod wix_bundle_builder;
 pub mod wix_installer;
 pub mod wix_msi_builder;
+pub mod zip_builder;
 
 use {
     starlark::{
@@ -88,12 +95,18 @@ pub fn register_starlark_dialect(
     env: &mut Environment,
     type_values: &mut TypeValues,
 ) -> Result<(), EnvironmentError> {
+    appimage_builder::appimage_builder_module(env, type_values);
+    debian_package_builder::debian_package_builder_module(env, type_values);
     file_resource::file_resource_module(env, type_values);
     macos_application_bundle_builder::macos_application_bundle_builder_module(env, type_values);
+    oci_image_builder::oci_image_builder_module(env, type_values);
+    rpm_builder::rpm_builder_module(env, type_values);
     snapcraft::snapcraft_module(env, type_values);
+    tarball_builder::tarball_builder_module(env, type_values);
     wix_bundle_builder::wix_bundle_builder_module(env, type_values);
     wix_installer::wix_installer_module(env, type_values);
     wix_msi_builder::wix_msi_builder_module(env, type_values);
+    zip_builder::zip_builder_module(env, type_values);
 
     Ok(())
 }
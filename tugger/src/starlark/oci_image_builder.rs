@@ -0,0 +1,417 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::{
+        oci::OciImageBuilder,
+        starlark::{
+            common::{add_manifest_with_prefix, mtime_from_arg, resolve_mtime},
+            file_resource::FileManifestValue,
+        },
+    },
+    anyhow::{Context, Result},
+    starlark::{
+        environment::TypeValues,
+        values::{
+            error::{RuntimeError, ValueError},
+            none::NoneType,
+            {Mutable, TypedValue, Value, ValueResult},
+        },
+        {
+            starlark_fun, starlark_module, starlark_parse_param_type, starlark_signature,
+            starlark_signature_extraction, starlark_signatures,
+        },
+    },
+    starlark_dialect_build_targets::{
+        fingerprint::TargetInput, get_context_value, optional_str_arg, record_target_input,
+        required_list_arg, EnvironmentContext, ResolvedTarget, ResolvedTargetValue, RunMode,
+    },
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+    tugger_file_manifest::FileManifest,
+};
+
+fn to_runtime_error(err: anyhow::Error, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: "TUGGER_OCI_IMAGE_BUILDER",
+        message: format!("{:?}", err),
+        label: label.to_string(),
+    })
+}
+
+fn string_list_arg(name: &str, value: &Value) -> Result<Vec<String>, ValueError> {
+    required_list_arg(name, "string", value)?;
+
+    Ok(value.iter()?.iter().map(|x| x.to_string()).collect())
+}
+
+#[derive(Clone, Debug)]
+pub struct OciImageBuilderValue {
+    pub architecture: String,
+    pub os: String,
+    pub variant: Option<String>,
+    /// Image layout directory of the base image.
+    pub base_image: Option<PathBuf>,
+    /// Files in the image layer, relative to the filesystem root.
+    pub files: FileManifest,
+    pub entrypoint: Option<Vec<String>>,
+    pub cmd: Option<Vec<String>>,
+    pub env: BTreeMap<String, String>,
+    pub labels: BTreeMap<String, String>,
+    pub working_dir: Option<String>,
+    pub user: Option<String>,
+    pub tag: Option<String>,
+    /// Modified time of files in the layer, in seconds since the UNIX epoch.
+    pub mtime: Option<u64>,
+}
+
+impl TypedValue for OciImageBuilderValue {
+    type Holder = Mutable<OciImageBuilderValue>;
+    const TYPE: &'static str = "OciImageBuilder";
+
+    fn values_for_descendant_check_and_freeze(&self) -> Box<dyn Iterator<Item = Value>> {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl OciImageBuilderValue {
+    pub fn new(architecture: String, os: String, variant: Option<String>) -> Self {
+        Self {
+            architecture,
+            os,
+            variant,
+            base_image: None,
+            files: FileManifest::default(),
+            entrypoint: None,
+            cmd: None,
+            env: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            working_dir: None,
+            user: None,
+            tag: None,
+            mtime: None,
+        }
+    }
+
+    /// Add files from a `FileManifest` to be installed under a directory.
+    pub fn add_manifest(&mut self, manifest: &FileManifest, install_prefix: &str) -> Result<()> {
+        add_manifest_with_prefix(&mut self.files, manifest, install_prefix)
+    }
+
+    /// Convert to an `OciImageBuilder`.
+    pub fn to_oci_image_builder(&self) -> Result<OciImageBuilder> {
+        let mut builder = OciImageBuilder::new(&self.architecture, &self.os)
+            .add_manifest(&self.files)?
+            .mtime(resolve_mtime(self.mtime)?);
+
+        if let Some(variant) = &self.variant {
+            builder = builder.variant(variant);
+        }
+        if let Some(path) = &self.base_image {
+            builder = builder.base_image(path);
+        }
+        if let Some(entrypoint) = &self.entrypoint {
+            builder = builder.entrypoint(entrypoint.clone());
+        }
+        if let Some(cmd) = &self.cmd {
+            builder = builder.cmd(cmd.clone());
+        }
+        for (name, value) in &self.env {
+            builder = builder.env(name, value);
+        }
+        for (name, value) in &self.labels {
+            builder = builder.label(name, value);
+        }
+        if let Some(working_dir) = &self.working_dir {
+            builder = builder.working_dir(working_dir);
+        }
+        if let Some(user) = &self.user {
+            builder = builder.user(user);
+        }
+        if let Some(tag) = &self.tag {
+            builder = builder.tag(tag);
+        }
+
+        Ok(builder)
+    }
+
+    /// Write the image to a fresh image layout directory.
+    pub fn write_layout(&self, layout_path: &Path) -> Result<()> {
+        if layout_path.exists() {
+            std::fs::remove_dir_all(layout_path)
+                .with_context(|| format!("removing {}", layout_path.display()))?;
+        }
+
+        self.to_oci_image_builder()?
+            .write_layout(layout_path)
+            .with_context(|| format!("writing image to {}", layout_path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn new_from_args(architecture: String, os: String, variant: &Value) -> ValueResult {
+        let variant = optional_str_arg("variant", variant)?;
+
+        Ok(Value::new(Self::new(architecture, os, variant)))
+    }
+
+    pub fn set_base_image_starlark(
+        &mut self,
+        type_values: &TypeValues,
+        path: String,
+    ) -> ValueResult {
+        let path = PathBuf::from(path);
+
+        record_target_input(type_values, TargetInput::Path(path.join("index.json")))?;
+        self.base_image = Some(path);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_manifest_starlark(
+        &mut self,
+        manifest: FileManifestValue,
+        install_prefix: String,
+    ) -> ValueResult {
+        self.add_manifest(&manifest.manifest, &install_prefix)
+            .map_err(|e| to_runtime_error(e, "add_manifest()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_entrypoint_starlark(&mut self, args: &Value) -> ValueResult {
+        self.entrypoint = Some(string_list_arg("args", args)?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_cmd_starlark(&mut self, args: &Value) -> ValueResult {
+        self.cmd = Some(string_list_arg("args", args)?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_env_starlark(&mut self, name: String, value: String) -> ValueResult {
+        self.env.insert(name, value);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_label_starlark(&mut self, name: String, value: String) -> ValueResult {
+        self.labels.insert(name, value);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_working_dir_starlark(&mut self, path: String) -> ValueResult {
+        self.working_dir = Some(path);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_user_starlark(&mut self, user: String) -> ValueResult {
+        self.user = Some(user);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_tag_starlark(&mut self, tag: String) -> ValueResult {
+        self.tag = Some(tag);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_mtime_starlark(&mut self, mtime: i64) -> ValueResult {
+        self.mtime = Some(mtime_from_arg(mtime, "set_mtime()")?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn build(&self, type_values: &TypeValues, target: String) -> ValueResult {
+        let context_value = get_context_value(type_values)?;
+        let context = context_value
+            .downcast_ref::<EnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        let output_path = context.target_build_path(&target).join("image");
+
+        self.write_layout(&output_path)
+            .map_err(|e| to_runtime_error(e, "build()"))?;
+
+        slog::warn!(
+            context.logger(),
+            "wrote OCI image layout to {}",
+            output_path.display()
+        );
+
+        Ok(Value::new(ResolvedTargetValue {
+            inner: ResolvedTarget {
+                run_mode: RunMode::None,
+                output_path,
+            },
+        }))
+    }
+}
+
+starlark_module! { oci_image_builder_module =>
+    #[allow(non_snake_case)]
+    OciImageBuilder(
+        architecture: String = "amd64".to_string(),
+        os: String = "linux".to_string(),
+        variant = NoneType::None
+    ) {
+        OciImageBuilderValue::new_from_args(architecture, os, &variant)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.set_base_image(env env, this, path: String) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.set_base_image_starlark(env, path)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.add_manifest(
+        this,
+        manifest: FileManifestValue,
+        install_prefix: String = "/".to_string()
+    ) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.add_manifest_starlark(manifest, install_prefix)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.set_entrypoint(this, args) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.set_entrypoint_starlark(&args)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.set_cmd(this, args) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.set_cmd_starlark(&args)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.set_env(this, name: String, value: String) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.set_env_starlark(name, value)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.set_label(this, name: String, value: String) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.set_label_starlark(name, value)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.set_working_dir(this, path: String) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.set_working_dir_starlark(path)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.set_user(this, user: String) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.set_user_starlark(user)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.set_tag(this, tag: String) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.set_tag_starlark(tag)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.set_mtime(this, mtime: i64) {
+        let mut this = this.downcast_mut::<OciImageBuilderValue>().unwrap().unwrap();
+        this.set_mtime_starlark(mtime)
+    }
+
+    #[allow(non_snake_case)]
+    OciImageBuilder.build(env env, this, target: String) {
+        let this = this.downcast_ref::<OciImageBuilderValue>().unwrap();
+        this.build(env, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, crate::oci::OciImage, crate::starlark::testutil::*, tugger_common::testutil::*,
+        tugger_file_manifest::FileEntry,
+    };
+
+    #[test]
+    fn test_new() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval("OciImageBuilder()")?;
+        assert_eq!(v.get_type(), "OciImageBuilder");
+
+        let builder = v.downcast_ref::<OciImageBuilderValue>().unwrap();
+        assert_eq!(builder.architecture, "amd64");
+        assert_eq!(builder.os, "linux");
+
+        let v = env.eval("OciImageBuilder('arm', variant = 'v7')")?;
+        let builder = v.downcast_ref::<OciImageBuilderValue>().unwrap();
+        assert_eq!(builder.variant, Some("v7".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_layout() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval("image = OciImageBuilder(); image")?;
+        let m = env.eval("m = FileManifest(); m")?;
+        m.downcast_mut::<FileManifestValue>()
+            .unwrap()
+            .unwrap()
+            .manifest
+            .add_file_entry(
+                "myapp",
+                FileEntry {
+                    data: vec![42].into(),
+                    executable: true,
+                },
+            )?;
+
+        env.eval("image.add_manifest(m, '/app')")?;
+        env.eval("image.set_entrypoint(['/app/myapp'])")?;
+        env.eval("image.set_env('LANG', 'C.UTF-8')")?;
+        env.eval("image.set_label('org.opencontainers.image.title', 'myapp')")?;
+        env.eval("image.set_tag('latest')")?;
+        assert!(env.eval("image.set_cmd(['--verbose', 1])").is_err());
+
+        let builder = v.downcast_ref::<OciImageBuilderValue>().unwrap();
+        assert!(builder.files.has_path("app/myapp"));
+
+        let path = DEFAULT_TEMP_DIR
+            .path()
+            .join("oci_image_builder_test_write_layout");
+        builder.write_layout(&path)?;
+        // Writing again replaces the existing layout.
+        builder.write_layout(&path)?;
+
+        let image = OciImage::from_layout(&path, builder.to_oci_image_builder()?.platform())?;
+        assert_eq!(
+            image.config.config.entrypoint,
+            Some(vec!["/app/myapp".to_string()])
+        );
+        assert_eq!(
+            image
+                .config
+                .config
+                .labels
+                .unwrap()
+                .get("org.opencontainers.image.title"),
+            Some(&"myapp".to_string())
+        );
+
+        Ok(())
+    }
+}
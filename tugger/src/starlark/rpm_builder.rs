@@ -0,0 +1,741 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::starlark::{
+        common::{mtime_from_arg, resolve_mtime},
+        file_resource::FileManifestValue,
+    },
+    anyhow::{Context, Result},
+    starlark::{
+        environment::TypeValues,
+        values::{
+            error::{RuntimeError, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE},
+            none::NoneType,
+            {Mutable, TypedValue, Value, ValueResult},
+        },
+        {
+            starlark_fun, starlark_module, starlark_parse_param_type, starlark_signature,
+            starlark_signature_extraction, starlark_signatures,
+        },
+    },
+    starlark_dialect_build_targets::{
+        get_context_value, optional_bool_arg, optional_int_arg, optional_str_arg,
+        EnvironmentContext, ResolvedTarget, ResolvedTargetValue, RunMode,
+    },
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+    tugger_file_manifest::FileManifest,
+    tugger_rpm::{RpmCompression, RpmDependency, RpmFileAttributes, RpmScriptlet},
+};
+
+fn to_runtime_error(err: anyhow::Error, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: "TUGGER_RPM_BUILDER",
+        message: format!("{:?}", err),
+        label: label.to_string(),
+    })
+}
+
+fn to_parameter_error(message: String, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+        message,
+        label: label.to_string(),
+    })
+}
+
+/// Resolve an optional `mode` argument to permission bits.
+fn optional_mode_arg(value: &Value, label: &str) -> Result<Option<u32>, ValueError> {
+    match optional_int_arg("mode", value)? {
+        Some(mode) if (0..=0o7777).contains(&mode) => Ok(Some(mode as u32)),
+        Some(mode) => Err(to_parameter_error(
+            format!(
+                "mode {:#o} is not valid; expected a value up to 0o7777",
+                mode
+            ),
+            label,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// Normalize a Starlark path to be relative to the filesystem root.
+fn root_relative_path(path: &str) -> PathBuf {
+    PathBuf::from(path.trim_start_matches('/'))
+}
+
+#[derive(Clone, Debug)]
+pub struct RpmBuilderValue {
+    pub name: String,
+    pub version: String,
+    pub license: String,
+    pub arch: String,
+    pub description: String,
+    pub release: u16,
+    pub epoch: i32,
+    pub requires: Vec<RpmDependency>,
+    pub provides: Vec<RpmDependency>,
+    pub conflicts: Vec<RpmDependency>,
+    pub obsoletes: Vec<RpmDependency>,
+    pub scriptlets: BTreeMap<RpmScriptlet, String>,
+    /// Changelog entries as (author, text, seconds since UNIX epoch).
+    pub changelog_entries: Vec<(String, String, i32)>,
+    pub compression: RpmCompression,
+    /// Files to install, relative to the filesystem root.
+    pub install_files: FileManifest,
+    /// Attributes of files in `install_files`.
+    pub file_attributes: BTreeMap<PathBuf, RpmFileAttributes>,
+    /// Symlinks to install, relative to the filesystem root, mapped to their targets.
+    pub install_symlinks: BTreeMap<PathBuf, PathBuf>,
+    /// Modified time of installed files, in seconds since the UNIX epoch.
+    pub mtime: Option<u64>,
+}
+
+impl TypedValue for RpmBuilderValue {
+    type Holder = Mutable<RpmBuilderValue>;
+    const TYPE: &'static str = "RpmBuilder";
+
+    fn values_for_descendant_check_and_freeze(&self) -> Box<dyn Iterator<Item = Value>> {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl RpmBuilderValue {
+    pub fn new(
+        name: String,
+        version: String,
+        license: String,
+        arch: String,
+        description: String,
+    ) -> Self {
+        Self {
+            name,
+            version,
+            license,
+            arch,
+            description,
+            release: 1,
+            epoch: 0,
+            requires: vec![],
+            provides: vec![],
+            conflicts: vec![],
+            obsoletes: vec![],
+            scriptlets: BTreeMap::new(),
+            changelog_entries: vec![],
+            compression: RpmCompression::Gzip,
+            install_files: FileManifest::default(),
+            file_attributes: BTreeMap::new(),
+            install_symlinks: BTreeMap::new(),
+            mtime: None,
+        }
+    }
+
+    /// Add files from a `FileManifest` to be installed under a directory.
+    ///
+    /// `install_prefix` is an absolute path like `/usr/lib/myapp`. Every
+    /// added file receives `attributes`.
+    pub fn add_manifest(
+        &mut self,
+        manifest: &FileManifest,
+        install_prefix: &str,
+        attributes: &RpmFileAttributes,
+    ) -> Result<()> {
+        let prefix = root_relative_path(install_prefix);
+
+        for (path, entry) in manifest.iter_entries() {
+            let install_path = prefix.join(path);
+
+            self.install_files
+                .add_file_entry(&install_path, entry.clone())
+                .with_context(|| format!("adding {}", path.display()))?;
+            self.file_attributes
+                .insert(install_path, attributes.clone());
+        }
+
+        Ok(())
+    }
+
+    /// Add a symlink to be installed.
+    pub fn add_symlink(&mut self, path: &str, target: &str) {
+        self.install_symlinks
+            .insert(root_relative_path(path), PathBuf::from(target));
+    }
+
+    /// The filename of the `.rpm` file, following RPM conventions.
+    pub fn rpm_filename(&self) -> String {
+        format!(
+            "{}-{}-{}.{}.rpm",
+            self.name, self.version, self.release, self.arch
+        )
+    }
+
+    /// Convert to a `RpmBuilder` from the tugger-rpm crate.
+    ///
+    /// `build_path` is a directory where files are staged.
+    #[cfg(target_family = "unix")]
+    pub fn to_rpm_builder(&self, build_path: &Path) -> Result<tugger_rpm::RpmBuilder> {
+        let mut builder = tugger_rpm::RpmBuilder::new(
+            build_path,
+            &self.name,
+            &self.version,
+            &self.license,
+            &self.arch,
+            &self.description,
+        )
+        .release(self.release)
+        .epoch(self.epoch)
+        .compression(self.compression)?
+        .mtime(resolve_mtime(self.mtime)?)?;
+
+        for dependency in &self.requires {
+            builder = builder.requires(dependency);
+        }
+        for dependency in &self.provides {
+            builder = builder.provides(dependency);
+        }
+        for dependency in &self.conflicts {
+            builder = builder.conflicts(dependency);
+        }
+        for dependency in &self.obsoletes {
+            builder = builder.obsoletes(dependency);
+        }
+
+        for (scriptlet, content) in &self.scriptlets {
+            builder = builder.scriptlet(*scriptlet, content);
+        }
+
+        for (author, text, time) in &self.changelog_entries {
+            builder = builder.changelog_entry(author, text, *time);
+        }
+
+        for (path, entry) in self.install_files.iter_entries() {
+            let attributes = self.file_attributes.get(path).cloned().unwrap_or_default();
+
+            builder = builder
+                .install_file(path, entry.clone(), attributes)
+                .with_context(|| format!("adding {}", path.display()))?;
+        }
+
+        for (path, target) in &self.install_symlinks {
+            builder = builder.install_symlink(path, target);
+        }
+
+        Ok(builder)
+    }
+
+    /// Write the `.rpm` file to a filesystem path.
+    ///
+    /// `build_path` is a directory where files are staged.
+    #[cfg(target_family = "unix")]
+    pub fn write_rpm(&self, build_path: &Path, dest_path: &Path) -> Result<()> {
+        self.to_rpm_builder(build_path)?
+            .build_to_path(dest_path)
+            .context("writing .rpm file")
+    }
+
+    #[cfg(not(target_family = "unix"))]
+    pub fn write_rpm(&self, _build_path: &Path, _dest_path: &Path) -> Result<()> {
+        Err(anyhow::anyhow!(
+            "building RPMs is not supported on this platform"
+        ))
+    }
+
+    pub fn new_from_args(
+        name: String,
+        version: String,
+        license: String,
+        arch: String,
+        description: String,
+    ) -> ValueResult {
+        Ok(Value::new(Self::new(
+            name,
+            version,
+            license,
+            arch,
+            description,
+        )))
+    }
+
+    pub fn set_release_starlark(&mut self, release: i64) -> ValueResult {
+        if !(0..=i64::from(u16::MAX)).contains(&release) {
+            return Err(to_parameter_error(
+                format!("release must be between 0 and {}", u16::MAX),
+                "set_release()",
+            ));
+        }
+
+        self.release = release as u16;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_epoch_starlark(&mut self, epoch: i64) -> ValueResult {
+        if !(0..=i64::from(i32::MAX)).contains(&epoch) {
+            return Err(to_parameter_error(
+                format!("epoch must be between 0 and {}", i32::MAX),
+                "set_epoch()",
+            ));
+        }
+
+        self.epoch = epoch as i32;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    fn parse_dependency(dependency: &str, label: &str) -> Result<RpmDependency, ValueError> {
+        dependency
+            .parse::<RpmDependency>()
+            .map_err(|e| to_parameter_error(e.to_string(), label))
+    }
+
+    pub fn add_requires_starlark(&mut self, dependency: String) -> ValueResult {
+        self.requires
+            .push(Self::parse_dependency(&dependency, "add_requires()")?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_provides_starlark(&mut self, dependency: String) -> ValueResult {
+        self.provides
+            .push(Self::parse_dependency(&dependency, "add_provides()")?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_conflicts_starlark(&mut self, dependency: String) -> ValueResult {
+        self.conflicts
+            .push(Self::parse_dependency(&dependency, "add_conflicts()")?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_obsoletes_starlark(&mut self, dependency: String) -> ValueResult {
+        self.obsoletes
+            .push(Self::parse_dependency(&dependency, "add_obsoletes()")?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_scriptlet_starlark(&mut self, name: String, content: String) -> ValueResult {
+        let scriptlet = name
+            .parse::<RpmScriptlet>()
+            .map_err(|e| to_parameter_error(e.to_string(), "set_scriptlet()"))?;
+
+        self.scriptlets.insert(scriptlet, content);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_changelog_entry_starlark(
+        &mut self,
+        author: String,
+        text: String,
+        time: i64,
+    ) -> ValueResult {
+        if !(0..=i64::from(i32::MAX)).contains(&time) {
+            return Err(to_parameter_error(
+                "time must be a non-negative number of seconds since the UNIX epoch \
+                 representable in 32 bits"
+                    .to_string(),
+                "add_changelog_entry()",
+            ));
+        }
+
+        self.changelog_entries.push((author, text, time as i32));
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_compression_starlark(&mut self, format: String) -> ValueResult {
+        self.compression = format
+            .parse::<RpmCompression>()
+            .map_err(|e| to_parameter_error(e.to_string(), "set_compression()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_mtime_starlark(&mut self, mtime: i64) -> ValueResult {
+        if mtime > i64::from(i32::MAX) {
+            return Err(to_parameter_error(
+                "mtime must be representable in 32 bits".to_string(),
+                "set_mtime()",
+            ));
+        }
+
+        self.mtime = Some(mtime_from_arg(mtime, "set_mtime()")?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_manifest_starlark(
+        &mut self,
+        manifest: FileManifestValue,
+        install_prefix: String,
+        mode: &Value,
+        user: &Value,
+        group: &Value,
+        config: bool,
+    ) -> ValueResult {
+        let attributes = RpmFileAttributes {
+            mode: optional_mode_arg(mode, "add_manifest()")?,
+            user: optional_str_arg("user", user)?,
+            group: optional_str_arg("group", group)?,
+            config,
+        };
+
+        self.add_manifest(&manifest.manifest, &install_prefix, &attributes)
+            .map_err(|e| to_runtime_error(e, "add_manifest()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_file_attributes_starlark(
+        &mut self,
+        path: String,
+        mode: &Value,
+        user: &Value,
+        group: &Value,
+        config: &Value,
+    ) -> ValueResult {
+        let mode = optional_mode_arg(mode, "set_file_attributes()")?;
+        let user = optional_str_arg("user", user)?;
+        let group = optional_str_arg("group", group)?;
+        let config = optional_bool_arg("config", config)?;
+
+        let rel_path = root_relative_path(&path);
+        if self.install_files.get(&rel_path).is_none() {
+            return Err(to_parameter_error(
+                format!("{} is not installed by this package", path),
+                "set_file_attributes()",
+            ));
+        }
+
+        let attributes = self.file_attributes.entry(rel_path).or_default();
+        if mode.is_some() {
+            attributes.mode = mode;
+        }
+        if user.is_some() {
+            attributes.user = user;
+        }
+        if group.is_some() {
+            attributes.group = group;
+        }
+        if let Some(config) = config {
+            attributes.config = config;
+        }
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn add_symlink_starlark(&mut self, path: String, target: String) -> ValueResult {
+        self.add_symlink(&path, &target);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn build(&self, type_values: &TypeValues, target: String) -> ValueResult {
+        let context_value = get_context_value(type_values)?;
+        let context = context_value
+            .downcast_ref::<EnvironmentContext>()
+            .ok_or(ValueError::IncorrectParameterType)?;
+
+        let output_path = context.target_build_path(&target);
+        let rpm_path = output_path.join(self.rpm_filename());
+
+        self.write_rpm(&output_path.join("rpm-files"), &rpm_path)
+            .map_err(|e| to_runtime_error(e, "build()"))?;
+
+        slog::warn!(context.logger(), "wrote {}", rpm_path.display());
+
+        Ok(Value::new(ResolvedTargetValue {
+            inner: ResolvedTarget {
+                run_mode: RunMode::None,
+                output_path,
+            },
+        }))
+    }
+}
+
+starlark_module! { rpm_builder_module =>
+    #[allow(non_snake_case)]
+    RpmBuilder(
+        name: String,
+        version: String,
+        license: String,
+        arch: String,
+        description: String
+    ) {
+        RpmBuilderValue::new_from_args(name, version, license, arch, description)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.set_release(this, release: i64) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.set_release_starlark(release)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.set_epoch(this, epoch: i64) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.set_epoch_starlark(epoch)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.add_requires(this, dependency: String) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.add_requires_starlark(dependency)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.add_provides(this, dependency: String) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.add_provides_starlark(dependency)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.add_conflicts(this, dependency: String) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.add_conflicts_starlark(dependency)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.add_obsoletes(this, dependency: String) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.add_obsoletes_starlark(dependency)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.set_scriptlet(this, name: String, content: String) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.set_scriptlet_starlark(name, content)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.add_changelog_entry(this, author: String, text: String, time: i64) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.add_changelog_entry_starlark(author, text, time)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.set_compression(this, format: String) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.set_compression_starlark(format)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.set_mtime(this, mtime: i64) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.set_mtime_starlark(mtime)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.add_manifest(
+        this,
+        manifest: FileManifestValue,
+        install_prefix: String = "/".to_string(),
+        mode = NoneType::None,
+        user = NoneType::None,
+        group = NoneType::None,
+        config: bool = false
+    ) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.add_manifest_starlark(manifest, install_prefix, &mode, &user, &group, config)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.set_file_attributes(
+        this,
+        path: String,
+        mode = NoneType::None,
+        user = NoneType::None,
+        group = NoneType::None,
+        config = NoneType::None
+    ) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.set_file_attributes_starlark(path, &mode, &user, &group, &config)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.add_symlink(this, path: String, target: String) {
+        let mut this = this.downcast_mut::<RpmBuilderValue>().unwrap().unwrap();
+        this.add_symlink_starlark(path, target)
+    }
+
+    #[allow(non_snake_case)]
+    RpmBuilder.build(env env, this, target: String) {
+        let this = this.downcast_ref::<RpmBuilderValue>().unwrap();
+        this.build(env, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, crate::starlark::testutil::*, tugger_common::testutil::*,
+        tugger_file_manifest::FileEntry,
+    };
+
+    #[test]
+    fn test_new() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval("RpmBuilder('myapp', '0.1', 'MPL-2.0', 'x86_64', 'My app')")?;
+        assert_eq!(v.get_type(), "RpmBuilder");
+
+        let builder = v.downcast_ref::<RpmBuilderValue>().unwrap();
+        assert_eq!(builder.release, 1);
+        assert_eq!(builder.rpm_filename(), "myapp-0.1-1.x86_64.rpm");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval("rpm = RpmBuilder('myapp', '0.1', 'MPL-2.0', 'x86_64', 'My app'); rpm")?;
+        env.eval("rpm.set_release(3)")?;
+        env.eval("rpm.add_requires('glibc >= 2.17')")?;
+        env.eval("rpm.add_provides('myapp-bin')")?;
+        env.eval("rpm.add_conflicts('otherapp < 2')")?;
+        env.eval("rpm.set_scriptlet('post', 'exit 0')")?;
+        env.eval("rpm.add_changelog_entry('Me', '- Initial release', 1000)")?;
+        env.eval("rpm.set_compression('none')")?;
+        env.eval("rpm.set_mtime(1000)")?;
+
+        assert!(env.eval("rpm.set_release(-1)").is_err());
+        assert!(env.eval("rpm.add_requires('glibc => 2.17')").is_err());
+        assert!(env.eval("rpm.set_scriptlet('install', 'exit 0')").is_err());
+        assert!(env.eval("rpm.set_compression('xz')").is_err());
+        assert!(env.eval("rpm.set_mtime(-1)").is_err());
+        assert!(env.eval("rpm.set_mtime(2147483648)").is_err());
+
+        let builder = v.downcast_ref::<RpmBuilderValue>().unwrap();
+        assert_eq!(builder.rpm_filename(), "myapp-0.1-3.x86_64.rpm");
+        assert_eq!(builder.requires[0].to_string(), "glibc >= 2.17");
+        assert_eq!(builder.provides[0].to_string(), "myapp-bin");
+        assert_eq!(builder.conflicts[0].to_string(), "otherapp < 2");
+        assert_eq!(
+            builder.scriptlets.get(&RpmScriptlet::Post),
+            Some(&"exit 0".to_string())
+        );
+        assert_eq!(builder.changelog_entries.len(), 1);
+        assert_eq!(builder.compression, RpmCompression::None);
+        assert_eq!(builder.mtime, Some(1000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_manifest() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval("rpm = RpmBuilder('myapp', '0.1', 'MPL-2.0', 'x86_64', 'My app'); rpm")?;
+        let m = env.eval("m = FileManifest(); m")?;
+        m.downcast_mut::<FileManifestValue>()
+            .unwrap()
+            .unwrap()
+            .manifest
+            .add_file_entry(
+                "myapp.conf",
+                FileEntry {
+                    data: vec![42].into(),
+                    executable: false,
+                },
+            )?;
+
+        env.eval("rpm.add_manifest(m, '/etc', user = 'myapp', config = True)")?;
+        env.eval("rpm.set_file_attributes('/etc/myapp.conf', mode = 0o600)")?;
+        env.eval("rpm.add_symlink('/etc/myapp.d/default.conf', '../myapp.conf')")?;
+
+        assert!(env
+            .eval("rpm.set_file_attributes('/etc/missing.conf', mode = 0o600)")
+            .is_err());
+        assert!(env
+            .eval("rpm.set_file_attributes('/etc/myapp.conf', mode = 0o10000)")
+            .is_err());
+
+        let builder = v.downcast_ref::<RpmBuilderValue>().unwrap();
+        assert!(builder.install_files.get("etc/myapp.conf").is_some());
+        assert_eq!(
+            builder.file_attributes.get(Path::new("etc/myapp.conf")),
+            Some(&RpmFileAttributes {
+                mode: Some(0o600),
+                user: Some("myapp".to_string()),
+                group: None,
+                config: true,
+            })
+        );
+        assert_eq!(
+            builder
+                .install_symlinks
+                .get(Path::new("etc/myapp.d/default.conf")),
+            Some(&PathBuf::from("../myapp.conf"))
+        );
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_write_rpm() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval("rpm = RpmBuilder('myapp', '0.1', 'MPL-2.0', 'x86_64', 'My app'); rpm")?;
+        env.eval("rpm.add_requires('glibc')")?;
+        env.eval("rpm.add_symlink('/usr/bin/myapp', '/usr/lib/myapp/myapp')")?;
+
+        let builder = v.downcast_ref::<RpmBuilderValue>().unwrap();
+        let build_path = DEFAULT_TEMP_DIR.path().join("test_write_rpm");
+        let rpm_path = build_path.join(builder.rpm_filename());
+        builder.write_rpm(&build_path.join("files"), &rpm_path)?;
+
+        assert!(rpm_path.exists());
+
+        Ok(())
+    }
+
+    #[cfg(target_family = "unix")]
+    #[test]
+    fn test_write_rpm_deterministic() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval("rpm = RpmBuilder('myapp', '0.1', 'MPL-2.0', 'x86_64', 'My app'); rpm")?;
+        let m = env.eval("m = FileManifest(); m")?;
+        m.downcast_mut::<FileManifestValue>()
+            .unwrap()
+            .unwrap()
+            .manifest
+            .add_file_entry(
+                "myapp",
+                FileEntry {
+                    data: vec![42].into(),
+                    executable: true,
+                },
+            )?;
+        env.eval("rpm.add_manifest(m, '/usr/lib/myapp')")?;
+        env.eval("rpm.add_symlink('/usr/bin/myapp', '/usr/lib/myapp/myapp')")?;
+        env.eval("rpm.set_mtime(1000)")?;
+
+        let builder = v.downcast_ref::<RpmBuilderValue>().unwrap();
+        let build_path = DEFAULT_TEMP_DIR.path().join("test_write_rpm_deterministic");
+
+        let first_path = build_path.join("first.rpm");
+        builder.write_rpm(&build_path.join("first"), &first_path)?;
+        std::thread::sleep(std::time::Duration::from_millis(1100));
+        let second_path = build_path.join("second.rpm");
+        builder.write_rpm(&build_path.join("second"), &second_path)?;
+
+        assert_eq!(
+            std::fs::read(&first_path)?,
+            std::fs::read(&second_path)?,
+            "setting mtime makes output deterministic"
+        );
+
+        Ok(())
+    }
+}
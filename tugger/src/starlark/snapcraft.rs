@@ -3,11 +3,14 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    crate::starlark::file_resource::FileManifestValue,
+    crate::starlark::{common::resolve_mtime, file_resource::FileManifestValue},
+    anyhow::Context,
     starlark::{
         environment::TypeValues,
         values::{
-            error::{RuntimeError, UnsupportedOperation, ValueError},
+            error::{
+                RuntimeError, UnsupportedOperation, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            },
             none::NoneType,
             {Mutable, TypedValue, Value, ValueResult},
         },
@@ -20,7 +23,12 @@ use {
         get_context_value, optional_bool_arg, EnvironmentContext, ResolvedTarget,
         ResolvedTargetValue, RunMode, ToOptional, TryToOptional,
     },
-    std::{borrow::Cow, collections::HashMap, convert::TryFrom},
+    std::{
+        borrow::Cow,
+        collections::HashMap,
+        convert::TryFrom,
+        path::{Path, PathBuf},
+    },
     tugger_snapcraft::{
         Adapter, Architecture, Architectures, BuildAttribute, Confinement, Daemon, Grade,
         RestartCondition, SnapApp, SnapPart, Snapcraft, SnapcraftBuilder, SnapcraftInvocation,
@@ -658,10 +666,35 @@ impl<'a> SnapValue<'a> {
     }
 }
 
+/// How a `SnapcraftBuilder` produces snaps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SnapBuildMode {
+    /// Invoke `snapcraft` with the registered invocations.
+    Snapcraft,
+    /// Write the `.snap` file directly from the registered files.
+    Native,
+}
+
+impl std::str::FromStr for SnapBuildMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "snapcraft" => Ok(Self::Snapcraft),
+            "native" => Ok(Self::Native),
+            _ => Err(format!(
+                "{} is not a valid build mode; use snapcraft or native",
+                s
+            )),
+        }
+    }
+}
+
 /// Starlark Value wrapper for `SnapcraftBuilder`.
 #[derive(Clone, Debug)]
 pub struct SnapcraftBuilderValue<'a> {
     pub inner: SnapcraftBuilder<'a>,
+    pub build_mode: SnapBuildMode,
 }
 
 impl TypedValue for SnapcraftBuilderValue<'static> {
@@ -677,7 +710,10 @@ impl SnapcraftBuilderValue<'static> {
     pub fn new_from_snap_value(value: SnapValue<'static>) -> Self {
         let inner = SnapcraftBuilder::new(value.inner.clone());
 
-        SnapcraftBuilderValue { inner }
+        SnapcraftBuilderValue {
+            inner,
+            build_mode: SnapBuildMode::Snapcraft,
+        }
     }
 
     pub fn add_invocation(&mut self, args: Vec<String>, purge_build: Value) -> ValueResult {
@@ -713,23 +749,62 @@ impl SnapcraftBuilderValue<'static> {
         Ok(Value::new(NoneType::None))
     }
 
+    pub fn set_build_mode(&mut self, mode: String) -> ValueResult {
+        self.build_mode = mode.parse::<SnapBuildMode>().map_err(|e| {
+            ValueError::Runtime(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e,
+                label: "set_build_mode()".to_string(),
+            })
+        })?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    /// Write a `.snap` file into a directory without invoking `snapcraft`.
+    ///
+    /// Returns the path of the written file.
+    pub fn write_native(&self, dest_dir: &Path) -> anyhow::Result<PathBuf> {
+        let mtime = resolve_mtime(None)?;
+        let mtime = u32::try_from(mtime)
+            .map_err(|_| anyhow::anyhow!("mtime {} is out of range for snaps", mtime))?;
+
+        std::fs::create_dir_all(dest_dir)
+            .with_context(|| format!("creating {}", dest_dir.display()))?;
+
+        let snap_path = dest_dir.join(self.inner.snap_filename()?);
+        self.inner.write_snap(&snap_path, mtime)?;
+
+        Ok(snap_path)
+    }
+
     pub fn build(&self, type_values: &TypeValues, target: String) -> ValueResult {
         let context_value = get_context_value(type_values)?;
         let context = context_value
             .downcast_ref::<EnvironmentContext>()
             .ok_or(ValueError::IncorrectParameterType)?;
 
-        let output_path = context.target_build_path(&target);
-
-        self.inner
-            .build(context.logger(), &output_path)
-            .map_err(|e| {
-                ValueError::Runtime(RuntimeError {
-                    code: "TUGGER_SNAPCRAFT",
-                    message: format!("{:?}", e),
-                    label: "build()".to_string(),
-                })
-            })?;
+        let build_path = context.target_build_path(&target);
+
+        let res = match self.build_mode {
+            SnapBuildMode::Snapcraft => self
+                .inner
+                .build(context.logger(), &build_path)
+                .map(|_| build_path),
+            SnapBuildMode::Native => self.write_native(&build_path),
+        };
+
+        let output_path = res.map_err(|e| {
+            ValueError::Runtime(RuntimeError {
+                code: "TUGGER_SNAPCRAFT",
+                message: format!("{:?}", e),
+                label: "build()".to_string(),
+            })
+        })?;
+
+        if self.build_mode == SnapBuildMode::Native {
+            slog::warn!(context.logger(), "wrote {}", output_path.display());
+        }
 
         Ok(Value::new(ResolvedTargetValue {
             inner: ResolvedTarget {
@@ -780,6 +855,13 @@ starlark_module! { snapcraft_module =>
         this.add_file_manifest(manifest)
     }
 
+    #[allow(non_snake_case)]
+    SnapcraftBuilder.set_build_mode(this, mode: String) {
+        let mut this = this.downcast_mut::<SnapcraftBuilderValue>().unwrap().unwrap();
+
+        this.set_build_mode(mode)
+    }
+
     #[allow(non_snake_case)]
     SnapcraftBuilder.build(env env, this, target: String) {
         let this = this.downcast_ref::<SnapcraftBuilderValue>().unwrap();
@@ -1064,4 +1146,53 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_snapcraft_builder_native() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        env.eval("snap = Snap('myapp', '0.1', 'summary', 'description')")?;
+        env.eval("app = SnapApp(); app.command = 'bin/myapp'")?;
+        env.eval("snap.apps = {'myapp': app}")?;
+        env.eval("snap.architectures = {'build_on': ['amd64'], 'run_on': None}")?;
+        let builder_value = env.eval("builder = snap.to_builder(); builder")?;
+        let m = env.eval("m = FileManifest(); m")?;
+        m.downcast_mut::<FileManifestValue>()
+            .unwrap()
+            .unwrap()
+            .manifest
+            .add_file_entry(
+                "bin/myapp",
+                tugger_file_manifest::FileEntry {
+                    data: vec![42].into(),
+                    executable: true,
+                },
+            )?;
+        env.eval("builder.add_file_manifest(m)")?;
+
+        assert_eq!(
+            builder_value
+                .downcast_ref::<SnapcraftBuilderValue>()
+                .unwrap()
+                .build_mode,
+            SnapBuildMode::Snapcraft
+        );
+        env.eval("builder.set_build_mode('native')")?;
+        assert!(env.eval("builder.set_build_mode('lxd')").is_err());
+
+        let builder = builder_value
+            .downcast_ref::<SnapcraftBuilderValue>()
+            .unwrap();
+        assert_eq!(builder.build_mode, SnapBuildMode::Native);
+
+        let path = builder.write_native(
+            &tugger_common::testutil::DEFAULT_TEMP_DIR
+                .path()
+                .join("test_snapcraft_builder_native"),
+        )?;
+        assert!(path.ends_with("myapp_0.1_amd64.snap"));
+        assert!(path.exists());
+
+        Ok(())
+    }
 }
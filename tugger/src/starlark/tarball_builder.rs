@@ -0,0 +1,254 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::{
+        starlark::{
+            common::{add_manifest_with_prefix, build_archive, mtime_from_arg, resolve_mtime},
+            file_resource::FileManifestValue,
+        },
+        tarball::{write_compressed_tarball_from_manifest, TarballCompression},
+    },
+    anyhow::{Context, Result},
+    starlark::{
+        environment::TypeValues,
+        values::{
+            error::{RuntimeError, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE},
+            none::NoneType,
+            {Mutable, TypedValue, Value, ValueResult},
+        },
+        {
+            starlark_fun, starlark_module, starlark_parse_param_type, starlark_signature,
+            starlark_signature_extraction, starlark_signatures,
+        },
+    },
+    starlark_dialect_build_targets::optional_str_arg,
+    std::{io::Write, path::Path},
+    tugger_file_manifest::FileManifest,
+};
+
+fn to_runtime_error(err: anyhow::Error, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: "TUGGER_TARBALL_BUILDER",
+        message: format!("{:?}", err),
+        label: label.to_string(),
+    })
+}
+
+fn to_parameter_error(message: String, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+        message,
+        label: label.to_string(),
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct TarballBuilderValue {
+    /// Name of the archive file.
+    pub filename: String,
+    /// Files in the archive.
+    pub manifest: FileManifest,
+    pub compression: TarballCompression,
+    /// Modified time of archive members, in seconds since the UNIX epoch.
+    pub mtime: Option<u64>,
+}
+
+impl TypedValue for TarballBuilderValue {
+    type Holder = Mutable<TarballBuilderValue>;
+    const TYPE: &'static str = "TarballBuilder";
+
+    fn values_for_descendant_check_and_freeze(&self) -> Box<dyn Iterator<Item = Value>> {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl TarballBuilderValue {
+    /// Write the tarball to a filesystem path.
+    pub fn write_to_path(&self, path: &Path) -> Result<()> {
+        let fh =
+            std::fs::File::create(path).with_context(|| format!("creating {}", path.display()))?;
+
+        let mut writer = write_compressed_tarball_from_manifest(
+            std::io::BufWriter::new(fh),
+            &self.manifest,
+            None,
+            resolve_mtime(self.mtime)?,
+            self.compression,
+        )
+        .with_context(|| format!("writing {}", path.display()))?;
+
+        writer
+            .flush()
+            .with_context(|| format!("flushing {}", path.display()))
+    }
+
+    pub fn new_from_args(filename: String, compression: &Value) -> ValueResult {
+        let compression = match optional_str_arg("compression", compression)? {
+            Some(compression) => compression
+                .parse::<TarballCompression>()
+                .map_err(|e| to_parameter_error(e.to_string(), "TarballBuilder()"))?,
+            None => TarballCompression::from_filename(&filename).ok_or_else(|| {
+                to_parameter_error(
+                    format!(
+                        "unable to determine compression format from {}; pass compression",
+                        filename
+                    ),
+                    "TarballBuilder()",
+                )
+            })?,
+        };
+
+        Ok(Value::new(Self {
+            filename,
+            manifest: FileManifest::default(),
+            compression,
+            mtime: None,
+        }))
+    }
+
+    pub fn add_manifest_starlark(
+        &mut self,
+        manifest: FileManifestValue,
+        prefix: String,
+    ) -> ValueResult {
+        add_manifest_with_prefix(&mut self.manifest, &manifest.manifest, &prefix)
+            .map_err(|e| to_runtime_error(e, "add_manifest()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_mtime_starlark(&mut self, mtime: i64) -> ValueResult {
+        self.mtime = Some(mtime_from_arg(mtime, "set_mtime()")?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn build(&self, type_values: &TypeValues, target: String) -> ValueResult {
+        build_archive(
+            type_values,
+            "TUGGER_TARBALL_BUILDER",
+            &target,
+            &self.filename,
+            |path| self.write_to_path(path),
+        )
+    }
+}
+
+starlark_module! { tarball_builder_module =>
+    #[allow(non_snake_case)]
+    TarballBuilder(filename: String, compression = NoneType::None) {
+        TarballBuilderValue::new_from_args(filename, &compression)
+    }
+
+    #[allow(non_snake_case)]
+    TarballBuilder.add_manifest(
+        this,
+        manifest: FileManifestValue,
+        prefix: String = "".to_string()
+    ) {
+        let mut this = this.downcast_mut::<TarballBuilderValue>().unwrap().unwrap();
+        this.add_manifest_starlark(manifest, prefix)
+    }
+
+    #[allow(non_snake_case)]
+    TarballBuilder.set_mtime(this, mtime: i64) {
+        let mut this = this.downcast_mut::<TarballBuilderValue>().unwrap().unwrap();
+        this.set_mtime_starlark(mtime)
+    }
+
+    #[allow(non_snake_case)]
+    TarballBuilder.build(env env, this, target: String) {
+        let this = this.downcast_ref::<TarballBuilderValue>().unwrap();
+        this.build(env, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, crate::starlark::testutil::*, tugger_common::testutil::*,
+        tugger_file_manifest::FileEntry,
+    };
+
+    fn add_manifest(env: &mut StarlarkEnvironment) -> Result<()> {
+        let m = env.eval("m = FileManifest(); m")?;
+        m.downcast_mut::<FileManifestValue>()
+            .unwrap()
+            .unwrap()
+            .manifest
+            .add_file_entry(
+                "bin/myapp",
+                FileEntry {
+                    data: vec![42].into(),
+                    executable: true,
+                },
+            )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tarball_builder_new() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+
+        let v = env.eval("TarballBuilder('myapp.tar.zst')")?;
+        assert_eq!(v.get_type(), "TarballBuilder");
+        assert_eq!(
+            v.downcast_ref::<TarballBuilderValue>().unwrap().compression,
+            TarballCompression::Zstd
+        );
+
+        let v = env.eval("TarballBuilder('myapp.tar.zst', compression = 'xz')")?;
+        assert_eq!(
+            v.downcast_ref::<TarballBuilderValue>().unwrap().compression,
+            TarballCompression::Xz
+        );
+
+        assert!(env.eval("TarballBuilder('myapp.tar.lz4')").is_err());
+        assert!(env
+            .eval("TarballBuilder('myapp.tar', compression = 'lz4')")
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tarball_builder_write() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+        add_manifest(&mut env)?;
+
+        let v = env.eval("tar = TarballBuilder('myapp.tar.gz'); tar")?;
+        env.eval("tar.add_manifest(m, 'myapp-0.1')")?;
+        env.eval("tar.set_mtime(1000)")?;
+        assert!(env.eval("tar.set_mtime(-1)").is_err());
+
+        let builder = v.downcast_ref::<TarballBuilderValue>().unwrap();
+        assert!(builder.manifest.has_path("myapp-0.1/bin/myapp"));
+        assert_eq!(builder.mtime, Some(1000));
+
+        let first = DEFAULT_TEMP_DIR
+            .path()
+            .join("test_tarball_builder_write_0.tar.gz");
+        let second = DEFAULT_TEMP_DIR
+            .path()
+            .join("test_tarball_builder_write_1.tar.gz");
+        builder.write_to_path(&first)?;
+        builder.write_to_path(&second)?;
+        assert_eq!(std::fs::read(&first)?, std::fs::read(&second)?);
+
+        let mut archive =
+            tar::Archive::new(flate2::read::GzDecoder::new(std::fs::File::open(&first)?));
+        let paths = archive
+            .entries()?
+            .map(|entry| Ok(entry?.path()?.display().to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            paths,
+            vec!["myapp-0.1", "myapp-0.1/bin", "myapp-0.1/bin/myapp"]
+        );
+
+        Ok(())
+    }
+}
@@ -18,8 +18,8 @@ use {
         },
     },
     starlark_dialect_build_targets::{
-        get_context_value, optional_dict_arg, optional_str_arg, EnvironmentContext, ResolvedTarget,
-        ResolvedTargetValue, RunMode,
+        fingerprint::TargetInput, get_context_value, optional_dict_arg, optional_str_arg,
+        record_target_input, EnvironmentContext, ResolvedTarget, ResolvedTargetValue, RunMode,
     },
     std::{convert::TryFrom, path::Path},
     tugger_file_manifest::FileEntry,
@@ -82,10 +82,16 @@ impl WiXInstallerValue {
 
     fn add_build_file(
         &mut self,
+        type_values: &TypeValues,
         install_path: String,
         filesystem_path: String,
         force_read: bool,
     ) -> ValueResult {
+        record_target_input(
+            type_values,
+            TargetInput::Path(filesystem_path.clone().into()),
+        )?;
+
         let entry = self
             .resolve_file_entry(Path::new(&filesystem_path), force_read)
             .map_err(|e| {
@@ -111,10 +117,16 @@ impl WiXInstallerValue {
 
     fn add_install_file(
         &mut self,
+        type_values: &TypeValues,
         install_path: String,
         filesystem_path: String,
         force_read: bool,
     ) -> ValueResult {
+        record_target_input(
+            type_values,
+            TargetInput::Path(filesystem_path.clone().into()),
+        )?;
+
         let entry = self
             .resolve_file_entry(Path::new(&filesystem_path), force_read)
             .map_err(|e| {
@@ -206,7 +218,12 @@ impl WiXInstallerValue {
         Ok(Value::new(NoneType::None))
     }
 
-    fn add_wxs_file(&mut self, path: String, preprocessor_parameters: Value) -> ValueResult {
+    fn add_wxs_file(
+        &mut self,
+        type_values: &TypeValues,
+        path: String,
+        preprocessor_parameters: Value,
+    ) -> ValueResult {
         optional_dict_arg(
             "preprocessor_parameters",
             "string",
@@ -214,6 +231,8 @@ impl WiXInstallerValue {
             &preprocessor_parameters,
         )?;
 
+        record_target_input(type_values, TargetInput::Path(path.clone().into()))?;
+
         let mut builder = WxsBuilder::from_path(path).map_err(|e| {
             ValueError::from(RuntimeError {
                 code: "TUGGER",
@@ -284,13 +303,14 @@ starlark_module! { wix_installer_module =>
     }
 
     WiXInstaller.add_build_file(
+        env env,
         this,
         build_path: String,
         filesystem_path: String,
         force_read: bool = false
     ) {
         let mut this = this.downcast_mut::<WiXInstallerValue>().unwrap().unwrap();
-        this.add_build_file(build_path, filesystem_path, force_read)
+        this.add_build_file(env, build_path, filesystem_path, force_read)
     }
 
     WiXInstaller.add_build_files(this, manifest: FileManifestValue) {
@@ -299,13 +319,14 @@ starlark_module! { wix_installer_module =>
     }
 
     WiXInstaller.add_install_file(
+        env env,
         this,
         install_path: String,
         filesystem_path: String,
         force_read: bool = false
     ) {
         let mut this = this.downcast_mut::<WiXInstallerValue>().unwrap().unwrap();
-        this.add_install_file(install_path, filesystem_path, force_read)
+        this.add_install_file(env, install_path, filesystem_path, force_read)
     }
 
     WiXInstaller.add_install_files(this, manifest: FileManifestValue) {
@@ -336,9 +357,14 @@ starlark_module! { wix_installer_module =>
         )
     }
 
-    WiXInstaller.add_wxs_file(this, path: String, preprocessor_parameters = NoneType::None) {
+    WiXInstaller.add_wxs_file(
+        env env,
+        this,
+        path: String,
+        preprocessor_parameters = NoneType::None
+    ) {
         let mut this = this.downcast_mut::<WiXInstallerValue>().unwrap().unwrap();
-        this.add_wxs_file(path, preprocessor_parameters)
+        this.add_wxs_file(env, path, preprocessor_parameters)
     }
 
     WiXInstaller.build(env env, this, target: String) {
@@ -0,0 +1,198 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    crate::{
+        starlark::{
+            common::{add_manifest_with_prefix, build_archive, mtime_from_arg, resolve_mtime},
+            file_resource::FileManifestValue,
+        },
+        zipfile::{write_zip_from_manifest, ZipCompression},
+    },
+    anyhow::{Context, Result},
+    starlark::{
+        environment::TypeValues,
+        values::{
+            error::{RuntimeError, ValueError, INCORRECT_PARAMETER_TYPE_ERROR_CODE},
+            none::NoneType,
+            {Mutable, TypedValue, Value, ValueResult},
+        },
+        {
+            starlark_fun, starlark_module, starlark_parse_param_type, starlark_signature,
+            starlark_signature_extraction, starlark_signatures,
+        },
+    },
+    std::path::Path,
+    tugger_file_manifest::FileManifest,
+};
+
+fn to_runtime_error(err: anyhow::Error, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: "TUGGER_ZIP_BUILDER",
+        message: format!("{:?}", err),
+        label: label.to_string(),
+    })
+}
+
+fn to_parameter_error(message: String, label: impl ToString) -> ValueError {
+    ValueError::Runtime(RuntimeError {
+        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+        message,
+        label: label.to_string(),
+    })
+}
+
+#[derive(Clone, Debug)]
+pub struct ZipBuilderValue {
+    /// Name of the archive file.
+    pub filename: String,
+    /// Files in the archive.
+    pub manifest: FileManifest,
+    pub compression: ZipCompression,
+    /// Modified time of archive members, in seconds since the UNIX epoch.
+    pub mtime: Option<u64>,
+}
+
+impl TypedValue for ZipBuilderValue {
+    type Holder = Mutable<ZipBuilderValue>;
+    const TYPE: &'static str = "ZipBuilder";
+
+    fn values_for_descendant_check_and_freeze(&self) -> Box<dyn Iterator<Item = Value>> {
+        Box::new(std::iter::empty())
+    }
+}
+
+impl ZipBuilderValue {
+    /// Write the zip archive to a filesystem path.
+    pub fn write_to_path(&self, path: &Path) -> Result<()> {
+        let fh =
+            std::fs::File::create(path).with_context(|| format!("creating {}", path.display()))?;
+
+        write_zip_from_manifest(
+            fh,
+            &self.manifest,
+            None,
+            resolve_mtime(self.mtime)?,
+            self.compression,
+        )
+        .with_context(|| format!("writing {}", path.display()))
+    }
+
+    pub fn new_from_args(filename: String, compression: String) -> ValueResult {
+        let compression = compression
+            .parse::<ZipCompression>()
+            .map_err(|e| to_parameter_error(e.to_string(), "ZipBuilder()"))?;
+
+        Ok(Value::new(Self {
+            filename,
+            manifest: FileManifest::default(),
+            compression,
+            mtime: None,
+        }))
+    }
+
+    pub fn add_manifest_starlark(
+        &mut self,
+        manifest: FileManifestValue,
+        prefix: String,
+    ) -> ValueResult {
+        add_manifest_with_prefix(&mut self.manifest, &manifest.manifest, &prefix)
+            .map_err(|e| to_runtime_error(e, "add_manifest()"))?;
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn set_mtime_starlark(&mut self, mtime: i64) -> ValueResult {
+        self.mtime = Some(mtime_from_arg(mtime, "set_mtime()")?);
+
+        Ok(Value::new(NoneType::None))
+    }
+
+    pub fn build(&self, type_values: &TypeValues, target: String) -> ValueResult {
+        build_archive(
+            type_values,
+            "TUGGER_ZIP_BUILDER",
+            &target,
+            &self.filename,
+            |path| self.write_to_path(path),
+        )
+    }
+}
+
+starlark_module! { zip_builder_module =>
+    #[allow(non_snake_case)]
+    ZipBuilder(filename: String, compression: String = "deflate".to_string()) {
+        ZipBuilderValue::new_from_args(filename, compression)
+    }
+
+    #[allow(non_snake_case)]
+    ZipBuilder.add_manifest(
+        this,
+        manifest: FileManifestValue,
+        prefix: String = "".to_string()
+    ) {
+        let mut this = this.downcast_mut::<ZipBuilderValue>().unwrap().unwrap();
+        this.add_manifest_starlark(manifest, prefix)
+    }
+
+    #[allow(non_snake_case)]
+    ZipBuilder.set_mtime(this, mtime: i64) {
+        let mut this = this.downcast_mut::<ZipBuilderValue>().unwrap().unwrap();
+        this.set_mtime_starlark(mtime)
+    }
+
+    #[allow(non_snake_case)]
+    ZipBuilder.build(env env, this, target: String) {
+        let this = this.downcast_ref::<ZipBuilderValue>().unwrap();
+        this.build(env, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, crate::starlark::testutil::*, tugger_common::testutil::*,
+        tugger_file_manifest::FileEntry,
+    };
+
+    fn add_manifest(env: &mut StarlarkEnvironment) -> Result<()> {
+        let m = env.eval("m = FileManifest(); m")?;
+        m.downcast_mut::<FileManifestValue>()
+            .unwrap()
+            .unwrap()
+            .manifest
+            .add_file_entry(
+                "bin/myapp",
+                FileEntry {
+                    data: vec![42].into(),
+                    executable: true,
+                },
+            )?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_zip_builder_write() -> Result<()> {
+        let mut env = StarlarkEnvironment::new()?;
+        add_manifest(&mut env)?;
+
+        assert!(env.eval("ZipBuilder('myapp.zip', 'lzma')").is_err());
+
+        let v = env.eval("zip = ZipBuilder('myapp.zip', compression = 'bzip2'); zip")?;
+        env.eval("zip.add_manifest(m)")?;
+
+        let builder = v.downcast_ref::<ZipBuilderValue>().unwrap();
+        assert_eq!(builder.compression, ZipCompression::Bzip2);
+        assert!(builder.manifest.has_path("bin/myapp"));
+
+        let first = DEFAULT_TEMP_DIR.path().join("test_zip_builder_write_0.zip");
+        let second = DEFAULT_TEMP_DIR.path().join("test_zip_builder_write_1.zip");
+        builder.write_to_path(&first)?;
+        builder.write_to_path(&second)?;
+        assert_eq!(std::fs::read(&first)?, std::fs::read(&second)?);
+
+        Ok(())
+    }
+}
@@ -3,12 +3,68 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    anyhow::Result,
+    anyhow::{anyhow, Result},
     slog::warn,
-    std::{io::Write, path::Path},
+    std::{
+        collections::BTreeMap,
+        io::Write,
+        path::{Path, PathBuf},
+    },
     tar,
+    tugger_file_manifest::{FileEntry, FileManifest},
 };
 
+/// Compression format of a tarball.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TarballCompression {
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+}
+
+impl TarballCompression {
+    /// Resolve the compression format from the name of a tarball.
+    ///
+    /// e.g. `foo.tar.gz` and `foo.tgz` resolve to gzip.
+    pub fn from_filename(filename: &str) -> Option<Self> {
+        const SUFFIXES: &[(&str, TarballCompression)] = &[
+            (".tar", TarballCompression::None),
+            (".tar.gz", TarballCompression::Gzip),
+            (".tgz", TarballCompression::Gzip),
+            (".tar.xz", TarballCompression::Xz),
+            (".txz", TarballCompression::Xz),
+            (".tar.zst", TarballCompression::Zstd),
+            (".tar.bz2", TarballCompression::Bzip2),
+            (".tbz2", TarballCompression::Bzip2),
+        ];
+
+        SUFFIXES
+            .iter()
+            .find(|(suffix, _)| filename.ends_with(suffix))
+            .map(|(_, compression)| *compression)
+    }
+}
+
+impl std::str::FromStr for TarballCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            "xz" => Ok(Self::Xz),
+            "zstd" => Ok(Self::Zstd),
+            "bzip2" => Ok(Self::Bzip2),
+            _ => Err(anyhow!(
+                "unknown compression format {}; expected one of none, gzip, xz, zstd, bzip2",
+                s
+            )),
+        }
+    }
+}
+
 /// Create a tarball from a filesystem path.
 ///
 /// The uncompressed tar contents will be emitted to the passed writer.
@@ -57,3 +113,221 @@ pub fn write_tarball_from_directory<W: Write, P: AsRef<Path>>(
 
     Ok(())
 }
+
+/// Write an uncompressed tarball containing files in a `FileManifest`.
+///
+/// Files are stored under `archive_prefix`, if given. Entries for all
+/// directories are emitted. All entries have the same owner (`root`) and
+/// modified time (`mtime`, in seconds since the UNIX epoch). Directories and
+/// executable files have mode `0755`, other files `0644`. The output is
+/// therefore a function of the inputs only.
+///
+/// Returns the writer, which the caller is responsible for flushing.
+pub fn write_tarball_from_manifest<W: Write>(
+    writer: W,
+    manifest: &FileManifest,
+    archive_prefix: Option<&Path>,
+    mtime: u64,
+) -> Result<W> {
+    let prefix = archive_prefix.unwrap_or_else(|| Path::new(""));
+
+    // Paths order components lexicographically, so directories are emitted
+    // before their content.
+    let mut entries: BTreeMap<PathBuf, Option<&FileEntry>> = BTreeMap::new();
+    for (rel_path, entry) in manifest.iter_entries() {
+        let path = prefix.join(rel_path);
+
+        for directory in path.ancestors().skip(1) {
+            if !directory.as_os_str().is_empty() {
+                entries.insert(directory.to_path_buf(), None);
+            }
+        }
+
+        entries.insert(path, Some(entry));
+    }
+
+    let mut builder = tar::Builder::new(writer);
+
+    for (path, entry) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_username("root")?;
+        header.set_groupname("root")?;
+        header.set_mtime(mtime);
+
+        match entry {
+            Some(entry) => {
+                let data = entry.data.resolve()?;
+
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_mode(if entry.executable { 0o755 } else { 0o644 });
+                header.set_size(data.len() as _);
+                builder.append_data(&mut header, &path, &*data)?;
+            }
+            None => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                builder.append_data(&mut header, &path, std::io::empty())?;
+            }
+        }
+    }
+
+    Ok(builder.into_inner()?)
+}
+
+/// Write a compressed tarball containing files in a `FileManifest`.
+///
+/// See [write_tarball_from_manifest] for the format of the tarball.
+/// Compressors are configured to not record timestamps, so output is
+/// deterministic.
+///
+/// Returns the writer, which the caller is responsible for flushing.
+pub fn write_compressed_tarball_from_manifest<W: Write>(
+    writer: W,
+    manifest: &FileManifest,
+    archive_prefix: Option<&Path>,
+    mtime: u64,
+    compression: TarballCompression,
+) -> Result<W> {
+    Ok(match compression {
+        TarballCompression::None => {
+            write_tarball_from_manifest(writer, manifest, archive_prefix, mtime)?
+        }
+        TarballCompression::Gzip => {
+            let mut encoder = flate2::GzBuilder::new()
+                .mtime(0)
+                .write(writer, flate2::Compression::default());
+            write_tarball_from_manifest(&mut encoder, manifest, archive_prefix, mtime)?;
+            encoder.finish()?
+        }
+        TarballCompression::Xz => {
+            let mut encoder = xz2::write::XzEncoder::new(writer, 6);
+            write_tarball_from_manifest(&mut encoder, manifest, archive_prefix, mtime)?;
+            encoder.finish()?
+        }
+        TarballCompression::Zstd => {
+            let mut encoder = zstd::stream::Encoder::new(writer, 3)?;
+            write_tarball_from_manifest(&mut encoder, manifest, archive_prefix, mtime)?;
+            encoder.finish()?
+        }
+        TarballCompression::Bzip2 => {
+            let mut encoder = bzip2::write::BzEncoder::new(writer, bzip2::Compression::Default);
+            write_tarball_from_manifest(&mut encoder, manifest, archive_prefix, mtime)?;
+            encoder.finish()?
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Read};
+
+    fn manifest() -> Result<FileManifest> {
+        let mut manifest = FileManifest::default();
+        manifest.add_file_entry(
+            "bin/myapp",
+            FileEntry {
+                data: vec![42].into(),
+                executable: true,
+            },
+        )?;
+        manifest.add_file_entry(
+            "README",
+            FileEntry {
+                data: b"hello".to_vec().into(),
+                executable: false,
+            },
+        )?;
+
+        Ok(manifest)
+    }
+
+    #[test]
+    fn test_compression_from_filename() {
+        assert_eq!(
+            TarballCompression::from_filename("foo.tar.gz"),
+            Some(TarballCompression::Gzip)
+        );
+        assert_eq!(
+            TarballCompression::from_filename("foo.tar"),
+            Some(TarballCompression::None)
+        );
+        assert_eq!(TarballCompression::from_filename("foo.zip"), None);
+    }
+
+    #[test]
+    fn test_write_tarball_from_manifest() -> Result<()> {
+        let manifest = manifest()?;
+
+        let mut data = vec![];
+        write_tarball_from_manifest(&mut data, &manifest, Some(Path::new("myapp-0.1")), 1000)?;
+
+        let mut archive = tar::Archive::new(std::io::Cursor::new(data));
+        let mut seen = vec![];
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let header = entry.header();
+            assert_eq!(header.uid()?, 0);
+            assert_eq!(header.mtime()?, 1000);
+
+            seen.push((
+                entry.path()?.display().to_string(),
+                header.entry_type(),
+                header.mode()?,
+            ));
+
+            if entry.path()? == Path::new("myapp-0.1/README") {
+                let mut content = String::new();
+                entry.read_to_string(&mut content)?;
+                assert_eq!(content, "hello");
+            }
+        }
+
+        assert_eq!(
+            seen,
+            vec![
+                ("myapp-0.1".to_string(), tar::EntryType::Directory, 0o755),
+                (
+                    "myapp-0.1/README".to_string(),
+                    tar::EntryType::Regular,
+                    0o644
+                ),
+                (
+                    "myapp-0.1/bin".to_string(),
+                    tar::EntryType::Directory,
+                    0o755
+                ),
+                (
+                    "myapp-0.1/bin/myapp".to_string(),
+                    tar::EntryType::Regular,
+                    0o755
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compressed_tarball_deterministic() -> Result<()> {
+        let manifest = manifest()?;
+
+        for compression in &[
+            TarballCompression::Gzip,
+            TarballCompression::Xz,
+            TarballCompression::Zstd,
+            TarballCompression::Bzip2,
+        ] {
+            let mut first = vec![];
+            write_compressed_tarball_from_manifest(&mut first, &manifest, None, 0, *compression)?;
+            let mut second = vec![];
+            write_compressed_tarball_from_manifest(&mut second, &manifest, None, 0, *compression)?;
+
+            assert_eq!(first, second, "{:?} output is deterministic", compression);
+        }
+
+        Ok(())
+    }
+}
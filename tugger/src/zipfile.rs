@@ -0,0 +1,213 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    anyhow::{anyhow, Result},
+    std::{
+        collections::BTreeMap,
+        io::{Seek, Write},
+        path::{Path, PathBuf},
+    },
+    tugger_file_manifest::{FileEntry, FileManifest},
+    zip::{write::FileOptions, CompressionMethod, DateTime, ZipWriter},
+};
+
+/// Compression method of files in a zip archive.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ZipCompression {
+    Stored,
+    Deflate,
+    Bzip2,
+}
+
+impl std::str::FromStr for ZipCompression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "stored" => Ok(Self::Stored),
+            "deflate" => Ok(Self::Deflate),
+            "bzip2" => Ok(Self::Bzip2),
+            _ => Err(anyhow!(
+                "unknown compression method {}; expected one of stored, deflate, bzip2",
+                s
+            )),
+        }
+    }
+}
+
+impl From<ZipCompression> for CompressionMethod {
+    fn from(compression: ZipCompression) -> Self {
+        match compression {
+            ZipCompression::Stored => CompressionMethod::Stored,
+            ZipCompression::Deflate => CompressionMethod::Deflated,
+            ZipCompression::Bzip2 => CompressionMethod::Bzip2,
+        }
+    }
+}
+
+/// Convert seconds since the UNIX epoch to a zip (MS-DOS) timestamp.
+///
+/// Zip timestamps can't represent times before 1980, so earlier times are
+/// clamped to 1980-01-01.
+fn zip_datetime(mtime: u64) -> Result<DateTime> {
+    let days = (mtime / 86400) as i64;
+    let seconds = mtime % 86400;
+
+    // Convert days since 1970-01-01 to a civil date. See
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    if year < 1980 {
+        return Ok(DateTime::default());
+    }
+
+    DateTime::from_date_and_time(
+        year as u16,
+        month as u8,
+        day as u8,
+        (seconds / 3600) as u8,
+        (seconds % 3600 / 60) as u8,
+        (seconds % 60) as u8,
+    )
+    .map_err(|_| anyhow!("time {} cannot be represented in a zip archive", mtime))
+}
+
+/// Write a zip archive containing files in a `FileManifest`.
+///
+/// Files are stored under `archive_prefix`, if given. Entries for all
+/// directories are emitted. All entries have the same modified time
+/// (`mtime`, in seconds since the UNIX epoch). Directories and executable
+/// files have mode `0755`, other files `0644`. The output is therefore a
+/// function of the inputs only.
+pub fn write_zip_from_manifest<W: Write + Seek>(
+    writer: W,
+    manifest: &FileManifest,
+    archive_prefix: Option<&Path>,
+    mtime: u64,
+    compression: ZipCompression,
+) -> Result<()> {
+    let prefix = archive_prefix.unwrap_or_else(|| Path::new(""));
+    let options = FileOptions::default()
+        .compression_method(compression.into())
+        .last_modified_time(zip_datetime(mtime)?);
+
+    // Paths order components lexicographically, so directories are emitted
+    // before their content.
+    let mut entries: BTreeMap<PathBuf, Option<&FileEntry>> = BTreeMap::new();
+    for (rel_path, entry) in manifest.iter_entries() {
+        let path = prefix.join(rel_path);
+
+        for directory in path.ancestors().skip(1) {
+            if !directory.as_os_str().is_empty() {
+                entries.insert(directory.to_path_buf(), None);
+            }
+        }
+
+        entries.insert(path, Some(entry));
+    }
+
+    let mut zf = ZipWriter::new(writer);
+
+    for (path, entry) in entries {
+        // Zip archives always use `/` as the directory separator.
+        let name = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        match entry {
+            Some(entry) => {
+                let mode = if entry.executable { 0o755 } else { 0o644 };
+                zf.start_file(name, options.unix_permissions(mode))?;
+                zf.write_all(&entry.data.resolve()?)?;
+            }
+            None => {
+                zf.add_directory(name, options.unix_permissions(0o755))?;
+            }
+        }
+    }
+
+    zf.finish()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::io::Cursor};
+
+    #[test]
+    fn test_zip_datetime() -> Result<()> {
+        let dt = zip_datetime(0)?;
+        assert_eq!((dt.year(), dt.month(), dt.day()), (1980, 1, 1));
+
+        // 2021-05-01T12:34:56Z
+        let dt = zip_datetime(1_619_872_496)?;
+        assert_eq!(
+            (
+                dt.year(),
+                dt.month(),
+                dt.day(),
+                dt.hour(),
+                dt.minute(),
+                dt.second()
+            ),
+            (2021, 5, 1, 12, 34, 56)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_zip_from_manifest() -> Result<()> {
+        let mut manifest = FileManifest::default();
+        manifest.add_file_entry(
+            "bin/myapp",
+            FileEntry {
+                data: vec![42].into(),
+                executable: true,
+            },
+        )?;
+
+        let mut first = Cursor::new(vec![]);
+        write_zip_from_manifest(
+            &mut first,
+            &manifest,
+            Some(Path::new("myapp")),
+            0,
+            ZipCompression::Deflate,
+        )?;
+        let mut second = Cursor::new(vec![]);
+        write_zip_from_manifest(
+            &mut second,
+            &manifest,
+            Some(Path::new("myapp")),
+            0,
+            ZipCompression::Deflate,
+        )?;
+        assert_eq!(first.get_ref(), second.get_ref());
+
+        let mut archive = zip::ZipArchive::new(first)?;
+        let names = (0..archive.len())
+            .map(|i| Ok(archive.by_index(i)?.name().to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(names, vec!["myapp/", "myapp/bin/", "myapp/bin/myapp"]);
+        assert_eq!(
+            archive.by_name("myapp/bin/myapp")?.unix_mode(),
+            Some(0o100755)
+        );
+
+        Ok(())
+    }
+}